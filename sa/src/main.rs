@@ -1,18 +1,44 @@
 mod modules;
 
-use clap::Parser;
+use clap::{CommandFactory, Parser};
 use std::process;
 use std::fs;
-use std::env;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use tokio::process::Command;
 use colored::*;
-use crate::modules::models::{Commands, CacheAction, SecurityAction, MirrorAction, DockerAction};
-use crate::modules::cache::{PackageCache, ensure_venv_exists, install_package_with_cache};
-use crate::modules::security::SecurityScanner;
-use crate::modules::mirrors::MirrorManager;
-use crate::modules::visualize::DependencyVisualizer;
-use crate::modules::docker::DockerManager;
+use crate::modules::models::{AuditFixPlan, BuildArtifact, BuildLock, BuildOptions, Commands, InstallOptions, InstalledPackage, SecurityVulnerability, CacheAction, CacheListFilter, ConfigAction, TrustAction, LicenseAction, SecurityAction, MirrorAction, Mirror, MirrorAuth, MirrorCredentials, MirrorProbe, PublishRepository, TlsOptions, TransferOptions, AuthAction, ContainerSettings, DockerAction, EnvAction, ReleaseConfig, SAConfig, VersionAction, VisualizeAction, WorkspaceConfig};
+use crate::modules::cache::{PackageCache, install_package_with_cache, installed_dists, lock_selection, sha256_file};
+use crate::modules::security::{SecurityScanner, VULNERABILITY_SOURCES, add_vulnerability_ignore, audit_exit_code, audit_targets, block_threshold, json_report, load_security_policy, sarif_report, severity_rank};
+use crate::modules::allowlist::AllowList;
+use crate::modules::licenses::LicenseChecker;
+use crate::modules::remediation::{apply_to_environment, apply_to_requirements, Remediator};
+use crate::modules::typosquat::confirm;
+use crate::modules::mirrors::{filename_version, success_rate, MirrorManager};
+use crate::modules::index_kinds::{index_url, upload_url};
+use crate::modules::snapshot::{print_usage_hint, read_lockfile, SnapshotBuilder, SnapshotPin};
+use crate::modules::network;
+use crate::modules::output;
+use crate::modules::completions::script as completion_script;
+use crate::modules::credentials::{builtin_repository, keyring_delete, keyring_set, mirror_account, publish_account, publish_credentials, read_secret};
+use crate::modules::visualize::{format_size, DependencyGraph, DependencySource, DependencyVisualizer, GraphFilter, Overlay};
+use crate::modules::compose::{compose_file, declared_services};
+use crate::modules::registry::registry_credentials;
+use crate::modules::docker::{check_platform, container_settings, manylinux_image, manylinux_python, parse_env_flags, project_dockerfile, BuildTarget, DockerManager, DOCKERIGNORE};
+use crate::modules::dotenv::resolve_env_files;
+use crate::modules::ephemeral::EphemeralEnvs;
+use crate::modules::pep517::BuildBackend;
+use crate::modules::trusted_publishing::{mint_token, setup_hint, trusted_index};
+use crate::modules::upload::{find_distributions, index_files, wait_for_files, UploadOutcome, Uploader};
+use crate::modules::dist_check::{check_release, Finding};
+use crate::modules::versioning::{bump, ProjectVersion};
+use crate::modules::release::{changes, check_git, commits_since, git_commit, git_push, git_tag, has_section, insert_section, last_tag, render_section, suggested_part, uncommitted_files, Snapshot};
+use crate::modules::pep440::Version;
+use crate::modules::config::{check_settings, find_setting, lookup, parse_duration, parse_size, project_settings, project_tool_section, set_global_setting, set_project_setting, Setting, SETTINGS};
+use crate::modules::installer::normalize_dist_name;
+use crate::modules::pep508::Requirement;
+use crate::modules::workspace::{build_order, load_members, Member, MemberDir, PinnedPyproject};
+use crate::modules::venv::{installed_packages, ensure_venv_exists, ensure_venv_with_python, pip_command, bin_path, create_venv, apply_seed_pins, venv_dir, detect_shell, activation_snippet, spawn_shell, env_bin_path, read_env_metadata};
 
 /// sa - Super Accelerated Python Package Manager
 #[derive(Parser)]
@@ -21,13 +47,32 @@ use crate::modules::docker::DockerManager;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Work from local data only (no vulnerability database refresh or OSV queries)
+    #[arg(long, global = true)]
+    offline: bool,
+    /// Print results as JSON on stdout, with messages on stderr (also
+    /// SA_OUTPUT=json)
+    #[arg(long, global = true)]
+    json: bool,
 }
 
 // Main function with comprehensive command handling
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
-    let _ = run_sa(cli).await;
+    if cli.json || output::json_requested() {
+        if let Err(e) = output::enable_json() {
+            eprintln!("{}", format!("❌ {}", e).red());
+            process::exit(1);
+        }
+    }
+    if let Err(e) = run_sa(cli).await {
+        eprintln!("{}", format!("❌ {}", e).red());
+        if output::json_mode() {
+            output::print_error(&e.to_string());
+        }
+        process::exit(1);
+    }
 }
 
 async fn run_sa(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
@@ -38,8 +83,8 @@ async fn run_sa(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
             match ensure_venv_exists().await {
                 Ok(_) => {
                     // Install the package
-                    let install_output = Command::new(".sa_env/bin/pip")
-                        .args(&["install", package])
+                    let install_output = pip_command()
+                        .args(["install", package])
                         .output()
                         .await?;
 
@@ -48,8 +93,8 @@ async fn run_sa(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
 
                         // Show dependencies
                         println!("{}", "📋 Dependencies:".cyan());
-                        let output = Command::new(".sa_env/bin/pip")
-                            .args(&["show", package])
+                        let output = pip_command()
+                            .args(["show", package])
                             .output()
                             .await?;
 
@@ -68,7 +113,9 @@ async fn run_sa(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
             }
         },
 
-        Commands::Add { package, skip_security, mirror: _, refresh_cache: _ } => {
+        Commands::Add { package, skip_security, mirror, refresh_cache: _, compile, require_attestations, yes, python } => {
+            ensure_venv_with_python(python.as_deref()).await?;
+
             let mut cache = match PackageCache::new() {
                 Ok(cache) => cache,
                 Err(e) => {
@@ -77,15 +124,24 @@ async fn run_sa(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
                 }
             };
 
-            let mirror_manager = match MirrorManager::new() {
+            let mut mirror_manager = match MirrorManager::new() {
                 Ok(manager) => manager,
                 Err(e) => {
                     eprintln!("Failed to initialize mirror manager: {}", e);
                     process::exit(1);
                 }
             };
+            if !cli.offline {
+                if let Err(e) = mirror_manager.check_health().await {
+                    println!("{}", format!("Warning: mirror health check failed: {}", e).yellow());
+                }
+            }
+            if let Some(name) = mirror {
+                mirror_manager.use_only(name)?;
+                println!("{}", format!("🪞 Installing from mirror '{}' only", name).cyan());
+            }
 
-            let security_scanner = match SecurityScanner::new() {
+            let mut security_scanner = match SecurityScanner::new(cli.offline) {
                 Ok(scanner) => scanner,
                 Err(e) => {
                     eprintln!("Failed to initialize security scanner: {}", e);
@@ -93,7 +149,15 @@ async fn run_sa(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
                 }
             };
 
+            let options = InstallOptions {
+                skip_security: *skip_security,
+                compile: *compile,
+                require_attestations: *require_attestations,
+                assume_yes: *yes,
+                no_save: false,
+            };
             let mut all_success = true;
+            let mut errors: HashMap<&str, String> = HashMap::new();
 
             for pkg in package {
                 println!("{}", format!("📦 Adding package '{}'", pkg).cyan());
@@ -102,17 +166,39 @@ async fn run_sa(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
                     pkg,
                     &mut cache,
                     &mirror_manager,
-                    &security_scanner,
-                    *skip_security,
+                    &mut security_scanner,
+                    options,
                 ).await {
                     Ok(_) => println!("{}", format!("✅ Successfully added '{}'", pkg).green()),
                     Err(e) => {
                         println!("{}", format!("❌ Error adding '{}': {}", pkg, e).red());
+                        errors.insert(pkg.as_str(), e.to_string());
                         all_success = false;
                     }
                 }
             }
 
+            if let Ok(true) = cache.schedule_background_gc() {
+                println!("{}", "🧹 Cache is over its size budget, running gc in the background".blue());
+            }
+
+            if output::json_mode() {
+                // Each requested package with the version it got
+                let installed = installed_packages().await.unwrap_or_default();
+                let results: Vec<serde_json::Value> = package
+                    .iter()
+                    .map(|pkg| {
+                        let name = Requirement::parse(pkg).map(|requirement| requirement.name).unwrap_or_else(|_| pkg.clone());
+                        let version = installed.iter().find(|installed| normalize_dist_name(&installed.name) == normalize_dist_name(&name)).map(|installed| installed.version.clone());
+                        match errors.get(pkg.as_str()) {
+                            Some(error) => serde_json::json!({ "requirement": pkg, "name": name, "added": false, "error": error }),
+                            None => serde_json::json!({ "requirement": pkg, "name": name, "added": true, "version": version }),
+                        }
+                    })
+                    .collect();
+                output::print_json(&results)?;
+            }
+
             if all_success { Ok(()) } else { Err("Some packages failed to install".into()) }
         }
 
@@ -121,15 +207,15 @@ async fn run_sa(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
 
             if *clean_cache {
                 let _cache = PackageCache::new()?;
-                if let Err(e) = _cache.remove_package(package, "latest") {
+                if let Err(e) = _cache.remove_release(package, "latest") {
                     println!("{}", format!("Warning: Could not clean cache: {}", e).yellow());
                 }
             }
 
             match ensure_venv_exists().await {
                 Ok(_) => {
-                    match Command::new(".sa_env/bin/pip")
-                        .args(&["uninstall", "-y", package])
+                    match pip_command()
+                        .args(["uninstall", "-y", package])
                         .status()
                         .await
                     {
@@ -153,8 +239,8 @@ async fn run_sa(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
 
             match ensure_venv_exists().await {
                 Ok(_) => {
-                    let status = Command::new(".sa_env/bin/pip")
-                        .args(&["uninstall", "-y", package])
+                    let status = pip_command()
+                        .args(["uninstall", "-y", package])
                         .status()
                         .await;
 
@@ -171,9 +257,27 @@ async fn run_sa(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
             }
         }
 
-        Commands::List { tree, format } => {
+        Commands::List { tree, format, outdated } => {
             println!("{}", "📋 Listing installed packages...".cyan());
 
+            if output::json_mode() {
+                if *tree {
+                    return Err("--tree has no JSON form; use 'sa visualize --format json'".into());
+                }
+                ensure_venv_exists().await?;
+                let mut list = pip_command();
+                list.args(["list", "--format", "json", "--disable-pip-version-check"]);
+                if *outdated {
+                    list.arg("--outdated");
+                }
+                let listed = list.stderr(std::process::Stdio::inherit()).output().await?;
+                if !listed.status.success() {
+                    return Err("pip list failed".into());
+                }
+                let packages: serde_json::Value = serde_json::from_slice(&listed.stdout).map_err(|e| format!("Unexpected pip list output: {}", e))?;
+                return output::print_json(&packages);
+            }
+
             let valid_formats = ["columns", "freeze", "json"];
             let chosen_format = if valid_formats.contains(&format.as_str()) {
                 format.as_str()
@@ -186,8 +290,8 @@ async fn run_sa(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
                 Ok(_) => {
                     if *tree {
                         // Get dependency tree
-                        let output = Command::new(".sa_env/bin/pip")
-                            .args(&["show", "--verbose"])
+                        let output = pip_command()
+                            .args(["show", "--verbose"])
                             .output()
                             .await?;
 
@@ -198,8 +302,12 @@ async fn run_sa(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
                         }
                         Ok(())
                     } else {
-                        match Command::new(".sa_env/bin/pip")
-                            .args(&["list", "--format", chosen_format])
+                        let mut list = pip_command();
+                        list.args(["list", "--format", chosen_format]);
+                        if *outdated {
+                            list.arg("--outdated");
+                        }
+                        match list
                             .status()
                             .await
                         {
@@ -212,165 +320,338 @@ async fn run_sa(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
             }
         }
 
-        Commands::Run { with, script, docker, docker_image } => {
+        Commands::Run { with, script, docker, docker_image, gpus, env_files, no_env_file, python } => {
+            let env_vars = resolve_env_files(env_files, *no_env_file)?;
+            if !env_vars.is_empty() {
+                println!("{}", format!("🔧 Loaded {} environment variable(s)", env_vars.len()).blue());
+            }
+
             if *docker {
                 let docker_manager = DockerManager::new()?;
 
-                // Create temporary environment
-                let env_name = format!("sa-temp-{}", uuid::Uuid::new_v4());
-                docker_manager.create_environment(&env_name, docker_image, None).await?;
-
-                // Install dependency in container
-                let install_cmd = vec!["pip".to_string(), "install".to_string(), with.clone()];
-                docker_manager.execute_in_environment(&env_name, &install_cmd).await?;
+                // The --with dependencies are baked into a cached image, since
+                // every command runs in a fresh container
+                let image = docker_manager.run_image(docker_image, with).await?;
 
-                // Run script in container
+                // Run the script against the live project files
                 let mut run_cmd = vec!["python".to_string()];
                 run_cmd.extend(script.clone());
-                docker_manager.execute_in_environment(&env_name, &run_cmd).await?;
-
-                // Cleanup
-                use bollard::image::RemoveImageOptions;
-                let remove_options = RemoveImageOptions {
-                    force: true,
-                    ..Default::default()
-                };
-                let _ = docker_manager.docker.remove_image(&env_name, Some(remove_options), None).await;
-
+                let project_dir = std::env::current_dir()?;
+                let code = docker_manager.execute_in_image(&image, &run_cmd, Some(&project_dir), gpus.as_deref(), &env_vars).await?;
+                if code != 0 {
+                    process::exit(code as i32);
+                }
                 Ok(())
             } else {
-                // Regular execution
-                let _cache = PackageCache::new()?;
-                let mirror_manager = MirrorManager::new()?;
-                let security_scanner = SecurityScanner::new()?;
+                // Regular execution: --with dependencies live in a cached
+                // ephemeral environment so the project env stays untouched
+                let python_path = if with.is_empty() {
+                    ensure_venv_with_python(python.as_deref()).await?;
+                    bin_path("python")
+                } else {
+                    let ephemeral = EphemeralEnvs::new()?;
+                    if let Ok(removed) = ephemeral.collect_garbage() {
+                        if removed > 0 {
+                            println!("{}", format!("🧹 Removed {} unused ephemeral environment(s)", removed).blue());
+                        }
+                    }
+                    let env_dir = ephemeral.prepare(with, python.as_deref()).await?;
+                    env_bin_path(&env_dir, "python")
+                };
 
-                match install_package_with_cache(
-                    with,
-                    &mut PackageCache::new()?,
-                    &mirror_manager,
-                    &security_scanner,
-                    false,
-                ).await {
-                    Ok(_) => {
-                        if !script.is_empty() {
-                            let mut cmd = Command::new(".sa_env/bin/python");
-                            cmd.args(script);
-
-                            match cmd.status().await {
-                                Ok(status) => {
-                                    if status.success() {
-                                        println!("{}", "✅ Script executed successfully".green());
-                                        Ok(())
-                                    } else {
-                                        Err("Script execution failed".into())
-                                    }
-                                }
-                                Err(e) => Err(format!("Error executing script: {}", e).into()),
+                if !script.is_empty() {
+                    let mut cmd = Command::new(python_path);
+                    cmd.args(script).envs(env_vars);
+
+                    match cmd.status().await {
+                        Ok(status) => {
+                            if status.success() {
+                                println!("{}", "✅ Script executed successfully".green());
+                                Ok(())
+                            } else {
+                                Err("Script execution failed".into())
                             }
-                        } else {
-                            Ok(())
                         }
+                        Err(e) => Err(format!("Error executing script: {}", e).into()),
                     }
-                    Err(e) => Err(e),
+                } else {
+                    Ok(())
                 }
             }
         }
 
-        Commands::Build { docker } => {
-            println!("{}", "🏗️  Building project...".cyan());
-
-            if *docker {
-                let docker_manager = DockerManager::new()?;
-                let build_env = "sa-build-env";
-
-                docker_manager.create_environment(build_env, "python:3.11-slim", Some("requirements.txt")).await?;
-
-                let build_cmd = vec![
-                    "pip".to_string(),
-                    "install".to_string(),
-                    "build".to_string(),
-                    "&&".to_string(),
-                    "python".to_string(),
-                    "-m".to_string(),
-                    "build".to_string(),
-                ];
-
-                docker_manager.execute_in_environment(build_env, &build_cmd).await?;
-                Ok(())
-            } else {
-                // Regular build process
-                ensure_venv_exists().await?;
+        Commands::Build { docker, python, platforms, matrix, manylinux, wheel, sdist, out_dir, all, repair, plat } => {
+            // Both unless only one is asked for
+            let kinds: Vec<&str> = match (*sdist, *wheel) {
+                (true, false) => vec!["sdist"],
+                (false, true) => vec!["wheel"],
+                _ => vec!["sdist", "wheel"],
+            };
+            let options = BuildOptions {
+                docker: *docker,
+                python: python.as_deref(),
+                platforms,
+                kinds: &kinds,
+                out_dir,
+                json: output::json_mode(),
+                repair: *repair,
+                plat: plat.as_deref(),
+                matrix,
+                manylinux,
+            };
+            if !*all {
+                let artifacts = build_project(&options).await?;
+                if output::json_mode() {
+                    output::print_json(&artifacts)?;
+                }
+                return Ok(());
+            }
 
-                let status = Command::new(".sa_env/bin/pip")
-                    .args(&["install", "build"])
-                    .status()
-                    .await?;
+            let root = std::env::current_dir()?;
+            let members = workspace_members()?;
+            let mut artifacts = Vec::new();
+            for member in build_order(&members)? {
+                print_member(&root, member);
+                let _member_dir = MemberDir::enter(member)?;
+                let built = build_project(&options).await.map_err(|e| format!("{}: {}", member.name, e))?;
+                // Paths relative to the workspace root
+                let member_dir = member.dir.strip_prefix(&root).unwrap_or(&member.dir);
+                artifacts.extend(built.into_iter().map(|artifact| BuildArtifact { path: member_dir.join(&artifact.path).display().to_string(), ..artifact }));
+            }
+            println!("{}", format!("✅ Built {} workspace members", members.len()).green());
+            if output::json_mode() {
+                output::print_json(&artifacts)?;
+            }
+            Ok(())
+        }
 
-                if !status.success() {
-                    return Err("Failed to install build dependencies".into());
-                }
+        Commands::Publish { repository, repository_url, check, dry_run, all, pin_path_deps, no_verify, verify_timeout, smoke_test, .. } => {
+            let verify = if *no_verify { None } else { Some(parse_duration(verify_timeout)?.to_std()?) };
+            if !*all {
+                return publish_project(repository.as_deref(), repository_url.as_deref(), *check, *dry_run, verify, *smoke_test).await.map(|_| ());
+            }
 
-                let status = Command::new(".sa_env/bin/python")
-                    .args(&["-m", "build"])
-                    .status()
-                    .await?;
-
-                if status.success() {
-                    println!("{}", "✅ Build completed successfully".green());
-
-                    // Generate lock file with timestamp
-                    let lock_content = format!(
-                        r#"{{
-    "build_time": "{}",
-    "sa_version": "0.1.0",
-    "python_version": "3.11",
-    "platform": "{}"
-}}"#,
-                        chrono::Utc::now().to_rfc3339(),
-                        std::env::consts::OS
-                    );
+            // Every member is checked before anything is uploaded, so one
+            // that would fail does not leave the workspace half published
+            let root = std::env::current_dir()?;
+            let members = workspace_members()?;
+            let order = build_order(&members)?;
+            for member in &order {
+                print_member(&root, member);
+                let _member_dir = MemberDir::enter(member)?;
+                publish_project(repository.as_deref(), repository_url.as_deref(), true, false, None, false)
+                    .await
+                    .map_err(|e| format!("{}: {}", member.name, e))?;
+            }
+            if *check {
+                println!("{}", format!("✅ Checked {} workspace members", members.len()).green());
+                return Ok(());
+            }
 
-                    fs::write("sa.lock", lock_content)?;
-                    println!("{}", "📄 Lock file 'sa.lock' generated".blue());
-                    Ok(())
-                } else {
-                    Err("Build failed".into())
+            // Dependencies first, so each member's are on the index before it
+            let mut published: HashMap<String, String> = HashMap::new();
+            for member in order {
+                print_member(&root, member);
+                let _member_dir = MemberDir::enter(member)?;
+                let in_context = |e: Box<dyn std::error::Error>| format!("{}: {}", member.name, e);
+                if *pin_path_deps && !member.path_deps.is_empty() {
+                    let pinned = PinnedPyproject::pin(member, &published).map_err(in_context)?;
+                    if *dry_run {
+                        println!("  {} would rebuild with {}", "•".blue(), pinned.pins.join(", "));
+                    } else {
+                        println!("  {} rebuilding with {}", "•".blue(), pinned.pins.join(", "));
+                        let options = BuildOptions {
+                            docker: false,
+                            python: None,
+                            platforms: &[],
+                            kinds: &["sdist", "wheel"],
+                            out_dir: Path::new("dist"),
+                            json: false,
+                            repair: false,
+                            plat: None,
+                            matrix: &[],
+                            manylinux: "",
+                        };
+                        build_project(&options).await.map_err(in_context)?;
+                    }
                 }
+                let version = publish_project(repository.as_deref(), repository_url.as_deref(), false, *dry_run, verify, *smoke_test)
+                    .await
+                    .map_err(in_context)?;
+                published.insert(normalize_dist_name(&member.name), version);
             }
+            let done = if *dry_run { "Checked" } else { "Published" };
+            println!("{}", format!("✅ {} {} workspace members", done, members.len()).green());
+            Ok(())
         }
 
-        Commands::Publish => {
-            println!("{}", "📤 Publishing project...".cyan());
+        Commands::Release { part, pre, skip, repository, repository_url, push, allow_dirty, dry_run } => {
+            let project_dir = std::env::current_dir()?;
+            let config: ReleaseConfig = match project_tool_section("release")? {
+                Some(section) => section.try_into().map_err(|e| format!("Invalid [tool.sa.release]: {}", e))?,
+                None => ReleaseConfig::default(),
+            };
+            let skipped = |step: &str| skip.iter().chain(&config.skip).any(|skipped| skipped == step);
+            let version = ProjectVersion::read(&project_dir)?;
+
+            // Refuse before changing anything
+            check_git(None).await?;
+            let dirty = uncommitted_files().await?;
+            if !dirty.is_empty() && !*allow_dirty {
+                return Err(format!("Uncommitted changes in {}; commit or stash them first, or pass --allow-dirty", dirty.join(", ")).into());
+            }
+            let previous_tag = last_tag().await;
+            let commits = commits_since(previous_tag.as_deref()).await?;
+            let since = previous_tag.as_deref().map(|tag| format!("since {}", tag)).unwrap_or_else(|| "in the history".to_string());
+            let next = if skipped("bump") {
+                Version::parse(&version.current)?
+            } else {
+                let part = match part.as_str() {
+                    "auto" if commits.is_empty() => return Err(format!("No commits {}; nothing to release", since).into()),
+                    "auto" => suggested_part(&commits),
+                    part => part,
+                };
+                bump(&Version::parse(&version.current)?, part, pre.as_deref())?
+            };
+            let next_text = next.to_string();
+            let tag_name = version.tag_name(&next_text);
+            if !skipped("tag") {
+                check_git(Some(&tag_name)).await?;
+            }
+            let counted = format!("{} commit{}", commits.len(), if commits.len() == 1 { "" } else { "s" });
+            println!("{}", format!("🚀 Releasing {} {} → {} ({} {})", version.name, version.current, next_text, counted, since).cyan());
+
+            // The files the release commit holds
+            let mut changed = if skipped("bump") { Vec::new() } else { version.paths(&project_dir) };
+            let changelog = fs::read_to_string(&config.changelog).unwrap_or_default();
+            let section = if skipped("changelog") {
+                None
+            } else if has_section(&changelog, &next_text) {
+                println!("  {} {} already has a section for {}; keeping it", "•".blue(), config.changelog, next_text);
+                None
+            } else {
+                Some(render_section(&config.template, &next_text, &version.current, &changes(&commits)))
+            };
+            if section.is_some() {
+                changed.push(config.changelog.clone());
+            }
+            let message = format!("Release {}", next_text);
 
-            ensure_venv_exists().await?;
+            if *dry_run {
+                if !skipped("bump") {
+                    println!("  {} bump {}", "•".blue(), version.paths(&project_dir).join(", "));
+                }
+                if let Some(section) = &section {
+                    println!("  {} add to {}:", "•".blue(), config.changelog);
+                    for line in section.lines() {
+                        println!("      {}", line.dimmed());
+                    }
+                }
+                let steps = [
+                    (!skipped("build")).then(|| "build dist/".to_string()),
+                    (!changed.is_empty()).then(|| format!("commit \"{}\"", message)),
+                    (!skipped("tag")).then(|| format!("tag {}", tag_name)),
+                    (!skipped("publish")).then(|| format!("publish to {}", repository_url.as_deref().or(repository.as_deref()).unwrap_or("pypi"))),
+                    push.then(|| "push to origin".to_string()),
+                ];
+                for step in steps.into_iter().flatten() {
+                    println!("  {} {}", "•".blue(), step);
+                }
+                println!("{}", "Dry run; nothing was changed".yellow());
+                return Ok(());
+            }
 
-            let status = Command::new(".sa_env/bin/pip")
-                .args(&["install", "twine"])
-                .status()
-                .await?;
+            // Files of earlier releases would fail the check
+            let stale: Vec<PathBuf> = if skipped("build") {
+                Vec::new()
+            } else {
+                fs::read_dir("dist")
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.file_name().is_some_and(|name| [".whl", ".tar.gz", ".zip"].iter().any(|suffix| name.to_string_lossy().ends_with(suffix))))
+                    .collect()
+            };
 
-            if !status.success() {
-                return Err("Failed to install twine".into());
+            // Until committed, a failed step puts the files back, including
+            // the sa.lock the build may rewrite and the removed dist files
+            let mut saved = changed.clone();
+            if !skipped("build") && !saved.iter().any(|path| path == "sa.lock") {
+                saved.push("sa.lock".to_string());
             }
-
-            if env::var("PYPI_TOKEN").is_err() {
-                return Err("PYPI_TOKEN environment variable not set".into());
+            saved.extend(stale.iter().map(|path| path.to_string_lossy().to_string()));
+            let snapshot = Snapshot::take(&saved);
+            if !skipped("bump") {
+                version.write(&next)?;
+                println!("  {} bumped {}", "•".blue(), version.paths(&project_dir).join(", "));
+            }
+            if let Some(section) = &section {
+                fs::write(&config.changelog, insert_section(&changelog, section))?;
+                println!("  {} updated {}", "•".blue(), config.changelog);
+            }
+            if !skipped("build") {
+                for path in &stale {
+                    fs::remove_file(path)?;
+                }
+                let options = BuildOptions {
+                    docker: false,
+                    python: None,
+                    platforms: &[],
+                    kinds: &["sdist", "wheel"],
+                    out_dir: Path::new("dist"),
+                    json: false,
+                    repair: false,
+                    plat: None,
+                    matrix: &[],
+                    manylinux: "",
+                };
+                build_project(&options).await?;
+                // A tracked sa.lock records the release's build
+                if uncommitted_files().await?.iter().any(|path| path == "sa.lock") && !changed.iter().any(|path| path == "sa.lock") {
+                    changed.push("sa.lock".to_string());
+                }
+            }
+            if !skipped("publish") || !skipped("build") {
+                publish_project(None, None, true, false, None, false).await?;
             }
 
-            let status = Command::new(".sa_env/bin/twine")
-                .args(&["upload", "dist/*"])
-                .status()
-                .await?;
-
-            if status.success() {
-                println!("{}", "✅ Project published successfully".green());
-                Ok(())
-            } else {
-                Err("Publish failed".into())
+            if !changed.is_empty() {
+                git_commit(&changed, &message).await?;
+                snapshot.keep();
+                println!("  {} committed \"{}\"", "•".blue(), message);
             }
+            if !skipped("tag") {
+                git_tag(&tag_name, &format!("Version {}", next_text)).await?;
+                println!("  {} tagged {}", "•".blue(), tag_name);
+            }
+            if !skipped("publish") {
+                let verify = Some(std::time::Duration::from_secs(300));
+                publish_project(repository.as_deref(), repository_url.as_deref(), false, false, verify, false)
+                    .await
+                    .map_err(|e| format!("{}; the release is committed and tagged, so run 'sa publish' to finish uploading it", e))?;
+            }
+            if *push {
+                git_push((!skipped("tag")).then_some(tag_name.as_str())).await?;
+                println!("  {} pushed to origin", "•".blue());
+            }
+            println!("{}", format!("✅ Released {} {}", version.name, next_text).green());
+            Ok(())
         }
 
-        Commands::Version => {
+        Commands::Audit { requirements, format, fail_on, fix, yes } => {
+            let format = if output::json_mode() && format == "table" { "json" } else { format.as_str() };
+            let mut security_scanner = SecurityScanner::new(cli.offline)?;
+            run_audit(&mut security_scanner, requirements.as_deref(), format, fail_on, *fix, *yes).await
+        }
+
+        Commands::Version { action: None } => {
+            if output::json_mode() {
+                return output::print_json(&serde_json::json!({
+                    "version": "0.1.0",
+                    "platform": std::env::consts::OS,
+                    "architecture": std::env::consts::ARCH,
+                }));
+            }
             println!("{}", "🚀 SA - Super Accelerated Python Package Manager".cyan().bold());
             println!("Version: {}", "0.1.0".green());
             println!("Built with: {}", "Rust 🦀".yellow());
@@ -379,78 +660,522 @@ async fn run_sa(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
             Ok(())
         }
 
+        Commands::Version { action: Some(VersionAction::Bump { part, pre, commit, tag, dry_run }) } => {
+            let project_dir = std::env::current_dir()?;
+            let version = ProjectVersion::read(&project_dir)?;
+            let bumped = bump(&Version::parse(&version.current)?, part, pre.as_deref())?;
+            let next = bumped.to_string();
+            let tag_name = version.tag_name(&next);
+            println!("{}", format!("🔖 {} {} → {}", version.name, version.current, next).cyan());
+
+            let changed = version.paths(&project_dir);
+            for file in version.files.iter().filter(|file| file.version != version.current) {
+                let path = file.path.strip_prefix(&project_dir).unwrap_or(&file.path).display();
+                println!("{}", format!("Warning: {} had {} rather than {}; it gets {} too", path, file.version, version.current, next).yellow());
+            }
+            if *dry_run {
+                for path in &changed {
+                    println!("  {} {}", "•".blue(), path);
+                }
+                if *tag {
+                    println!("  {} tag {}", "•".blue(), tag_name);
+                }
+                return Ok(());
+            }
+
+            // Refuse before changing anything
+            if *commit || *tag {
+                check_git(tag.then_some(tag_name.as_str())).await?;
+            }
+
+            version.write(&bumped)?;
+            for path in &changed {
+                println!("  {} updated {}", "•".blue(), path);
+            }
+
+            if *commit || *tag {
+                let message = format!("Bump version: {} → {}", version.current, next);
+                git_commit(&changed, &message).await.map_err(|e| format!("{}; the version files were changed but not committed", e))?;
+                println!("  {} committed \"{}\"", "•".blue(), message);
+            }
+            if *tag {
+                git_tag(&tag_name, &format!("Version {}", next)).await?;
+                println!("  {} tagged {}", "•".blue(), tag_name);
+            }
+            println!("{}", format!("✅ Version {}", next).green());
+            Ok(())
+        }
+
         Commands::Cache { action } => {
             let cache = PackageCache::new()?;
 
             match action {
                 CacheAction::Clear => {
                     println!("{}", "🧹 Clearing package cache...".yellow());
-                    cache.clear_all()?;
+                    let kept = cache.clear_all()?;
+                    if kept > 0 {
+                        println!("  Kept {} pinned package(s); use 'sa cache unpin' to release them", kept);
+                    }
                     println!("{}", "✅ Cache cleared successfully".green());
                     Ok(())
                 }
 
-                CacheAction::Stats => {
+                CacheAction::Trust { action: TrustAction::List { name } } => {
+                    let records = cache.trust_records(name.as_deref())?;
+                    if output::json_mode() {
+                        return output::print_json(&records);
+                    }
+                    if records.is_empty() {
+                        println!("{}", "No trust records".yellow());
+                        return Ok(());
+                    }
+
+                    println!("{}", "🔐 Trusted Artifacts:".cyan());
+                    for record in &records {
+                        println!(
+                            "  {}=={} {}\n    sha256:{} (first seen {} via {})",
+                            record.name.bold(),
+                            record.version,
+                            record.filename,
+                            record.sha256,
+                            record.first_seen.format("%Y-%m-%d %H:%M"),
+                            record.source,
+                        );
+                    }
+                    Ok(())
+                }
+
+                CacheAction::Trust { action: TrustAction::Forget { spec } } => {
+                    let (name, version) = match spec.split_once("==") {
+                        Some((name, version)) => (name.trim(), Some(version.trim())),
+                        None => (spec.trim(), None),
+                    };
+                    let removed = cache.forget_trust(name, version)?;
+                    if removed == 0 {
+                        return Err(format!("No trust records for '{}'", spec).into());
+                    }
+                    println!("{}", format!("✅ Forgot {} trust record(s) for '{}'", removed, spec).green());
+                    Ok(())
+                }
+
+                CacheAction::Pin { spec } | CacheAction::Unpin { spec } => {
+                    let pin = matches!(action, CacheAction::Pin { .. });
+                    let (name, version) = match spec.split_once("==") {
+                        Some((name, version)) => (name.trim(), Some(version.trim())),
+                        None => (spec.trim(), None),
+                    };
+
+                    for label in cache.set_pinned(name, version, pin)? {
+                        if pin {
+                            println!("{}", format!("📌 Pinned {}", label).green());
+                        } else {
+                            println!("{}", format!("✅ Unpinned {}", label).green());
+                        }
+                    }
+                    Ok(())
+                }
+
+                CacheAction::Stats { packages } => {
+                    let mut stats = cache.get_stats()?;
+                    if *packages || output::json_mode() {
+                        stats.per_package = cache.package_stats()?;
+                    }
+
+                    if output::json_mode() {
+                        return output::print_json(&stats);
+                    }
+
+                    let lookups = stats.hits + stats.misses;
+                    let hit_rate = if lookups > 0 { stats.hits as f64 * 100.0 / lookups as f64 } else { 0.0 };
+
                     println!("{}", "📊 Cache Statistics:".cyan());
-                    let (count, size) = cache.get_stats()?;
-                    println!("  Cached packages: {}", count.to_string().green());
-                    println!("  Total size: {}", format!("{:.2} MB", size as f64 / 1024.0 / 1024.0).green());
+                    println!("  Cached packages: {}", stats.packages.to_string().green());
+                    println!("  Pinned packages: {}", stats.pinned.to_string().green());
+                    println!("  Total size: {}", format!("{:.2} MB", stats.total_size as f64 / 1024.0 / 1024.0).green());
+                    println!("  Hits / misses: {} / {} ({:.1}% hit rate)", stats.hits.to_string().green(), stats.misses.to_string().yellow(), hit_rate);
+                    println!("  Served from cache: {}", format!("{:.2} MB", stats.bytes_from_cache as f64 / 1024.0 / 1024.0).green());
+                    println!("  Downloaded: {}", format!("{:.2} MB", stats.bytes_downloaded as f64 / 1024.0 / 1024.0).green());
                     println!("  Cache directory: {}", cache.cache_dir.display().to_string().blue());
+
+                    if !stats.per_package.is_empty() {
+                        println!();
+                        println!("  {:<30} {:<15} {:>10} {:>6} {:<6}  Last access", "Package", "Version", "Size", "Hits", "Pinned");
+                        for package in &stats.per_package {
+                            println!(
+                                "  {:<30} {:<15} {:>10} {:>6} {:<6}  {}",
+                                package.name,
+                                package.version,
+                                format!("{:.2} MB", package.size as f64 / 1024.0 / 1024.0),
+                                package.hit_count,
+                                if package.pinned { "yes" } else { "" },
+                                package.last_accessed
+                                    .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                                    .unwrap_or_else(|| "never".to_string()),
+                            );
+                        }
+                    }
                     Ok(())
                 }
 
-                CacheAction::Verify => {
+                CacheAction::Verify { fix } => {
                     println!("{}", "🔍 Verifying cache integrity...".yellow());
-                    // Implementation for cache verification
-                    println!("{}", "✅ Cache verification completed".green());
+                    let report = cache.verify(*fix)?;
+
+                    println!("  Entries checked: {}", report.checked.to_string().green());
+                    for label in &report.corrupt {
+                        println!("  {} {} (hash mismatch)", "✗".red(), label);
+                    }
+                    for label in &report.missing {
+                        println!("  {} {} (artifact missing)", "✗".red(), label);
+                    }
+                    for path in &report.orphaned_files {
+                        println!("  {} {} (orphaned)", "•".yellow(), path.display());
+                    }
+
+                    let problems = report.corrupt.len() + report.missing.len() + report.orphaned_files.len();
+                    if problems == 0 {
+                        println!("{}", "✅ Cache verification completed, no problems found".green());
+                        Ok(())
+                    } else if report.fixed {
+                        println!("{}", format!("✅ Repaired {} problem(s)", problems).green());
+                        Ok(())
+                    } else {
+                        Err(format!("Found {} problem(s); run 'sa cache verify --fix' to repair", problems).into())
+                    }
+                }
+
+                CacheAction::Gc { max_size, max_age, dry_run, quiet } => {
+                    let policy = SAConfig::load()?.cache;
+                    let max_size = parse_size(max_size.as_deref().unwrap_or(&policy.max_size))?;
+                    let max_age = parse_duration(max_age.as_deref().unwrap_or(&policy.max_age))?;
+
+                    if !*quiet {
+                        println!("{}", "🧹 Collecting cache garbage...".yellow());
+                    }
+                    let report = cache.gc(max_size, max_age, *dry_run)?;
+                    if *quiet {
+                        return Ok(());
+                    }
+
+                    let verb = if *dry_run { "would evict" } else { "evicted" };
+                    for label in &report.abandoned {
+                        println!("  {} {} (project deleted)", verb, label);
+                    }
+                    for label in &report.expired {
+                        println!("  {} {} (expired)", verb, label);
+                    }
+                    for label in &report.evicted {
+                        println!("  {} {} (over size budget)", verb, label);
+                    }
+
+                    let reclaimed = report.bytes_before.saturating_sub(report.bytes_after);
+                    println!("{}", format!(
+                        "✅ Cache gc completed: {} entries, {:.2} MB {}",
+                        report.abandoned.len() + report.expired.len() + report.evicted.len(),
+                        reclaimed as f64 / 1024.0 / 1024.0,
+                        if *dry_run { "reclaimable" } else { "reclaimed" },
+                    ).green());
+                    Ok(())
+                }
+
+                CacheAction::List { name, older_than, mirror } => {
+                    let filter = CacheListFilter {
+                        name: name.clone(),
+                        mirror: mirror.clone(),
+                        older_than: older_than
+                            .as_deref()
+                            .map(parse_duration)
+                            .transpose()?
+                            .map(|age| chrono::Utc::now() - age),
+                    };
+                    let entries = cache.list_entries(&filter)?;
+
+                    if output::json_mode() {
+                        return output::print_json(&entries);
+                    }
+                    if entries.is_empty() {
+                        println!("{}", "No cached packages".yellow());
+                        return Ok(());
+                    }
+
+                    println!("{}", "📦 Cached Packages:".cyan());
+                    println!("  {:<30} {:<15} {:>10}  {:<16}  {:<12} Source", "Package", "Version", "Size", "Cached at", "Mirror");
+                    for entry in &entries {
+                        println!(
+                            "  {:<30} {:<15} {:>10}  {:<16}  {:<12} {}",
+                            entry.name,
+                            entry.version,
+                            format!("{:.2} MB", entry.size as f64 / 1024.0 / 1024.0),
+                            entry.cached_at.format("%Y-%m-%d %H:%M"),
+                            if entry.mirror.is_empty() { "unknown" } else { &entry.mirror },
+                            entry.download_url,
+                        );
+                    }
+
+                    let total: u64 = entries.iter().map(|entry| entry.size).sum();
+                    println!("{}", format!("  {} packages, {:.2} MB", entries.len(), total as f64 / 1024.0 / 1024.0).green());
+                    Ok(())
+                }
+
+                CacheAction::Purge { mirror, dry_run } => {
+                    println!("{}", format!("🧹 Purging artifacts from mirror '{}'...", mirror).yellow());
+                    let purged = cache.purge_mirror(mirror, *dry_run)?;
+                    let verb = if *dry_run { "would remove" } else { "removed" };
+                    for label in &purged {
+                        println!("  {} {}", verb, label);
+                    }
+                    println!("{}", format!("✅ Purge completed: {} {} entries", verb, purged.len()).green());
                     Ok(())
                 }
 
-                CacheAction::Optimize => {
+                CacheAction::Export { bundle, requirements, all } => {
+                    let selection = if *all {
+                        None
+                    } else {
+                        Some(lock_selection(requirements.as_deref().map(Path::new))?)
+                    };
+
+                    println!("{}", format!("📦 Exporting cache bundle to {}...", bundle).yellow());
+                    let report = cache.export_bundle(Path::new(bundle), selection.as_ref())?;
+                    for label in &report.missing {
+                        println!("  {} {} (not cached)", "•".yellow(), label);
+                    }
+                    println!("{}", format!(
+                        "✅ Exported {} artifact(s), {:.2} MB",
+                        report.packages,
+                        report.bytes as f64 / 1024.0 / 1024.0
+                    ).green());
+                    Ok(())
+                }
+
+                CacheAction::Import { bundle } => {
+                    println!("{}", format!("📥 Importing cache bundle {}...", bundle).yellow());
+                    let report = cache.import_bundle(Path::new(bundle))?;
+                    for label in &report.missing {
+                        println!("  {} {} (artifact missing from bundle)", "•".yellow(), label);
+                    }
+                    println!("  Already cached: {}", report.already_cached.to_string().green());
+                    println!("{}", format!("✅ Imported {} package entries", report.packages).green());
+                    Ok(())
+                }
+
+                CacheAction::Optimize { no_prune } => {
                     println!("{}", "⚡ Optimizing cache storage...".yellow());
-                    // Implementation for cache optimization
-                    println!("{}", "✅ Cache optimization completed".green());
+                    let report = cache.optimize(!*no_prune)?;
+
+                    if !*no_prune && report.known_projects == 0 {
+                        println!("{}", "  No known projects yet, skipping pruning".yellow());
+                    }
+                    for label in &report.pruned {
+                        println!("  {} {} (unused)", "🗑️ ".yellow(), label);
+                    }
+                    println!("  Deduplicated files: {}", report.deduplicated_files.to_string().green());
+                    println!("  Pruned artifacts: {}", report.pruned.len().to_string().green());
+
+                    let reclaimed = report.bytes_before.saturating_sub(report.bytes_after);
+                    println!("{}", format!("✅ Cache optimization completed, reclaimed {:.2} MB", reclaimed as f64 / 1024.0 / 1024.0).green());
                     Ok(())
                 }
             }
         }
 
         Commands::Security { action } => {
-            let mut security_scanner = SecurityScanner::new()?;
+            let mut security_scanner = SecurityScanner::new(cli.offline)?;
 
             match action {
-                SecurityAction::Scan { package, format: _ } => {
-                    if let Some(pkg) = package {
-                        println!("{}", format!("🔒 Scanning package '{}'...", pkg).yellow());
-                        let vulnerabilities = security_scanner.scan_package(pkg, "latest");
+                SecurityAction::Scan { package, format, fail_on } => {
+                    let format = if output::json_mode() && format == "table" { "json" } else { format.as_str() };
+                    let installed = if venv_dir().exists() { installed_packages().await? } else { Vec::new() };
 
-                        if vulnerabilities.is_empty() {
+                    if let Some(pkg) = package {
+                        let (name, version) = match pkg.split_once("==") {
+                            Some((name, version)) => (name.trim().to_string(), version.trim().to_string()),
+                            None => {
+                                let wanted = normalize_dist_name(pkg);
+                                let found = installed
+                                    .iter()
+                                    .find(|p| normalize_dist_name(&p.name) == wanted)
+                                    .ok_or_else(|| format!("'{}' is not installed; pass an explicit version as {}==<version>", pkg, pkg))?;
+                                (found.name.clone(), found.version.clone())
+                            }
+                        };
+                        let label = format!("{}=={}", name, version);
+                        if format == "table" {
+                            println!("{}", format!("🔒 Scanning package '{}' {}...", name, version).yellow());
+                        }
+                        let target = [InstalledPackage { name, version }];
+                        let findings = security_scanner.scan_installed(&target).await?;
+                        if format != "table" {
+                            print_report(format, &label, None, &findings, fail_on)?;
+                        } else if findings.iter().all(|(_, vulns)| vulns.is_empty()) {
                             println!("{}", "✅ No vulnerabilities found".green());
                         } else {
+                            let vulnerabilities: Vec<_> = findings.iter().flat_map(|(_, vulns)| vulns).collect();
                             println!("{}", format!("⚠️  Found {} vulnerabilities:", vulnerabilities.len()).red());
                             for vuln in vulnerabilities {
-                                println!("  {} {}: {}", "•".red(), vuln.severity.to_uppercase(), vuln.description);
+                                print_vulnerability(vuln);
                             }
                         }
+                        match audit_exit_code(&findings, fail_on) {
+                            0 => {}
+                            code => process::exit(code),
+                        }
                     } else {
-                        println!("{}", "🔒 Scanning all packages...".yellow());
-                        // Scan all installed packages
-                        println!("{}", "✅ Security scan completed".green());
+                        run_audit(&mut security_scanner, None, format, fail_on, false, false).await?;
+                    }
+                    Ok(())
+                }
+
+                SecurityAction::Ignore { id, until, reason } => {
+                    if *until < chrono::Utc::now().date_naive() {
+                        return Err(format!("--until {} is already in the past", until).into());
+                    }
+                    let replaced = add_vulnerability_ignore(id, *until, reason)?;
+                    let verb = if replaced { "Updated" } else { "Added" };
+                    println!("{}", format!("✅ {} ignore for {} until {} in pyproject.toml", verb, id, until).green());
+                    Ok(())
+                }
+
+                SecurityAction::Approve { name, versions, reason } => {
+                    let allowlist = AllowList::load().await?;
+                    let ranges = allowlist.approve(name, versions.as_deref(), reason.as_deref())?;
+                    println!("{}", format!("✅ Allowed {} {} in pyproject.toml", name, ranges.join(", ")).green());
+                    if !allowlist.policy.enabled {
+                        println!("{}", "Note: allow-list mode is off; set enabled = true under [tool.sa.allowlist] to enforce it".yellow());
+                    }
+                    Ok(())
+                }
+
+                SecurityAction::Quarantine => {
+                    let quarantine = AllowList::load().await?.quarantine()?;
+                    if quarantine.is_empty() {
+                        println!("{}", "✅ Nothing is waiting for approval".green());
+                        return Ok(());
+                    }
+                    println!("{}", format!("⛔ {} install requests awaiting approval:", quarantine.len()).yellow());
+                    for entry in &quarantine {
+                        println!(
+                            "  {} {}  {}  {}",
+                            format!("{}=={}", entry.name, entry.version).bold(),
+                            entry.requested_at.format("%Y-%m-%d %H:%M UTC"),
+                            entry.project.display(),
+                            entry.url.dimmed()
+                        );
                     }
+                    println!("Approve with: sa security approve <name> [--versions <range>] [--reason <text>]");
                     Ok(())
                 }
 
-                SecurityAction::Update => {
-                    security_scanner.update_vulnerability_db().await?;
+                SecurityAction::Update { source } => {
+                    security_scanner.update_vulnerability_db(source).await?;
                     Ok(())
                 }
 
                 SecurityAction::Policy => {
+                    let policy = load_security_policy()?;
                     println!("{}", "🛡️  Security Policy:".cyan());
                     println!("  • Automatic vulnerability scanning enabled");
-                    println!("  • Critical vulnerabilities block installation");
-                    println!("  • Database updated from PyUp.io Safety DB");
-                    println!("  • Use --skip-security to bypass scanning");
+                    match block_threshold(&policy.block_severity)? {
+                        Some(_) => println!("  • Vulnerabilities rated {} or higher block installation", policy.block_severity.to_lowercase()),
+                        None => println!("  • Vulnerabilities are reported but never block installation"),
+                    }
+                    println!("  • Advisories from the PyPA advisory database, OSV.dev and the PyUp.io Safety DB");
+                    match policy.typosquatting.as_str() {
+                        "block" => println!("  • Look-alikes of popular packages are blocked"),
+                        "off" => println!("  • Look-alike package names are not checked"),
+                        _ => println!("  • Look-alikes of popular packages need confirmation (or --yes)"),
+                    }
+                    let allowlist = AllowList::load().await?;
+                    if allowlist.policy.enabled {
+                        let shared = allowlist.policy.source.as_deref().map(|source| format!(" plus {}", source)).unwrap_or_default();
+                        println!("  • Allow-list mode: only the {} entries in [tool.sa.allowlist]{} may be installed", allowlist.policy.packages.len(), shared);
+                    }
+                    let supply_chain = &policy.supply_chain;
+                    if supply_chain.action != "off" {
+                        let outcome = if supply_chain.action == "block" { "block installation" } else { "are reported" };
+                        println!("  • Supply-chain red flags (projects newer than {} days, maintainer changes, suspicious setup.py) {}", supply_chain.min_age_days, outcome);
+                        if !supply_chain.internal_prefixes.is_empty() {
+                            println!("  • Internal names ({}) must not resolve from public PyPI", supply_chain.internal_prefixes.join(", "));
+                        }
+                    }
+                    if let Some(scanner) = SAConfig::load()?.security.artifact_scanner.filter(|command| !command.trim().is_empty()) {
+                        println!("  • Downloaded artifacts must pass `{}` before install", scanner);
+                    }
+                    if policy.allow_skip_security {
+                        println!("  • Use --skip-security to bypass scanning");
+                    } else {
+                        println!("  • --skip-security is not permitted");
+                    }
+                    if !policy.ignore.is_empty() {
+                        println!("{}", "Ignored findings:".cyan());
+                        let today = chrono::Utc::now().date_naive();
+                        for ignore in &policy.ignore {
+                            let until = if ignore.until < today {
+                                format!("expired {}", ignore.until).red().to_string()
+                            } else {
+                                format!("until {}", ignore.until)
+                            };
+                            println!("  • {}, {}: {}", ignore.id, until, ignore.reason);
+                        }
+                    }
+                    if !policy.exemptions.is_empty() {
+                        println!("{}", "Exemptions:".cyan());
+                        let today = chrono::Utc::now().date_naive();
+                        for exemption in &policy.exemptions {
+                            let scope = exemption.id.as_deref().unwrap_or("all advisories");
+                            let expiry = match exemption.expires {
+                                Some(expires) if expires < today => format!("expired {}", expires).red().to_string(),
+                                Some(expires) => format!("until {}", expires),
+                                None => "no expiry".to_string(),
+                            };
+                            println!("  • {} ({}), {}", exemption.package, scope, expiry);
+                        }
+                    }
+                    let ttl = SAConfig::load()?.security.db_ttl;
+                    println!("{}", format!("Vulnerability sources (refreshed when older than {}):", ttl).cyan());
+                    for source in VULNERABILITY_SOURCES {
+                        let updated = security_scanner
+                            .updated
+                            .get(source)
+                            .map(|t| t.format("%Y-%m-%d %H:%M UTC").to_string())
+                            .unwrap_or_else(|| "never updated".to_string());
+                        println!("  • {:<9} {}", source, updated);
+                    }
+                    Ok(())
+                }
+            }
+        }
+
+        Commands::License { action } => {
+            match action {
+                LicenseAction::Check { verbose } => {
+                    let checker = LicenseChecker::new()?;
+                    println!("{}", format!("📜 Checking licenses of {} packages...", checker.dists.len()).cyan());
+                    let findings = checker.check();
+
+                    for finding in &findings {
+                        let license = finding.license.as_deref().unwrap_or("unknown");
+                        match &finding.violation {
+                            Some(reason) => {
+                                println!("  {} {} {} ({}): {}", "✗".red(), finding.name.bold(), finding.version, license, reason.red());
+                                if finding.path.len() > 1 {
+                                    println!("      via {}", finding.path.join(" -> ").dimmed());
+                                }
+                            }
+                            None if *verbose => println!("  {} {} {} ({})", "✓".green(), finding.name, finding.version, license),
+                            None => {}
+                        }
+                    }
+
+                    let unknown = findings.iter().filter(|f| f.license.is_none()).count();
+                    let violations = findings.iter().filter(|f| f.violation.is_some()).count();
+                    if unknown > 0 && checker.policy.allow.is_empty() {
+                        println!("{}", format!("Warning: could not determine the license of {} packages", unknown).yellow());
+                    }
+                    if violations > 0 {
+                        return Err(format!("{} of {} packages violate the license policy", violations, findings.len()).into());
+                    }
+                    println!("{}", format!("✅ All {} packages comply with the license policy", findings.len()).green());
                     Ok(())
                 }
             }
@@ -460,9 +1185,50 @@ async fn run_sa(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
             let mut mirror_manager = MirrorManager::new()?;
 
             match action {
-                MirrorAction::Add { name, url, default } => {
+                MirrorAction::Add { name, url, default, priority, role, kind, probe_path, ca_cert, client_cert, insecure, connect_timeout, read_timeout, retries, max_bandwidth, username, password, token } => {
                     println!("{}", format!("🪞 Adding mirror '{}'...", name).cyan());
-                    mirror_manager.add_mirror(name.clone(), url.clone(), *default)?;
+                    let credentials = (username.is_some() || token.is_some()).then(|| MirrorCredentials {
+                        username: username.clone(),
+                        password: password.clone(),
+                        token: token.clone(),
+                        keyring: false,
+                    });
+                    if credentials.is_some() {
+                        println!("  {} credentials stored in ~/.config/sa/credentials.toml", "🔑".blue());
+                    }
+                    let tls = TlsOptions { ca_cert: ca_cert.clone(), client_cert: client_cert.clone(), insecure: *insecure };
+                    if *insecure {
+                        println!("{}", format!("⚠️  TLS certificates of '{}' will NOT be verified; anyone on the network path can impersonate it", name).red());
+                    }
+                    if let Some(cap) = max_bandwidth {
+                        parse_size(cap)?;
+                    }
+                    let transfer = TransferOptions {
+                        connect_timeout: *connect_timeout,
+                        read_timeout: *read_timeout,
+                        retries: *retries,
+                        max_bandwidth: max_bandwidth.clone(),
+                    };
+                    let index = index_url(kind, url);
+                    if index != *url {
+                        println!("  {} using the {} simple index at {}", "•".blue(), kind, index);
+                    }
+                    let mirror = Mirror {
+                        name: name.clone(),
+                        url: index,
+                        is_default: *default,
+                        last_tested: None,
+                        is_active: true,
+                        priority: *priority,
+                        role: role.clone(),
+                        kind: kind.clone(),
+                        probe_path: probe_path.clone(),
+                        json_api: None,
+                        tls,
+                        transfer,
+                        recent_checks: Vec::new(),
+                    };
+                    mirror_manager.add_mirror(mirror, credentials)?;
                     println!("{}", format!("✅ Mirror '{}' added successfully", name).green());
                     Ok(())
                 }
@@ -474,71 +1240,293 @@ async fn run_sa(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
                     Ok(())
                 }
 
-                MirrorAction::List => {
-                    println!("{}", "🪞 Configured Mirrors:".cyan());
-                    for (i, mirror) in mirror_manager.mirrors.iter().enumerate() {
+                MirrorAction::List { effective } => {
+                    let listed: Vec<(&Mirror, &str)> = if *effective {
+                        println!("{}", "🪞 Effective Mirrors (SA_INDEX_URL/SA_EXTRA_INDEX_URLS > [tool.sa.mirrors] > mirrors.json):".cyan());
+                        let mut listed = mirror_manager.effective_mirrors();
+                        listed.sort_by_key(|(mirror, _)| mirror.priority);
+                        listed
+                    } else {
+                        println!("{}", "🪞 Configured Mirrors:".cyan());
+                        mirror_manager.mirrors.iter().map(|mirror| (mirror, "")).collect()
+                    };
+                    if output::json_mode() {
+                        let mut mirrors = Vec::new();
+                        for (mirror, source) in &listed {
+                            let mut entry = serde_json::to_value(mirror)?;
+                            entry["url"] = network::redact(&mirror.url).into();
+                            entry["has_credentials"] = mirror_manager.credentials.get(&mirror.name).is_some().into();
+                            entry["success_rate"] = success_rate(mirror).into();
+                            if !source.is_empty() {
+                                entry["source"] = source.to_string().into();
+                            }
+                            mirrors.push(entry);
+                        }
+                        return output::print_json(&mirrors);
+                    }
+                    for (i, (mirror, source)) in listed.into_iter().enumerate() {
                         let status = if mirror.is_default { "default".green() } else { "".normal() };
+                        let role = if mirror.role == "primary" { "".normal() } else { format!(" {}", mirror.role).yellow() };
+                        let mut kind = if mirror.kind == "simple" { String::new() } else { format!(", {}", mirror.kind) };
+                        if mirror.json_api == Some(true) {
+                            kind.push_str(", JSON API");
+                        }
                         let active = if mirror.is_active { "active".blue() } else { "inactive".red() };
-                        println!("  {}. {} ({}) [{}] - {}",
+                        let auth = if mirror_manager.credentials.get(&mirror.name).is_some() { " 🔑" } else { "" };
+                        let source = if source.is_empty() { String::new() } else { format!(" (from {})", source).dimmed().to_string() };
+                        println!("  {}. {} ({}) [{}{}, priority {}{}] - {}{}{}",
                             i + 1,
                             mirror.name.bold(),
                             status,
                             active,
-                            mirror.url
+                            role,
+                            mirror.priority,
+                            kind,
+                            network::redact(&mirror.url),
+                            auth,
+                            source
                         );
+                        if let (Some(tested), Some(rate)) = (mirror.last_tested, success_rate(mirror)) {
+                            println!("     last tested {}, {:.0}% of the last {} checks passed",
+                                tested.format("%Y-%m-%d %H:%M UTC"),
+                                rate * 100.0,
+                                mirror.recent_checks.len()
+                            );
+                        }
                     }
                     Ok(())
                 }
 
-                MirrorAction::Test { name } => {
+                MirrorAction::Test { name, benchmark: true } => {
+                    let names: Vec<String> = match name {
+                        Some(name) => vec![name.clone()],
+                        None => mirror_manager.mirrors.iter().map(|m| m.name.clone()).collect(),
+                    };
+                    println!("{}", format!("⏱️  Benchmarking {} mirrors...", names.len()).yellow());
+                    let mut fastest: Option<(std::time::Duration, String)> = None;
+                    for mirror_name in &names {
+                        let result = mirror_manager.benchmark(mirror_name).await?;
+                        let latency = result.latency.map(|l| format!("{} ms", l.as_millis())).unwrap_or_else(|| "-".to_string());
+                        let throughput = result
+                            .throughput
+                            .map(|t| format!("{:.2} MB/s", t / 1024.0 / 1024.0))
+                            .unwrap_or_else(|| "-".to_string());
+                        match &result.error {
+                            Some(e) if result.latency.is_none() => println!("  {} {:<20} {}", "❌".red(), result.name, e),
+                            Some(e) => println!("  {} {:<20} latency {:>8}  throughput failed: {}", "⚠️ ".yellow(), result.name, latency, e),
+                            None => println!("  {} {:<20} latency {:>8}  throughput {:>12}", "✅".green(), result.name, latency, throughput),
+                        }
+                        if let Some(latency) = result.latency {
+                            if fastest.as_ref().is_none_or(|(best, _)| latency < *best) {
+                                fastest = Some((latency, result.name.clone()));
+                            }
+                        }
+                    }
+                    if let Some((_, name)) = fastest.filter(|_| names.len() > 1) {
+                        println!("{}", format!("Fastest: {} (set `preferred = \"fastest\"` under [mirror] in ~/.config/sa/config.toml to pick it automatically)", name).green());
+                    }
+                    Ok(())
+                }
+
+                MirrorAction::Test { name, benchmark: false } => {
                     if let Some(mirror_name) = name {
                         println!("{}", format!("🧪 Testing mirror '{}'...", mirror_name).yellow());
-                        match mirror_manager.test_mirror(mirror_name).await {
-                            Ok(true) => println!("{}", format!("✅ Mirror '{}' is reachable", mirror_name).green()),
-                            Ok(false) => println!("{}", format!("❌ Mirror '{}' is not reachable", mirror_name).red()),
-                            Err(e) => println!("{}", format!("❌ Error testing mirror: {}", e).red()),
+                        let probe = match mirror_manager.test_mirror(mirror_name).await {
+                            Ok(probe) => probe,
+                            Err(e) => {
+                                println!("{}", format!("❌ Error testing mirror: {}", e).red());
+                                return Ok(());
+                            }
+                        };
+                        match &probe.problem {
+                            None if probe.json_api == Some(true) => {
+                                println!("{}", format!("✅ Mirror '{}' is reachable and serves the JSON simple API", mirror_name).green())
+                            }
+                            None => println!("{}", format!("✅ Mirror '{}' is reachable", mirror_name).green()),
+                            Some(problem) => println!("{}", format!("❌ Mirror '{}' is not reachable: {}", mirror_name, problem).red()),
                         }
+                        mirror_manager.record_check(mirror_name, &probe)?;
                     } else {
                         println!("{}", "🧪 Testing all mirrors...".yellow());
-                        for mirror in &mirror_manager.mirrors {
-                            match mirror_manager.test_mirror(&mirror.name).await {
-                                Ok(true) => println!("  {} {}", "✅".green(), mirror.name),
-                                Ok(false) => println!("  {} {}", "❌".red(), mirror.name),
-                                Err(_) => println!("  {} {} (error)", "❌".red(), mirror.name),
+                        let names: Vec<String> = mirror_manager.mirrors.iter().map(|m| m.name.clone()).collect();
+                        for mirror_name in &names {
+                            let probe = match mirror_manager.test_mirror(mirror_name).await {
+                                Ok(probe) => probe,
+                                Err(_) => MirrorProbe { problem: Some("error".to_string()), json_api: None },
+                            };
+                            match &probe.problem {
+                                None if probe.json_api == Some(true) => println!("  {} {} (JSON API)", "✅".green(), mirror_name),
+                                None => println!("  {} {}", "✅".green(), mirror_name),
+                                Some(problem) => println!("  {} {} ({})", "❌".red(), mirror_name, problem),
                             }
+                            mirror_manager.record_check(mirror_name, &probe)?;
                         }
                     }
                     Ok(())
                 }
+
+                MirrorAction::Snapshot { output, requirements, packages } => {
+                    let pins = match requirements.as_deref().map(read_lockfile).transpose()?.flatten() {
+                        Some(pins) => pins,
+                        None => {
+                            let mut args = vec!["--ignore-installed".to_string()];
+                            match requirements {
+                                Some(path) => args.extend(["-r".to_string(), path.to_string_lossy().to_string()]),
+                                None if packages.is_empty() => return Err("Pass --requirements <lockfile> or the packages to snapshot".into()),
+                                None => args.extend(packages.iter().cloned()),
+                            }
+                            println!("{}", "🔍 Resolving dependencies...".cyan());
+                            ensure_venv_exists().await?;
+                            mirror_manager.select_session_mirror().await?;
+                            mirror_manager
+                                .resolve_with_precedence(&args)
+                                .await?
+                                .ok_or("sa mirror snapshot needs a pip that supports install reports (pip >= 22.2)")?
+                                .into_iter()
+                                .map(|item| SnapshotPin { name: item.name, version: item.version, hashes: Vec::new() })
+                                .collect()
+                        }
+                    };
+
+                    println!("{}", format!("📸 Snapshotting {} releases into {}...", pins.len(), output.display()).cyan());
+                    let builder = SnapshotBuilder::new(&mirror_manager, output)?;
+                    let mut downloaded = 0;
+                    for pin in &pins {
+                        let files = builder.add(pin).await?;
+                        println!("  {} {}=={} ({} new files)", "✅".green(), pin.name, pin.version, files);
+                        downloaded += files;
+                    }
+                    let projects = builder.write_root_index()?;
+                    println!("{}", format!("✅ Snapshot has {} projects ({} files downloaded)", projects, downloaded).green());
+                    print_usage_hint(output);
+                    Ok(())
+                }
             }
         }
 
-        Commands::Visualize { package, format, output, transitive } => {
-            println!("{}", format!("📊 Visualizing dependencies for '{}'...", package).cyan());
-
-            // Get package dependencies (simplified implementation)
-            let mut dependencies = HashMap::new();
-            dependencies.insert(package.clone(), vec!["requests".to_string(), "numpy".to_string()]);
-            dependencies.insert("requests".to_string(), vec!["urllib3".to_string(), "certifi".to_string()]);
+        Commands::Auth { action } => {
+            match action {
+                AuthAction::Login { name, publish: true, .. } => {
+                    let token = read_secret(&format!("Upload token for {}", name))?;
+                    keyring_set(&publish_account(name), &token)?;
+                    println!("{}", format!("✅ Stored the upload token for '{}' in the OS keyring", name).green());
+                    Ok(())
+                }
 
-            let graph = DependencyVisualizer::create_dependency_graph(package, &dependencies, *transitive);
+                AuthAction::Login { name, username, publish: false } => {
+                    let mut mirror_manager = MirrorManager::new()?;
+                    if !mirror_manager.mirrors.iter().any(|mirror| mirror.name == *name) {
+                        return Err(format!("Mirror '{}' not found; add it with 'sa mirror add'", name).into());
+                    }
+                    let prompt = match username {
+                        Some(username) => format!("Password for {} on {}", username, name),
+                        None => format!("Token for {}", name),
+                    };
+                    let secret = read_secret(&prompt)?;
+                    keyring_set(&mirror_account(name), &secret)?;
+                    mirror_manager.credentials.set(name, MirrorCredentials {
+                        username: username.clone(),
+                        keyring: true,
+                        ..MirrorCredentials::default()
+                    })?;
+                    println!("{}", format!("✅ Stored the credentials for mirror '{}' in the OS keyring", name).green());
+                    Ok(())
+                }
 
-            match format.as_str() {
-                "dot" => {
-                    let dot_output = DependencyVisualizer::export_dot(&graph);
-                    if let Some(output_file) = output {
-                        fs::write(output_file, dot_output)?;
-                        println!("{}", format!("✅ Dependency graph saved to {}", output_file).green());
+                AuthAction::Logout { name, publish } => {
+                    let removed = if *publish {
+                        keyring_delete(&publish_account(name))?
                     } else {
-                        println!("{}", dot_output);
+                        let keyring = keyring_delete(&mirror_account(name))?;
+                        MirrorManager::new()?.credentials.remove(name)? || keyring
+                    };
+                    if removed {
+                        println!("{}", format!("✅ Removed the stored credentials for '{}'", name).green());
+                    } else {
+                        println!("{}", format!("No stored credentials for '{}'", name).yellow());
                     }
+                    Ok(())
                 }
-                "svg" | "png" => {
-                    println!("{}", "Note: SVG/PNG export requires Graphviz to be installed".yellow());
-                    println!("{}", "Use 'dot' format and convert manually with: dot -Tsvg input.dot -o output.svg".blue());
+            }
+        }
+
+        Commands::Visualize { action: Some(VisualizeAction::Diff { old, new, format, output, charset }), .. } => {
+            if output.is_some() {
+                println!("{}", format!("📊 Visualizing changes from {} to {}...", old.display(), new.display()).cyan());
+            }
+            let before = DependencySource::new(Some(old))?.load_pins().await?;
+            let after = DependencySource::new(Some(new))?.load_pins().await?;
+            let graph = DependencyVisualizer::create_diff_graph(&new.display().to_string(), before, after);
+            write_graph(&graph, format, charset, output.as_deref())?;
+
+            let summary = graph.change_counts().iter().map(|(change, count)| format!("{} {}", count, change)).collect::<Vec<_>>().join(", ");
+            let summary = if summary.is_empty() { "No packages changed".to_string() } else { format!("Changes: {}", summary) };
+            if output.is_some() {
+                println!("{}", summary);
+            } else {
+                eprintln!("{}", summary);
+            }
+            Ok(())
+        }
+
+        Commands::Visualize { action: None, package, format, output, charset, transitive, requirements, reverse, top_sizes, depth, include, exclude, annotate } => {
+            let Some(package) = package else {
+                return Err("Name a package to visualize, e.g. 'sa visualize requests'".into());
+            };
+            if *depth == Some(0) {
+                return Err("--depth must be at least 1".into());
+            }
+            // Graphs printed to stdout are often piped; keep them clean
+            if output.is_some() {
+                let subject = if *reverse { "dependents of" } else { "dependencies for" };
+                println!("{}", format!("📊 Visualizing {} '{}'...", subject, package).cyan());
+            }
+
+            let source = DependencySource::new(requirements.as_deref())?;
+            let mut graph = if *reverse {
+                let packages = source.load_dependents(package, *transitive)?;
+                DependencyVisualizer::create_reverse_graph(package, packages)
+            } else {
+                let filter = GraphFilter { depth: *depth, include: include.clone(), exclude: exclude.clone() };
+                let transitive = *transitive || depth.is_some();
+                let packages = source.load(package, transitive, &filter).await?;
+                DependencyVisualizer::create_dependency_graph(package, packages, transitive, &filter)
+            };
+            match annotate.as_deref() {
+                Some("licenses") => graph.overlay = Some(Overlay::Licenses),
+                Some("vulns") => {
+                    let mut security_scanner = SecurityScanner::new(cli.offline)?;
+                    graph.annotate_vulnerabilities(&mut security_scanner).await?;
                 }
-                _ => {
-                    return Err("Unsupported format. Use 'dot', 'svg', or 'png'".into());
+                _ => {}
+            }
+            write_graph(&graph, format, charset, output.as_deref())?;
+
+            if let Some(limit) = top_sizes {
+                // Next to a graph on stdout, the summary goes to stderr
+                let mut lines = vec![format!("📦 Largest packages in {}:", graph.label(graph.root, " ")).cyan().to_string()];
+                let largest = graph.largest(*limit);
+                if largest.is_empty() {
+                    lines.push("  (no package sizes known)".to_string());
+                } else {
+                    lines.push(format!("  {:<30} {:>10} {:>10}  Pulled in by", "Package", "Size", "Retained"));
+                }
+                for entry in &largest {
+                    let chain: Vec<String> = entry.chain.iter().map(|idx| graph.label(*idx, " ")).collect();
+                    lines.push(format!(
+                        "  {:<30} {:>10} {:>10}  {}",
+                        graph.label(entry.package, " "),
+                        format_size(entry.size),
+                        format_size(entry.retained),
+                        chain[..chain.len() - 1].join(" → ").dimmed()
+                    ));
+                }
+                for line in lines {
+                    if output.is_some() {
+                        println!("{}", line);
+                    } else {
+                        eprintln!("{}", line);
+                    }
                 }
             }
 
@@ -546,43 +1534,1040 @@ async fn run_sa(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
         }
 
         Commands::Docker { action } => {
-            let docker_manager = DockerManager::new()?;
-
+            // Connect per action: generating files needs no container engine
             match action {
-                DockerAction::Create { name, image, requirements } => {
-                    docker_manager.create_environment(name, image, requirements.as_deref()).await?;
+                DockerAction::Create { name, image, requirements, production, gpus, memory, cpus, network, ports, env, platforms, push } => {
+                    let settings = container_settings(ContainerSettings {
+                        memory: memory.clone(),
+                        cpus: *cpus,
+                        network: network.clone(),
+                        ports: ports.clone(),
+                        environment: parse_env_flags(env)?,
+                        platforms: platforms.clone(),
+                    })?;
+                    let docker_manager = DockerManager::new()?;
+                    docker_manager
+                        .create_environment(name, image, requirements.as_deref(), *production, gpus.as_deref(), &settings, push.as_deref())
+                        .await?;
                     Ok(())
                 }
 
                 DockerAction::List => {
-                    println!("{}", "🐳 Docker Environments:".cyan());
+                    let docker_manager = DockerManager::new()?;
+                    println!("{} {}", "🐳 Docker Environments:".cyan(), format!("({})", docker_manager.endpoint).dimmed());
                     let environments = docker_manager.list_environments().await?;
+                    if environments.is_empty() {
+                        println!("  (none; create one with 'sa docker create <name>')");
+                    }
                     for (i, env) in environments.iter().enumerate() {
-                        println!("  {}. {}", i + 1, env.blue());
+                        let state = if env.state == "running" { env.state.green() } else { env.state.yellow() };
+                        let created = env.created.map(|created| format!(", created {}", created.format("%Y-%m-%d"))).unwrap_or_default();
+                        println!("  {}. {} [{}] - {} ({}{})", i + 1, env.name.blue(), state, env.image, env.status, created);
+                        if let (true, Some(lockfile)) = (env.stale, &env.lockfile) {
+                            println!(
+                                "     {}",
+                                format!("⚠️  {} changed since it was built; run 'sa docker sync {}'", lockfile.display(), env.name).yellow()
+                            );
+                        }
+                    }
+                    Ok(())
+                }
+
+                DockerAction::Sync { name } => {
+                    let docker_manager = DockerManager::new()?;
+                    if docker_manager.sync_environment(name).await? {
+                        println!("{}", format!("✅ Environment '{}' rebuilt; its /data volume was kept", name).green());
+                        println!("  Packages installed with 'sa docker exec' were replaced by the requirements file's");
+                    } else {
+                        println!("{}", format!("✅ Environment '{}' is up to date", name).green());
                     }
                     Ok(())
                 }
 
-                DockerAction::Remove { name } => {
+                DockerAction::Start { name } => {
+                    let docker_manager = DockerManager::new()?;
+                    docker_manager.start_environment(name).await?;
+                    println!("{}", format!("✅ Environment '{}' started", name).green());
+                    Ok(())
+                }
+
+                DockerAction::Stop { name } => {
+                    let docker_manager = DockerManager::new()?;
+                    println!("{}", format!("⏹️  Stopping Docker environment '{}'...", name).yellow());
+                    docker_manager.stop_environment(name).await?;
+                    println!("{}", format!("✅ Environment '{}' stopped", name).green());
+                    Ok(())
+                }
+
+                DockerAction::Remove { name, keep_data } => {
+                    let docker_manager = DockerManager::new()?;
                     println!("{}", format!("🗑️  Removing Docker environment '{}'...", name).yellow());
-                    use bollard::image::RemoveImageOptions;
-                    let options = RemoveImageOptions {
-                        force: true,
-                        ..Default::default()
-                    };
-                    docker_manager.docker.remove_image(name, Some(options), None).await?;
+                    docker_manager.remove_environment(name, *keep_data).await?;
                     println!("{}", format!("✅ Environment '{}' removed", name).green());
+                    if *keep_data {
+                        println!("  Its data volume was kept; recreating '{}' mounts it again", name);
+                    }
+                    Ok(())
+                }
+
+                DockerAction::Generate { requirements, image, cmd, output, force } => {
+                    if output.exists() && !*force {
+                        return Err(format!("{} already exists; pass --force to overwrite it", output.display()).into());
+                    }
+                    let dockerfile = project_dockerfile(requirements, image, cmd.as_deref(), true)?;
+                    fs::write(output, &dockerfile.content)?;
+                    println!(
+                        "{}",
+                        format!("✅ Wrote {} ({} pinned packages{})", output.display(), dockerfile.packages, if dockerfile.hashed { ", hash-checked" } else { "" }).green()
+                    );
+                    if !Path::new(".dockerignore").exists() {
+                        fs::write(".dockerignore", DOCKERIGNORE)?;
+                        println!("{}", "📄 Wrote .dockerignore".blue());
+                    }
+                    println!("  Review and commit it, then build with: {}", format!("docker build -t <name> -f {} .", output.display()).bold());
+                    Ok(())
+                }
+
+                DockerAction::Compose { image, dockerfile, output, force } => {
+                    if output.exists() && !*force {
+                        return Err(format!("{} already exists; pass --force to overwrite it", output.display()).into());
+                    }
+                    let services = declared_services()?;
+                    let app_image = match image {
+                        Some(image) => image.clone(),
+                        None => {
+                            let project_dir = std::env::current_dir()?;
+                            let name = project_dir.file_name().map(|name| name.to_string_lossy().to_lowercase()).unwrap_or_default();
+                            let name: String = name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '-' }).collect();
+                            if name.trim_matches('-').is_empty() { "app".to_string() } else { name.trim_matches('-').to_string() }
+                        }
+                    };
+
+                    fs::write(output, compose_file(&app_image, dockerfile, &services))?;
+                    println!("{}", format!("✅ Wrote {} (app '{}' with {} services)", output.display(), app_image, services.len()).green());
+                    for name in services.keys() {
+                        println!("  • {}", name);
+                    }
+                    if !Path::new(dockerfile).exists() {
+                        println!("{}", format!("Warning: {} does not exist yet; create it with 'sa docker generate'", dockerfile).yellow());
+                    }
+                    println!("  Start the stack with: {}", format!("docker compose -f {} up --build", output.display()).bold());
+                    Ok(())
+                }
+
+                DockerAction::Export { tag, image, cmd, output, push, username } => {
+                    let docker_manager = DockerManager::new()?;
+                    docker_manager.export_environment(tag, image.as_deref(), cmd.as_deref()).await?;
+                    println!("{}", format!("✅ Exported image '{}'", tag).green());
+                    if let Some(output) = output {
+                        docker_manager.save_image(tag, output).await?;
+                        println!("{}", format!("💾 Wrote {} (load it with 'docker load -i {}')", output.display(), output.display()).green());
+                    }
+                    if *push {
+                        let credentials = registry_credentials(tag, username.as_deref())?;
+                        println!("{}", format!("📤 Pushing {}...", tag).cyan());
+                        docker_manager.push_image(tag, credentials).await?;
+                        println!("{}", format!("✅ Pushed {}", tag).green());
+                    }
+                    Ok(())
+                }
+
+                DockerAction::Shell { name, gpus, command } => {
+                    let docker_manager = DockerManager::new()?;
+                    let code = docker_manager.shell(name, &std::env::current_dir()?, command, gpus.as_deref()).await?;
+                    if code != 0 {
+                        process::exit(code as i32);
+                    }
+                    Ok(())
+                }
+
+                DockerAction::Push { name, reference, username } => {
+                    let credentials = registry_credentials(reference, username.as_deref())?;
+                    let docker_manager = DockerManager::new()?;
+                    let login = if credentials.is_some() { "" } else { " (no stored login; pushing anonymously)" };
+                    println!("{}", format!("📤 Pushing environment '{}' to {}{}...", name, reference, login).cyan());
+                    docker_manager.push_environment(name, reference, credentials).await?;
+                    println!("{}", format!("✅ Pushed {}", reference).green());
                     Ok(())
                 }
 
-                DockerAction::Exec { name, command } => {
+                DockerAction::Exec { name, gpus, memory, cpus, network, env, command } => {
+                    let settings = container_settings(ContainerSettings {
+                        memory: memory.clone(),
+                        cpus: *cpus,
+                        network: network.clone(),
+                        ports: Vec::new(),
+                        environment: parse_env_flags(env)?,
+                        platforms: Vec::new(),
+                    })?;
+                    let docker_manager = DockerManager::new()?;
                     println!("{}", format!("🐳 Executing in environment '{}'...", name).cyan());
-                    docker_manager.execute_in_environment(name, command).await?;
+                    let code = docker_manager.exec_in_environment(name, command, gpus.as_deref(), &settings).await?;
+                    if code != 0 {
+                        process::exit(code as i32);
+                    }
+                    Ok(())
+                }
+            }
+        }
+
+        Commands::Env { action } => {
+            match action {
+                EnvAction::Create { without_pip, without_setuptools, seeds, clear, python } => {
+                    let mut settings = SAConfig::load()?.venv;
+                    settings.without_pip |= *without_pip;
+                    settings.without_setuptools |= *without_setuptools;
+                    apply_seed_pins(&mut settings, seeds)?;
+
+                    if venv_dir().exists() {
+                        if !*clear {
+                            return Err(format!("Environment '{}' already exists (use --clear to recreate)", venv_dir().display()).into());
+                        }
+                        println!("{}", format!("🧹 Removing existing environment '{}'", venv_dir().display()).yellow());
+                        fs::remove_dir_all(venv_dir())?;
+                    }
+
+                    create_venv(&settings, python.as_deref()).await?;
+                    println!("{}", format!("✅ Environment '{}' created", venv_dir().display()).green());
+                    Ok(())
+                }
+
+                EnvAction::Activate { print, shell } => {
+                    if !venv_dir().exists() {
+                        return Err("No environment found. Create one with 'sa env create'".into());
+                    }
+                    let shell = shell.clone().unwrap_or_else(detect_shell);
+                    let snippet = activation_snippet(&shell)?;
+
+                    if *print {
+                        print!("{}", snippet);
+                    } else {
+                        let hint = match shell.as_str() {
+                            "fish" => "sa env activate --print --shell fish | source",
+                            "powershell" | "pwsh" => "sa env activate --print --shell powershell | Invoke-Expression",
+                            _ => "eval \"$(sa env activate --print)\"",
+                        };
+                        println!("{}", "To activate the environment in your current shell run:".cyan());
+                        println!("  {}", hint.bold());
+                        println!("{}", "Or start an activated subshell with 'sa shell'".blue());
+                    }
                     Ok(())
                 }
             }
         }
+
+        Commands::Shell { shell } => {
+            ensure_venv_exists().await?;
+            spawn_shell(shell.as_deref()).await
+        }
+
+        Commands::Config { action } => match action {
+            ConfigAction::Get { key } => {
+                let setting = find_setting(key)?;
+                let effective = toml::Value::try_from(SAConfig::load()?)?;
+                let value = effective.as_table().and_then(|table| lookup(table, setting.key)).ok_or_else(|| format!("{} is not set", setting.key))?;
+                if output::json_mode() {
+                    output::print_json(value)?;
+                } else {
+                    println!("{}", value.as_str().map(str::to_string).unwrap_or_else(|| value.to_string()));
+                }
+                Ok(())
+            }
+
+            ConfigAction::Set { key, value, project } => {
+                let setting = find_setting(key)?;
+                if *project && !setting.project {
+                    return Err(format!("{} cannot be overridden per project; set it in config.toml", setting.key).into());
+                }
+                let value = setting.parse_value(value)?;
+                let target = if *project { std::env::current_dir()?.join("pyproject.toml") } else { SAConfig::config_path() };
+                let previous = fs::read_to_string(&target).ok();
+                let path = if *project { set_project_setting(setting.key, value.clone())? } else { set_global_setting(setting.key, value.clone())? };
+                // What was written must still load; otherwise the file is put back
+                let loaded = if *project { project_settings().map(|_| ()) } else { SAConfig::load().map(|_| ()) };
+                if let Err(e) = loaded {
+                    match &previous {
+                        Some(content) => fs::write(&path, content)?,
+                        None => fs::remove_file(&path)?,
+                    }
+                    return Err(format!("Not setting {}: {}", setting.key, e).into());
+                }
+                println!("{}", format!("✅ Set {} = {} in {}", setting.key, value, path.display()).green());
+                if !*project {
+                    if let Some(local) = lookup(&project_settings()?, setting.key) {
+                        println!("{}", format!("Warning: this project's pyproject.toml overrides it with {}", local).yellow());
+                    }
+                }
+                if output::json_mode() {
+                    output::print_json(&serde_json::json!({ "key": setting.key, "value": value, "file": path }))?;
+                }
+                Ok(())
+            }
+
+            ConfigAction::List => {
+                let effective = toml::Value::try_from(SAConfig::load()?)?;
+                let effective = effective.as_table().ok_or("Configuration is not a table")?;
+                let (global, project) = (SAConfig::load_file()?, project_settings()?);
+                let rows: Vec<(&Setting, Option<&toml::Value>, &str)> = SETTINGS
+                    .iter()
+                    .map(|setting| {
+                        let source = if lookup(&project, setting.key).is_some() {
+                            "pyproject.toml"
+                        } else if lookup(&global, setting.key).is_some() {
+                            "config.toml"
+                        } else {
+                            "default"
+                        };
+                        (setting, lookup(effective, setting.key), source)
+                    })
+                    .collect();
+
+                if output::json_mode() {
+                    let settings: Vec<serde_json::Value> = rows
+                        .iter()
+                        .map(|(setting, value, source)| {
+                            serde_json::json!({ "key": setting.key, "value": value, "source": source, "project": setting.project, "description": setting.description })
+                        })
+                        .collect();
+                    return output::print_json(&settings);
+                }
+
+                println!("{}", format!("⚙️  Settings ({}, overridden by [tool.sa] in pyproject.toml)", SAConfig::config_path().display()).cyan());
+                for (setting, value, source) in rows {
+                    let value = value.map(|value| value.to_string()).unwrap_or_else(|| "(not set)".dimmed().to_string());
+                    let source = match source {
+                        "default" => source.dimmed(),
+                        _ => source.blue(),
+                    };
+                    println!("  {} = {}  {}", setting.key.bold(), value, source);
+                    let scope = if setting.project { "" } else { " (not per project)" };
+                    println!("      {}", format!("{}{}", setting.description, scope).dimmed());
+                }
+                Ok(())
+            }
+
+            ConfigAction::Edit { project } => {
+                let path = if *project {
+                    let path = std::env::current_dir()?.join("pyproject.toml");
+                    if !path.exists() {
+                        return Err("No pyproject.toml in the current directory".into());
+                    }
+                    path
+                } else {
+                    let path = SAConfig::config_path();
+                    if !path.exists() {
+                        fs::create_dir_all(SAConfig::config_dir())?;
+                        fs::write(&path, "# sa settings; 'sa config list' shows them all\n")?;
+                    }
+                    path
+                };
+
+                let editor = ["VISUAL", "EDITOR"]
+                    .iter()
+                    .find_map(|name| std::env::var(name).ok().filter(|value| !value.trim().is_empty()))
+                    .unwrap_or_else(|| if cfg!(windows) { "notepad".to_string() } else { "vi".to_string() });
+                // The editor may come with arguments, e.g. "code --wait"
+                let mut words = editor.split_whitespace();
+                let program = words.next().ok_or("Empty VISUAL/EDITOR")?;
+                let status = Command::new(program)
+                    .args(words)
+                    .arg(&path)
+                    .status()
+                    .await
+                    .map_err(|e| format!("Failed to launch editor '{}': {}", editor, e))?;
+                if !status.success() {
+                    return Err(format!("Editor exited with {}", status).into());
+                }
+
+                let again = if *project { "sa config edit --project" } else { "sa config edit" };
+                SAConfig::load_file()
+                    .and_then(|settings| check_settings(&settings).map_err(|e| format!("Invalid config file {}: {}", SAConfig::config_path().display(), e).into()))
+                    .and_then(|_| SAConfig::load())
+                    .map_err(|e| format!("{}; run '{}' to fix it", e, again))?;
+                println!("{}", format!("✅ {} is valid", path.display()).green());
+                Ok(())
+            }
+        },
+
+        Commands::Completions { shell, installed } => {
+            if *installed {
+                let mut names: Vec<String> = installed_dists(&venv_dir()).into_iter().map(|(name, _)| name.replace('_', "-")).collect();
+                names.sort();
+                names.dedup();
+                for name in names {
+                    println!("{}", name);
+                }
+                return Ok(());
+            }
+            let shell = shell.as_deref().ok_or("Name the shell: bash, zsh, fish or powershell")?;
+            print!("{}", completion_script(shell, &mut Cli::command())?);
+            Ok(())
+        }
     };
 
     result
 }
+
+/// Builds the project in the current directory, with the PEP 517 backend
+/// or in a container
+async fn build_project(options: &BuildOptions<'_>) -> Result<Vec<BuildArtifact>, Box<dyn std::error::Error>> {
+    let BuildOptions { docker, python, platforms, kinds, out_dir, json, repair, plat, matrix, manylinux } = *options;
+    // JSON output keeps stdout to itself
+    let status = |message: ColoredString| if json { eprintln!("{}", message) } else { println!("{}", message) };
+    status("🏗️  Building project...".cyan());
+
+    if !matrix.is_empty() {
+        build_matrix(matrix, manylinux, platforms, out_dir).await
+    } else if docker {
+        for platform in platforms {
+            check_platform(platform)?;
+        }
+        // The project is mounted, so only a directory inside it
+        // lands on the host
+        if out_dir.is_absolute() || out_dir.components().any(|component| component == std::path::Component::ParentDir) {
+            return Err("--out-dir with --docker must be a directory inside the project".into());
+        }
+        let docker_manager = DockerManager::new()?;
+        let started = std::time::SystemTime::now();
+
+        let base_image = match python {
+            Some(version) if version.chars().all(|c| c.is_ascii_digit() || c == '.') => format!("python:{}-slim", version),
+            Some(other) => return Err(format!("--python with --docker expects a version like 3.11, got '{}'", other).into()),
+            None => "python:3.11-slim".to_string(),
+        };
+
+        // Builds the mounted project
+        let only = if kinds.len() == 1 { format!(" --{}", kinds[0]) } else { String::new() };
+        let out = out_dir.to_string_lossy().replace('\\', "/").replace('\'', "'\\''");
+        let mut script = format!("pip install build && python -m build{} --outdir '{}'", only, out);
+        if repair {
+            // Each binary wheel is replaced by its repaired (manylinux) one;
+            // a wheel that cannot be repaired fails the build
+            let plat = plat.map(|plat| format!(" --plat '{}'", plat.replace('\'', "'\\''"))).unwrap_or_default();
+            script.push_str(&format!(
+                " && pip install auditwheel patchelf && for wheel in '{out}'/*.whl; do case \"$wheel\" in *-none-any.whl) ;; *) {{ auditwheel repair{plat} -w /tmp/repaired \"$wheel\" && rm \"$wheel\" && mv /tmp/repaired/*.whl '{out}'/; }} || exit 1 ;; esac; done",
+                out = out,
+                plat = plat
+            ));
+        }
+        let build_cmd = vec!["sh".to_string(), "-c".to_string(), script];
+        let project_dir = std::env::current_dir()?;
+
+        // One build per platform, each in a container of its own
+        // image, so wheels with native code are built for each
+        let targets: Vec<Option<&String>> = if platforms.is_empty() { vec![None] } else { platforms.iter().map(Some).collect() };
+        for platform in targets {
+            let build_env = match platform {
+                Some(platform) => format!("sa-build-env-{}", platform.replace('/', "-")),
+                None => "sa-build-env".to_string(),
+            };
+            let platforms: Vec<String> = platform.cloned().into_iter().collect();
+            let target = BuildTarget { platforms: &platforms, push: None };
+            docker_manager.build_image(&build_env, &base_image, Some("requirements.txt"), &target).await?;
+
+            match docker_manager.execute_in_image(&build_env, &build_cmd, Some(&project_dir), None, &[]).await? {
+                0 => {}
+                code => {
+                    let platform = platform.map(|platform| format!(" for {}", platform)).unwrap_or_default();
+                    return Err(format!("Build{} failed in the container (exit code {})", platform, code).into());
+                }
+            }
+        }
+        artifacts_since(out_dir, started)
+    } else {
+        // The backend from [build-system], in a throwaway environment
+        // created from the project's interpreter, with the build
+        // requirements the last build pinned in sa.lock
+        let project_dir = std::env::current_dir()?;
+        let python = python.map(str::to_string).or_else(|| read_env_metadata().map(|metadata| metadata.python.display().to_string()));
+        let previous = fs::read_to_string("sa.lock").ok().and_then(|content| serde_json::from_str::<BuildLock>(&content).ok());
+        let pinned = previous.and_then(|lock| lock.build_deps);
+        let mut backend = BuildBackend::new(&project_dir, python.as_deref(), json, pinned.as_ref()).await?;
+        let mut artifacts = Vec::new();
+        let mut build_deps = None;
+        for kind in kinds {
+            let mut path = backend.build(kind, &project_dir.join(out_dir)).await?;
+            if repair && *kind == "wheel" {
+                // The repair tools are no build requirements
+                build_deps.get_or_insert_with(|| backend.build_deps());
+                path = backend.repair(&path, plat).await?;
+            }
+            let artifact = BuildArtifact {
+                kind: kind.to_string(),
+                path: path.strip_prefix(project_dir.canonicalize()?).unwrap_or(&path).display().to_string(),
+                filename: path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default(),
+                size: fs::metadata(&path)?.len(),
+                sha256: sha256_file(&path)?,
+            };
+            status(format!("  {} {} ({})", "•".blue(), artifact.path, format_size(artifact.size)).normal());
+            artifacts.push(artifact);
+        }
+
+        status("✅ Build completed successfully".green());
+
+        // Record the build; `sa publish` checks dist/ against it
+        let lock = BuildLock {
+            build_time: chrono::Utc::now().to_rfc3339(),
+            sa_version: "0.1.0".to_string(),
+            python_version: "3.11".to_string(),
+            platform: std::env::consts::OS.to_string(),
+            artifacts: artifacts.clone(),
+            build_deps: Some(build_deps.unwrap_or_else(|| backend.build_deps())),
+        };
+        fs::write("sa.lock", serde_json::to_string_pretty(&lock)?)?;
+        status("📄 Lock file 'sa.lock' generated".blue());
+        Ok(artifacts)
+    }
+}
+
+/// The distributions written to `out_dir` since `started`, by a build that
+/// does not report them
+fn artifacts_since(out_dir: &Path, started: std::time::SystemTime) -> Result<Vec<BuildArtifact>, Box<dyn std::error::Error>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(out_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.file_name().is_some_and(|name| [".whl", ".tar.gz", ".zip"].iter().any(|suffix| name.to_string_lossy().ends_with(suffix))))
+        .filter(|path| fs::metadata(path).and_then(|metadata| metadata.modified()).is_ok_and(|modified| modified >= started))
+        .collect();
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| {
+            Ok(BuildArtifact {
+                kind: if path.extension().is_some_and(|extension| extension == "whl") { "wheel" } else { "sdist" }.to_string(),
+                filename: path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default(),
+                size: fs::metadata(&path)?.len(),
+                sha256: sha256_file(&path)?,
+                path: path.display().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Builds the project's wheels for each Python version of `matrix` (and
+/// each platform) at once, each in a container of the manylinux `policy`
+/// image, repaired with the image's auditwheel into `out_dir`
+async fn build_matrix(matrix: &[String], policy: &str, platforms: &[String], out_dir: &Path) -> Result<Vec<BuildArtifact>, Box<dyn std::error::Error>> {
+    if out_dir.is_absolute() || out_dir.components().any(|component| component == std::path::Component::ParentDir) {
+        return Err("--out-dir with --matrix must be a directory inside the project".into());
+    }
+    let interpreters = matrix.iter().map(|version| manylinux_python(version).map(|python| (version.as_str(), python))).collect::<Result<Vec<_>, _>>()?;
+    for platform in platforms {
+        check_platform(platform)?;
+        manylinux_image(policy, platform)?;
+    }
+    let docker_manager = DockerManager::new()?;
+    let native = docker_manager.engine_platform().await.ok_or("Cannot tell which platform the Docker engine runs")?;
+    let targets: Vec<(Option<&str>, String)> = if platforms.is_empty() {
+        vec![(None, manylinux_image(policy, &native)?)]
+    } else {
+        platforms.iter().map(|platform| Ok((Some(platform.as_str()), manylinux_image(policy, platform)?))).collect::<Result<_, Box<dyn std::error::Error>>>()?
+    };
+    for (platform, image) in &targets {
+        // Only pulled for another platform than the engine's own
+        let platform = platform.filter(|platform| !platform.starts_with(native.as_str()));
+        docker_manager.ensure_image(image, platform).await?;
+    }
+
+    // Each job builds from its own copy of the project, so the backends'
+    // build directories do not collide
+    let out = out_dir.to_string_lossy().replace('\\', "/").replace('\'', "'\\''");
+    let project_dir = std::env::current_dir()?;
+    let started = std::time::SystemTime::now();
+    let mut jobs = Vec::new();
+    for (platform, image) in &targets {
+        for (version, python) in &interpreters {
+            let script = format!(
+                "set -e; test -x {python} || {{ echo 'The image has no Python {version}' >&2; exit 3; }}; \
+                 mkdir -p /tmp/src /tmp/wheels '{out}'; \
+                 tar -C /app --exclude=./.git --exclude=./.venv --exclude='./{out}' -cf - . | tar -C /tmp/src -xf -; \
+                 {python} -m pip wheel --no-deps --disable-pip-version-check -w /tmp/wheels /tmp/src; \
+                 for wheel in /tmp/wheels/*.whl; do case \"$wheel\" in *-none-any.whl) cp \"$wheel\" '{out}'/ ;; *) auditwheel repair -w '{out}' \"$wheel\" ;; esac; done",
+                python = python,
+                version = version,
+                out = out
+            );
+            let label = match platform {
+                Some(platform) => format!("[{} {}]", version, platform),
+                None => format!("[{}]", version),
+            };
+            println!("  {} {} in {}", "•".blue(), label, image);
+            let (docker_manager, project_dir) = (&docker_manager, &project_dir);
+            jobs.push(async move {
+                let code = docker_manager.run_job(image, *platform, &script, project_dir, &label).await;
+                (label, code)
+            });
+        }
+    }
+
+    let mut failed = Vec::new();
+    for (label, code) in futures_util::future::join_all(jobs).await {
+        match code {
+            Ok(0) => {}
+            Ok(code) => failed.push(format!("{} (exit code {})", label, code)),
+            Err(e) => failed.push(format!("{} ({})", label, e)),
+        }
+    }
+    if !failed.is_empty() {
+        return Err(format!("Builds failed: {}", failed.join(", ")).into());
+    }
+
+    let wheels = artifacts_since(out_dir, started)?;
+    for wheel in &wheels {
+        println!("  {} {} ({})", "•".blue(), wheel.path, format_size(wheel.size));
+    }
+    if wheels.len() == 1 && targets.len() * interpreters.len() > 1 {
+        println!("{}", "Warning: the project builds one pure-Python wheel; a single build would do".yellow());
+    }
+    println!("{}", format!("✅ Built {} wheels into {}", wheels.len(), out_dir.display()).green());
+    Ok(wheels)
+}
+
+/// Checks the distributions in dist/ of the project in the current
+/// directory and uploads them (only checks with `check`, and checks the
+/// credentials too with `dry_run`), returning the version of the release.
+/// Then waits up to `verify` for the index to list the files with the same
+/// hashes, and with `smoke_test` installs the release from it.
+async fn publish_project(
+    repository: Option<&str>,
+    repository_url: Option<&str>,
+    check: bool,
+    dry_run: bool,
+    verify: Option<std::time::Duration>,
+    smoke_test: bool,
+) -> Result<String, Box<dyn std::error::Error>> {
+    println!("{}", if check || dry_run { "🔍 Checking distributions..." } else { "📤 Publishing project..." }.cyan());
+    let distributions = find_distributions(Path::new("dist"))?;
+
+    let findings = check_release(&distributions, &std::env::current_dir()?)?;
+    for distribution in &distributions {
+        let problems: Vec<&Finding> = findings.iter().filter(|finding| finding.file.as_ref() == Some(&distribution.filename)).collect();
+        let mark = if problems.iter().any(|finding| finding.error) { "✗".red() } else { "✓".green() };
+        println!("  {} {}", mark, distribution.filename);
+        for finding in problems {
+            print_finding(finding);
+        }
+    }
+    let release: Vec<&Finding> = findings.iter().filter(|finding| finding.file.is_none()).collect();
+    if !release.is_empty() {
+        println!("  {} release", if release.iter().any(|finding| finding.error) { "✗".red() } else { "✓".green() });
+        for finding in release {
+            print_finding(finding);
+        }
+    }
+    let errors = findings.iter().filter(|finding| finding.error).count();
+    if errors > 0 {
+        return Err(format!("{} problem{} found in dist/; nothing was uploaded", errors, if errors == 1 { "" } else { "s" }).into());
+    }
+    if check {
+        println!("{}", "✅ Ready to publish".green());
+        return Ok(distributions[0].version.clone());
+    }
+
+    let config = SAConfig::load()?;
+    let name = repository.unwrap_or("pypi");
+    let target = match repository_url {
+        Some(url) => Some(PublishRepository { url: url.to_string(), ..PublishRepository::default() }),
+        None => config.repositories.get(name).cloned().or_else(|| builtin_repository(name)),
+    };
+    let (url, mirror, index, (username, password)) = match target {
+        Some(target) => {
+            println!("  {} uploading to {}", "•".blue(), target.url);
+            let account = repository_url.unwrap_or(name);
+            let minted = match (publish_credentials(account, &target)?, trusted_index(&target.url)) {
+                (Some(credentials), _) => Some(credentials),
+                (None, Some(index)) => mint_token(index).await?.map(|token| ("__token__".to_string(), token)),
+                (None, None) => None,
+            };
+            let credentials = minted.ok_or_else(|| {
+                let variable = target.password_env.as_deref().map(|variable| format!("set {}, ", variable)).unwrap_or_default();
+                let host = reqwest::Url::parse(&target.url).ok().and_then(|url| url.host_str().map(str::to_string)).unwrap_or(target.url.clone());
+                let hint = trusted_index(&target.url).and_then(setup_hint).map(|hint| format!(" ({})", hint)).unwrap_or_default();
+                format!("No credentials for '{}': {}run 'sa auth login {} --publish' or add {} to ~/.netrc{}", account, variable, account, host, hint)
+            })?;
+            (target.url, None, target.index_url, credentials)
+        }
+        None => {
+            let mirror_manager = MirrorManager::new()?;
+            let mirror = mirror_manager.mirrors.iter().find(|mirror| mirror.name == name).ok_or_else(|| {
+                format!("Repository '{}' not found; add it under [repositories.{}] in {} or as a mirror with 'sa mirror add --kind <kind>'", name, name, SAConfig::config_path().display())
+            })?;
+            let upload = upload_url(mirror)
+                .ok_or_else(|| format!("Mirror '{}' has no known upload endpoint; re-add it with --kind devpi, artifactory or nexus", name))?;
+            println!("  {} uploading to {}", "•".blue(), upload);
+
+            let credentials = match mirror_manager.credentials.auth_for(mirror)? {
+                Some(MirrorAuth::Basic { username, password }) => (username, password.unwrap_or_default()),
+                Some(MirrorAuth::Bearer(token)) => ("__token__".to_string(), token),
+                None => return Err(format!("No credentials for mirror '{}'; run 'sa auth login {}'", name, name).into()),
+            };
+            (upload, Some(mirror.clone()), Some(mirror.url.clone()), credentials)
+        }
+    };
+
+    let client = network::client_builder_for(mirror.as_ref())?.redirect(reqwest::redirect::Policy::none()).build()?;
+    let (retries, _) = network::transfer_limits(mirror.as_ref())?;
+    let release = &distributions[0];
+
+    // Files the repository already has are skipped, so publishing
+    // again resumes a release; one it has with other content can
+    // never be replaced
+    let reader = network::client_builder_for(mirror.as_ref())?.build()?;
+    // PyPI's index is public; never send it the upload token
+    let auth = trusted_index(&url).is_none().then_some((username.as_str(), password.as_str()));
+    let existing = match &index {
+        Some(index) => index_files(&reader, index, &release.name, auth, retries).await?,
+        None => {
+            if dry_run {
+                println!("{}", format!("Warning: cannot tell which files {} already has; set index_url for it under [repositories]", url).yellow());
+            }
+            Vec::new()
+        }
+    };
+    let released = existing
+        .iter()
+        .filter(|file| filename_version(&file.filename).is_some_and(|version| Version::parse(&version).ok() == Version::parse(&release.version).ok()))
+        .count();
+    if released > 0 {
+        println!("  {} {} {} is already on the index ({} file{})", "•".blue(), release.name, release.version, released, if released == 1 { "" } else { "s" });
+    }
+    let mut pending = Vec::new();
+    for distribution in &distributions {
+        let Some(file) = existing.iter().find(|file| file.filename == distribution.filename) else {
+            pending.push(distribution);
+            continue;
+        };
+        let local = sha256_file(&distribution.path)?;
+        if file.sha256.as_ref().is_some_and(|sha256| *sha256 != local) {
+            return Err(format!("{} is already on the index with different content; files cannot be replaced, so release a new version", distribution.filename).into());
+        }
+        println!("  {} {} {}", "=".dimmed(), distribution.filename, "already on the index, skipped".dimmed());
+    }
+
+    let uploader = Uploader::new(client, &url, username.clone(), password.clone(), retries);
+    if dry_run {
+        uploader.verify_credentials().await?;
+        println!("  {} credentials accepted", "✓".green());
+        for distribution in &pending {
+            println!("  {} would upload {} ({})", "⬆".cyan(), distribution.filename, format_size(fs::metadata(&distribution.path)?.len()));
+        }
+        println!("{}", format!("✅ Dry run: {} of {} files would be uploaded", pending.len(), distributions.len()).green());
+        return Ok(distributions[0].version.clone());
+    }
+    let mut uploaded = 0;
+    for distribution in pending {
+        let outcome = uploader.upload(distribution).await?;
+        if let UploadOutcome::Uploaded = outcome {
+            uploaded += 1;
+            continue;
+        }
+        // The index has a file of that name; whether it is this one
+        // shows in the SHA-256 it lists, when it can be queried
+        let listed = match &index {
+            Some(index) => index_files(&reader, index, &release.name, auth, retries)
+                .await?
+                .into_iter()
+                .find(|file| file.filename == distribution.filename)
+                .and_then(|file| file.sha256),
+            None => None,
+        };
+        let same = match listed {
+            Some(sha256) if sha256 != sha256_file(&distribution.path)? => {
+                return Err(format!("{} is already on the index with different content; files cannot be replaced, so release a new version", distribution.filename).into());
+            }
+            Some(_) => true,
+            None => false,
+        };
+        match outcome {
+            UploadOutcome::Skipped if same => println!("  {} {} {}", "=".dimmed(), distribution.filename, "already on the index, skipped".dimmed()),
+            UploadOutcome::Skipped => {
+                let why = match &index {
+                    Some(index) => format!("{} lists no SHA-256 for it", index),
+                    None => "set index_url for it under [repositories] to compare".to_string(),
+                };
+                println!("{}", format!("Warning: {} reports {} already exists; skipped without comparing it ({})", url, distribution.filename, why).yellow());
+            }
+            _ if same => {
+                println!("    {}", "the earlier attempt got through".dimmed());
+                uploaded += 1;
+            }
+            _ => {
+                return Err(format!(
+                    "{} reports {} already exists after a retry, and with no SHA-256 listed for it there is no telling whether it is this file",
+                    url, distribution.filename
+                ).into())
+            }
+        }
+    }
+
+    println!(
+        "{}",
+        format!("✅ Published {} {} ({} of {} files uploaded)", release.name, release.version, uploaded, distributions.len()).green()
+    );
+
+    let (Some(timeout), Some(index)) = (verify, &index) else {
+        if verify.is_some() {
+            println!("{}", format!("Warning: not verifying the release; set index_url for {} under [repositories]", url).yellow());
+        }
+        return Ok(release.version.clone());
+    };
+    println!("  {} waiting for {} to list the release", "•".blue(), index);
+    let filenames: Vec<&str> = distributions.iter().map(|distribution| distribution.filename.as_str()).collect();
+    let listed = wait_for_files(&reader, index, &release.name, auth, &filenames, timeout).await?;
+    for distribution in &distributions {
+        let local = sha256_file(&distribution.path)?;
+        match listed.iter().find(|file| file.filename == distribution.filename).and_then(|file| file.sha256.as_ref()) {
+            Some(sha256) if *sha256 == local => println!("  {} {} {}", "✓".green(), distribution.filename, "matches".dimmed()),
+            Some(sha256) => return Err(format!("{} on the index does not match the local file (SHA-256 {} instead of {})", distribution.filename, sha256, local).into()),
+            None => println!("{}", format!("Warning: the index lists {} without a hash to compare", distribution.filename).yellow()),
+        }
+    }
+    if smoke_test {
+        smoke_install(&release.name, &release.version, index).await?;
+    }
+    println!("{}", format!("✅ Verified {} {} on the index", release.name, release.version).green());
+    Ok(release.version.clone())
+}
+
+/// Installs `name==version` with `sa add` in a scratch project, as a user
+/// would, to prove the release installs. `index` is added to the usual
+/// indexes, which the release's dependencies may come from.
+async fn smoke_install(name: &str, version: &str, index: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let scratch = tempfile::Builder::new().prefix("sa-smoke-").tempdir()?;
+    let requirement = format!("{}=={}", name, version);
+    println!("  {} installing {} in a scratch environment", "•".blue(), requirement);
+    let status = Command::new(std::env::current_exe()?)
+        .args(["add", "--yes", &requirement])
+        .current_dir(scratch.path())
+        .env("SA_EXTRA_INDEX_URLS", index)
+        .status()
+        .await?;
+    if !status.success() {
+        return Err(format!("{} was published but does not install from {}", requirement, index).into());
+    }
+    Ok(())
+}
+
+/// The members of the workspace whose root is the current directory
+fn workspace_members() -> Result<Vec<Member>, Box<dyn std::error::Error>> {
+    let config: WorkspaceConfig = match project_tool_section("workspace")? {
+        Some(section) => section.try_into().map_err(|e| format!("Invalid [tool.sa.workspace]: {}", e))?,
+        None => return Err("--all needs a workspace: list the member projects under [tool.sa.workspace] members in pyproject.toml".into()),
+    };
+    load_members(&std::env::current_dir()?, &config)
+}
+
+fn print_member(root: &Path, member: &Member) {
+    println!("{}", format!("📦 {} ({})", member.name, member.dir.strip_prefix(root).unwrap_or(&member.dir).display()).cyan().bold());
+}
+
+/// Scans the environment or lockfile, prints a severity-sorted report and
+/// exits with a code reflecting the worst finding
+async fn run_audit(
+    security_scanner: &mut SecurityScanner,
+    requirements: Option<&Path>,
+    format: &str,
+    fail_on: &str,
+    fix: bool,
+    assume_yes: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let packages = audit_targets(requirements).await?;
+    let target = requirements
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|| "the environment".to_string());
+    if format == "table" {
+        println!("{}", format!("🔒 Auditing {} packages from {}...", packages.len(), target).yellow());
+    }
+
+    let findings = security_scanner.scan_installed(&packages).await?;
+    if format != "table" {
+        print_report(format, &target, requirements, &findings, fail_on)?;
+        match audit_exit_code(&findings, fail_on) {
+            0 => return Ok(()),
+            code => process::exit(code),
+        }
+    }
+    let mut rows: Vec<(&InstalledPackage, &SecurityVulnerability)> = findings
+        .iter()
+        .flat_map(|(package, vulns)| vulns.iter().map(move |vuln| (package, vuln)))
+        .collect();
+
+    if rows.is_empty() {
+        println!("{}", format!("✅ No known vulnerabilities in {} packages", packages.len()).green());
+        return Ok(());
+    }
+
+    rows.sort_by(|(a_pkg, a), (b_pkg, b)| {
+        severity_rank(&b.severity)
+            .cmp(&severity_rank(&a.severity))
+            .then_with(|| a_pkg.name.to_lowercase().cmp(&b_pkg.name.to_lowercase()))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+
+    let affected = findings.iter().filter(|(_, vulns)| !vulns.is_empty()).count();
+    println!("{}", format!("⚠️  Found {} vulnerabilities in {} packages:", rows.len(), affected).red());
+    for (package, vuln) in &rows {
+        let fixed = vuln
+            .fixed_version
+            .as_ref()
+            .map(|v| format!("fixed in {}", v))
+            .unwrap_or_else(|| "no fix".to_string());
+        let severity = format!("{:<8}", vuln.severity.to_uppercase());
+        let severity = match severity_rank(&vuln.severity) {
+            4 | 3 => severity.red().bold(),
+            2 => severity.yellow(),
+            _ => severity.normal(),
+        };
+        println!("  {} {:<20} {} {} ({})", severity, vuln.id, package.name.bold(), package.version, fixed.green());
+        println!("             {}", vuln.description.lines().next().unwrap_or_default().dimmed());
+    }
+
+    let mut summary: Vec<String> = Vec::new();
+    for label in ["critical", "high", "medium", "low"] {
+        let count = rows.iter().filter(|(_, vuln)| severity_rank(&vuln.severity) == severity_rank(label)).count();
+        if count > 0 {
+            summary.push(format!("{} {}", count, label));
+        }
+    }
+    println!("{}", format!("Summary: {}", summary.join(", ")).cyan());
+
+    if fix {
+        let plan = Remediator::new(security_scanner)?.plan(&findings, requirements).await?;
+        if fix_audit_findings(security_scanner, requirements, &plan, assume_yes).await? {
+            let packages = audit_targets(requirements).await?;
+            let findings = security_scanner.scan_installed(&packages).await?;
+            let remaining: usize = findings.iter().map(|(_, vulns)| vulns.len()).sum();
+            if remaining == 0 {
+                println!("{}", "✅ All findings cleared".green());
+            } else {
+                println!("{}", format!("⚠️  {} findings remain", remaining).yellow());
+            }
+            process::exit(audit_exit_code(&findings, fail_on));
+        }
+    }
+
+    process::exit(audit_exit_code(&findings, fail_on));
+}
+
+/// Shows the `sa audit --fix` plan and applies it on confirmation. Returns
+/// whether anything was changed.
+async fn fix_audit_findings(
+    security_scanner: &mut SecurityScanner,
+    requirements: Option<&Path>,
+    plan: &AuditFixPlan,
+    assume_yes: bool,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    for (name, ids) in &plan.unfixable {
+        println!("{}", format!("  ✗ {}: no fixed release for {}", name, ids.join(", ")).yellow());
+    }
+    for (name, reason) in &plan.blocked {
+        println!("{}", format!("  ✗ {}: upgrade cannot be resolved ({})", name, reason).yellow());
+    }
+    if plan.changes.is_empty() {
+        println!("{}", "No upgrades can be applied automatically".yellow());
+        return Ok(false);
+    }
+
+    println!("{}", "🔧 Remediation plan:".cyan());
+    for change in &plan.changes {
+        match &change.from {
+            Some(from) if change.fixes.is_empty() => println!("  {} {} → {} (required by the upgrades)", change.name.bold(), from, change.to.green()),
+            Some(from) => println!("  {} {} → {} (fixes {})", change.name.bold(), from, change.to.green(), change.fixes.join(", ")),
+            None => println!("  {} {} {} (new dependency)", "+".green(), change.name.bold(), change.to),
+        }
+    }
+
+    let target = requirements.map(|path| path.display().to_string()).unwrap_or_else(|| "the environment".to_string());
+    if !assume_yes && !confirm(&format!("Apply {} changes to {}?", plan.changes.len(), target)) {
+        println!("{}", "Not applied (pass --yes to apply without asking)".yellow());
+        return Ok(false);
+    }
+
+    match requirements {
+        Some(path) => {
+            let written = apply_to_requirements(path, &plan.changes, true)?;
+            println!("{}", format!("✅ Updated {} entries in {}", written, path.display()).green());
+        }
+        None => apply_to_environment(&plan.changes, security_scanner).await?,
+    }
+    Ok(true)
+}
+
+/// Prints scan findings as JSON or SARIF. SARIF results point at the audited
+/// lockfile, or the project's requirements.txt / pyproject.toml.
+fn print_report(
+    format: &str,
+    target: &str,
+    requirements: Option<&Path>,
+    findings: &[(InstalledPackage, Vec<SecurityVulnerability>)],
+    fail_on: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let report = match format {
+        "sarif" => {
+            let artifact = requirements.map(Path::to_path_buf).unwrap_or_else(|| {
+                ["requirements.txt", "pyproject.toml"]
+                    .iter()
+                    .map(PathBuf::from)
+                    .find(|path| path.exists())
+                    .unwrap_or_else(|| PathBuf::from("requirements.txt"))
+            });
+            sarif_report(&artifact, findings)
+        }
+        _ => json_report(target, findings, fail_on),
+    };
+    output::print_json(&report)
+}
+
+/// Renders a dependency graph in `format` to `output`, or to stdout, after
+/// warning about any conflicting requirements in it
+fn write_graph(graph: &DependencyGraph, format: &str, charset: &str, output: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let conflicts = graph.conflicts();
+    if !conflicts.is_empty() {
+        let names: Vec<String> = conflicts.iter().map(|conflict| graph.label(conflict.package, " ")).collect();
+        eprintln!("{}", format!("Warning: conflicting requirements on {} (highlighted in red)", names.join(", ")).yellow());
+    }
+    for warning in graph.warnings() {
+        eprintln!("{}", format!("⚠️  {}", warning).yellow());
+    }
+
+    // JSON output mode prints the graph as JSON, unless saved to a file
+    let format = if output.is_none() && output::json_mode() { "json" } else { format };
+    let rendered = match format {
+        "dot" => DependencyVisualizer::export_dot(graph).into_bytes(),
+        "mermaid" => DependencyVisualizer::export_mermaid(graph).into_bytes(),
+        "json" => DependencyVisualizer::export_json(graph)?.into_bytes(),
+        "html" => DependencyVisualizer::export_html(graph)?.into_bytes(),
+        "svg" => DependencyVisualizer::export_svg(graph).into_bytes(),
+        "png" if output.is_none() => return Err("PNG output is binary; write it to a file with -o, e.g. -o deps.png".into()),
+        "png" => DependencyVisualizer::export_png(graph)?,
+        // Colors only for the terminal, not for a saved file
+        "tree" => DependencyVisualizer::export_tree(graph, charset == "ascii", output.is_none()).into_bytes(),
+        _ => {
+            return Err("Unsupported format. Use 'dot', 'mermaid', 'json', 'html', 'svg', 'png', or 'tree'".into());
+        }
+    };
+    if let Some(output_file) = output {
+        fs::write(output_file, rendered)?;
+        println!("{}", format!("✅ Dependency graph saved to {}", output_file).green());
+    } else {
+        output::print_result(&String::from_utf8_lossy(&rendered))?;
+    }
+    Ok(())
+}
+
+/// One problem `sa publish` found in a distribution
+fn print_finding(finding: &Finding) {
+    if finding.error {
+        println!("    ❌ {}", finding.message.red());
+    } else {
+        println!("    ⚠️  {}", finding.message.yellow());
+    }
+}
+
+fn print_vulnerability(vuln: &SecurityVulnerability) {
+    let fixed = vuln
+        .fixed_version
+        .as_ref()
+        .map(|v| format!(" (fixed in {})", v))
+        .unwrap_or_default();
+    println!("    {} {} {}: {}{}", "•".red(), vuln.severity.to_uppercase(), vuln.id, vuln.description, fixed.green());
+}
@@ -0,0 +1,197 @@
+use std::path::PathBuf;
+use std::fs;
+use chrono::Utc;
+use colored::*;
+use dirs::cache_dir;
+use toml_edit::{value, ArrayOfTables, Item, Table};
+use crate::modules::config::{project_tool_section, tool_table, update_pyproject};
+use crate::modules::http_cache::HttpCache;
+use crate::modules::installer::normalize_dist_name;
+use crate::modules::models::{AllowListPolicy, AllowedPackage, InstallPlanItem, QuarantineEntry};
+use crate::modules::pep440::{SpecifierSet, Version};
+
+// Allow-list ("quarantine") mode for regulated environments, configured by
+// [tool.sa.allowlist]. When enabled, only distributions matching an entry in
+// `packages` or in the shared list at `source` (a path or URL to a TOML file
+// with the same `packages` array) may be installed. Everything else is
+// recorded in the quarantine report until approved with `sa security approve`.
+pub struct AllowList {
+    pub policy: AllowListPolicy,
+    entries: Vec<AllowedPackage>,
+    quarantine_path: PathBuf,
+}
+
+impl AllowList {
+    pub async fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        let policy: AllowListPolicy = match project_tool_section("allowlist")? {
+            Some(section) => section
+                .try_into()
+                .map_err(|e| format!("Invalid [tool.sa.allowlist]: {}", e))?,
+            None => AllowListPolicy::default(),
+        };
+
+        let mut entries = policy.packages.clone();
+        if let Some(source) = policy.source.as_deref().filter(|_| policy.enabled) {
+            entries.extend(load_shared_list(source).await?);
+        }
+
+        let quarantine_path = cache_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("sa-cache")
+            .join("quarantine.json");
+        Ok(AllowList { policy, entries, quarantine_path })
+    }
+
+    /// Whether a distribution version is on the allow-list
+    pub fn permits(&self, name: &str, version: &str) -> bool {
+        let name = normalize_dist_name(name);
+        let Ok(version) = Version::parse(version) else {
+            return false;
+        };
+        self.entries
+            .iter()
+            .filter(|entry| normalize_dist_name(&entry.name) == name)
+            .any(|entry| versions_match(&entry.versions, &version))
+    }
+
+    /// Quarantines every planned distribution that is not allowed and fails
+    /// if there were any. Does nothing unless allow-list mode is enabled.
+    pub fn enforce(&self, plan: &[InstallPlanItem]) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.policy.enabled {
+            return Ok(());
+        }
+
+        let rejected: Vec<&InstallPlanItem> = plan.iter().filter(|item| !self.permits(&item.name, &item.version)).collect();
+        if rejected.is_empty() {
+            return Ok(());
+        }
+
+        let project = std::env::current_dir()?;
+        let mut quarantine = self.quarantine()?;
+        for item in &rejected {
+            println!("  {} {}=={} is not on the allow-list", "⛔".red(), item.name, item.version);
+            let known = quarantine.iter().any(|entry| {
+                normalize_dist_name(&entry.name) == normalize_dist_name(&item.name) && entry.version == item.version && entry.project == project
+            });
+            if !known {
+                quarantine.push(QuarantineEntry {
+                    name: item.name.clone(),
+                    version: item.version.clone(),
+                    url: item.url.clone(),
+                    project: project.clone(),
+                    requested_at: Utc::now(),
+                });
+            }
+        }
+        self.save_quarantine(&quarantine)?;
+
+        Err(format!(
+            "{} packages were quarantined; review them with 'sa security quarantine' and approve with 'sa security approve <name>'",
+            rejected.len()
+        ).into())
+    }
+
+    /// Pending install requests, oldest first
+    pub fn quarantine(&self) -> Result<Vec<QuarantineEntry>, Box<dyn std::error::Error>> {
+        if !self.quarantine_path.exists() {
+            return Ok(Vec::new());
+        }
+        let mut entries: Vec<QuarantineEntry> = serde_json::from_str(&fs::read_to_string(&self.quarantine_path)?)?;
+        entries.sort_by_key(|entry| entry.requested_at);
+        Ok(entries)
+    }
+
+    fn save_quarantine(&self, entries: &[QuarantineEntry]) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(parent) = self.quarantine_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.quarantine_path, serde_json::to_string_pretty(entries)?)?;
+        Ok(())
+    }
+
+    /// Adds `name` to the project's allow-list, for `versions` or else for
+    /// exactly the versions waiting in quarantine, and releases the matching
+    /// quarantine entries. Returns the approved version ranges.
+    pub fn approve(&self, name: &str, versions: Option<&str>, reason: Option<&str>) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let wanted = normalize_dist_name(name);
+        let mut quarantine = self.quarantine()?;
+
+        let ranges: Vec<String> = match versions {
+            Some(versions) => {
+                if versions.trim() != "*" {
+                    SpecifierSet::parse(versions)?;
+                }
+                vec![versions.to_string()]
+            }
+            None => {
+                let mut pending: Vec<String> = quarantine
+                    .iter()
+                    .filter(|entry| normalize_dist_name(&entry.name) == wanted)
+                    .map(|entry| format!("=={}", entry.version))
+                    .collect();
+                pending.sort();
+                pending.dedup();
+                if pending.is_empty() {
+                    return Err(format!("'{}' is not in quarantine; pass --versions to approve it ahead of time", name).into());
+                }
+                pending
+            }
+        };
+
+        update_pyproject(|doc| {
+            let allowlist = tool_table(doc, "allowlist")?;
+            let packages = allowlist
+                .entry("packages")
+                .or_insert(Item::ArrayOfTables(ArrayOfTables::new()))
+                .as_array_of_tables_mut()
+                .ok_or("[tool.sa.allowlist] packages must be an array of tables")?;
+            for range in &ranges {
+                let exists = packages.iter().any(|entry| {
+                    entry.get("name").and_then(|v| v.as_str()).map(normalize_dist_name).as_deref() == Some(wanted.as_str())
+                        && entry.get("versions").and_then(|v| v.as_str()).unwrap_or("*") == range
+                });
+                if exists {
+                    continue;
+                }
+                let mut entry = Table::new();
+                entry["name"] = value(name);
+                entry["versions"] = value(range.as_str());
+                if let Some(reason) = reason {
+                    entry["reason"] = value(reason);
+                }
+                entry["approved"] = value(Utc::now().date_naive().to_string());
+                packages.push(entry);
+            }
+            Ok(())
+        })?;
+
+        quarantine.retain(|entry| {
+            normalize_dist_name(&entry.name) != wanted
+                || !Version::parse(&entry.version).is_ok_and(|version| ranges.iter().any(|range| versions_match(range, &version)))
+        });
+        self.save_quarantine(&quarantine)?;
+        Ok(ranges)
+    }
+}
+
+/// `*` or an empty range allows every version
+fn versions_match(versions: &str, version: &Version) -> bool {
+    let versions = versions.trim();
+    if versions.is_empty() || versions == "*" {
+        return true;
+    }
+    SpecifierSet::parse(versions).is_ok_and(|set| set.contains(version))
+}
+
+/// Reads the `packages` array of a shared allow-list file or URL
+async fn load_shared_list(source: &str) -> Result<Vec<AllowedPackage>, Box<dyn std::error::Error>> {
+    let content = if source.starts_with("http://") || source.starts_with("https://") {
+        let response = HttpCache::new()?.get(source, None).await?;
+        String::from_utf8(response.body)?
+    } else {
+        fs::read_to_string(source).map_err(|e| format!("Failed to read allow-list {}: {}", source, e))?
+    };
+
+    let list: AllowListPolicy = toml::from_str(&content).map_err(|e| format!("Invalid allow-list {}: {}", source, e))?;
+    Ok(list.packages)
+}
@@ -0,0 +1,216 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use reqwest::{Client, StatusCode};
+use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_ASN1, ECDSA_P384_SHA384_ASN1};
+use serde_json::Value;
+use x509_parser::prelude::*;
+use x509_parser::pem::Pem;
+use crate::modules::http_cache::HttpCache;
+use crate::modules::models::AttestationStatus;
+use crate::modules::network;
+
+const PYPI_INTEGRITY_URL: &str = "https://pypi.org/integrity";
+const FULCIO_TRUST_BUNDLE_URL: &str = "https://fulcio.sigstore.dev/api/v2/trustBundle";
+const IN_TOTO_PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+
+// Verifies PEP 740 attestations published on PyPI's integrity API.
+//
+// Each attestation is checked for: a signing certificate chaining to the
+// Fulcio CA and valid when the entry was logged, a code-signing EKU, a DSSE
+// signature by that certificate over an in-toto statement naming this exact
+// file and digest, and a certificate identity matching the trusted publisher.
+// Rekor inclusion proofs are not re-verified.
+pub struct AttestationVerifier {
+    client: Client,
+    http: HttpCache,
+    trust_bundle: Option<Vec<Vec<u8>>>,
+}
+
+impl AttestationVerifier {
+    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(AttestationVerifier { client: network::client()?, http: HttpCache::new()?, trust_bundle: None })
+    }
+
+    /// Only files served by PyPI have provenance on the integrity API
+    pub fn supports(url: &str) -> bool {
+        url.split_once("://")
+            .and_then(|(_, rest)| rest.split('/').next())
+            .is_some_and(|host| host.eq_ignore_ascii_case("files.pythonhosted.org"))
+    }
+
+    /// Checks the provenance of one distribution file. Errors mean the
+    /// provenance could not be fetched; a bad attestation is `Invalid`.
+    pub async fn verify(&mut self, name: &str, version: &str, filename: &str, sha256: &str) -> Result<AttestationStatus, Box<dyn std::error::Error>> {
+        let url = format!("{}/{}/{}/{}/provenance", PYPI_INTEGRITY_URL, name, version, filename);
+        let response = self
+            .client
+            .get(&url)
+            .header("Accept", "application/vnd.pypi.integrity.v1+json")
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(AttestationStatus::Missing);
+        }
+        if !response.status().is_success() {
+            return Err(format!("Provenance request for {} failed with status {}", filename, response.status()).into());
+        }
+        let provenance: Value = response.json().await?;
+
+        let bundles = provenance["attestation_bundles"].as_array().cloned().unwrap_or_default();
+        if bundles.is_empty() {
+            return Ok(AttestationStatus::Missing);
+        }
+
+        let trust_bundle = self.trust_bundle().await?.clone();
+        let mut publishers = Vec::new();
+        for bundle in &bundles {
+            let publisher = &bundle["publisher"];
+            for attestation in bundle["attestations"].as_array().into_iter().flatten() {
+                match verify_attestation(attestation, publisher, filename, sha256, &trust_bundle) {
+                    Ok(identity) => publishers.push(identity),
+                    Err(e) => return Ok(AttestationStatus::Invalid(format!("{}: {}", filename, e))),
+                }
+            }
+        }
+
+        match publishers.first() {
+            Some(publisher) => Ok(AttestationStatus::Verified(publisher.clone())),
+            None => Ok(AttestationStatus::Missing),
+        }
+    }
+
+    async fn trust_bundle(&mut self) -> Result<&Vec<Vec<u8>>, Box<dyn std::error::Error>> {
+        if self.trust_bundle.is_none() {
+            let bundle = self.http.get_json(FULCIO_TRUST_BUNDLE_URL).await?;
+            let mut certificates = Vec::new();
+            for chain in bundle["chains"].as_array().into_iter().flatten() {
+                for pem in chain["certificates"].as_array().into_iter().flatten().filter_map(|c| c.as_str()) {
+                    for block in Pem::iter_from_buffer(pem.as_bytes()) {
+                        certificates.push(block.map_err(|e| format!("Invalid Fulcio certificate: {}", e))?.contents);
+                    }
+                }
+            }
+            if certificates.is_empty() {
+                return Err("Fulcio trust bundle is empty".into());
+            }
+            self.trust_bundle = Some(certificates);
+        }
+        Ok(self.trust_bundle.as_ref().expect("trust bundle loaded above"))
+    }
+}
+
+/// Verifies a single PEP 740 attestation, returning the publisher identity
+fn verify_attestation(attestation: &Value, publisher: &Value, filename: &str, sha256: &str, trust_bundle: &[Vec<u8>]) -> Result<String, Box<dyn std::error::Error>> {
+    let material = &attestation["verification_material"];
+    let certificate_der = STANDARD.decode(material["certificate"].as_str().ok_or("missing signing certificate")?)?;
+    let (_, certificate) = parse_x509_certificate(&certificate_der).map_err(|e| format!("invalid signing certificate: {}", e))?;
+
+    // Fulcio certificates live for minutes; they must have been valid when
+    // the signature was logged
+    let integrated_time = material["transparency_entries"]
+        .as_array()
+        .and_then(|entries| entries.first())
+        .and_then(|entry| {
+            let time = &entry["integratedTime"];
+            time.as_i64().or_else(|| time.as_str().and_then(|t| t.parse().ok()))
+        })
+        .ok_or("missing transparency log entry")?;
+    let signed_at = ASN1Time::from_timestamp(integrated_time)?;
+    if !certificate.validity().is_valid_at(signed_at) {
+        return Err("signing certificate was not valid when the attestation was logged".into());
+    }
+    verify_chain(&certificate, trust_bundle, signed_at)?;
+
+    let code_signing = certificate.extended_key_usage()?.is_some_and(|eku| eku.value.code_signing);
+    if !code_signing {
+        return Err("signing certificate is not issued for code signing".into());
+    }
+
+    let envelope = &attestation["envelope"];
+    let statement = STANDARD.decode(envelope["statement"].as_str().ok_or("missing statement")?)?;
+    let signature = STANDARD.decode(envelope["signature"].as_str().ok_or("missing signature")?)?;
+    let key = &certificate.public_key().subject_public_key.data;
+    let message = dsse_pae(IN_TOTO_PAYLOAD_TYPE, &statement);
+    let signed = UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, key).verify(&message, &signature).is_ok()
+        || UnparsedPublicKey::new(&ECDSA_P384_SHA384_ASN1, key).verify(&message, &signature).is_ok();
+    if !signed {
+        return Err("signature does not match the signing certificate".into());
+    }
+
+    let statement: Value = serde_json::from_slice(&statement)?;
+    let covers_file = statement["subject"].as_array().into_iter().flatten().any(|subject| {
+        subject["name"].as_str() == Some(filename)
+            && subject["digest"]["sha256"].as_str().is_some_and(|digest| digest.eq_ignore_ascii_case(sha256))
+    });
+    if !covers_file {
+        return Err("attestation does not cover this file's digest".into());
+    }
+
+    publisher_identity(&certificate, publisher)
+}
+
+/// The leaf must be signed by a currently trusted Fulcio certificate
+fn verify_chain(certificate: &X509Certificate, trust_bundle: &[Vec<u8>], signed_at: ASN1Time) -> Result<(), Box<dyn std::error::Error>> {
+    for der in trust_bundle {
+        let Ok((_, issuer)) = parse_x509_certificate(der) else { continue };
+        if issuer.subject() == certificate.issuer()
+            && issuer.validity().is_valid_at(signed_at)
+            && certificate.verify_signature(Some(issuer.public_key())).is_ok()
+        {
+            return Ok(());
+        }
+    }
+    Err("signing certificate was not issued by Fulcio".into())
+}
+
+/// Matches the certificate's identity against the trusted publisher PyPI
+/// recorded and describes it, e.g. "GitHub pypa/sampleproject (release.yml)"
+fn publisher_identity(certificate: &X509Certificate, publisher: &Value) -> Result<String, Box<dyn std::error::Error>> {
+    let names: Vec<String> = certificate
+        .subject_alternative_name()?
+        .map(|san| {
+            san.value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::URI(uri) => Some(uri.to_string()),
+                    GeneralName::RFC822Name(email) => Some(email.to_string()),
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let field = |key: &str| publisher[key].as_str().unwrap_or_default();
+    let (expected, label) = match field("kind") {
+        "GitHub" => (
+            format!("https://github.com/{}/.github/workflows/{}@", field("repository"), field("workflow")),
+            format!("GitHub {} ({})", field("repository"), field("workflow")),
+        ),
+        "GitLab" => (
+            format!("https://gitlab.com/{}//{}@", field("repository"), field("workflow_filepath")),
+            format!("GitLab {} ({})", field("repository"), field("workflow_filepath")),
+        ),
+        "Google" => (field("email").to_string(), format!("Google {}", field("email"))),
+        other => {
+            // Unknown publisher kinds are reported by certificate identity
+            let identity = names.first().cloned().ok_or("signing certificate has no identity")?;
+            return Ok(format!("{} {}", other, identity));
+        }
+    };
+
+    let matches = names.iter().any(|name| {
+        name.to_lowercase().starts_with(&expected.to_lowercase()) || name.eq_ignore_ascii_case(&expected)
+    });
+    if !matches {
+        return Err(format!("certificate identity does not match trusted publisher {}", label).into());
+    }
+    Ok(label)
+}
+
+/// DSSE pre-authentication encoding of a payload
+fn dsse_pae(payload_type: &str, payload: &[u8]) -> Vec<u8> {
+    let mut message = format!("DSSEv1 {} {} {} ", payload_type.len(), payload_type, payload.len()).into_bytes();
+    message.extend_from_slice(payload);
+    message
+}
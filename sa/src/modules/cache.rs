@@ -2,16 +2,42 @@
 
 use std::path::{Path, PathBuf};
 use std::fs;
-use rusqlite::Connection;
+use std::io::{self, Read, Write};
+use rusqlite::{Connection, OptionalExtension, Params};
+use sha2::{Digest, Sha256};
 use dirs::cache_dir;
-use chrono::{DateTime, Utc};
-use crate::modules::models::{CachedPackage};
-use tokio::process::Command;
+use chrono::{DateTime, Duration, Utc};
+use walkdir::WalkDir;
+use colored::*;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard};
+use crate::modules::models::{CachedPackage, CacheVerifyReport, CacheOptimizeReport, CacheGcReport, CacheBundleManifest, CacheBundleReport, CacheStats, PackageCacheStats, CacheListFilter, CacheListEntry, TrustRecord, InstalledPackage, InstallOptions, InstallPlanItem, AttestationStatus, SAConfig};
+use crate::modules::config::parse_size;
+use crate::modules::venv::{ensure_venv_exists, pip_command, venv_dir};
+use crate::modules::allowlist::AllowList;
+use crate::modules::malware::MalwareScanner;
+use crate::modules::attestations::AttestationVerifier;
+use crate::modules::security::{block_threshold, load_security_policy};
+use crate::modules::supply_chain::SupplyChainChecker;
+use crate::modules::typosquat::check_typosquatting;
+use crate::modules::pep440::compare_versions;
+use crate::modules::installer::{download_artifact, artifact_filename, normalize_dist_name, LinkMode, WheelInstaller};
+
+const PACKAGE_COLUMNS: &str = "name, version, hash, download_url, cached_at, file_path, metadata, filename, mirror";
+
+/// (normalized name, version) pairs of installed distributions
+type InstalledSet = HashSet<(String, String)>;
+
+const BUNDLE_MANIFEST: &str = "manifest.json";
+const BUNDLE_FORMAT_VERSION: u32 = 1;
 
 // Core cache system implementation
 pub struct PackageCache {
     pub cache_dir: PathBuf,
-    pub db_conn: Connection,
+    db_conn: Mutex<Connection>,
 }
 
 impl PackageCache {
@@ -25,6 +51,12 @@ impl PackageCache {
         let db_path = cache_dir.join("cache.db");
         let db_conn = Connection::open(db_path)?;
 
+        // WAL lets background gc run alongside installs without blocking readers
+        db_conn.pragma_update(None, "journal_mode", "WAL")?;
+        db_conn.pragma_update(None, "synchronous", "NORMAL")?;
+        db_conn.busy_timeout(std::time::Duration::from_secs(10))?;
+        db_conn.set_prepared_statement_cache_capacity(64);
+
         // Initialize database schema
         db_conn.execute(
             "CREATE TABLE IF NOT EXISTS cached_packages (
@@ -35,161 +67,1663 @@ impl PackageCache {
                 cached_at TEXT,
                 file_path TEXT,
                 metadata TEXT,
-                PRIMARY KEY (name, version)
+                filename TEXT NOT NULL DEFAULT '',
+                PRIMARY KEY (name, version, filename)
+            )",
+            [],
+        )?;
+        ensure_column(&db_conn, "cached_packages", "filename", "TEXT NOT NULL DEFAULT ''")?;
+        ensure_column(&db_conn, "cached_packages", "last_accessed", "TEXT")?;
+        ensure_column(&db_conn, "cached_packages", "hit_count", "INTEGER NOT NULL DEFAULT 0")?;
+        ensure_column(&db_conn, "cached_packages", "mirror", "TEXT NOT NULL DEFAULT ''")?;
+        ensure_column(&db_conn, "cached_packages", "pinned", "INTEGER NOT NULL DEFAULT 0")?;
+        key_packages_by_filename(&db_conn)?;
+        db_conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_cached_packages_hash ON cached_packages (hash)",
+            [],
+        )?;
+        db_conn.execute(
+            "CREATE TABLE IF NOT EXISTS projects (
+                path TEXT PRIMARY KEY,
+                env_path TEXT,
+                last_used TEXT
+            )",
+            [],
+        )?;
+        db_conn.execute(
+            "CREATE TABLE IF NOT EXISTS project_artifacts (
+                project_id TEXT,
+                hash TEXT,
+                PRIMARY KEY (project_id, hash)
+            )",
+            [],
+        )?;
+        db_conn.execute(
+            "CREATE TABLE IF NOT EXISTS trust_records (
+                name TEXT,
+                version TEXT,
+                filename TEXT,
+                sha256 TEXT,
+                first_seen TEXT,
+                source TEXT,
+                PRIMARY KEY (name, version, filename)
+            )",
+            [],
+        )?;
+        db_conn.execute(
+            "CREATE TABLE IF NOT EXISTS artifact_scans (
+                hash TEXT,
+                scanner TEXT,
+                passed INTEGER NOT NULL,
+                output TEXT,
+                scanned_at TEXT,
+                PRIMARY KEY (hash, scanner)
+            )",
+            [],
+        )?;
+        db_conn.execute(
+            "CREATE TABLE IF NOT EXISTS cache_metrics (
+                metric TEXT PRIMARY KEY,
+                value INTEGER NOT NULL DEFAULT 0
             )",
             [],
         )?;
 
-        Ok(PackageCache { cache_dir, db_conn })
+        Ok(PackageCache { cache_dir, db_conn: Mutex::new(db_conn) })
     }
 
-    #[allow(dead_code)]
-    pub fn get_package(&self, name: &str, version: &str) -> Option<CachedPackage> {
-        let mut stmt = self.db_conn.prepare(
-            "SELECT name, version, hash, download_url, cached_at, file_path, metadata
-             FROM cached_packages WHERE name = ?1 AND version = ?2"
-        ).ok()?;
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.db_conn.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
 
-        let row = stmt.query_row([name, version], |row| {
-            let cached_at_str: String = row.get(4)?;
-            let metadata_str: String = row.get(6)?;
+    /// Runs a statement through the prepared-statement cache
+    fn execute<P: Params>(&self, sql: &str, params: P) -> rusqlite::Result<usize> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare_cached(sql)?;
+        stmt.execute(params)
+    }
 
-            Ok(CachedPackage {
-                name: row.get(0)?,
-                version: row.get(1)?,
-                hash: row.get(2)?,
-                download_url: row.get(3)?,
-                cached_at: DateTime::parse_from_rfc3339(&cached_at_str)
-                    .unwrap_or_else(|_| Utc::now().into())
-                    .with_timezone(&Utc),
-                file_path: PathBuf::from(row.get::<_, String>(5)?),
-                metadata: serde_json::from_str(&metadata_str).unwrap_or_default(),
-            })
-        }).ok()?;
+    fn query_row<T, P, F>(&self, sql: &str, params: P, f: F) -> rusqlite::Result<T>
+    where
+        P: Params,
+        F: FnOnce(&rusqlite::Row<'_>) -> rusqlite::Result<T>,
+    {
+        let conn = self.conn();
+        let mut stmt = conn.prepare_cached(sql)?;
+        stmt.query_row(params, f)
+    }
+
+    /// Whether any version of the project has been cached before
+    pub fn has_package_name(&self, name: &str) -> bool {
+        let name = normalize_dist_name(name);
+        let names: Vec<String> = {
+            let conn = self.conn();
+            let Ok(mut stmt) = conn.prepare_cached("SELECT DISTINCT name FROM cached_packages") else {
+                return false;
+            };
+            let Ok(rows) = stmt.query_map([], |row| row.get(0)) else {
+                return false;
+            };
+            rows.filter_map(|row| row.ok()).collect()
+        };
+        names.iter().any(|cached| normalize_dist_name(cached) == name)
+    }
+
+    pub fn get_package(&self, name: &str, version: &str, filename: &str) -> Option<CachedPackage> {
+        let row = self.query_row(
+            &format!("SELECT {} FROM cached_packages WHERE name = ?1 AND version = ?2 AND filename = ?3", PACKAGE_COLUMNS),
+            [name, version, filename],
+            package_from_row,
+        ).ok()?;
 
         // Verify file still exists
         if row.file_path.exists() {
+            let _ = self.execute(
+                "UPDATE cached_packages SET last_accessed = ?1 WHERE name = ?2 AND version = ?3 AND filename = ?4",
+                (Utc::now().to_rfc3339(), name, version, filename),
+            );
             Some(row)
         } else {
             // Clean up stale entry
-            let _ = self.remove_package(name, version);
+            let _ = self.remove_package(name, version, filename);
             None
         }
     }
 
-    #[allow(dead_code)]
     pub fn store_package(&self, package: &CachedPackage) -> Result<(), Box<dyn std::error::Error>> {
-        let metadata_json = serde_json::to_string(&package.metadata)?;
+        self.store_packages(std::slice::from_ref(package))
+    }
 
-        self.db_conn.execute(
-            "INSERT OR REPLACE INTO cached_packages
-             (name, version, hash, download_url, cached_at, file_path, metadata)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-            (
-                &package.name,
-                &package.version,
-                &package.hash,
-                &package.download_url,
-                &package.cached_at.to_rfc3339(),
-                package.file_path.to_string_lossy().as_ref(),
-                &metadata_json,
-            ),
-        )?;
+    /// Stores many entries in a single transaction with one prepared statement
+    pub fn store_packages(&self, packages: &[CachedPackage]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        {
+            // Upsert rather than replace so hit counts and pins survive re-indexing
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO cached_packages
+                 (name, version, hash, download_url, cached_at, file_path, metadata, filename, mirror)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+                 ON CONFLICT(name, version, filename) DO UPDATE SET
+                    hash = excluded.hash,
+                    download_url = excluded.download_url,
+                    cached_at = excluded.cached_at,
+                    file_path = excluded.file_path,
+                    metadata = excluded.metadata,
+                    filename = excluded.filename,
+                    mirror = excluded.mirror"
+            )?;
+            for package in packages {
+                stmt.execute((
+                    &package.name,
+                    &package.version,
+                    &package.hash,
+                    &package.download_url,
+                    &package.cached_at.to_rfc3339(),
+                    package.file_path.to_string_lossy().as_ref(),
+                    &serde_json::to_string(&package.metadata)?,
+                    &package.filename,
+                    &package.mirror,
+                ))?;
+            }
+        }
+        tx.commit()?;
 
         Ok(())
     }
 
-    pub fn remove_package(&self, name: &str, version: &str) -> Result<(), Box<dyn std::error::Error>> {
+    /// Removes one cached file of a release
+    pub fn remove_package(&self, name: &str, version: &str, filename: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let hash: Option<String> = self.query_row(
+            "SELECT hash FROM cached_packages WHERE name = ?1 AND version = ?2 AND filename = ?3",
+            [name, version, filename],
+            |row| row.get(0),
+        ).optional()?;
+
         // Remove from database
-        self.db_conn.execute(
-            "DELETE FROM cached_packages WHERE name = ?1 AND version = ?2",
-            [name, version],
+        self.execute(
+            "DELETE FROM cached_packages WHERE name = ?1 AND version = ?2 AND filename = ?3",
+            [name, version, filename],
         )?;
 
-        // Remove file
-        let cache_path = self.cache_dir.join(format!("{}-{}.whl", name, version));
-        if cache_path.exists() {
-            fs::remove_file(cache_path)?;
+        if let Some(hash) = hash {
+            self.discard_unreferenced_blob(&hash)?;
         }
 
         Ok(())
     }
 
-    pub fn clear_all(&self) -> Result<(), Box<dyn std::error::Error>> {
+    /// Removes every cached file of a release (one per wheel tag, plus the sdist)
+    pub fn remove_release(&self, name: &str, version: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let filenames: Vec<String> = {
+            let conn = self.conn();
+            let mut stmt = conn.prepare_cached("SELECT filename FROM cached_packages WHERE name = ?1 AND version = ?2")?;
+            let rows = stmt.query_map([name, version], |row| row.get(0))?;
+            rows.collect::<Result<_, _>>()?
+        };
+        for filename in filenames {
+            self.remove_package(name, version, &filename)?;
+        }
+        Ok(())
+    }
+
+    /// Drops a blob once no index entry refers to it any more
+    fn discard_unreferenced_blob(&self, hash: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let still_referenced: bool = self.query_row(
+            "SELECT EXISTS(SELECT 1 FROM cached_packages WHERE hash = ?1)",
+            [hash],
+            |row| row.get(0),
+        )?;
+        if !still_referenced {
+            self.remove_blob(hash)?;
+        }
+        Ok(())
+    }
+
+    /// Content-addressed location of an artifact: blobs/sha256/<aa>/<digest>
+    pub fn blob_path(&self, hash: &str) -> PathBuf {
+        self.cache_dir
+            .join("blobs")
+            .join("sha256")
+            .join(hash.get(..2).unwrap_or("00"))
+            .join(hash)
+    }
+
+    /// Directory holding the extracted contents of a cached wheel
+    pub fn unpacked_path(&self, hash: &str) -> PathBuf {
+        self.cache_dir.join("unpacked").join(hash)
+    }
+
+    /// The cached artifacts under their file names in <cache>/wheelhouse,
+    /// hard-linked to the blobs, for pip's --find-links in containers that
+    /// mount the cache. Links to artifacts no longer cached are dropped.
+    pub fn refresh_wheelhouse(&self) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let wheelhouse = self.cache_dir.join("wheelhouse");
+        fs::create_dir_all(&wheelhouse)?;
+
+        let mut wanted = HashSet::new();
+        for package in self.list_packages(&CacheListFilter::default())? {
+            let blob = self.blob_path(&package.hash);
+            // Names come from indexes, so nothing but a plain file name is linked
+            let filename = package.filename;
+            if filename.is_empty() || filename.contains(['/', '\\']) || !blob.is_file() {
+                continue;
+            }
+            let link = wheelhouse.join(&filename);
+            // A file name cached again with another digest has a new blob,
+            // so the link is current only while it is that very file
+            if !same_file(&link, &blob) {
+                let _ = fs::remove_file(&link);
+                fs::hard_link(&blob, &link).map_err(|e| format!("Failed to link {} into {}: {}", filename, wheelhouse.display(), e))?;
+            }
+            wanted.insert(filename);
+        }
+
+        for entry in fs::read_dir(&wheelhouse)? {
+            let entry = entry?;
+            // Only links are managed here; anything else placed in the
+            // directory is left alone
+            if entry.file_type()?.is_dir() {
+                continue;
+            }
+            if !wanted.contains(entry.file_name().to_string_lossy().as_ref()) {
+                fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(wheelhouse)
+    }
+
+    /// Moves a file into content-addressed storage and returns its digest.
+    /// Content already present (e.g. the same wheel from another mirror) is
+    /// reused and the incoming copy dropped.
+    pub fn store_blob(&self, src: &Path) -> Result<String, Box<dyn std::error::Error>> {
+        let hash = sha256_file(src)?;
+        let dest = self.blob_path(&hash);
+
+        if dest.exists() {
+            fs::remove_file(src)?;
+        } else {
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            if fs::rename(src, &dest).is_err() {
+                fs::copy(src, &dest)?;
+                fs::remove_file(src)?;
+            }
+        }
+
+        Ok(hash)
+    }
+
+    /// Returns a blob only after re-hashing it; corrupted content is evicted
+    pub fn verified_blob(&self, hash: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let path = self.blob_path(hash);
+        if !path.exists() {
+            return Err(format!("Artifact {} is not in the cache", hash).into());
+        }
+
+        let actual = sha256_file(&path)?;
+        if actual != hash {
+            self.execute("DELETE FROM cached_packages WHERE hash = ?1", [hash])?;
+            self.remove_blob(hash)?;
+            return Err(format!("Cached artifact {} is corrupt (found {}); it has been evicted", hash, actual).into());
+        }
+
+        Ok(path)
+    }
+
+    fn remove_blob(&self, hash: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let blob = self.blob_path(hash);
+        if blob.exists() {
+            fs::remove_file(blob)?;
+        }
+        let unpacked = self.unpacked_path(hash);
+        if unpacked.exists() {
+            fs::remove_dir_all(unpacked)?;
+        }
+        Ok(())
+    }
+
+    /// Unpacked tree for a blob, extracting it (after verification) on first use
+    pub fn ensure_unpacked(&self, hash: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let unpacked = self.unpacked_path(hash);
+        if !unpacked.is_dir() {
+            let blob = self.verified_blob(hash)?;
+            unpack_wheel(&blob, &unpacked)?;
+        }
+        Ok(unpacked)
+    }
+
+    /// Cached wheel whose unpacked tree is ready to be linked into an
+    /// environment. Only the same file counts: another wheel of the release
+    /// is built for another Python or platform.
+    pub fn get_unpacked(&self, name: &str, version: &str, filename: &str) -> Option<(CachedPackage, PathBuf)> {
+        let package = self.query_row(
+            &format!("SELECT {} FROM cached_packages WHERE name = ?1 AND version = ?2 AND filename = ?3", PACKAGE_COLUMNS),
+            [name, version, filename],
+            package_from_row,
+        ).ok()?;
+        if !package.file_path.exists() {
+            let _ = self.execute(
+                "DELETE FROM cached_packages WHERE name = ?1 AND version = ?2 AND filename = ?3",
+                [name, version, filename],
+            );
+            return None;
+        }
+        let _ = self.execute(
+            "UPDATE cached_packages SET last_accessed = ?1 WHERE name = ?2 AND version = ?3 AND filename = ?4",
+            (Utc::now().to_rfc3339(), name, version, filename),
+        );
+        let unpacked = self.ensure_unpacked(&package.hash).ok()?;
+        Some((package, unpacked))
+    }
+
+    /// Cached entries matching the filter, ordered by name and version
+    pub fn list_packages(&self, filter: &CacheListFilter) -> Result<Vec<CachedPackage>, Box<dyn std::error::Error>> {
+        let packages: Vec<CachedPackage> = {
+            let conn = self.conn();
+            let mut stmt = conn.prepare_cached(&format!(
+                "SELECT {} FROM cached_packages WHERE ?1 IS NULL OR mirror = ?1 ORDER BY name, version",
+                PACKAGE_COLUMNS
+            ))?;
+            let rows = stmt.query_map([filter.mirror.as_deref()], package_from_row)?;
+            rows.collect::<Result<_, _>>()?
+        };
+
+        let name = filter.name.as_deref().map(normalize_dist_name);
+        let mut packages: Vec<CachedPackage> = packages
+            .into_iter()
+            .filter(|package| name.as_ref().is_none_or(|name| normalize_dist_name(&package.name).contains(name.as_str())))
+            .filter(|package| filter.older_than.is_none_or(|cutoff| package.cached_at < cutoff))
+            .collect();
+        // SQLite orders versions as text, so 2.10 would sort before 2.9
+        packages.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| compare_versions(&a.version, &b.version)));
+        Ok(packages)
+    }
+
+    /// Listing rows for `sa cache list`, with on-disk sizes
+    pub fn list_entries(&self, filter: &CacheListFilter) -> Result<Vec<CacheListEntry>, Box<dyn std::error::Error>> {
+        Ok(self
+            .list_packages(filter)?
+            .into_iter()
+            .map(|package| CacheListEntry {
+                size: self.artifact_size(&package.hash),
+                name: package.name,
+                version: package.version,
+                filename: package.filename,
+                download_url: package.download_url,
+                mirror: package.mirror,
+                cached_at: package.cached_at,
+            })
+            .collect())
+    }
+
+    /// Removes every entry sourced from a mirror, e.g. after it turned out
+    /// to be compromised. Returns the purged name==version labels.
+    pub fn purge_mirror(&self, mirror: &str, dry_run: bool) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut purged = Vec::new();
+        let filter = CacheListFilter { mirror: Some(mirror.to_string()), ..Default::default() };
+        for package in self.list_packages(&filter)? {
+            if !dry_run {
+                self.remove_package(&package.name, &package.version, &package.filename)?;
+            }
+            purged.push(format!("{}=={}", package.name, package.version));
+        }
+        Ok(purged)
+    }
+
+    /// Records a name/version entry pointing at a stored blob
+    pub fn index_artifact(
+        &self,
+        name: &str,
+        version: &str,
+        filename: &str,
+        hash: &str,
+        download_url: &str,
+        mirror: &str,
+    ) -> Result<CachedPackage, Box<dyn std::error::Error>> {
+        let package = CachedPackage {
+            name: name.to_string(),
+            version: version.to_string(),
+            hash: hash.to_string(),
+            download_url: download_url.to_string(),
+            cached_at: Utc::now(),
+            file_path: self.blob_path(hash),
+            metadata: Default::default(),
+            filename: filename.to_string(),
+            mirror: mirror.to_string(),
+        };
+        self.store_package(&package)?;
+        Ok(package)
+    }
+
+    /// Empties the cache except for pinned entries. Returns how many pinned
+    /// entries were kept.
+    pub fn clear_all(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        let pinned: usize = self.query_row("SELECT COUNT(*) FROM cached_packages WHERE pinned = 1", [], |row| row.get(0))?;
+
         // Clear database
-        self.db_conn.execute("DELETE FROM cached_packages", [])?;
+        self.execute("DELETE FROM cache_metrics", [])?;
+        self.execute("DELETE FROM project_artifacts", [])?;
+
+        let mut dirs = vec!["downloads", "http", "wheelhouse"];
+        if pinned == 0 {
+            self.execute("DELETE FROM cached_packages", [])?;
+            dirs.extend(["blobs", "unpacked"]);
+        } else {
+            let unpinned: Vec<(String, String, String)> = {
+                let conn = self.conn();
+                let mut stmt = conn.prepare_cached("SELECT name, version, filename FROM cached_packages WHERE pinned = 0")?;
+                let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+                rows.collect::<Result<_, _>>()?
+            };
+            for (name, version, filename) in unpinned {
+                self.remove_package(&name, &version, &filename)?;
+            }
+        }
+
+        for dir in dirs {
+            let path = self.cache_dir.join(dir);
+            if path.exists() {
+                fs::remove_dir_all(path)?;
+            }
+        }
 
-        // Clear cache directory
+        // Clear wheels stored by older, name-based cache layouts
         if self.cache_dir.exists() {
             for entry in fs::read_dir(&self.cache_dir)? {
                 let entry = entry?;
-                if entry.path().extension().map_or(false, |ext| ext == "whl") {
+                if entry.path().extension().is_some_and(|ext| ext == "whl") {
                     fs::remove_file(entry.path())?;
                 }
             }
         }
 
+        Ok(pinned)
+    }
+
+    /// Pins or unpins cached entries for `name` (all versions when `version`
+    /// is None). Returns the name==version labels changed.
+    pub fn set_pinned(&self, name: &str, version: Option<&str>, pinned: bool) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let wanted = normalize_dist_name(name);
+        let matches: Vec<CachedPackage> = self
+            .list_packages(&CacheListFilter::default())?
+            .into_iter()
+            .filter(|package| normalize_dist_name(&package.name) == wanted)
+            .filter(|package| version.is_none_or(|version| package.version == version))
+            .collect();
+
+        if matches.is_empty() {
+            let spec = match version {
+                Some(version) => format!("{}=={}", name, version),
+                None => name.to_string(),
+            };
+            return Err(format!("'{}' is not in the cache", spec).into());
+        }
+
+        let mut labels = Vec::new();
+        for package in matches {
+            self.execute(
+                "UPDATE cached_packages SET pinned = ?1 WHERE name = ?2 AND version = ?3",
+                (pinned, &package.name, &package.version),
+            )?;
+            labels.push(format!("{}=={}", package.name, package.version));
+        }
+        Ok(labels)
+    }
+
+    /// Re-hashes every cached artifact and cross-checks the index against the
+    /// files on disk. With `fix`, bad entries and stray files are removed.
+    pub fn verify(&self, fix: bool) -> Result<CacheVerifyReport, Box<dyn std::error::Error>> {
+        let mut report = CacheVerifyReport { fixed: fix, ..Default::default() };
+
+        let mut entries: HashMap<String, Vec<(String, String)>> = HashMap::new();
+        {
+            let conn = self.conn();
+            let mut stmt = conn.prepare_cached("SELECT name, version, hash FROM cached_packages")?;
+            let rows = stmt.query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+            })?;
+            for row in rows {
+                let (name, version, hash) = row?;
+                entries.entry(hash).or_default().push((name, version));
+            }
+        }
+
+        for (hash, packages) in &entries {
+            let labels = packages.iter().map(|(n, v)| format!("{}=={}", n, v));
+            let blob = self.blob_path(hash);
+            report.checked += packages.len();
+
+            if !blob.exists() {
+                report.missing.extend(labels);
+                if fix {
+                    self.execute("DELETE FROM cached_packages WHERE hash = ?1", [hash])?;
+                    self.remove_blob(hash)?;
+                }
+            } else if sha256_file(&blob)? != *hash {
+                report.corrupt.extend(labels);
+                if fix {
+                    self.execute("DELETE FROM cached_packages WHERE hash = ?1", [hash])?;
+                    self.remove_blob(hash)?;
+                }
+            }
+        }
+
+        let referenced: HashSet<&str> = entries.keys().map(String::as_str).collect();
+
+        // Blobs and unpacked trees nobody points at
+        for entry in WalkDir::new(self.cache_dir.join("blobs")).into_iter().filter_map(|e| e.ok()) {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if entry.file_type().is_file() && !referenced.contains(name.as_str()) {
+                report.orphaned_files.push(entry.path().to_path_buf());
+            }
+        }
+        if let Ok(dirs) = fs::read_dir(self.cache_dir.join("unpacked")) {
+            for dir in dirs.flatten() {
+                let name = dir.file_name().to_string_lossy().to_string();
+                if !referenced.contains(name.as_str()) || !self.blob_path(&name).exists() {
+                    report.orphaned_files.push(dir.path());
+                }
+            }
+        }
+        // Interrupted downloads and wheels from the old name-based layout
+        if let Ok(files) = fs::read_dir(self.cache_dir.join("downloads")) {
+            report.orphaned_files.extend(files.flatten().map(|f| f.path()));
+        }
+        for file in fs::read_dir(&self.cache_dir)?.flatten() {
+            if file.path().extension().is_some_and(|ext| ext == "whl") {
+                report.orphaned_files.push(file.path());
+            }
+        }
+
+        if fix {
+            for path in &report.orphaned_files {
+                let result = if path.is_dir() { fs::remove_dir_all(path) } else { fs::remove_file(path) };
+                if let Err(e) = result {
+                    if path.exists() {
+                        return Err(format!("Failed to remove {}: {}", path.display(), e).into());
+                    }
+                }
+            }
+        }
+
+        report.corrupt.sort();
+        report.missing.sort();
+        Ok(report)
+    }
+
+    /// Remembers a project environment so optimize/gc know what is in use
+    pub fn register_project(&self, project_dir: &Path, env_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        self.execute(
+            "INSERT OR REPLACE INTO projects (path, env_path, last_used) VALUES (?1, ?2, ?3)",
+            (
+                project_dir.to_string_lossy().as_ref(),
+                env_dir.to_string_lossy().as_ref(),
+                Utc::now().to_rfc3339(),
+            ),
+        )?;
         Ok(())
     }
 
-    pub fn get_stats(&self) -> Result<(usize, u64), Box<dyn std::error::Error>> {
-        let mut stmt = self.db_conn.prepare("SELECT COUNT(*) FROM cached_packages")?;
-        let count: usize = stmt.query_row([], |row| row.get(0))?;
+    /// Trust-on-first-use check: the first digest seen for a (name, version,
+    /// filename) is recorded, and any later artifact with a different digest
+    /// is refused regardless of which mirror served it
+    pub fn check_trust(&self, name: &str, version: &str, filename: &str, hash: &str, source: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let name = normalize_dist_name(name);
+        let trusted: Option<(String, String, String)> = self.query_row(
+            "SELECT sha256, first_seen, source FROM trust_records WHERE name = ?1 AND version = ?2 AND filename = ?3",
+            [&name, version, filename],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        ).optional()?;
 
-        let mut total_size = 0u64;
-        if self.cache_dir.exists() {
-            for entry in fs::read_dir(&self.cache_dir)? {
-                let entry = entry?;
-                if let Ok(metadata) = entry.metadata() {
-                    total_size += metadata.len();
+        match trusted {
+            Some((sha256, _, _)) if sha256 == hash => Ok(()),
+            Some((sha256, first_seen, source_seen)) => Err(format!(
+                "Refusing {}: sha256 {} does not match {} first seen {} via {}. \
+                 If the change is legitimate, run 'sa cache trust forget {}=={}'",
+                filename, hash, sha256, first_seen, source_seen, name, version
+            ).into()),
+            None => {
+                self.execute(
+                    "INSERT INTO trust_records (name, version, filename, sha256, first_seen, source)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    (&name, version, filename, hash, Utc::now().to_rfc3339(), source),
+                )?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Earlier verdict of the external malware scanner on an artifact
+    pub fn scan_verdict(&self, hash: &str, scanner: &str) -> Result<Option<(bool, String)>, Box<dyn std::error::Error>> {
+        Ok(self.query_row(
+            "SELECT passed, output FROM artifact_scans WHERE hash = ?1 AND scanner = ?2",
+            [hash, scanner],
+            |row| Ok((row.get(0)?, row.get::<_, Option<String>>(1)?.unwrap_or_default())),
+        ).optional()?)
+    }
+
+    pub fn record_scan_verdict(&self, hash: &str, scanner: &str, passed: bool, output: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.execute(
+            "INSERT OR REPLACE INTO artifact_scans (hash, scanner, passed, output, scanned_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            (hash, scanner, passed, output, Utc::now().to_rfc3339()),
+        )?;
+        Ok(())
+    }
+
+    /// Trust records, optionally only for one package
+    pub fn trust_records(&self, name: Option<&str>) -> Result<Vec<TrustRecord>, Box<dyn std::error::Error>> {
+        let name = name.map(normalize_dist_name);
+        let conn = self.conn();
+        let mut stmt = conn.prepare_cached(
+            "SELECT name, version, filename, sha256, first_seen, source FROM trust_records
+             WHERE ?1 IS NULL OR name = ?1 ORDER BY name, version, filename"
+        )?;
+        let rows = stmt.query_map([name], |row| {
+            let first_seen: String = row.get(4)?;
+            Ok(TrustRecord {
+                name: row.get(0)?,
+                version: row.get(1)?,
+                filename: row.get(2)?,
+                sha256: row.get(3)?,
+                first_seen: DateTime::parse_from_rfc3339(&first_seen)
+                    .map(|t| t.with_timezone(&Utc))
+                    .unwrap_or_else(|_| Utc::now()),
+                source: row.get(5)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Forgets trust records for a package (all versions when `version` is
+    /// None) so the next download is trusted afresh. Returns the number removed.
+    pub fn forget_trust(&self, name: &str, version: Option<&str>) -> Result<usize, Box<dyn std::error::Error>> {
+        Ok(self.execute(
+            "DELETE FROM trust_records WHERE name = ?1 AND (?2 IS NULL OR version = ?2)",
+            (normalize_dist_name(name), version),
+        )?)
+    }
+
+    /// Records that a project installed the artifact with this digest
+    pub fn record_project_artifact(&self, project_dir: &Path, hash: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.execute(
+            "INSERT OR IGNORE INTO project_artifacts (project_id, hash) VALUES (?1, ?2)",
+            (project_id(project_dir), hash),
+        )?;
+        Ok(())
+    }
+
+    /// Splits artifact references into those held by projects used since
+    /// `active_since` (protected from gc) and those held only by projects whose
+    /// directory is gone. Deleted projects are forgotten unless `dry_run`.
+    fn project_references(&self, active_since: DateTime<Utc>, dry_run: bool) -> Result<(HashSet<String>, HashSet<String>), Box<dyn std::error::Error>> {
+        let projects: Vec<(String, Option<String>)> = {
+            let conn = self.conn();
+            let mut stmt = conn.prepare_cached("SELECT path, last_used FROM projects")?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<Result<_, _>>()?
+        };
+
+        let references = |id: &str| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+            let conn = self.conn();
+            let mut stmt = conn.prepare_cached("SELECT hash FROM project_artifacts WHERE project_id = ?1")?;
+            let rows = stmt.query_map([id], |row| row.get(0))?;
+            Ok(rows.collect::<Result<_, _>>()?)
+        };
+
+        let mut protected = HashSet::new();
+        let mut retained = HashSet::new();
+        let mut orphaned = HashSet::new();
+
+        for (path, last_used) in projects {
+            let id = project_id(Path::new(&path));
+            let hashes = references(&id)?;
+
+            if !Path::new(&path).is_dir() {
+                orphaned.extend(hashes);
+                if !dry_run {
+                    self.execute("DELETE FROM project_artifacts WHERE project_id = ?1", [&id])?;
+                    self.execute("DELETE FROM projects WHERE path = ?1", [&path])?;
                 }
+                continue;
             }
+
+            let active = last_used
+                .and_then(|t| DateTime::parse_from_rfc3339(&t).ok())
+                .is_some_and(|t| t.with_timezone(&Utc) >= active_since);
+            if active {
+                protected.extend(hashes.iter().cloned());
+            }
+            retained.extend(hashes);
         }
 
-        Ok((count, total_size))
+        orphaned.retain(|hash| !retained.contains(hash));
+        Ok((protected, orphaned))
     }
+
+    /// Distributions (normalized name, version) installed across known
+    /// projects. Projects whose environment disappeared are forgotten.
+    fn installed_in_projects(&self) -> Result<(usize, InstalledSet), Box<dyn std::error::Error>> {
+        let projects: Vec<(String, String)> = {
+            let conn = self.conn();
+            let mut stmt = conn.prepare_cached("SELECT path, env_path FROM projects")?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<Result<_, _>>()?
+        };
+
+        let mut installed = HashSet::new();
+        let mut live_projects = 0;
+
+        for (project, env_path) in projects {
+            let env_dir = PathBuf::from(&env_path);
+            if !env_dir.is_dir() {
+                self.execute("DELETE FROM projects WHERE path = ?1", [&project])?;
+                self.execute("DELETE FROM project_artifacts WHERE project_id = ?1", [project_id(Path::new(&project))])?;
+                continue;
+            }
+            live_projects += 1;
+            installed.extend(installed_dists(&env_dir));
+        }
+
+        Ok((live_projects, installed))
+    }
+
+    /// Merges identical files across unpacked wheels into hardlinks of a single copy
+    fn dedupe_unpacked(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut by_size: HashMap<(u64, u32), Vec<PathBuf>> = HashMap::new();
+        for entry in WalkDir::new(self.cache_dir.join("unpacked")).into_iter().filter_map(|e| e.ok()) {
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            if metadata.len() > 0 {
+                by_size.entry((metadata.len(), file_mode(&metadata))).or_default().push(entry.into_path());
+            }
+        }
+
+        let mut merged = 0;
+        for (_, paths) in by_size.into_iter().filter(|(_, paths)| paths.len() > 1) {
+            let mut canonical: HashMap<String, PathBuf> = HashMap::new();
+            for path in paths {
+                let hash = sha256_file(&path)?;
+                match canonical.get(&hash) {
+                    Some(original) if !same_file(original, &path) => {
+                        fs::remove_file(&path)?;
+                        fs::hard_link(original, &path)?;
+                        merged += 1;
+                    }
+                    Some(_) => {}
+                    None => {
+                        canonical.insert(hash, path);
+                    }
+                }
+            }
+        }
+
+        Ok(merged)
+    }
+
+    /// Deduplicates unpacked files, prunes artifacts no known project uses and
+    /// compacts the database
+    pub fn optimize(&self, prune: bool) -> Result<CacheOptimizeReport, Box<dyn std::error::Error>> {
+        let mut report = CacheOptimizeReport {
+            bytes_before: self.get_stats()?.total_size,
+            ..Default::default()
+        };
+
+        if prune {
+            let (live_projects, installed) = self.installed_in_projects()?;
+            report.known_projects = live_projects;
+
+            // Without any known project everything would look unused
+            if live_projects > 0 {
+                let entries: Vec<(String, String, String)> = {
+                    let conn = self.conn();
+                    let mut stmt = conn.prepare_cached("SELECT name, version, filename FROM cached_packages WHERE pinned = 0")?;
+                    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+                    rows.collect::<Result<_, _>>()?
+                };
+
+                for (name, version, filename) in entries {
+                    if !installed.contains(&(normalize_dist_name(&name), version.clone())) {
+                        self.remove_package(&name, &version, &filename)?;
+                        report.pruned.push(format!("{}=={}", name, version));
+                    }
+                }
+            }
+        }
+
+        report.deduplicated_files = self.dedupe_unpacked()?;
+        self.conn().execute_batch("VACUUM")?;
+
+        report.bytes_after = self.get_stats()?.total_size;
+        Ok(report)
+    }
+
+    /// Disk usage of one artifact: the blob plus its unpacked tree
+    fn artifact_size(&self, hash: &str) -> u64 {
+        let blob = fs::metadata(self.blob_path(hash)).map(|m| m.len()).unwrap_or(0);
+        let unpacked: u64 = WalkDir::new(self.unpacked_path(hash))
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .filter_map(|e| e.metadata().ok())
+            .map(|m| m.len())
+            .sum();
+        blob + unpacked
+    }
+
+    /// Evicts entries left behind by deleted projects, then entries older
+    /// than `max_age` (by last access), then least recently used entries until
+    /// the cache fits in `max_size`. Artifacts used by active projects are kept.
+    pub fn gc(&self, max_size: u64, max_age: Duration, dry_run: bool) -> Result<CacheGcReport, Box<dyn std::error::Error>> {
+        let bytes_before = self.get_stats()?.total_size;
+        let mut report = CacheGcReport { bytes_before, ..Default::default() };
+        let cutoff = Utc::now() - max_age;
+        let (protected, orphaned) = self.project_references(cutoff, dry_run)?;
+
+        // Oldest access first
+        let entries: Vec<(String, String, String, String, String)> = {
+            let conn = self.conn();
+            let mut stmt = conn.prepare_cached(
+                "SELECT name, version, filename, hash, COALESCE(last_accessed, cached_at) AS accessed
+                 FROM cached_packages WHERE pinned = 0 ORDER BY accessed ASC"
+            )?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)))?;
+            rows.collect::<Result<_, _>>()?
+        };
+
+        let mut remaining = bytes_before;
+        for (name, version, filename, hash, accessed) in entries {
+            if protected.contains(&hash) {
+                continue;
+            }
+
+            let accessed = DateTime::parse_from_rfc3339(&accessed)
+                .map(|t| t.with_timezone(&Utc))
+                .unwrap_or_else(|_| Utc::now());
+            let label = format!("{}=={}", name, version);
+
+            let abandoned = orphaned.contains(&hash);
+            let expired = accessed < cutoff;
+            if !abandoned && !expired && remaining <= max_size {
+                continue;
+            }
+
+            remaining = remaining.saturating_sub(self.artifact_size(&hash));
+            if !dry_run {
+                self.remove_package(&name, &version, &filename)?;
+            }
+            if abandoned {
+                report.abandoned.push(label);
+            } else if expired {
+                report.expired.push(label);
+            } else {
+                report.evicted.push(label);
+            }
+        }
+
+        report.bytes_after = if dry_run { remaining } else { self.get_stats()?.total_size };
+        Ok(report)
+    }
+
+    /// Starts a detached `sa cache gc` when auto-gc is enabled and the cache
+    /// is over budget, so the current command doesn't wait for eviction
+    pub fn schedule_background_gc(&self) -> Result<bool, Box<dyn std::error::Error>> {
+        let policy = SAConfig::load()?.cache;
+        if !policy.auto_gc {
+            return Ok(false);
+        }
+
+        let size = self.get_stats()?.total_size;
+        if size <= parse_size(&policy.max_size)? {
+            return Ok(false);
+        }
+
+        std::process::Command::new(std::env::current_exe()?)
+            .args(["cache", "gc", "--quiet"])
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn()?;
+        Ok(true)
+    }
+
+    /// Writes the selected artifacts (every entry when `selection` is None)
+    /// and a manifest into a tar bundle, compressed by file extension
+    pub fn export_bundle(&self, bundle: &Path, selection: Option<&InstalledSet>) -> Result<CacheBundleReport, Box<dyn std::error::Error>> {
+        let mut report = CacheBundleReport::default();
+
+        let entries: Vec<(String, String, String)> = {
+            let conn = self.conn();
+            let mut stmt = conn.prepare_cached("SELECT name, version, filename FROM cached_packages ORDER BY name, version, filename")?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+            rows.collect::<Result<_, _>>()?
+        };
+
+        let mut packages = Vec::new();
+        for (name, version, filename) in entries {
+            let key = (normalize_dist_name(&name), version.clone());
+            if selection.is_some_and(|wanted| !wanted.contains(&key)) {
+                continue;
+            }
+            match self.get_package(&name, &version, &filename) {
+                Some(package) if self.verified_blob(&package.hash).is_ok() => packages.push(package),
+                _ => {
+                    let label = format!("{}=={}", name, version);
+                    if !report.missing.contains(&label) {
+                        report.missing.push(label);
+                    }
+                }
+            }
+        }
+
+        // Requested pins with no cache entry at all
+        if let Some(wanted) = selection {
+            for (name, version) in wanted {
+                if !packages.iter().any(|p| normalize_dist_name(&p.name) == *name && p.version == *version) {
+                    let label = format!("{}=={}", name, version);
+                    if !report.missing.contains(&label) {
+                        report.missing.push(label);
+                    }
+                }
+            }
+            report.missing.sort();
+        }
+
+        let mut staging = bundle.as_os_str().to_owned();
+        staging.push(".partial");
+        let staging = PathBuf::from(staging);
+        let file = fs::File::create(&staging)?;
+
+        let name = bundle.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+        let written = self.write_compressed(file, &name, &packages);
+        if let Err(e) = written {
+            let _ = fs::remove_file(&staging);
+            return Err(e);
+        }
+        fs::rename(&staging, bundle)?;
+
+        report.packages = packages.len();
+        report.bytes = fs::metadata(bundle)?.len();
+        Ok(report)
+    }
+
+    fn write_compressed(&self, file: fs::File, name: &str, packages: &[CachedPackage]) -> Result<(), Box<dyn std::error::Error>> {
+        if name.ends_with(".zst") || name.ends_with(".tzst") {
+            self.write_bundle(zstd::Encoder::new(file, 10)?, packages)?.finish()?;
+        } else if name.ends_with(".gz") || name.ends_with(".tgz") {
+            self.write_bundle(GzEncoder::new(file, Compression::default()), packages)?.finish()?;
+        } else {
+            self.write_bundle(file, packages)?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    fn write_bundle<W: Write>(&self, writer: W, packages: &[CachedPackage]) -> Result<W, Box<dyn std::error::Error>> {
+        let mut builder = tar::Builder::new(writer);
+
+        let manifest = serde_json::to_vec_pretty(&CacheBundleManifest {
+            format_version: BUNDLE_FORMAT_VERSION,
+            created_at: Utc::now(),
+            packages: packages.to_vec(),
+        })?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(Utc::now().timestamp() as u64);
+        builder.append_data(&mut header, BUNDLE_MANIFEST, manifest.as_slice())?;
+
+        let mut written = HashSet::new();
+        for package in packages {
+            if written.insert(package.hash.clone()) {
+                builder.append_path_with_name(self.blob_path(&package.hash), format!("blobs/{}", package.hash))?;
+            }
+        }
+
+        Ok(builder.into_inner()?)
+    }
+
+    /// Restores artifacts and index entries from a bundle written by
+    /// `export_bundle`. Every blob is checked against its digest on the way in.
+    pub fn import_bundle(&self, bundle: &Path) -> Result<CacheBundleReport, Box<dyn std::error::Error>> {
+        let mut report = CacheBundleReport {
+            bytes: fs::metadata(bundle)?.len(),
+            ..Default::default()
+        };
+
+        let mut magic = [0u8; 4];
+        let read = fs::File::open(bundle)?.read(&mut magic)?;
+        let file = fs::File::open(bundle)?;
+        let reader: Box<dyn Read> = match &magic[..read] {
+            [0x28, 0xb5, 0x2f, 0xfd] => Box::new(zstd::Decoder::new(file)?),
+            [0x1f, 0x8b, ..] => Box::new(GzDecoder::new(file)),
+            _ => Box::new(file),
+        };
+
+        let downloads = self.cache_dir.join("downloads");
+        fs::create_dir_all(&downloads)?;
+
+        let mut manifest: Option<CacheBundleManifest> = None;
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.to_string_lossy().to_string();
+
+            if path == BUNDLE_MANIFEST {
+                let mut content = String::new();
+                entry.read_to_string(&mut content)?;
+                let parsed: CacheBundleManifest = serde_json::from_str(&content)
+                    .map_err(|e| format!("Invalid bundle manifest: {}", e))?;
+                if parsed.format_version > BUNDLE_FORMAT_VERSION {
+                    return Err(format!("Bundle format {} is newer than this version of sa supports", parsed.format_version).into());
+                }
+                manifest = Some(parsed);
+                continue;
+            }
+
+            let Some(hash) = path.strip_prefix("blobs/") else { continue };
+            if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                continue;
+            }
+            if self.blob_path(hash).exists() {
+                report.already_cached += 1;
+                continue;
+            }
+
+            let incoming = downloads.join(format!("{}.import", hash));
+            entry.unpack(&incoming)?;
+            let actual = sha256_file(&incoming)?;
+            if actual != hash {
+                fs::remove_file(&incoming)?;
+                return Err(format!("Bundle artifact {} is corrupt (found {})", hash, actual).into());
+            }
+            self.store_blob(&incoming)?;
+        }
+
+        let manifest = manifest.ok_or("Bundle has no manifest.json")?;
+        let mut restored = Vec::new();
+        for mut package in manifest.packages {
+            if !self.blob_path(&package.hash).exists() {
+                report.missing.push(format!("{}=={}", package.name, package.version));
+                continue;
+            }
+            self.check_trust(&package.name, &package.version, &package.filename, &package.hash, &format!("bundle {}", bundle.display()))?;
+            package.file_path = self.blob_path(&package.hash);
+            package.cached_at = Utc::now();
+            restored.push(package);
+        }
+        self.store_packages(&restored)?;
+        report.packages = restored.len();
+
+        Ok(report)
+    }
+
+    pub fn get_stats(&self) -> Result<CacheStats, Box<dyn std::error::Error>> {
+        let count: usize = self.query_row("SELECT COUNT(*) FROM cached_packages", [], |row| row.get(0))?;
+        let pinned: usize = self.query_row("SELECT COUNT(*) FROM cached_packages WHERE pinned = 1", [], |row| row.get(0))?;
+
+        // The wheelhouse only holds hard links to blobs
+        let total_size = WalkDir::new(&self.cache_dir)
+            .into_iter()
+            .filter_entry(|e| e.file_name() != "wheelhouse")
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .filter_map(|e| e.metadata().ok())
+            .map(|m| m.len())
+            .sum();
+
+        let metric = |name: &str| -> u64 {
+            self.query_row("SELECT value FROM cache_metrics WHERE metric = ?1", [name], |row| row.get::<_, i64>(0))
+                .map(|value| value as u64)
+                .unwrap_or(0)
+        };
+
+        Ok(CacheStats {
+            packages: count,
+            pinned,
+            total_size,
+            hits: metric("hits"),
+            misses: metric("misses"),
+            bytes_from_cache: metric("bytes_from_cache"),
+            bytes_downloaded: metric("bytes_downloaded"),
+            per_package: Vec::new(),
+        })
+    }
+
+    /// Per-entry breakdown for `sa cache stats`, largest first
+    pub fn package_stats(&self) -> Result<Vec<PackageCacheStats>, Box<dyn std::error::Error>> {
+        let rows: Vec<(String, String, String, Option<String>, i64, bool)> = {
+            let conn = self.conn();
+            let mut stmt = conn.prepare_cached(
+                "SELECT name, version, hash, last_accessed, hit_count, pinned FROM cached_packages"
+            )?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?)))?;
+            rows.collect::<Result<_, _>>()?
+        };
+
+        let mut stats: Vec<PackageCacheStats> = rows
+            .into_iter()
+            .map(|(name, version, hash, last_accessed, hit_count, pinned)| PackageCacheStats {
+                name,
+                version,
+                size: self.artifact_size(&hash),
+                last_accessed: last_accessed
+                    .and_then(|t| DateTime::parse_from_rfc3339(&t).ok())
+                    .map(|t| t.with_timezone(&Utc)),
+                hit_count: hit_count as u64,
+                pinned,
+            })
+            .collect();
+        stats.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+        Ok(stats)
+    }
+
+    fn bump_metric(&self, metric: &str, amount: u64) -> Result<(), Box<dyn std::error::Error>> {
+        self.execute(
+            "INSERT INTO cache_metrics (metric, value) VALUES (?1, ?2)
+             ON CONFLICT(metric) DO UPDATE SET value = value + excluded.value",
+            (metric, amount as i64),
+        )?;
+        Ok(())
+    }
+
+    /// Counts an install served from the cache
+    pub fn record_hit(&self, name: &str, version: &str, filename: &str, bytes: u64) -> Result<(), Box<dyn std::error::Error>> {
+        self.execute(
+            "UPDATE cached_packages SET hit_count = hit_count + 1 WHERE name = ?1 AND version = ?2 AND filename = ?3",
+            (name, version, filename),
+        )?;
+        self.bump_metric("hits", 1)?;
+        self.bump_metric("bytes_from_cache", bytes)
+    }
+
+    /// Counts an install that had to download its artifact
+    pub fn record_miss(&self, bytes: u64) -> Result<(), Box<dyn std::error::Error>> {
+        self.bump_metric("misses", 1)?;
+        self.bump_metric("bytes_downloaded", bytes)
+    }
+}
+
+/// Maps a row selected with PACKAGE_COLUMNS
+fn package_from_row(row: &rusqlite::Row) -> rusqlite::Result<CachedPackage> {
+    let cached_at_str: String = row.get(4)?;
+    let metadata_str: String = row.get(6)?;
+
+    Ok(CachedPackage {
+        name: row.get(0)?,
+        version: row.get(1)?,
+        hash: row.get(2)?,
+        download_url: row.get(3)?,
+        cached_at: DateTime::parse_from_rfc3339(&cached_at_str)
+            .unwrap_or_else(|_| Utc::now().into())
+            .with_timezone(&Utc),
+        file_path: PathBuf::from(row.get::<_, String>(5)?),
+        metadata: serde_json::from_str(&metadata_str).unwrap_or_default(),
+        filename: row.get(7)?,
+        mirror: row.get(8)?,
+    })
 }
 
-pub async fn ensure_venv_exists() -> Result<(), Box<dyn std::error::Error>> {
-    if !Path::new(".sa_env").exists() {
-        println!("Creating virtual environment...");
-        let status = Command::new("python3")
-            .args(&["-m", "venv", ".sa_env"])
-            .status()
-            .await?;
+/// Distributions (normalized name, version) installed in an environment
+pub fn installed_dists(env_dir: &Path) -> InstalledSet {
+    WalkDir::new(env_dir)
+        .max_depth(4)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_dir() && e.file_name().to_string_lossy().ends_with(".dist-info"))
+        .filter_map(|dist_info| {
+            let dir_name = dist_info.file_name().to_string_lossy().to_string();
+            let (name, version) = dir_name.trim_end_matches(".dist-info").rsplit_once('-')?;
+            Some((normalize_dist_name(name), version.to_string()))
+        })
+        .collect()
+}
 
-        if !status.success() {
-            return Err("Failed to create virtual environment".into());
+/// Artifacts a project needs: the pinned `name==version` lines of a lockfile,
+/// or what the project environment currently has installed
+pub fn lock_selection(requirements: Option<&Path>) -> Result<InstalledSet, Box<dyn std::error::Error>> {
+    let Some(requirements) = requirements else {
+        let env_dir = venv_dir();
+        if !env_dir.is_dir() {
+            return Err("No project environment found; pass --requirements or --all".into());
         }
+        return Ok(installed_dists(&env_dir));
+    };
+
+    let content = fs::read_to_string(requirements)
+        .map_err(|e| format!("Failed to read {}: {}", requirements.display(), e))?;
+    let mut selection = HashSet::new();
+    for line in content.lines() {
+        // Drop comments, environment markers and hash options
+        let line = line.split('#').next().unwrap_or("");
+        let line = line.split(';').next().unwrap_or("").trim();
+        let line = line.split_whitespace().next().unwrap_or("");
+        if let Some((name, version)) = line.split_once("==") {
+            let name = name.split('[').next().unwrap_or(name);
+            selection.insert((normalize_dist_name(name.trim()), version.trim().to_string()));
+        }
+    }
+
+    if selection.is_empty() {
+        return Err(format!("{} has no pinned name==version requirements", requirements.display()).into());
+    }
+    Ok(selection)
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode()
+}
+
+#[cfg(not(unix))]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    metadata.permissions().readonly() as u32
+}
+
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file(_a: &Path, _b: &Path) -> bool {
+    false
+}
+
+/// Adds a column to an existing table when upgrading an older cache database
+fn ensure_column(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .filter_map(|c| c.ok())
+        .any(|c| c == column);
+
+    if !exists {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+    }
+    Ok(())
+}
+
+/// Caches created before entries were told apart by file name keyed them by
+/// name and version alone; rebuilds the table with the file name in its key
+fn key_packages_by_filename(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare("PRAGMA table_info(cached_packages)")?;
+    let keyed = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(1)?, row.get::<_, i64>(5)?)))?
+        .filter_map(|c| c.ok())
+        .any(|(column, pk)| column == "filename" && pk > 0);
+    if keyed {
+        return Ok(());
+    }
+
+    conn.execute_batch(
+        "BEGIN;
+         CREATE TABLE cached_packages_by_file (
+            name TEXT,
+            version TEXT,
+            hash TEXT,
+            download_url TEXT,
+            cached_at TEXT,
+            file_path TEXT,
+            metadata TEXT,
+            filename TEXT NOT NULL DEFAULT '',
+            last_accessed TEXT,
+            hit_count INTEGER NOT NULL DEFAULT 0,
+            mirror TEXT NOT NULL DEFAULT '',
+            pinned INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (name, version, filename)
+         );
+         INSERT INTO cached_packages_by_file
+            (name, version, hash, download_url, cached_at, file_path, metadata, filename, last_accessed, hit_count, mirror, pinned)
+            SELECT name, version, hash, download_url, cached_at, file_path, metadata, filename, last_accessed, hit_count, mirror, pinned
+            FROM cached_packages;
+         DROP TABLE cached_packages;
+         ALTER TABLE cached_packages_by_file RENAME TO cached_packages;
+         COMMIT;",
+    )?;
+    Ok(())
+}
+
+/// Stable identifier for a project directory: a truncated hash of its path
+fn project_id(project_dir: &Path) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(project_dir.to_string_lossy().as_bytes());
+    hex::encode(hasher.finalize())[..16].to_string()
+}
+
+fn blob_size(path: &Path) -> u64 {
+    fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+pub fn sha256_file(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+fn unpack_wheel(wheel_path: &Path, dest: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let file = fs::File::open(wheel_path)?;
+    let mut archive = zip::ZipArchive::new(file)
+        .map_err(|e| format!("Invalid wheel {}: {}", wheel_path.display(), e))?;
+
+    // Extract next to the final location and rename, so a crash never leaves
+    // a half-populated tree that looks usable
+    let mut staging = dest.as_os_str().to_owned();
+    staging.push(".partial");
+    let staging = PathBuf::from(staging);
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
     }
+    fs::create_dir_all(&staging)?;
+    archive.extract(&staging)
+        .map_err(|e| format!("Failed to unpack {}: {}", wheel_path.display(), e))?;
+    fs::rename(&staging, dest)?;
     Ok(())
 }
 
+/// Normalized project name of a requirement such as `Requests[socks]>=2`
+fn requirement_name(requirement: &str) -> String {
+    let end = requirement
+        .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+        .unwrap_or(requirement.len());
+    normalize_dist_name(&requirement[..end])
+}
+
+/// The security policy for an install left to pip, where the versions it
+/// will pick are unknown: only a `name==version` request can be scanned,
+/// and the supply-chain checks cannot run at all. `unsupported` says when
+/// this happens, e.g. "on Windows yet".
+async fn enforce_policy_without_plan(
+    package: &str,
+    security_scanner: &mut crate::modules::security::SecurityScanner,
+    skip_security: bool,
+    unsupported: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if skip_security {
+        return security_scanner.enforce_policy(&[], true).await;
+    }
+    let policy = load_security_policy()?;
+    if policy.supply_chain.action == "block" {
+        return Err(format!("Blocking supply-chain checks are not supported {}; set [tool.sa.security.supply-chain] action to warn or off", unsupported).into());
+    }
+    if policy.supply_chain.action != "off" {
+        println!("{}", format!("Warning: supply-chain checks are not supported {}; skipping them", unsupported).yellow());
+    }
+
+    let pinned = package
+        .split_once("==")
+        .map(|(name, version)| (name.trim(), version.trim()))
+        .filter(|(name, version)| requirement_name(name) == normalize_dist_name(name) && !version.is_empty() && !version.contains([',', '*', ';', ' ']));
+    match pinned {
+        Some((name, version)) => {
+            let candidate = InstalledPackage { name: name.to_string(), version: version.to_string() };
+            security_scanner.enforce_policy(&[candidate], false).await
+        }
+        None if block_threshold(&policy.block_severity)?.is_some() => Err(format!(
+            "The security policy can only check a pinned version {}; install {}==<version>, or pass --skip-security if the policy allows it",
+            unsupported,
+            requirement_name(package)
+        ).into()),
+        None => Ok(()),
+    }
+}
+
+/// Checks an artifact's PEP 740 attestations before it is downloaded. Bad
+/// attestations always fail; missing ones only in strict mode.
+async fn verify_provenance(
+    verifier: &mut AttestationVerifier,
+    item: &InstallPlanItem,
+    pinned: &str,
+    required: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let filename = artifact_filename(&item.url);
+    let (Some(sha256), true) = (&item.sha256, AttestationVerifier::supports(&item.url)) else {
+        if required {
+            return Err(format!("{} is not served by PyPI with a digest, so its attestations cannot be checked", pinned).into());
+        }
+        return Ok(());
+    };
+
+    match verifier.verify(&item.name, &item.version, &filename, sha256).await {
+        Ok(AttestationStatus::Verified(publisher)) => {
+            println!("  {} {} attested by {}", "🔏".green(), pinned, publisher.bold());
+            Ok(())
+        }
+        Ok(AttestationStatus::Missing) if required => Err(format!("{} has no attestations and --require-attestations is set", pinned).into()),
+        Ok(AttestationStatus::Missing) => Ok(()),
+        Ok(AttestationStatus::Invalid(reason)) => Err(format!("Attestation verification failed for {}", reason).into()),
+        Err(e) if required => Err(format!("Could not check attestations for {}: {}", pinned, e).into()),
+        Err(e) => {
+            println!("{}", format!("Warning: could not check attestations for {}: {}", pinned, e).yellow());
+            Ok(())
+        }
+    }
+}
+
 pub async fn install_package_with_cache(
-    _package: &str,
-    _cache: &mut PackageCache,
-    _mirror_manager: &crate::modules::mirrors::MirrorManager,
-    _security_scanner: &crate::modules::security::SecurityScanner,
-    _skip_security: bool,
+    package: &str,
+    cache: &mut PackageCache,
+    mirror_manager: &crate::modules::mirrors::MirrorManager,
+    security_scanner: &mut crate::modules::security::SecurityScanner,
+    options: InstallOptions,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let InstallOptions { skip_security, compile, require_attestations, assume_yes, no_save } = options;
+
     // Add package to requirements.txt
     let req_path = "requirements.txt";
     let mut requirements = std::fs::read_to_string(req_path).unwrap_or_default();
-    if !requirements.contains(_package) {
-        requirements.push_str(&format!("\n{}", _package));
+    if !no_save && !requirements.contains(package) {
+        requirements.push_str(&format!("\n{}", package));
         std::fs::write(req_path, requirements)?;
     }
 
     // Ensure virtual environment exists
-    crate::modules::cache::ensure_venv_exists().await?;
+    ensure_venv_exists().await?;
+    mirror_manager.select_session_mirror().await?;
+
+    // Catch look-alike names before the index is even asked about them
+    let requested = requirement_name(package);
+    let seen = cache.has_package_name(&requested) || installed_dists(&venv_dir()).iter().any(|(name, _)| *name == requested);
+    if !seen {
+        check_typosquatting(&requested, &load_security_policy()?.typosquatting, assume_yes).await?;
+    }
+
+    let project_dir = std::env::current_dir()?;
+    cache.register_project(&project_dir, &std::path::absolute(venv_dir())?)?;
+
+    let allowlist = AllowList::load().await?;
+    let malware = MalwareScanner::from_config()?;
+
+    // Console script launchers on Windows need pip's .exe shims
+    if cfg!(windows) {
+        if require_attestations {
+            return Err("--require-attestations is not supported on Windows yet".into());
+        }
+        if allowlist.policy.enabled {
+            return Err("Allow-list mode is not supported on Windows yet".into());
+        }
+        if malware.enabled() {
+            return Err("The artifact malware scanner is not supported on Windows yet".into());
+        }
+        enforce_policy_without_plan(package, security_scanner, skip_security, "on Windows yet").await?;
+        return pip_install(&[package.to_string()]).await;
+    }
+
+    let Some(plan) = mirror_manager.resolve_with_precedence(&[package.to_string()]).await? else {
+        if require_attestations {
+            return Err("--require-attestations needs a pip that supports install reports (pip >= 22.2)".into());
+        }
+        if allowlist.policy.enabled {
+            return Err("Allow-list mode needs a pip that supports install reports (pip >= 22.2)".into());
+        }
+        if malware.enabled() {
+            return Err("The artifact malware scanner needs a pip that supports install reports (pip >= 22.2)".into());
+        }
+        enforce_policy_without_plan(package, security_scanner, skip_security, "with a pip older than 22.2").await?;
+        println!("{}", "Warning: pip is too old for install reports; falling back to pip".yellow());
+        return pip_install(&[package.to_string()]).await;
+    };
+
+    if plan.is_empty() {
+        println!("{}", format!("'{}' is already satisfied", package).blue());
+        return Ok(());
+    }
+
+    allowlist.enforce(&plan)?;
+
+    let candidates: Vec<InstalledPackage> = plan
+        .iter()
+        .map(|item| InstalledPackage { name: item.name.clone(), version: item.version.clone() })
+        .collect();
+    security_scanner.enforce_policy(&candidates, skip_security).await?;
+    if !skip_security {
+        SupplyChainChecker::new()?.check(&plan).await?;
+    }
+    let mut attestations = AttestationVerifier::new()?;
+
+    let link_mode = LinkMode::parse(&SAConfig::load()?.venv.link_mode)?;
+    let installer = WheelInstaller::for_active_env(link_mode).await?;
+    let mut installed = Vec::new();
+
+    for item in &plan {
+        let pinned = format!("{}=={}", item.name, item.version);
+        verify_provenance(&mut attestations, item, &pinned, require_attestations).await?;
+
+        // Source distributions and direct references still go through pip,
+        // in hash-checking mode when the index published a digest
+        if !item.url.ends_with(".whl") {
+            println!("  {} {} (building with pip)", "•".blue(), pinned);
+            if malware.enabled() {
+                // pip must build the very file that was scanned
+                let dir = tempfile::tempdir()?;
+                let archive = download_artifact(&item.url, dir.path()).await?;
+                let actual = sha256_file(&archive)?;
+                if let Some(expected) = item.sha256.as_ref().filter(|expected| !actual.eq_ignore_ascii_case(expected)) {
+                    return Err(format!(
+                        "Hash mismatch for {}: the index publishes sha256 {} but the download has {}",
+                        artifact_filename(&item.url), expected, actual
+                    ).into());
+                }
+                malware.check(cache, &archive, &actual, &pinned).await?;
+                pip_install(&["--no-deps".to_string(), archive.to_string_lossy().to_string()]).await?;
+                continue;
+            }
+            match &item.sha256 {
+                Some(hash) => {
+                    let mut requirement = tempfile::Builder::new().suffix(".txt").tempfile()?;
+                    writeln!(requirement, "{} --hash=sha256:{}", pinned, hash)?;
+                    let path = requirement.path().to_string_lossy().to_string();
+                    pip_install(&["--no-deps".to_string(), "-r".to_string(), path]).await?;
+                }
+                None => {
+                    println!("{}", format!("Warning: the index publishes no sha256 for {}; it cannot be verified", pinned).yellow());
+                    pip_install(&["--no-deps".to_string(), pinned]).await?;
+                }
+            }
+            continue;
+        }
+
+        let filename = artifact_filename(&item.url);
+        let mirror = mirror_manager.mirror_for_url(&item.url);
+        // Refuse tampered artifacts before spending time downloading them
+        if let Some(hash) = &item.sha256 {
+            cache.check_trust(&item.name, &item.version, &filename, hash, &mirror)?;
+        }
+        let known_blob = item.sha256.as_deref().filter(|hash| cache.blob_path(hash).exists());
+
+        let (hash, unpacked) = if let Some((package, unpacked)) = cache.get_unpacked(&item.name, &item.version, &filename) {
+            if let Some(expected) = item.sha256.as_deref() {
+                if !package.hash.eq_ignore_ascii_case(expected) {
+                    return Err(format!(
+                        "Cached {} (sha256 {}) does not match the digest the index publishes ({}); run 'sa cache verify'",
+                        filename, package.hash, expected
+                    ).into());
+                }
+            }
+            println!("  {} {} (cached)", "⚡".green(), pinned);
+            cache.check_trust(&item.name, &item.version, &package.filename, &package.hash, &package.mirror)?;
+            cache.record_hit(&item.name, &item.version, &package.filename, blob_size(&package.file_path))?;
+            (package.hash, unpacked)
+        } else if let Some(hash) = known_blob {
+            // Same artifact already cached under another index entry
+            println!("  {} {} (deduplicated)", "⚡".green(), pinned);
+            cache.index_artifact(&item.name, &item.version, &filename, hash, &item.url, &mirror)?;
+            cache.record_hit(&item.name, &item.version, &filename, blob_size(&cache.blob_path(hash)))?;
+            (hash.to_string(), cache.ensure_unpacked(hash)?)
+        } else {
+            println!("  {} {}", "⬇️ ".cyan(), pinned);
+            let downloaded = download_artifact(&item.url, &cache.cache_dir.join("downloads")).await?;
+            cache.record_miss(blob_size(&downloaded))?;
+            // Nothing reaches the cache unless it is the file the index describes
+            match &item.sha256 {
+                Some(expected) => {
+                    let actual = sha256_file(&downloaded)?;
+                    if !actual.eq_ignore_ascii_case(expected) {
+                        fs::remove_file(&downloaded)?;
+                        return Err(format!(
+                            "Hash mismatch for {}: the index publishes sha256 {} but the download has {}",
+                            filename, expected, actual
+                        ).into());
+                    }
+                }
+                None => println!("{}", format!("Warning: the index publishes no sha256 for {}; it cannot be verified", filename).yellow()),
+            }
+            if malware.enabled() {
+                let digest = sha256_file(&downloaded)?;
+                if let Err(e) = malware.check(cache, &downloaded, &digest, &pinned).await {
+                    fs::remove_file(&downloaded)?;
+                    return Err(e);
+                }
+            }
+            let hash = cache.store_blob(&downloaded)?;
+            if let Err(e) = cache.check_trust(&item.name, &item.version, &filename, &hash, &mirror) {
+                cache.discard_unreferenced_blob(&hash)?;
+                return Err(e);
+            }
+            cache.index_artifact(&item.name, &item.version, &filename, &hash, &item.url, &mirror)?;
+            let unpacked = cache.ensure_unpacked(&hash)?;
+            (hash, unpacked)
+        };
+        cache.record_project_artifact(&project_dir, &hash)?;
+
+        // Cached artifacts may predate the scanner (or a change to it)
+        if malware.enabled() {
+            let blob = cache.blob_path(&hash);
+            let target = if blob.exists() { blob } else { unpacked.clone() };
+            malware.check(cache, &target, &hash, &pinned).await?;
+        }
+
+        installer.remove_existing(&item.name).await?;
+        installed.extend(installer.install(&unpacked, item.requested)?);
+    }
+
+    if compile {
+        match installer.compile(&installed).await {
+            Ok(count) => println!("  {} byte-compiled {} modules", "⚡".green(), count),
+            Err(e) => println!("{}", format!("Warning: {}", e).yellow()),
+        }
+    }
+
+    Ok(())
+}
 
-    // Install the package using pip in .sa_env
-    let status = tokio::process::Command::new(".sa_env/bin/pip")
-        .args(["install", _package])
+async fn pip_install(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let status = pip_command()
+        .arg("install")
+        .args(args)
         .status()
         .await?;
     if !status.success() {
-        return Err(format!("Failed to install package: {}", _package).into());
+        return Err(format!("Failed to install package: {}", args.join(" ")).into());
     }
     Ok(())
 }
@@ -0,0 +1,119 @@
+use clap_complete::{generate, Shell};
+
+// `sa completions <shell>` prints clap's completion script for the command
+// line, plus what clap cannot generate: installed package names for the
+// arguments that take one. The scripts get them from the hidden
+// `sa completions --installed`, which only lists the environment's
+// dist-info directories, so it stays fast enough to run on every Tab.
+// PowerShell gets the static script only.
+
+/// Subcommands taking an installed package, with the short and long option
+/// that takes it (None for a positional argument)
+const PACKAGE_ARGS: [(&str, Option<(char, &str)>); 2] = [("uninstall", None), ("remove", Some(('p', "package")))];
+
+const BASH_PACKAGES: &str = r#"
+_sa_packages() {
+    local cur="${COMP_WORDS[COMP_CWORD]}" prev="${COMP_WORDS[COMP_CWORD-1]}" subcommand="" i
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        if [[ "${COMP_WORDS[i]}" != -* ]]; then
+            subcommand="${COMP_WORDS[i]}"
+            break
+        fi
+    done
+    if [[ "$cur" != -* ]] && { [[ "$subcommand" == uninstall && "$prev" != -* ]] || [[ "$subcommand" == remove && ( "$prev" == -p || "$prev" == --package ) ]]; }; then
+        COMPREPLY=( $(compgen -W "$(sa completions --installed 2>/dev/null)" -- "$cur") )
+        return 0
+    fi
+    _sa "$@"
+}
+
+if [[ "${BASH_VERSINFO[0]}" -eq 4 && "${BASH_VERSINFO[1]}" -ge 4 || "${BASH_VERSINFO[0]}" -gt 4 ]]; then
+    complete -F _sa_packages -o nosort -o bashdefault -o default sa
+else
+    complete -F _sa_packages -o bashdefault -o default sa
+fi
+"#;
+
+const ZSH_PACKAGES: &str = r#"
+(( $+functions[_sa_installed_packages] )) ||
+_sa_installed_packages() {
+    local -a packages expl
+    packages=(${(f)"$(sa completions --installed 2>/dev/null)"})
+    _wanted packages expl 'installed package' compadd -a packages
+}
+"#;
+
+/// The completion script for `shell`: bash, zsh, fish or powershell
+pub fn script(shell: &str, command: &mut clap::Command) -> Result<String, Box<dyn std::error::Error>> {
+    let kind = match shell {
+        "bash" => Shell::Bash,
+        "zsh" => Shell::Zsh,
+        "fish" => Shell::Fish,
+        "powershell" => Shell::PowerShell,
+        other => return Err(format!("Unsupported shell '{}' (expected bash, zsh, fish or powershell)", other).into()),
+    };
+    let mut buffer = Vec::new();
+    generate(kind, command, "sa", &mut buffer);
+    let script = String::from_utf8(buffer)?;
+
+    Ok(match kind {
+        // Registered after clap's own, the wrapper replaces it
+        Shell::Bash => format!("{}{}", script, BASH_PACKAGES),
+        Shell::Zsh => zsh_with_packages(&script),
+        Shell::Fish => {
+            let mut script = script;
+            for (subcommand, option) in PACKAGE_ARGS {
+                let option = match option {
+                    Some((short, long)) => format!(" -s {} -l {} -r", short, long),
+                    None => String::new(),
+                };
+                script.push_str(&format!(
+                    "complete -c sa -n \"__fish_sa_using_subcommand {}\"{} -f -a \"(sa completions --installed 2>/dev/null)\"\n",
+                    subcommand, option
+                ));
+            }
+            script
+        }
+        _ => script,
+    })
+}
+
+/// clap's zsh script with the package arguments of the top-level
+/// subcommands completed by `_sa_installed_packages`
+fn zsh_with_packages(script: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut current: Option<&str> = None;
+    let mut done: Vec<&str> = Vec::new();
+    for line in script.lines() {
+        let mut line = line.to_string();
+        if let Some(name) = line.strip_prefix('(').and_then(|rest| rest.strip_suffix(')')) {
+            // The first case of a name is the top-level subcommand's; later
+            // ones belong to nested subcommands (`sa mirror remove`)
+            current = PACKAGE_ARGS.iter().map(|(subcommand, _)| *subcommand).find(|subcommand| *subcommand == name && !done.contains(subcommand));
+        } else if line == ";;" {
+            done.extend(current.take());
+        } else if let Some(subcommand) = current {
+            let takes_package = PACKAGE_ARGS.iter().any(|(name, option)| {
+                *name == subcommand
+                    && match option {
+                        Some(_) => line.contains("]:PACKAGE:_default'"),
+                        None => line.starts_with("':package -- "),
+                    }
+            });
+            if takes_package {
+                line = line.replace(":_default'", ":_sa_installed_packages'");
+            }
+        }
+        lines.push(line);
+    }
+
+    // The helper goes before the trailer that runs or registers `_sa`
+    let mut script = lines.join("\n");
+    let trailer = "if [ \"$funcstack[1]\" = \"_sa\" ]; then";
+    match script.rfind(trailer) {
+        Some(at) => script.insert_str(at, &format!("{}\n", ZSH_PACKAGES.trim_start())),
+        None => script.push_str(ZSH_PACKAGES),
+    }
+    script.push('\n');
+    script
+}
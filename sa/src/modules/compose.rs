@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+use crate::modules::config::project_tool_section;
+use crate::modules::models::ServiceSpec;
+
+// Compose files for `sa docker compose`: the project's app image (built from
+// the Dockerfile `sa docker generate` writes) wired to the backing services
+// declared in [tool.sa.services], e.g.
+//
+//   [tool.sa.services]
+//   postgres = { version = "16" }
+//   redis = true
+//   mailpit = { image = "axllent/mailpit", ports = ["8025:8025"] }
+//
+// postgres, redis and mysql get sensible images, credentials, data volumes,
+// health checks and a connection URL in the app's environment; any other
+// service needs an `image`.
+
+/// Defaults for a service SA knows how to wire up
+struct KnownService {
+    image: &'static str,
+    version: &'static str,
+    port: u16,
+    data_dir: Option<&'static str>,
+    environment: &'static [(&'static str, &'static str)],
+    healthcheck: &'static str,
+    /// Variable and value handed to the app
+    url: (&'static str, &'static str),
+}
+
+fn known_service(name: &str) -> Option<KnownService> {
+    match name {
+        "postgres" | "postgresql" => Some(KnownService {
+            image: "postgres",
+            version: "16",
+            port: 5432,
+            data_dir: Some("/var/lib/postgresql/data"),
+            environment: &[("POSTGRES_USER", "app"), ("POSTGRES_PASSWORD", "app"), ("POSTGRES_DB", "app")],
+            healthcheck: "pg_isready -U app",
+            url: ("DATABASE_URL", "postgresql://app:app@{service}:5432/app"),
+        }),
+        "redis" => Some(KnownService {
+            image: "redis",
+            version: "7",
+            port: 6379,
+            data_dir: Some("/data"),
+            environment: &[],
+            healthcheck: "redis-cli ping",
+            url: ("REDIS_URL", "redis://{service}:6379/0"),
+        }),
+        "mysql" => Some(KnownService {
+            image: "mysql",
+            version: "8",
+            port: 3306,
+            data_dir: Some("/var/lib/mysql"),
+            environment: &[("MYSQL_USER", "app"), ("MYSQL_PASSWORD", "app"), ("MYSQL_DATABASE", "app"), ("MYSQL_ROOT_PASSWORD", "app")],
+            healthcheck: "mysqladmin ping -h localhost",
+            url: ("DATABASE_URL", "mysql://app:app@{service}:3306/app"),
+        }),
+        _ => None,
+    }
+}
+
+/// Services declared in [tool.sa.services], by name; `name = true` declares
+/// a known service with its defaults
+pub fn declared_services() -> Result<BTreeMap<String, ServiceSpec>, Box<dyn std::error::Error>> {
+    let Some(section) = project_tool_section("services")? else {
+        return Ok(BTreeMap::new());
+    };
+    let table = section.as_table().ok_or("[tool.sa.services] must be a table")?;
+
+    let mut services = BTreeMap::new();
+    for (name, value) in table {
+        let spec = match value {
+            toml::Value::Boolean(false) => continue,
+            toml::Value::Boolean(true) => ServiceSpec::default(),
+            other => other.clone().try_into().map_err(|e| format!("Invalid [tool.sa.services.{}]: {}", name, e))?,
+        };
+        if spec.image.is_none() && known_service(name).is_none() {
+            return Err(format!("[tool.sa.services.{}] needs an `image`; SA only has defaults for postgres, redis and mysql", name).into());
+        }
+        services.insert(name.clone(), spec);
+    }
+    Ok(services)
+}
+
+/// A compose file running `app_image` (built from the project's Dockerfile)
+/// with the declared services
+pub fn compose_file(app_image: &str, dockerfile: &str, services: &BTreeMap<String, ServiceSpec>) -> String {
+    let mut yaml = String::from("# Generated by `sa docker compose` from [tool.sa.services]\nservices:\n");
+    let mut volumes = Vec::new();
+    let mut app_environment = Vec::new();
+
+    let mut service_blocks = String::new();
+    for (name, spec) in services {
+        let known = known_service(name);
+        let image = match (&spec.image, &known) {
+            (Some(image), _) => image.clone(),
+            (None, Some(known)) => format!("{}:{}", known.image, spec.version.as_deref().unwrap_or(known.version)),
+            (None, None) => continue,
+        };
+        service_blocks.push_str(&format!("  {}:\n    image: {}\n", name, quote(&image)));
+
+        let mut environment: Vec<(String, String)> = known
+            .as_ref()
+            .map(|known| known.environment.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect())
+            .unwrap_or_default();
+        for (key, value) in &spec.environment {
+            environment.retain(|(existing, _)| existing != key);
+            environment.push((key.clone(), value.clone()));
+        }
+        push_environment(&mut service_blocks, &environment);
+
+        let ports: Vec<String> = if spec.ports.is_empty() {
+            known.as_ref().map(|known| vec![format!("{}:{}", known.port, known.port)]).unwrap_or_default()
+        } else {
+            spec.ports.clone()
+        };
+        push_list(&mut service_blocks, "ports", &ports);
+
+        if let Some(data_dir) = known.as_ref().and_then(|known| known.data_dir) {
+            let volume = format!("{}-data", name);
+            push_list(&mut service_blocks, "volumes", &[format!("{}:{}", volume, data_dir)]);
+            volumes.push(volume);
+        }
+        if let Some(known) = &known {
+            service_blocks.push_str(&format!(
+                "    healthcheck:\n      test: [\"CMD-SHELL\", {}]\n      interval: 5s\n      timeout: 5s\n      retries: 10\n",
+                quote(known.healthcheck)
+            ));
+            app_environment.push((known.url.0.to_string(), known.url.1.replace("{service}", name)));
+        }
+    }
+
+    yaml.push_str(&format!("  app:\n    build:\n      context: .\n      dockerfile: {}\n    image: {}\n", quote(dockerfile), quote(app_image)));
+    push_environment(&mut yaml, &app_environment);
+    if !services.is_empty() {
+        yaml.push_str("    depends_on:\n");
+        for name in services.keys() {
+            let condition = if known_service(name).is_some() { "service_healthy" } else { "service_started" };
+            yaml.push_str(&format!("      {}:\n        condition: {}\n", name, condition));
+        }
+    }
+    yaml.push_str(&service_blocks);
+
+    if !volumes.is_empty() {
+        yaml.push_str("volumes:\n");
+        for volume in volumes {
+            yaml.push_str(&format!("  {}:\n", volume));
+        }
+    }
+    yaml
+}
+
+fn push_environment(yaml: &mut String, environment: &[(String, String)]) {
+    if environment.is_empty() {
+        return;
+    }
+    yaml.push_str("    environment:\n");
+    for (key, value) in environment {
+        yaml.push_str(&format!("      {}: {}\n", key, quote(value)));
+    }
+}
+
+fn push_list(yaml: &mut String, key: &str, items: &[String]) {
+    if items.is_empty() {
+        return;
+    }
+    yaml.push_str(&format!("    {}:\n", key));
+    for item in items {
+        yaml.push_str(&format!("      - {}\n", quote(item)));
+    }
+}
+
+/// A double-quoted YAML scalar
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,508 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::fs;
+use dirs;
+use chrono::Duration;
+use colored::*;
+use toml_edit::DocumentMut;
+use crate::modules::models::{SAConfig, VenvConfig, CacheConfig, SecurityConfig, MirrorConfig, NetworkConfig, DockerConfig, SecurityPolicy, SupplyChainPolicy, VersionPolicy, ReleaseConfig};
+
+impl Default for SAConfig {
+    fn default() -> Self {
+        SAConfig {
+            mirrors: Vec::new(),
+            cache_dir: dirs::cache_dir()
+                .unwrap_or_else(|| PathBuf::from("."))
+                .join("sa-cache"),
+            security_enabled: true,
+            docker_enabled: true,
+            default_python_version: "3.11".to_string(),
+            venv: VenvConfig::default(),
+            cache: CacheConfig::default(),
+            security: SecurityConfig::default(),
+            mirror: MirrorConfig::default(),
+            network: NetworkConfig::default(),
+            docker: DockerConfig::default(),
+            repositories: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for DockerConfig {
+    fn default() -> Self {
+        DockerConfig {
+            runtime: "auto".to_string(),
+            host: None,
+            buildkit: true,
+        }
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            max_size: "10GB".to_string(),
+            max_age: "90d".to_string(),
+            auto_gc: true,
+        }
+    }
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        SecurityConfig {
+            db_ttl: "24h".to_string(),
+            artifact_scanner: None,
+        }
+    }
+}
+
+impl Default for MirrorConfig {
+    fn default() -> Self {
+        MirrorConfig {
+            preferred: "default".to_string(),
+            health_check: "1h".to_string(),
+            deactivate_after: 3,
+        }
+    }
+}
+
+impl Default for VersionPolicy {
+    fn default() -> Self {
+        VersionPolicy {
+            files: Vec::new(),
+            tag_format: "v{version}".to_string(),
+        }
+    }
+}
+
+impl Default for ReleaseConfig {
+    fn default() -> Self {
+        ReleaseConfig {
+            changelog: "CHANGELOG.md".to_string(),
+            template: "## {version} ({date})\n\n{changes}\n".to_string(),
+            skip: Vec::new(),
+        }
+    }
+}
+
+impl Default for VenvConfig {
+    fn default() -> Self {
+        VenvConfig {
+            reuse_existing: true,
+            without_pip: false,
+            without_setuptools: false,
+            pip_version: None,
+            setuptools_version: None,
+            ephemeral_max_age_days: 30,
+            link_mode: "auto".to_string(),
+        }
+    }
+}
+
+impl Default for SecurityPolicy {
+    fn default() -> Self {
+        SecurityPolicy {
+            block_severity: "critical".to_string(),
+            allow_skip_security: true,
+            exemptions: Vec::new(),
+            ignore: Vec::new(),
+            typosquatting: "warn".to_string(),
+            supply_chain: SupplyChainPolicy::default(),
+        }
+    }
+}
+
+impl Default for SupplyChainPolicy {
+    fn default() -> Self {
+        SupplyChainPolicy {
+            action: "warn".to_string(),
+            min_age_days: 14,
+            internal_prefixes: Vec::new(),
+        }
+    }
+}
+
+// Global configuration (~/.config/sa/config.toml)
+impl SAConfig {
+    pub fn config_dir() -> PathBuf {
+        dirs::config_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("sa")
+    }
+
+    pub fn config_path() -> PathBuf {
+        Self::config_dir().join("config.toml")
+    }
+
+    /// config.toml with the project's `[tool.sa]` overrides applied
+    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        let mut settings = Self::load_file()?;
+        // A broken pyproject.toml is for the commands working on the
+        // project to report; the others run on the global settings
+        match project_settings() {
+            Ok(overrides) => merge_settings(&mut settings, overrides),
+            Err(e) => eprintln!("{}", format!("Warning: ignoring the [tool.sa] settings: {}", e).yellow()),
+        }
+        let config = toml::Value::Table(settings)
+            .try_into()
+            .map_err(|e| format!("Invalid config file {}: {}", Self::config_path().display(), e))?;
+        Ok(config)
+    }
+
+    /// config.toml as written, empty when there is none
+    pub fn load_file() -> Result<toml::Table, Box<dyn std::error::Error>> {
+        let config_path = Self::config_path();
+
+        if !config_path.exists() {
+            return Ok(toml::Table::new());
+        }
+
+        let content = fs::read_to_string(&config_path)?;
+        let settings = toml::from_str(&content)
+            .map_err(|e| format!("Invalid config file {}: {}", config_path.display(), e))?;
+        Ok(settings)
+    }
+}
+
+/// What a setting holds, checked by `sa config set` and in `[tool.sa]`
+pub enum SettingKind {
+    Text,
+    Flag,
+    Number,
+    /// A size like "10GB"
+    Size,
+    /// A duration like "24h"
+    Duration,
+    /// A duration, or "off"
+    Interval,
+    Choice(&'static [&'static str]),
+}
+
+impl SettingKind {
+    fn expected(&self) -> &'static str {
+        match self {
+            SettingKind::Flag => "true or false",
+            SettingKind::Number => "a whole number",
+            _ => "a string",
+        }
+    }
+}
+
+/// A setting of config.toml that `sa config` manages
+pub struct Setting {
+    /// Dotted key, e.g. "cache.max_size"
+    pub key: &'static str,
+    pub kind: SettingKind,
+    /// Whether a project's `[tool.sa]` may override it. Settings that run
+    /// commands, loosen TLS or concern the whole machine (the shared cache,
+    /// the proxy, the container engine) may not, so a cloned repository
+    /// cannot change them.
+    pub project: bool,
+    pub description: &'static str,
+}
+
+pub const SETTINGS: [Setting; 28] = [
+    Setting { key: "cache_dir", kind: SettingKind::Text, project: false, description: "Directory of the package cache" },
+    Setting { key: "cache.max_size", kind: SettingKind::Size, project: false, description: "Size budget of the cache" },
+    Setting { key: "cache.max_age", kind: SettingKind::Duration, project: false, description: "Evict artifacts not accessed for this long" },
+    Setting { key: "cache.auto_gc", kind: SettingKind::Flag, project: false, description: "Run gc in the background when the cache exceeds its budget" },
+    Setting { key: "venv.reuse_existing", kind: SettingKind::Flag, project: true, description: "Use an existing .venv/venv instead of creating .sa_env" },
+    Setting { key: "venv.without_pip", kind: SettingKind::Flag, project: true, description: "Create environments without pip" },
+    Setting { key: "venv.without_setuptools", kind: SettingKind::Flag, project: true, description: "Remove setuptools if the interpreter seeded it" },
+    Setting { key: "venv.pip_version", kind: SettingKind::Text, project: true, description: "Pin the seeded pip to this version" },
+    Setting { key: "venv.setuptools_version", kind: SettingKind::Text, project: true, description: "Pin (and install) setuptools at this version" },
+    Setting { key: "venv.ephemeral_max_age_days", kind: SettingKind::Number, project: true, description: "Days an unused 'sa run --with' environment is kept" },
+    Setting {
+        key: "venv.link_mode",
+        kind: SettingKind::Choice(&["auto", "reflink", "hardlink", "copy"]),
+        project: true,
+        description: "How cached files are placed into site-packages",
+    },
+    Setting { key: "security.db_ttl", kind: SettingKind::Duration, project: false, description: "Refresh the vulnerability database once it is older than this" },
+    Setting { key: "security.artifact_scanner", kind: SettingKind::Text, project: false, description: "Command run on each artifact before install ({} is the file)" },
+    Setting { key: "mirror.preferred", kind: SettingKind::Text, project: true, description: "\"default\", \"fastest\" or a mirror name" },
+    Setting { key: "mirror.health_check", kind: SettingKind::Interval, project: true, description: "How often mirrors are re-tested before an install, or \"off\"" },
+    Setting { key: "mirror.deactivate_after", kind: SettingKind::Number, project: true, description: "Failed checks in a row after which a mirror is deactivated" },
+    Setting { key: "network.proxy", kind: SettingKind::Text, project: false, description: "HTTP or SOCKS5 proxy for every request" },
+    Setting { key: "network.no_proxy", kind: SettingKind::Text, project: false, description: "Comma-separated hosts that bypass the proxy" },
+    Setting { key: "network.ca_cert", kind: SettingKind::Text, project: false, description: "Extra CA certificates (PEM bundle) to trust" },
+    Setting { key: "network.client_cert", kind: SettingKind::Text, project: false, description: "PEM file with a client certificate and its key" },
+    Setting { key: "network.insecure", kind: SettingKind::Flag, project: false, description: "Skip certificate verification" },
+    Setting { key: "network.connect_timeout", kind: SettingKind::Number, project: true, description: "Seconds to wait for a connection" },
+    Setting { key: "network.read_timeout", kind: SettingKind::Number, project: true, description: "Seconds to wait for data on an open connection" },
+    Setting { key: "network.retries", kind: SettingKind::Number, project: true, description: "Retries of failed connections, timeouts, 429 and 5xx responses" },
+    Setting { key: "network.max_bandwidth", kind: SettingKind::Size, project: true, description: "Download rate cap per transfer (per second)" },
+    Setting { key: "docker.runtime", kind: SettingKind::Choice(&["auto", "docker", "podman"]), project: false, description: "Container engine to talk to" },
+    Setting { key: "docker.host", kind: SettingKind::Text, project: false, description: "Engine endpoint (unix://, tcp:// or ssh://)" },
+    Setting { key: "docker.buildkit", kind: SettingKind::Flag, project: true, description: "Build with BuildKit when buildx is installed" },
+];
+
+/// The setting named `key`, which may be written with dashes as in
+/// pyproject.toml (`cache.max-size`)
+pub fn find_setting(key: &str) -> Result<&'static Setting, Box<dyn std::error::Error>> {
+    let normalized = key.trim().replace('-', "_");
+    SETTINGS
+        .iter()
+        .find(|setting| setting.key == normalized)
+        .ok_or_else(|| format!("Unknown setting '{}'; 'sa config list' shows them all", key).into())
+}
+
+impl Setting {
+    /// `raw` as the TOML value the setting holds, or why it does not fit
+    pub fn parse_value(&self, raw: &str) -> Result<toml::Value, Box<dyn std::error::Error>> {
+        let raw = raw.trim();
+        let value = match &self.kind {
+            SettingKind::Text => toml::Value::String(raw.to_string()),
+            SettingKind::Flag => match raw.to_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => toml::Value::Boolean(true),
+                "false" | "no" | "off" | "0" => toml::Value::Boolean(false),
+                _ => return Err(format!("{} takes true or false, not '{}'", self.key, raw).into()),
+            },
+            SettingKind::Number => {
+                let number: u32 = raw.parse().map_err(|_| format!("{} takes a whole number, not '{}'", self.key, raw))?;
+                toml::Value::Integer(number.into())
+            }
+            SettingKind::Size => {
+                parse_size(raw)?;
+                toml::Value::String(raw.to_string())
+            }
+            SettingKind::Duration => {
+                parse_duration(raw)?;
+                toml::Value::String(raw.to_string())
+            }
+            SettingKind::Interval => {
+                if raw != "off" {
+                    parse_duration(raw)?;
+                }
+                toml::Value::String(raw.to_string())
+            }
+            SettingKind::Choice(choices) => {
+                if !choices.contains(&raw) {
+                    return Err(format!("{} takes {}, not '{}'", self.key, choices.join(", "), raw).into());
+                }
+                toml::Value::String(raw.to_string())
+            }
+        };
+        Ok(value)
+    }
+
+    /// Checks a value read from a file as `parse_value` checks typed ones
+    pub fn check(&self, value: &toml::Value) -> Result<(), Box<dyn std::error::Error>> {
+        let fits = match self.kind {
+            SettingKind::Flag => value.is_bool(),
+            SettingKind::Number => value.is_integer(),
+            _ => value.is_str(),
+        };
+        if !fits {
+            return Err(format!("{} takes {}, not {}", self.key, self.kind.expected(), value).into());
+        }
+        if let Some(number) = value.as_integer().filter(|number| u32::try_from(*number).is_err()) {
+            return Err(format!("{} takes a whole number from 0 to {}, not {}", self.key, u32::MAX, number).into());
+        }
+        if let Some(text) = value.as_str() {
+            self.parse_value(text)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks every setting present in `settings` (laid out as config.toml)
+pub fn check_settings(settings: &toml::Table) -> Result<(), Box<dyn std::error::Error>> {
+    for setting in &SETTINGS {
+        if let Some(value) = lookup(settings, setting.key) {
+            setting.check(value)?;
+        }
+    }
+    Ok(())
+}
+
+/// The value at a dotted key of `table`
+pub fn lookup<'a>(table: &'a toml::Table, key: &str) -> Option<&'a toml::Value> {
+    let mut parts = key.split('.');
+    let mut value = table.get(parts.next()?)?;
+    for part in parts {
+        value = value.as_table()?.get(part)?;
+    }
+    Some(value)
+}
+
+/// Copies `overrides` into `settings`, table by table
+fn merge_settings(settings: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (settings.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(value)) => merge_settings(existing, value),
+            (_, value) => {
+                settings.insert(key, value);
+            }
+        }
+    }
+}
+
+/// The settings the project's `[tool.sa]` overrides (`[tool.sa.venv]`
+/// `link-mode = "copy"`), keyed as in config.toml
+pub fn project_settings() -> Result<toml::Table, Box<dyn std::error::Error>> {
+    let path = std::env::current_dir()?.join("pyproject.toml");
+    let mut overrides = toml::Table::new();
+    let Ok(content) = fs::read_to_string(&path) else {
+        return Ok(overrides);
+    };
+    let pyproject: toml::Value = toml::from_str(&content).map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+    let Some(tool) = pyproject.get("tool").and_then(|tool| tool.get("sa")) else {
+        return Ok(overrides);
+    };
+
+    for setting in SETTINGS.iter().filter(|setting| setting.project) {
+        let Some((section, name)) = setting.key.split_once('.') else { continue };
+        let Some(value) = tool.get(section).and_then(|section| section.get(name).or_else(|| section.get(name.replace('_', "-")))) else {
+            continue;
+        };
+        setting.check(value).map_err(|e| format!("Invalid [tool.sa.{}] in {}: {}", section, path.display(), e))?;
+        let table = overrides.entry(section).or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        if let Some(table) = table.as_table_mut() {
+            table.insert(name.to_string(), value.clone());
+        }
+    }
+    Ok(overrides)
+}
+
+/// Sets a setting in config.toml, keeping the file's comments and layout
+pub fn set_global_setting(key: &str, value: toml::Value) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let path = SAConfig::config_path();
+    let content = if path.exists() { fs::read_to_string(&path)? } else { String::new() };
+    let mut doc: DocumentMut = content
+        .parse()
+        .map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?;
+
+    let mut parts: Vec<&str> = key.split('.').collect();
+    let name = parts.pop().ok_or("Empty setting name")?;
+    let mut table = doc.as_table_mut();
+    for section in parts {
+        table = table
+            .entry(section)
+            .or_insert(toml_edit::table())
+            .as_table_mut()
+            .ok_or_else(|| format!("'{}' in {} is not a table", section, path.display()))?;
+    }
+    table.insert(name, toml_edit::value(edit_value(value)));
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, doc.to_string())?;
+    Ok(path)
+}
+
+/// Sets a project override under `[tool.sa.<section>]` in pyproject.toml,
+/// with a dashed key as the rest of `[tool.sa]`
+pub fn set_project_setting(key: &str, value: toml::Value) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let (section, name) = key.split_once('.').ok_or_else(|| format!("{} cannot be set per project", key))?;
+    update_pyproject(|doc| {
+        let table = tool_table(doc, section)?;
+        // Replace a snake_case spelling rather than keep both
+        table.remove(name);
+        table.insert(&name.replace('_', "-"), toml_edit::value(edit_value(value)));
+        Ok(())
+    })?;
+    Ok(std::env::current_dir()?.join("pyproject.toml"))
+}
+
+fn edit_value(value: toml::Value) -> toml_edit::Value {
+    match value {
+        toml::Value::Boolean(flag) => flag.into(),
+        toml::Value::Integer(number) => number.into(),
+        other => other.as_str().unwrap_or_default().into(),
+    }
+}
+
+/// `[tool.sa.<section>]` from the project's pyproject.toml, if present
+pub fn project_tool_section(section: &str) -> Result<Option<toml::Value>, Box<dyn std::error::Error>> {
+    let path = std::env::current_dir()?.join("pyproject.toml");
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let content = fs::read_to_string(&path)?;
+    let pyproject: toml::Value = toml::from_str(&content)
+        .map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+    Ok(pyproject
+        .get("tool")
+        .and_then(|tool| tool.get("sa"))
+        .and_then(|sa| sa.get(section))
+        .cloned())
+}
+
+/// Edits the project's pyproject.toml in place (creating it if needed),
+/// preserving its formatting and comments
+pub fn update_pyproject<F>(edit: F) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: FnOnce(&mut DocumentMut) -> Result<(), Box<dyn std::error::Error>>,
+{
+    let path = std::env::current_dir()?.join("pyproject.toml");
+    let content = if path.exists() { fs::read_to_string(&path)? } else { String::new() };
+    let mut doc: DocumentMut = content
+        .parse()
+        .map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+
+    edit(&mut doc)?;
+    fs::write(&path, doc.to_string())?;
+    Ok(())
+}
+
+/// `[tool.sa.<section>]` as an editable table, created when missing
+pub fn tool_table<'a>(doc: &'a mut DocumentMut, section: &str) -> Result<&'a mut toml_edit::Table, Box<dyn std::error::Error>> {
+    let mut table = doc.as_table_mut();
+    for key in ["tool", "sa", section] {
+        let item = table.entry(key).or_insert(toml_edit::table());
+        let next = item
+            .as_table_mut()
+            .ok_or_else(|| format!("'{}' in pyproject.toml is not a table", key))?;
+        next.set_implicit(true);
+        table = next;
+    }
+    Ok(table)
+}
+
+/// Parses sizes like "10GB", "512MB", "1.5G" or plain bytes
+pub fn parse_size(value: &str) -> Result<u64, Box<dyn std::error::Error>> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("Invalid size '{}'", value))?;
+    let multiplier: u64 = match unit.trim().to_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1024,
+        "M" | "MB" | "MIB" => 1024 * 1024,
+        "G" | "GB" | "GIB" => 1024 * 1024 * 1024,
+        "T" | "TB" | "TIB" => 1024 * 1024 * 1024 * 1024,
+        other => return Err(format!("Unknown size unit '{}' in '{}'", other, value).into()),
+    };
+
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// Parses durations like "90d", "12h", "2w", "30m" or "45s"
+pub fn parse_duration(value: &str) -> Result<Duration, Box<dyn std::error::Error>> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let number: i64 = number
+        .parse()
+        .map_err(|_| format!("Invalid duration '{}'", value))?;
+    match unit.trim() {
+        "s" => Ok(Duration::seconds(number)),
+        "m" => Ok(Duration::minutes(number)),
+        "h" => Ok(Duration::hours(number)),
+        "" | "d" => Ok(Duration::days(number)),
+        "w" => Ok(Duration::weeks(number)),
+        other => Err(format!("Unknown duration unit '{}' in '{}'", other, value).into()),
+    }
+}
@@ -0,0 +1,172 @@
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+use bollard::{Docker, API_DEFAULT_VERSION};
+use tempfile::TempDir;
+use crate::modules::models::DockerConfig;
+
+/// Seconds bollard waits for an engine response
+const ENGINE_TIMEOUT: u64 = 120;
+/// How long an SSH tunnel gets to come up
+const TUNNEL_TIMEOUT: Duration = Duration::from_secs(15);
+const DOCKER_SOCKET: &str = "/var/run/docker.sock";
+const PODMAN_ROOTFUL_SOCKET: &str = "/run/podman/podman.sock";
+
+// Finds the container engine for the Docker commands. Podman serves the same
+// API, so everything past the connection is engine-agnostic.
+//
+// The endpoint is `[docker] host` in ~/.config/sa/config.toml, else
+// DOCKER_HOST (CONTAINER_HOST for Podman), else the first local socket of
+// the configured runtime: Docker's, then rootless and rootful Podman's.
+// ssh:// endpoints are reached through an `ssh -L` tunnel to a local socket.
+
+/// A connected engine
+pub struct Engine {
+    pub docker: Docker,
+    /// Where the engine was found, for messages
+    pub endpoint: String,
+    /// Keep alive as long as `docker` is used
+    pub tunnel: Option<SshTunnel>,
+}
+
+/// An `ssh -L` process forwarding a local socket, stopped when dropped
+pub struct SshTunnel {
+    process: Child,
+    dir: TempDir,
+}
+
+impl Drop for SshTunnel {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+pub fn connect(config: &DockerConfig) -> Result<Engine, Box<dyn std::error::Error>> {
+    if !matches!(config.runtime.as_str(), "auto" | "docker" | "podman") {
+        return Err(format!("Unknown container runtime '{}' in [docker]; use auto, docker or podman", config.runtime).into());
+    }
+
+    let from_env = |name: &str| std::env::var(name).ok().filter(|value| !value.trim().is_empty());
+    let host = config.host.clone().or_else(|| match config.runtime.as_str() {
+        "podman" => from_env("CONTAINER_HOST"),
+        "docker" => from_env("DOCKER_HOST"),
+        _ => from_env("DOCKER_HOST").or_else(|| from_env("CONTAINER_HOST")),
+    });
+    if let Some(host) = host {
+        return connect_to(&host);
+    }
+
+    let candidates = local_sockets(&config.runtime);
+    if let Some(socket) = candidates.iter().find(|socket| socket.exists()) {
+        return connect_to(&format!("unix://{}", socket.display()));
+    }
+    if cfg!(windows) && config.runtime != "podman" {
+        let docker = Docker::connect_with_local_defaults()?;
+        return Ok(Engine { docker, endpoint: "Docker Desktop".to_string(), tunnel: None });
+    }
+
+    let tried: Vec<String> = candidates.iter().map(|socket| socket.display().to_string()).collect();
+    Err(format!(
+        "No container engine found (tried {}); start Docker or Podman (`systemctl --user start podman.socket`), or set `host` under [docker] in ~/.config/sa/config.toml",
+        tried.join(", ")
+    ).into())
+}
+
+/// Local engine sockets for a runtime, in the order they are tried
+fn local_sockets(runtime: &str) -> Vec<PathBuf> {
+    let mut sockets = Vec::new();
+    if runtime != "podman" {
+        sockets.push(PathBuf::from(DOCKER_SOCKET));
+    }
+    if runtime != "docker" {
+        let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from).or_else(user_runtime_dir);
+        if let Some(dir) = runtime_dir {
+            sockets.push(dir.join("podman").join("podman.sock"));
+        }
+        sockets.push(PathBuf::from(PODMAN_ROOTFUL_SOCKET));
+    }
+    sockets
+}
+
+/// /run/user/<uid>, taking the uid from the home directory's owner
+#[cfg(unix)]
+fn user_runtime_dir() -> Option<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+    let uid = std::fs::metadata(dirs::home_dir()?).ok()?.uid();
+    Some(PathBuf::from(format!("/run/user/{}", uid)))
+}
+
+#[cfg(not(unix))]
+fn user_runtime_dir() -> Option<PathBuf> {
+    None
+}
+
+fn connect_to(host: &str) -> Result<Engine, Box<dyn std::error::Error>> {
+    let docker = if host.starts_with("unix://") || host.starts_with("npipe://") {
+        Docker::connect_with_socket(host, ENGINE_TIMEOUT, API_DEFAULT_VERSION)?
+    } else if host.starts_with("tcp://") || host.starts_with("http://") {
+        Docker::connect_with_http(host, ENGINE_TIMEOUT, API_DEFAULT_VERSION)?
+    } else if let Some(target) = host.strip_prefix("ssh://") {
+        let tunnel = open_tunnel(target)?;
+        let socket = format!("unix://{}", tunnel.dir.path().join("engine.sock").display());
+        let docker = Docker::connect_with_socket(&socket, ENGINE_TIMEOUT, API_DEFAULT_VERSION)?;
+        return Ok(Engine { docker, endpoint: host.to_string(), tunnel: Some(tunnel) });
+    } else {
+        return Err(format!("Unsupported container engine endpoint '{}'; use unix://, tcp://, npipe:// or ssh://", host).into());
+    };
+    Ok(Engine { docker, endpoint: host.to_string(), tunnel: None })
+}
+
+/// Forwards a local socket to the engine socket on an SSH host, given as
+/// `user@host[:port][/path/to/engine.sock]`
+fn open_tunnel(target: &str) -> Result<SshTunnel, Box<dyn std::error::Error>> {
+    let (authority, remote_socket) = match target.find('/') {
+        Some(index) if index + 1 < target.len() => (&target[..index], target[index..].to_string()),
+        Some(index) => (&target[..index], DOCKER_SOCKET.to_string()),
+        None => (target, DOCKER_SOCKET.to_string()),
+    };
+    let (destination, port) = match authority.rsplit_once(':') {
+        Some((destination, port)) if port.chars().all(|c| c.is_ascii_digit()) => (destination, Some(port)),
+        _ => (authority, None),
+    };
+
+    let dir = tempfile::Builder::new().prefix("sa-engine-").tempdir()?;
+    let local_socket = dir.path().join("engine.sock");
+    let mut ssh = Command::new("ssh");
+    ssh.args(["-nNT", "-o", "ExitOnForwardFailure=yes", "-o", "StreamLocalBindUnlink=yes"])
+        .arg("-L")
+        .arg(format!("{}:{}", local_socket.display(), remote_socket));
+    if let Some(port) = port {
+        ssh.args(["-p", port]);
+    }
+    let process = ssh
+        .arg(destination)
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to start ssh for {}: {}", destination, e))?;
+    let mut tunnel = SshTunnel { process, dir };
+
+    wait_for_socket(&mut tunnel, &local_socket, destination)?;
+    Ok(tunnel)
+}
+
+fn wait_for_socket(tunnel: &mut SshTunnel, socket: &Path, destination: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let started = Instant::now();
+    while !socket.exists() {
+        if let Some(status) = tunnel.process.try_wait()? {
+            let mut stderr = String::new();
+            if let Some(mut pipe) = tunnel.process.stderr.take() {
+                use std::io::Read;
+                let _ = pipe.read_to_string(&mut stderr);
+            }
+            return Err(format!("SSH tunnel to {} exited with {}: {}", destination, status, stderr.trim()).into());
+        }
+        if started.elapsed() > TUNNEL_TIMEOUT {
+            return Err(format!("Timed out opening an SSH tunnel to {}", destination).into());
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    Ok(())
+}
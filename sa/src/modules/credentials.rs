@@ -0,0 +1,375 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use regex::Regex;
+use reqwest::{RequestBuilder, Url};
+use crate::modules::models::{Mirror, MirrorAuth, MirrorCredentials, PublishRepository};
+
+/// Service name of SA's entries in the OS keyring
+const KEYRING_SERVICE: &str = "sa";
+const PYPI_UPLOAD_URL: &str = "https://upload.pypi.org/legacy/";
+const TESTPYPI_UPLOAD_URL: &str = "https://test.pypi.org/legacy/";
+const PYPI_INDEX_URL: &str = "https://pypi.org/simple/";
+const TESTPYPI_INDEX_URL: &str = "https://test.pypi.org/simple/";
+
+// Mirror credentials, stored per mirror name in ~/.config/sa/credentials.toml
+// (readable only by the user) so mirrors.json never holds secrets. Values of
+// the form `${NAME}` are read from the environment each time they are used,
+// which keeps CI tokens out of files entirely. Entries written by
+// `sa auth login` keep the secret itself in the OS keyring, and mirrors
+// without an entry fall back to ~/.netrc.
+pub struct CredentialStore {
+    path: PathBuf,
+    entries: BTreeMap<String, MirrorCredentials>,
+}
+
+impl CredentialStore {
+    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        let path = dirs::config_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("sa")
+            .join("credentials.toml");
+
+        let entries = if path.exists() {
+            toml::from_str(&fs::read_to_string(&path)?)
+                .map_err(|e| format!("Invalid credentials file {}: {}", path.display(), e))?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(CredentialStore { path, entries })
+    }
+
+    pub fn get(&self, mirror: &str) -> Option<&MirrorCredentials> {
+        self.entries.get(mirror)
+    }
+
+    pub fn set(&mut self, mirror: &str, credentials: MirrorCredentials) -> Result<(), Box<dyn std::error::Error>> {
+        self.entries.insert(mirror.to_string(), credentials);
+        self.save()
+    }
+
+    /// Forgets a mirror's credentials; returns whether there were any
+    pub fn remove(&mut self, mirror: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let removed = self.entries.remove(mirror).is_some();
+        if removed {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    /// Resolved credentials for a mirror, if it has any
+    pub fn auth_for(&self, mirror: &Mirror) -> Result<Option<MirrorAuth>, Box<dyn std::error::Error>> {
+        let Some(credentials) = self.entries.get(&mirror.name) else {
+            let host = Url::parse(&mirror.url).ok().and_then(|url| url.host_str().map(str::to_string));
+            return Ok(host
+                .and_then(|host| netrc_lookup(&host))
+                .map(|(username, password)| MirrorAuth::Basic { username, password: Some(password) }));
+        };
+        let mirror = mirror.name.as_str();
+
+        if credentials.keyring {
+            let secret = keyring_get(&mirror_account(mirror))?
+                .ok_or_else(|| format!("The keyring has no secret for mirror '{}'; run 'sa auth login {}'", mirror, mirror))?;
+            return Ok(Some(match &credentials.username {
+                Some(username) => MirrorAuth::Basic { username: username.clone(), password: Some(secret) },
+                None => MirrorAuth::Bearer(secret),
+            }));
+        }
+
+        let resolve = |value: &Option<String>| value.as_deref().map(|v| expand_env(v, mirror)).transpose();
+
+        if let Some(token) = resolve(&credentials.token)? {
+            return Ok(Some(MirrorAuth::Bearer(token)));
+        }
+        match resolve(&credentials.username)? {
+            Some(username) => Ok(Some(MirrorAuth::Basic { username, password: resolve(&credentials.password)? })),
+            None if credentials.password.is_some() => Err(format!("Credentials for mirror '{}' have a password but no username", mirror).into()),
+            None => Ok(None),
+        }
+    }
+
+    fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, toml::to_string(&self.entries)?)?;
+        restrict_permissions(&self.path)?;
+        Ok(())
+    }
+}
+
+/// Keyring account holding a mirror's password or token
+pub fn mirror_account(mirror: &str) -> String {
+    format!("mirror:{}", mirror)
+}
+
+/// Keyring account holding an upload token for a repository
+pub fn publish_account(repository: &str) -> String {
+    format!("publish:{}", repository)
+}
+
+/// The upload repositories known without configuration, `pypi` and
+/// `testpypi`, each with its own token variable
+pub fn builtin_repository(name: &str) -> Option<PublishRepository> {
+    let (url, index, variable) = match name {
+        "pypi" => (PYPI_UPLOAD_URL, PYPI_INDEX_URL, "PYPI_TOKEN"),
+        "testpypi" => (TESTPYPI_UPLOAD_URL, TESTPYPI_INDEX_URL, "TEST_PYPI_TOKEN"),
+        _ => return None,
+    };
+    Some(PublishRepository {
+        url: url.to_string(),
+        username: None,
+        password_env: Some(variable.to_string()),
+        index_url: Some(index.to_string()),
+    })
+}
+
+/// Username and password for uploading to `repository`: its variable, then
+/// the keyring (`sa auth login <name> --publish`), then ~/.netrc
+pub fn publish_credentials(name: &str, repository: &PublishRepository) -> Result<Option<(String, String)>, Box<dyn std::error::Error>> {
+    let username = repository.username.clone().unwrap_or_else(|| "__token__".to_string());
+    if let Some(secret) = repository.password_env.as_deref().and_then(|variable| std::env::var(variable).ok()) {
+        return Ok(Some((username, secret)));
+    }
+    if let Some(secret) = keyring_get(&publish_account(name))? {
+        return Ok(Some((username, secret)));
+    }
+    let host = Url::parse(&repository.url).ok().and_then(|url| url.host_str().map(str::to_string));
+    Ok(host.and_then(|host| netrc_lookup(&host)))
+}
+
+/// Adds credentials to a request made by SA itself
+pub fn authorize(request: RequestBuilder, auth: Option<&MirrorAuth>) -> RequestBuilder {
+    match auth {
+        Some(MirrorAuth::Basic { username, password }) => request.basic_auth(username, password.as_ref()),
+        Some(MirrorAuth::Bearer(token)) => request.bearer_auth(token),
+        None => request,
+    }
+}
+
+/// An index URL with credentials embedded, the only form pip accepts.
+/// Tokens go in as the password of the `__token__` user.
+pub fn url_with_credentials(url: &str, auth: Option<&MirrorAuth>) -> Result<String, Box<dyn std::error::Error>> {
+    let Some(auth) = auth else {
+        return Ok(url.to_string());
+    };
+    let mut parsed = Url::parse(url).map_err(|e| format!("Invalid mirror URL {}: {}", url, e))?;
+    let (username, password) = match auth {
+        MirrorAuth::Basic { username, password } => (username.as_str(), password.as_deref()),
+        MirrorAuth::Bearer(token) => ("__token__", Some(token.as_str())),
+    };
+    parsed.set_username(username).map_err(|_| format!("Cannot add credentials to {}", url))?;
+    parsed.set_password(password).map_err(|_| format!("Cannot add credentials to {}", url))?;
+    Ok(parsed.to_string())
+}
+
+/// Replaces `${NAME}` references with environment variables
+fn expand_env(value: &str, mirror: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let pattern = Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}")?;
+    let mut missing = None;
+    let expanded = pattern.replace_all(value, |captures: &regex::Captures| {
+        std::env::var(&captures[1]).unwrap_or_else(|_| {
+            missing.get_or_insert_with(|| captures[1].to_string());
+            String::new()
+        })
+    });
+    match missing {
+        Some(name) => Err(format!("Environment variable {} (used by the credentials of mirror '{}') is not set", name, mirror).into()),
+        None => Ok(expanded.into_owned()),
+    }
+}
+
+/// Reads a secret without echoing it; piped input is read as is
+pub fn read_secret(prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let interactive = io::stdin().is_terminal();
+    if interactive {
+        print!("{}: ", prompt);
+        io::stdout().flush()?;
+        set_echo(false);
+    }
+    let mut secret = String::new();
+    let read = io::stdin().lock().read_line(&mut secret);
+    if interactive {
+        set_echo(true);
+        println!();
+    }
+    read?;
+
+    let secret = secret.trim_end_matches(['\r', '\n']).to_string();
+    if secret.is_empty() {
+        return Err("No secret entered".into());
+    }
+    Ok(secret)
+}
+
+#[cfg(unix)]
+fn set_echo(enabled: bool) {
+    let _ = Command::new("stty").arg(if enabled { "echo" } else { "-echo" }).stdin(Stdio::inherit()).status();
+}
+
+#[cfg(not(unix))]
+fn set_echo(_enabled: bool) {}
+
+/// Command-line front ends to the OS keyring, tried in order
+enum KeyringTool {
+    /// macOS Keychain
+    Security,
+    /// libsecret (GNOME Keyring, KWallet) on Linux
+    SecretTool,
+    /// Python's `keyring` CLI, which also covers Windows Credential Manager
+    PythonKeyring,
+}
+
+fn keyring_tools() -> Vec<KeyringTool> {
+    if cfg!(target_os = "macos") {
+        vec![KeyringTool::Security, KeyringTool::PythonKeyring]
+    } else if cfg!(windows) {
+        vec![KeyringTool::PythonKeyring]
+    } else {
+        vec![KeyringTool::SecretTool, KeyringTool::PythonKeyring]
+    }
+}
+
+impl KeyringTool {
+    fn command(&self, action: &str, account: &str) -> Command {
+        let (program, args): (&str, Vec<&str>) = match (self, action) {
+            (KeyringTool::Security, "get") => ("security", vec!["find-generic-password", "-s", KEYRING_SERVICE, "-a", account, "-w"]),
+            // A trailing -w without a value makes security prompt for the
+            // password, which keeps it out of the argument list
+            (KeyringTool::Security, "set") => ("security", vec!["add-generic-password", "-U", "-s", KEYRING_SERVICE, "-a", account, "-w"]),
+            (KeyringTool::Security, _) => ("security", vec!["delete-generic-password", "-s", KEYRING_SERVICE, "-a", account]),
+            (KeyringTool::SecretTool, "get") => ("secret-tool", vec!["lookup", "service", KEYRING_SERVICE, "account", account]),
+            (KeyringTool::SecretTool, "set") => ("secret-tool", vec!["store", "--label", account, "service", KEYRING_SERVICE, "account", account]),
+            (KeyringTool::SecretTool, _) => ("secret-tool", vec!["clear", "service", KEYRING_SERVICE, "account", account]),
+            (KeyringTool::PythonKeyring, "get") => ("keyring", vec!["get", KEYRING_SERVICE, account]),
+            (KeyringTool::PythonKeyring, "set") => ("keyring", vec!["set", KEYRING_SERVICE, account]),
+            (KeyringTool::PythonKeyring, _) => ("keyring", vec!["del", KEYRING_SERVICE, account]),
+        };
+        let mut command = Command::new(program);
+        command.args(args);
+        command
+    }
+}
+
+/// A secret from the OS keyring; `None` when it is missing or no keyring
+/// tool is installed
+pub fn keyring_get(account: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    for tool in keyring_tools() {
+        let output = match tool.command("get", account).stdin(Stdio::null()).stderr(Stdio::null()).output() {
+            Ok(output) => output,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let secret = String::from_utf8_lossy(&output.stdout).trim_end_matches(['\r', '\n']).to_string();
+        return Ok((output.status.success() && !secret.is_empty()).then_some(secret));
+    }
+    Ok(None)
+}
+
+pub fn keyring_set(account: &str, secret: &str) -> Result<(), Box<dyn std::error::Error>> {
+    for tool in keyring_tools() {
+        let mut child = match tool.command("set", account).stdin(Stdio::piped()).stdout(Stdio::null()).spawn() {
+            Ok(child) => child,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            writeln!(stdin, "{}", secret)?;
+            // security asks for the password twice
+            if matches!(tool, KeyringTool::Security) {
+                writeln!(stdin, "{}", secret)?;
+            }
+        }
+        if !child.wait()?.success() {
+            return Err(format!("Failed to store the secret for {} in the keyring", account).into());
+        }
+        return Ok(());
+    }
+    Err("No OS keyring tool found; install secret-tool (libsecret) or Python's keyring package".into())
+}
+
+/// Deletes a keyring entry; returns whether one existed
+pub fn keyring_delete(account: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    for tool in keyring_tools() {
+        match tool.command("delete", account).stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).status() {
+            Ok(status) => return Ok(status.success()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(false)
+}
+
+/// Login and password for a host from ~/.netrc (or $NETRC), falling back
+/// to its `default` entry
+pub fn netrc_lookup(host: &str) -> Option<(String, String)> {
+    let path = std::env::var_os("NETRC").map(PathBuf::from).or_else(|| {
+        dirs::home_dir().map(|home| home.join(if cfg!(windows) { "_netrc" } else { ".netrc" }))
+    })?;
+    let content = fs::read_to_string(path).ok()?;
+
+    let mut entries: Vec<(Option<String>, Option<String>, Option<String>)> = Vec::new();
+    let mut words: Vec<&str> = Vec::new();
+    let mut in_macro = false;
+    for line in content.lines() {
+        if in_macro {
+            // A macro body runs up to the next blank line
+            in_macro = !line.trim().is_empty();
+            continue;
+        }
+        if line.trim_start().starts_with('#') {
+            continue;
+        }
+        for word in line.split_whitespace() {
+            if word == "macdef" && !matches!(words.last(), Some(&("machine" | "login" | "password" | "account"))) {
+                // The macro's name ends the line, its body follows
+                in_macro = true;
+                break;
+            }
+            words.push(word);
+        }
+    }
+
+    let mut tokens = words.into_iter();
+    while let Some(token) = tokens.next() {
+        match token {
+            "machine" => entries.push((tokens.next().map(str::to_lowercase), None, None)),
+            "default" => entries.push((None, None, None)),
+            "login" => {
+                if let Some(entry) = entries.last_mut() {
+                    entry.1 = tokens.next().map(str::to_string);
+                }
+            }
+            "password" => {
+                if let Some(entry) = entries.last_mut() {
+                    entry.2 = tokens.next().map(str::to_string);
+                }
+            }
+            "account" => {
+                tokens.next();
+            }
+            _ => {}
+        }
+    }
+
+    let host = host.to_lowercase();
+    entries
+        .iter()
+        .find(|(machine, _, _)| machine.as_deref() == Some(host.as_str()))
+        .or_else(|| entries.iter().find(|(machine, _, _)| machine.is_none()))
+        .and_then(|(_, login, password)| Some((login.clone()?, password.clone()?)))
+}
+
+#[cfg(unix)]
+fn restrict_permissions(path: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
+    Ok(())
+}
@@ -0,0 +1,218 @@
+use std::fs;
+use std::path::Path;
+use regex::Regex;
+use crate::modules::cache::sha256_file;
+use crate::modules::installer::normalize_dist_name;
+use crate::modules::models::BuildLock;
+use crate::modules::pep440::{SpecifierSet, Version};
+use crate::modules::pep508::Requirement;
+use crate::modules::upload::Distribution;
+
+const METADATA_VERSIONS: [&str; 7] = ["1.0", "1.1", "1.2", "2.1", "2.2", "2.3", "2.4"];
+const CONTENT_TYPES: [&str; 3] = ["text/plain", "text/x-rst", "text/markdown"];
+
+// What `twine check` and the index would object to, found before anything
+// is uploaded: incomplete or malformed core metadata, a long description
+// PyPI cannot render, file names that do not match the metadata or carry
+// tags indexes refuse, and files that are not what the last `sa build`
+// produced for the project's current version.
+
+/// A problem with a file (or with the release, when `file` is None)
+pub struct Finding {
+    pub file: Option<String>,
+    /// Errors stop the upload; warnings are only reported
+    pub error: bool,
+    pub message: String,
+}
+
+/// Checks every distribution, then the release as a whole against
+/// pyproject.toml and sa.lock in `project_dir`
+pub fn check_release(distributions: &[Distribution], project_dir: &Path) -> Result<Vec<Finding>, Box<dyn std::error::Error>> {
+    let mut findings = Vec::new();
+    for dist in distributions {
+        let mut report = |error: bool, message: String| findings.push(Finding { file: Some(dist.filename.clone()), error, message });
+        check_metadata(dist, &mut report);
+        check_description(dist, &mut report);
+        check_filename(dist, &mut report);
+    }
+    let mut report = |error: bool, message: String| findings.push(Finding { file: None, error, message });
+
+    let mut releases: Vec<(String, String)> = distributions.iter().map(|dist| (normalize_dist_name(&dist.name), dist.version.clone())).collect();
+    releases.sort();
+    releases.dedup();
+    if releases.len() > 1 {
+        let listed: Vec<String> = releases.iter().map(|(name, version)| format!("{} {}", name, version)).collect();
+        report(true, format!("dist/ holds files of several releases ({}); remove the stale ones", listed.join(", ")));
+    }
+
+    if let (Some(declared), Some(dist)) = (project_version(project_dir)?, distributions.first()) {
+        if !same_version(&declared, &dist.version) {
+            report(true, format!("pyproject.toml declares version {}, but the files are {}; rebuild with 'sa build'", declared, dist.version));
+        }
+    }
+
+    let lock_path = project_dir.join("sa.lock");
+    if let Some(lock) = fs::read_to_string(&lock_path).ok().and_then(|content| serde_json::from_str::<BuildLock>(&content).ok()) {
+        for dist in distributions {
+            match lock.artifacts.iter().find(|artifact| artifact.filename == dist.filename) {
+                Some(artifact) if artifact.sha256 != sha256_file(&dist.path)? => report(
+                    true,
+                    format!("{} changed since 'sa build' wrote sa.lock ({}); rebuild before publishing", dist.filename, lock.build_time),
+                ),
+                Some(_) => {}
+                None if !lock.artifacts.is_empty() => report(false, format!("{} is not from the last 'sa build' ({})", dist.filename, lock.build_time)),
+                None => {}
+            }
+        }
+    }
+    Ok(findings)
+}
+
+fn check_metadata(dist: &Distribution, report: &mut impl FnMut(bool, String)) {
+    let get = |key: &str| dist.fields.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+    let all = |key: &str| dist.fields.iter().filter(|(k, _)| k == key).map(|(_, v)| v.as_str()).collect::<Vec<_>>();
+
+    match get("metadata-version") {
+        None => report(true, "no Metadata-Version".to_string()),
+        Some(version) if !METADATA_VERSIONS.contains(&version) => report(true, format!("unknown Metadata-Version {}", version)),
+        Some(_) => {}
+    }
+    let valid_name = Regex::new(r"(?i)^([a-z0-9]|[a-z0-9][a-z0-9._-]*[a-z0-9])$").expect("valid pattern");
+    if !valid_name.is_match(&dist.name) {
+        report(true, format!("'{}' is not a valid project name", dist.name));
+    }
+    match Version::parse(&dist.version) {
+        Err(_) => report(true, format!("'{}' is not a PEP 440 version", dist.version)),
+        Ok(version) if !version.local.is_empty() => report(false, format!("version {} has a local label, which PyPI rejects", dist.version)),
+        Ok(_) => {}
+    }
+    match get("summary") {
+        None | Some("") => report(false, "no Summary; the index shows an empty one-line description".to_string()),
+        Some(summary) if summary.contains('\n') => report(true, "Summary spans several lines".to_string()),
+        Some(_) => {}
+    }
+    if let Some(requires_python) = get("requires-python") {
+        if SpecifierSet::parse(requires_python).is_err() {
+            report(true, format!("invalid Requires-Python '{}'", requires_python));
+        }
+    }
+    for requirement in all("requires-dist") {
+        if Requirement::parse(requirement).is_err() {
+            report(true, format!("invalid Requires-Dist '{}'", requirement));
+        }
+    }
+}
+
+fn check_description(dist: &Distribution, report: &mut impl FnMut(bool, String)) {
+    let content_type = dist.fields.iter().find(|(key, _)| key == "description-content-type").map(|(_, value)| value.as_str());
+    let Some(description) = dist.description.as_deref().filter(|description| !description.trim().is_empty()) else {
+        report(false, "no long description; the project page will be empty".to_string());
+        return;
+    };
+    let media_type = content_type.map(|value| value.split(';').next().unwrap_or("").trim().to_lowercase());
+    match media_type.as_deref() {
+        None => report(false, "no Description-Content-Type; PyPI renders the description as reStructuredText".to_string()),
+        Some(media_type) if !CONTENT_TYPES.contains(&media_type) => {
+            report(true, format!("Description-Content-Type '{}' is not one of {}", media_type, CONTENT_TYPES.join(", ")));
+            return;
+        }
+        Some(_) => {}
+    }
+    if matches!(media_type.as_deref(), None | Some("text/x-rst")) {
+        for problem in rst_problems(description) {
+            report(true, format!("the description does not render as reStructuredText: {}", problem));
+        }
+    }
+}
+
+/// The mistakes that most often stop PyPI rendering a reStructuredText
+/// description: short section underlines and unclosed inline literals
+fn rst_problems(text: &str) -> Vec<String> {
+    let mut problems = Vec::new();
+    let lines: Vec<&str> = text.lines().collect();
+    let is_adornment = |line: &str| {
+        let line = line.trim_end();
+        line.len() >= 4 && line.chars().all(|c| c == line.chars().next().unwrap_or(' ')) && line.chars().all(|c| "=-~^\"'`#*+_:.".contains(c))
+    };
+    for (index, pair) in lines.windows(2).enumerate() {
+        let (title, underline) = (pair[0].trim_end(), pair[1]);
+        if !title.trim().is_empty() && !title.starts_with(' ') && !is_adornment(title) && is_adornment(underline) && underline.trim_end().chars().count() < title.chars().count() {
+            problems.push(format!("line {}: title underline too short for '{}'", index + 2, title));
+        }
+    }
+
+    let mut in_literal_block = false;
+    for (index, line) in lines.iter().enumerate() {
+        // Indented blocks after `::` are literal text
+        if in_literal_block && (line.trim().is_empty() || line.starts_with([' ', '\t'])) {
+            continue;
+        }
+        in_literal_block = line.trim_end().ends_with("::");
+        if line.matches("``").count() % 2 == 1 {
+            problems.push(format!("line {}: inline literal start-string without end-string", index + 1));
+        }
+    }
+    problems
+}
+
+fn check_filename(dist: &Distribution, report: &mut impl FnMut(bool, String)) {
+    let escaped = escape(&dist.name);
+    let (stem, kind) = if let Some(stem) = dist.filename.strip_suffix(".whl") {
+        (stem, "wheel")
+    } else if let Some(stem) = dist.filename.strip_suffix(".tar.gz") {
+        (stem, "sdist")
+    } else {
+        report(false, "sdists should be .tar.gz (PEP 625); indexes may refuse .zip".to_string());
+        (dist.filename.trim_end_matches(".zip"), "sdist")
+    };
+
+    let parts: Vec<&str> = if kind == "wheel" { stem.split('-').collect() } else { stem.rsplitn(2, '-').collect::<Vec<_>>().into_iter().rev().collect() };
+    let (name, version) = (parts.first().copied().unwrap_or(""), parts.get(1).copied().unwrap_or(""));
+    if escape(name) != escaped {
+        report(true, format!("the file name says '{}' but the metadata names '{}'", name, dist.name));
+    }
+    if !same_version(version, &dist.version) {
+        report(true, format!("the file name says version {} but the metadata says {}", version, dist.version));
+    }
+    if kind == "sdist" {
+        return;
+    }
+
+    // name-version(-build)?-python-abi-platform
+    if !(5..=6).contains(&parts.len()) {
+        report(true, "not a valid wheel file name (name-version[-build]-python-abi-platform.whl)".to_string());
+        return;
+    }
+    if parts.len() == 6 && !parts[2].starts_with(|c: char| c.is_ascii_digit()) {
+        report(true, format!("build tag '{}' must start with a digit", parts[2]));
+    }
+    let tags = &parts[parts.len() - 3..];
+    if tags.iter().any(|tag| tag.is_empty() || tag.split('.').any(str::is_empty)) {
+        report(true, format!("malformed compatibility tags {}", tags.join("-")));
+    }
+    if let Some(platform) = tags[2].split('.').find(|platform| platform.starts_with("linux_")) {
+        report(true, format!("platform tag {} is refused by PyPI; rebuild it with 'sa build --repair' for a manylinux or musllinux one", platform));
+    }
+}
+
+/// A project name as it appears in file names
+fn escape(name: &str) -> String {
+    normalize_dist_name(name).replace('-', "_")
+}
+
+fn same_version(a: &str, b: &str) -> bool {
+    match (Version::parse(a), Version::parse(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// `project.version` from pyproject.toml, unless it is dynamic
+fn project_version(project_dir: &Path) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let path = project_dir.join("pyproject.toml");
+    let Ok(content) = fs::read_to_string(&path) else {
+        return Ok(None);
+    };
+    let pyproject: toml::Value = toml::from_str(&content).map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+    Ok(pyproject.get("project").and_then(|project| project.get("version")).and_then(|version| version.as_str()).map(str::to_string))
+}
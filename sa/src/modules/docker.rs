@@ -1,44 +1,295 @@
-use std::path::Path;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use std::fs;
 use bollard::Docker;
+use bollard::auth::DockerCredentials;
+use bollard::container::{Config, CreateContainerOptions, ListContainersOptions, RemoveContainerOptions, StartContainerOptions, StopContainerOptions};
+use bollard::exec::{CreateExecOptions, StartExecResults};
+use bollard::models::{DeviceRequest, HostConfig, PortBinding};
+use bollard::volume::{CreateVolumeOptions, RemoveVolumeOptions};
+use chrono::DateTime;
 use futures_util::TryStreamExt;
+use sha2::{Digest, Sha256};
 use tempfile::TempDir;
 use colored::*;
+use crate::modules::cache::{sha256_file, PackageCache};
+use crate::modules::container_runtime::{self, SshTunnel};
+use crate::modules::config::{parse_size, project_tool_section};
+use crate::modules::models::{ContainerSettings, DockerEnvironment, SAConfig};
+use crate::modules::registry::registry_credentials;
+use crate::modules::snapshot::read_lockfile;
+use crate::modules::terminal;
+use crate::modules::venv::{env_bin_dir, env_bin_path, venv_dir};
+
+/// Label carrying the environment name on the container and volume SA
+/// creates for it; environments are discovered through it
+const ENVIRONMENT_LABEL: &str = "sa.environment";
+const BASE_IMAGE_LABEL: &str = "sa.base-image";
+/// Absolute path of the requirements file an environment was built from
+const LOCKFILE_LABEL: &str = "sa.lockfile";
+/// Project directory of a production environment, its build context
+const PROJECT_LABEL: &str = "sa.project";
+/// Platform an environment's container runs, when one was asked for
+const PLATFORM_LABEL: &str = "sa.platform";
+/// Hash of the requirements file an image was built from, on the image
+const LOCKFILE_HASH_LABEL: &str = "sa.lockfile-sha256";
+/// CFS period the CPU quota of `--cpus` is a share of, in microseconds
+const CPU_PERIOD: i64 = 100_000;
+/// Where an environment's data volume is mounted
+const DATA_MOUNT: &str = "/data";
+/// Where the SA package cache is mounted, read-only
+const CACHE_MOUNT: &str = "/sa-cache";
+/// Where a project directory is mounted, and where commands run in it
+const PROJECT_MOUNT: &str = "/app";
+/// Name of the generated Dockerfile inside a production build context, so a
+/// project's own Dockerfile is left alone
+const GENERATED_DOCKERFILE: &str = "Dockerfile.sa";
+/// Keeps pip's download cache between builds on builders that support it
+const PIP_CACHE_MOUNT: &str = "--mount=type=cache,target=/root/.cache/pip ";
+/// Packages and scripts the base image already ships; exporting the
+/// environment's copies over them would leave two versions installed
+const BASE_IMAGE_PACKAGES: &[&str] = &["pip", "setuptools", "wheel", "pkg_resources", "_distutils_hack", "distutils-precedence.pth"];
 
 // Docker integration
+//
+// An environment is a long-lived container `sa-env-<name>` running the image
+// `<name>` (built from the base image and requirements), with a named volume
+// `sa-env-<name>-data` at /data that survives the container. Packages
+// installed with `sa docker exec` persist until the environment is removed.
+//
+// Podman and remote engines work the same way; see container_runtime.
+//
+// Images are built with BuildKit through the `docker build` CLI when the
+// engine is Docker and buildx is installed: bollard speaks BuildKit's session
+// protocol only with gRPC support SA does not carry. Podman's builder honours
+// the same cache mounts through the API; anything else gets the classic
+// builder and Dockerfiles without cache mounts.
 pub struct DockerManager {
     pub docker: Docker,
+    /// Where the container engine was found
+    pub endpoint: String,
+    /// Whether BuildKit may be used, from [docker] in the SA config
+    buildkit: bool,
+    tunnel: Option<SshTunnel>,
+}
+
+/// How images get built on the connected engine
+#[derive(Clone, Copy, PartialEq)]
+enum BuildBackend {
+    /// `docker build` with BuildKit: parallel stages, cache mounts and its
+    /// own progress display
+    BuildKit,
+    /// Podman's builder behind the Docker API, which supports cache mounts
+    Podman,
+    /// The engine's classic builder
+    Classic,
+}
+
+impl BuildBackend {
+    fn cache_mounts(self) -> bool {
+        self != BuildBackend::Classic
+    }
+}
+
+/// Platforms an image is built for (none: the engine's own), and the
+/// reference it is pushed as
+#[derive(Default)]
+pub struct BuildTarget<'a> {
+    pub platforms: &'a [String],
+    /// Built for several platforms, the image is pushed as a multi-arch
+    /// manifest straight from BuildKit
+    pub push: Option<&'a str>,
 }
 
 impl DockerManager {
     pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let docker = Docker::connect_with_local_defaults()?;
-        Ok(DockerManager { docker })
+        let config = SAConfig::load()?.docker;
+        let engine = container_runtime::connect(&config)?;
+        Ok(DockerManager { docker: engine.docker, endpoint: engine.endpoint, buildkit: config.buildkit, tunnel: engine.tunnel })
     }
 
+    async fn build_backend(&self) -> BuildBackend {
+        let version = self.docker.version().await.ok();
+        let podman = version.is_some_and(|version| {
+            version.platform.is_some_and(|platform| platform.name.contains("Podman"))
+                || version.components.unwrap_or_default().iter().any(|component| component.name.contains("Podman"))
+        });
+        if podman {
+            return BuildBackend::Podman;
+        }
+        let buildx = self.buildkit
+            && tokio::process::Command::new("docker")
+                .args(["buildx", "version"])
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .status()
+                .await
+                .is_ok_and(|status| status.success());
+        if buildx {
+            BuildBackend::BuildKit
+        } else {
+            BuildBackend::Classic
+        }
+    }
+
+    /// Whether the engine runs on this machine and can see its files
+    fn is_local(&self) -> bool {
+        self.tunnel.is_none()
+            && (self.endpoint.starts_with("unix://") || self.endpoint.starts_with("npipe://") || self.endpoint == "Docker Desktop")
+    }
+
+    /// Bind mount of the SA package cache for a container, and the variable
+    /// pointing pip at its artifacts, so installs in the container reuse
+    /// what SA already downloaded. None for remote engines, which cannot
+    /// see this machine's files, or when the cache is unavailable.
+    fn cache_mount(&self) -> Option<(String, String)> {
+        if !self.is_local() {
+            return None;
+        }
+        let cache = PackageCache::new().ok()?;
+        if let Err(e) = cache.refresh_wheelhouse() {
+            println!("{}", format!("Warning: not mounting the SA cache: {}", e).yellow());
+            return None;
+        }
+        Some((
+            format!("{}:{}:ro", cache.cache_dir.display(), CACHE_MOUNT),
+            format!("PIP_FIND_LINKS={}/wheelhouse", CACHE_MOUNT),
+        ))
+    }
+
+    /// Builds the image for an environment, then creates and starts its
+    /// container and data volume. In production mode the image is the
+    /// multi-stage build of the project and its lockfile (`requirements`,
+    /// default requirements.txt) that `sa docker generate` writes. `gpus`
+    /// (see `gpu_request`) gives the container GPUs for its whole life, and
+    /// `settings` its limits, network, published ports, variables and the
+    /// platforms the image is built for. `push` also pushes the image (see
+    /// `BuildTarget`).
+    #[allow(clippy::too_many_arguments)]
     pub async fn create_environment(
         &self,
         name: &str,
         base_image: &str,
         requirements: Option<&str>,
+        production: bool,
+        gpus: Option<&str>,
+        settings: &ContainerSettings,
+        push: Option<&str>,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        let device_requests = gpus.map(gpu_request).transpose()?.map(|request| vec![request]);
+        let (memory, cpu_quota) = resource_limits(settings)?;
+        let ports = settings.ports.iter().map(|spec| parse_port(spec)).collect::<Result<Vec<_>, _>>()?;
+        let container = container_name(name);
+        if self.docker.inspect_container(&container, None).await.is_ok() {
+            return Err(format!("Docker environment '{}' already exists; remove it first with 'sa docker remove {}'", name, name).into());
+        }
         println!("{}", format!("🐳 Creating Docker environment '{}'...", name).cyan());
+        let mut labels = environment_labels(name, base_image);
+        let target = BuildTarget { platforms: &settings.platforms, push };
+        let platform = if production {
+            let lockfile = Path::new(requirements.unwrap_or("requirements.txt"));
+            let platform = self.build_production_image(name, base_image, lockfile, &target).await?;
+            labels.insert(LOCKFILE_LABEL.to_string(), lockfile.canonicalize()?.to_string_lossy().to_string());
+            labels.insert(PROJECT_LABEL.to_string(), std::env::current_dir()?.canonicalize()?.to_string_lossy().to_string());
+            platform
+        } else {
+            let platform = self.build_image(name, base_image, requirements, &target).await?;
+            if let Some(lockfile) = requirements.map(Path::new).filter(|path| path.exists()) {
+                labels.insert(LOCKFILE_LABEL.to_string(), lockfile.canonicalize()?.to_string_lossy().to_string());
+            }
+            platform
+        };
+        if let Some(platform) = &platform {
+            labels.insert(PLATFORM_LABEL.to_string(), platform.clone());
+        }
+
+        let volume = volume_name(name);
+        self.docker
+            .create_volume(CreateVolumeOptions {
+                name: volume.clone(),
+                labels: environment_labels(name, base_image),
+                ..Default::default()
+            })
+            .await?;
+
+        let mut port_bindings: HashMap<String, Option<Vec<PortBinding>>> = HashMap::new();
+        for (port, binding) in ports {
+            port_bindings.entry(port).or_default().get_or_insert_with(Vec::new).push(binding);
+        }
+        let mut binds = vec![format!("{}:{}", volume, DATA_MOUNT)];
+        let mut env = Vec::new();
+        if let Some((bind, variable)) = self.cache_mount() {
+            binds.push(bind);
+            env.push(variable);
+        }
+        // Variables given for the environment come last and win
+        env.extend(environment_assignments(&settings.environment));
+        let config = Config {
+            image: Some(name.to_string()),
+            cmd: Some(vec!["sleep".to_string(), "infinity".to_string()]),
+            working_dir: Some("/app".to_string()),
+            labels: Some(labels),
+            env: Some(env),
+            exposed_ports: Some(port_bindings.keys().map(|port| (port.clone(), HashMap::new())).collect()),
+            host_config: Some(HostConfig {
+                binds: Some(binds),
+                device_requests,
+                memory,
+                cpu_period: cpu_quota.map(|_| CPU_PERIOD),
+                cpu_quota,
+                network_mode: settings.network.clone(),
+                port_bindings: Some(port_bindings),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let options = CreateContainerOptions { name: container.as_str(), platform: platform.as_deref() };
+        self.docker.create_container(Some(options), config).await?;
+        self.docker
+            .start_container(&container, None::<StartContainerOptions<String>>)
+            .await
+            .map_err(|e| explain_start_error(e, gpus))?;
+
+        println!("{}", format!("✅ Environment '{}' created successfully", name).green());
+        Ok(())
+    }
+
+    /// Builds an image from a base image and an optional requirements file.
+    /// The requirements are installed in their own layers, keyed by the
+    /// file's hash, and earlier builds of the same tag seed the layer cache,
+    /// so rebuilding with unchanged requirements reinstalls nothing. The
+    /// image is labelled with the file's hash. Returns the platform the
+    /// image was built for when `target` names any (see `run_build`).
+    pub async fn build_image(
+        &self,
+        name: &str,
+        base_image: &str,
+        requirements: Option<&str>,
+        target: &BuildTarget<'_>,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let backend = self.build_backend().await;
+        let cache_mount = if backend.cache_mounts() { PIP_CACHE_MOUNT } else { "" };
 
         // Create Dockerfile content
         let mut dockerfile_content = format!(
             "FROM {}\n\
              WORKDIR /app\n\
-             RUN pip install --upgrade pip\n",
-            base_image
+             RUN {}pip install --upgrade pip\n",
+            base_image, cache_mount
         );
 
+        let mut requirements_hash = None;
         if let Some(req_file) = requirements {
             if Path::new(req_file).exists() {
+                let hash = sha256_file(Path::new(req_file))?;
+                // Copied into the build context as requirements.txt below
                 dockerfile_content.push_str(&format!(
-                    "COPY {} /app/requirements.txt\n\
-                     RUN pip install -r requirements.txt\n",
-                    req_file
+                    "ARG REQUIREMENTS_SHA256={}\n\
+                     COPY requirements.txt /app/requirements.txt\n\
+                     RUN {}pip install -r requirements.txt\n",
+                    hash, cache_mount
                 ));
+                requirements_hash = Some(hash);
             }
         }
 
@@ -57,65 +308,834 @@ impl DockerManager {
         }
 
         // Build image
-        use bollard::image::BuildImageOptions;
+        let mut tar_builder = tar::Builder::new(Vec::new());
+        tar_builder.append_dir_all(".", temp_dir.path())?;
+        let tar_data = tar_builder.into_inner()?;
 
-        let options = BuildImageOptions {
-            dockerfile: "Dockerfile",
-            t: name,
-            rm: true,
-            ..Default::default()
-        };
+        let labels: HashMap<&str, &str> = requirements_hash.iter().map(|hash| (LOCKFILE_HASH_LABEL, hash.as_str())).collect();
+        self.run_build(backend, name, "Dockerfile", &labels, tar_data, target).await
+    }
 
+    /// Builds the production image of the current project: dependencies from
+    /// the lockfile in their own stage, then the project sources on a clean
+    /// base image run by a non-root user; nothing used to install the
+    /// dependencies reaches the final image. The image is labelled with the
+    /// lockfile's hash.
+    pub async fn build_production_image(
+        &self,
+        name: &str,
+        base_image: &str,
+        lockfile: &Path,
+        target: &BuildTarget<'_>,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let backend = self.build_backend().await;
+        let dockerfile = project_dockerfile(lockfile, base_image, None, backend.cache_mounts())?;
+        let lockfile_hash = sha256_file(lockfile)?;
+        println!(
+            "{}",
+            format!("📦 Building production image '{}' ({} pinned packages)...", name, dockerfile.packages).cyan()
+        );
+
+        let project_dir = std::env::current_dir()?;
         let mut tar_builder = tar::Builder::new(Vec::new());
-        tar_builder.append_dir_all(".", temp_dir.path())?;
+        append_build_context(&mut tar_builder, &project_dir, Path::new(""))?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(dockerfile.content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar_builder.append_data(&mut header, GENERATED_DOCKERFILE, dockerfile.content.as_bytes())?;
         let tar_data = tar_builder.into_inner()?;
 
-        let mut stream = self.docker.build_image(options, None, Some(tar_data.into()));
+        let labels = HashMap::from([(LOCKFILE_HASH_LABEL, lockfile_hash.as_str())]);
+        self.run_build(backend, name, GENERATED_DOCKERFILE, &labels, tar_data, target).await
+    }
 
-        while let Some(msg) = stream.try_next().await? {
-            if let Some(stream) = msg.stream {
-                print!("{}", stream);
+    /// Builds and tags an image from a tar build context. Earlier builds of
+    /// the same tag seed the layer cache.
+    ///
+    /// With platforms in `target` the image is built for the engine's own
+    /// platform when listed, else the first (run under emulation), which is
+    /// returned. Several platforms are first all built by BuildKit, pushed
+    /// as one manifest when `target.push` is set; a single-platform image is
+    /// pushed after the build.
+    async fn run_build(
+        &self,
+        backend: BuildBackend,
+        name: &str,
+        dockerfile: &str,
+        labels: &HashMap<&str, &str>,
+        context: Vec<u8>,
+        target: &BuildTarget<'_>,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let platform = self.local_platform(target.platforms).await;
+        let manifest = target.platforms.len() > 1;
+        if manifest {
+            if backend != BuildBackend::BuildKit {
+                return Err("Building for several platforms needs BuildKit: install docker buildx and keep `buildkit` on under [docker]".into());
             }
+            let platforms = target.platforms.join(",");
+            println!("{}", format!("🏗️  Building for {}...", platforms).cyan());
+            // Without a registry to push to, the other platforms stay in
+            // the build cache: they are checked to build, and a later push
+            // reuses them
+            let output: &[&str] = if target.push.is_some() { &["--push"] } else { &["--output", "type=cacheonly"] };
+            self.run_buildkit(target.push.unwrap_or(name), dockerfile, labels, &context, Some(&platforms), output)
+                .await
+                .map_err(|e| format!("{}; building several platforms needs a builder that supports it, e.g. `docker buildx create --use`", e))?;
+        }
+        if let Some(platform) = &platform {
+            println!("{}", format!("🏗️  Building '{}' for {}...", name, platform).cyan());
         }
 
-        println!("{}", format!("✅ Environment '{}' created successfully", name).green());
+        if backend == BuildBackend::BuildKit {
+            self.run_buildkit(name, dockerfile, labels, &context, platform.as_deref(), &["--load"]).await?;
+        } else {
+            use bollard::image::BuildImageOptions;
+            let options = BuildImageOptions {
+                dockerfile,
+                t: name,
+                rm: true,
+                cachefrom: vec![name],
+                labels: labels.clone(),
+                platform: platform.as_deref().unwrap_or_default(),
+                ..Default::default()
+            };
+            let mut stream = self.docker.build_image(options, None, Some(context.into()));
+
+            while let Some(msg) = stream.try_next().await? {
+                if let Some(stream) = msg.stream {
+                    print!("{}", stream);
+                }
+                if let Some(error) = msg.error {
+                    return Err(format!("Image build failed: {}", error).into());
+                }
+            }
+        }
+
+        match target.push {
+            Some(reference) if manifest => println!("{}", format!("✅ Pushed the multi-arch manifest {}", reference).green()),
+            Some(reference) => {
+                use bollard::image::TagImageOptions;
+                let (repo, tag) = split_reference(reference);
+                self.docker.tag_image(name, Some(TagImageOptions { repo, tag })).await?;
+                self.push_image(reference, registry_credentials(reference, None)?).await?;
+            }
+            None => {}
+        }
+        Ok(platform)
+    }
+
+    /// The platform of `platforms` to build the local image for: the
+    /// engine's own when listed, else the first
+    async fn local_platform(&self, platforms: &[String]) -> Option<String> {
+        let first = platforms.first()?;
+        let native = self.engine_platform().await;
+        let local = platforms
+            .iter()
+            .find(|platform| native.as_ref().is_some_and(|native| platform.starts_with(native.as_str())))
+            .unwrap_or(first);
+        if native.is_some_and(|native| !local.starts_with(&native)) {
+            println!("{}", format!("Warning: the engine runs {} images under emulation, which is slower", local).yellow());
+        }
+        Some(local.clone())
+    }
+
+    /// The platform the engine runs natively, e.g. linux/amd64
+    pub async fn engine_platform(&self) -> Option<String> {
+        let version = self.docker.version().await.ok()?;
+        Some(format!("{}/{}", version.os.unwrap_or_default(), version.arch.unwrap_or_default()))
+    }
+
+    /// Builds with `docker build` and BuildKit, feeding the context on stdin.
+    /// `output` holds the options saying where the result goes.
+    async fn run_buildkit(
+        &self,
+        tag: &str,
+        dockerfile: &str,
+        labels: &HashMap<&str, &str>,
+        context: &[u8],
+        platform: Option<&str>,
+        output: &[&str],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        use tokio::io::AsyncWriteExt;
+
+        let mut build = tokio::process::Command::new("docker");
+        // The CLI reaches the same engine; ssh:// endpoints it tunnels itself
+        if self.endpoint.contains("://") {
+            build.env("DOCKER_HOST", &self.endpoint);
+        }
+        build
+            .env("DOCKER_BUILDKIT", "1")
+            .args(["build", "--tag", tag, "--file", dockerfile, "--cache-from", tag])
+            .args(["--build-arg", "BUILDKIT_INLINE_CACHE=1"])
+            .args(output);
+        if let Some(platform) = platform {
+            build.args(["--platform", platform]);
+        }
+        for (key, value) in labels {
+            build.arg("--label").arg(format!("{}={}", key, value));
+        }
+        let mut child = build
+            .arg("-")
+            .stdin(std::process::Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to run docker build: {}", e))?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(context).await?;
+        }
+        let status = child.wait().await?;
+        if !status.success() {
+            return Err(format!("Image build failed (docker build exited with {})", status).into());
+        }
         Ok(())
     }
 
-    pub async fn list_environments(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-        use bollard::image::ListImagesOptions;
+    /// The image `sa run --docker` uses for a base image and `--with`
+    /// dependencies, built on first use and kept as `sa-run-<hash>` so later
+    /// runs with the same dependencies start right away
+    pub async fn run_image(&self, base_image: &str, with: &[String]) -> Result<String, Box<dyn std::error::Error>> {
+        let key = hex::encode(Sha256::digest(format!("{}\n{}", base_image, with.join("\n"))));
+        let name = format!("sa-run-{}", &key[..12]);
+        if self.docker.inspect_image(&name).await.is_ok() {
+            return Ok(name);
+        }
+
+        let with_file = tempfile::Builder::new().suffix(".txt").tempfile()?;
+        fs::write(with_file.path(), with.join("\n"))?;
+        let with_path = with_file.path().to_string_lossy().to_string();
+        let requirements = (!with.is_empty()).then_some(with_path.as_str());
+        self.build_image(&name, base_image, requirements, &BuildTarget::default()).await?;
+        Ok(name)
+    }
 
-        let options = ListImagesOptions::<String> {
-            all: false,
+    /// Packages the project environment as the image `reference` without a
+    /// Dockerfile: a container of `base_image` (default: the slim image of
+    /// the environment's Python) receives the environment's packages and
+    /// scripts under /usr/local and the project at /app, and is committed
+    pub async fn export_environment(
+        &self,
+        reference: &str,
+        base_image: Option<&str>,
+        command: Option<&str>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let layout = EnvironmentLayout::read().await?;
+        if std::env::consts::OS != "linux" {
+            println!(
+                "{}",
+                format!("Warning: compiled packages from this {} environment will not load in a Linux image", std::env::consts::OS).yellow()
+            );
+        }
+        let base_image = base_image.map(str::to_string).unwrap_or_else(|| format!("python:{}-slim", layout.python));
+        self.ensure_image(&base_image, None).await?;
+
+        println!("{}", format!("📦 Packaging {} (Python {}) onto {}...", venv_dir().display(), layout.python, base_image).cyan());
+        let layer = layout.layer(&std::env::current_dir()?)?;
+
+        let container = format!("sa-export-{}", uuid::Uuid::new_v4());
+        let config = Config { image: Some(base_image.as_str()), ..Default::default() };
+        let options = CreateContainerOptions { name: container.as_str(), ..Default::default() };
+        self.docker.create_container(Some(options), config).await?;
+        let committed = self.commit_layer(&container, reference, layer, command).await;
+
+        let options = RemoveContainerOptions { force: true, ..Default::default() };
+        self.docker.remove_container(&container, Some(options)).await?;
+        committed
+    }
+
+    async fn commit_layer(&self, container: &str, reference: &str, layer: Vec<u8>, command: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+        use bollard::container::UploadToContainerOptions;
+        use bollard::image::CommitContainerOptions;
+
+        let options = UploadToContainerOptions { path: "/", ..Default::default() };
+        self.docker.upload_to_container(container, Some(options), layer.into()).await?;
+
+        let (repo, tag) = split_reference(reference);
+        let options = CommitContainerOptions {
+            container,
+            repo,
+            tag,
+            comment: "Exported by sa docker export",
             ..Default::default()
         };
+        let config = Config {
+            cmd: Some(command.unwrap_or("python").split_whitespace().collect()),
+            working_dir: Some(PROJECT_MOUNT),
+            env: Some(vec!["PYTHONUNBUFFERED=1"]),
+            ..Default::default()
+        };
+        self.docker.commit_container(options, config).await?;
+        Ok(())
+    }
+
+    /// Pulls an image unless the engine already has it. For another
+    /// `platform` it is always pulled, as the local one may be of the
+    /// engine's own.
+    pub async fn ensure_image(&self, image: &str, platform: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+        if platform.is_none() && self.docker.inspect_image(image).await.is_ok() {
+            return Ok(());
+        }
+        let target = platform.map(|platform| format!(" for {}", platform)).unwrap_or_default();
+        println!("{}", format!("⬇️  Pulling {}{}...", image, target).cyan());
+
+        use bollard::image::CreateImageOptions;
+        let options = CreateImageOptions { from_image: image, platform: platform.unwrap_or_default(), ..Default::default() };
+        let mut stream = self.docker.create_image(Some(options), None, None);
+        while let Some(info) = stream.try_next().await? {
+            if let Some(error) = info.error {
+                return Err(format!("Failed to pull {}: {}", image, error).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes an image to a tarball `docker load` accepts (an OCI layout on
+    /// Docker 25 and later)
+    pub async fn save_image(&self, reference: &str, output: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        use std::io::Write;
+        let mut file = fs::File::create(output).map_err(|e| format!("Failed to create {}: {}", output.display(), e))?;
+        let mut stream = self.docker.export_image(reference);
+        while let Some(chunk) = stream.try_next().await? {
+            file.write_all(&chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Tags an environment's image as `reference` and pushes it
+    pub async fn push_environment(
+        &self,
+        name: &str,
+        reference: &str,
+        credentials: Option<DockerCredentials>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        use bollard::image::TagImageOptions;
+        self.environment_container(name).await?;
+        let (repo, tag) = split_reference(reference);
+        self.docker.tag_image(name, Some(TagImageOptions { repo, tag })).await?;
+        self.push_image(reference, credentials).await
+    }
+
+    /// Pushes an image to the registry its reference names, showing layer
+    /// progress on one line when stdout is a terminal
+    pub async fn push_image(&self, reference: &str, credentials: Option<DockerCredentials>) -> Result<(), Box<dyn std::error::Error>> {
+        use std::io::{IsTerminal, Write};
+        use bollard::image::PushImageOptions;
+
+        let (repo, tag) = split_reference(reference);
+        let interactive = std::io::stdout().is_terminal();
+        let mut stream = self.docker.push_image(repo, Some(PushImageOptions { tag }), credentials);
+        let (mut pushed, mut present) = (0, 0);
+        while let Some(info) = stream.try_next().await? {
+            if let Some(error) = info.error {
+                if interactive {
+                    println!();
+                }
+                let hint = if error.contains("denied") || error.contains("unauthorized") || error.contains("authentication required") {
+                    "; log in with 'docker login' or pass --username"
+                } else {
+                    ""
+                };
+                return Err(format!("Failed to push {}: {}{}", reference, error, hint).into());
+            }
+            let status = info.status.unwrap_or_default();
+            match status.as_str() {
+                "Pushed" => pushed += 1,
+                "Layer already exists" => present += 1,
+                mounted if mounted.starts_with("Mounted from") => present += 1,
+                "Preparing" | "Waiting" | "Pushing" => {}
+                // The repository line at the start and the digest at the end
+                other if info.progress.is_none() && !other.is_empty() => {
+                    if interactive {
+                        print!("\r\x1b[2K");
+                    }
+                    println!("  {}", other);
+                }
+                _ => {}
+            }
+            if interactive {
+                print!(
+                    "\r\x1b[2K  ⬆️  {} layers pushed, {} already in the registry {}",
+                    pushed,
+                    present,
+                    info.progress.unwrap_or_default()
+                );
+                std::io::stdout().flush()?;
+            }
+        }
+        if interactive {
+            println!();
+        }
+        Ok(())
+    }
 
-        let images = self.docker.list_images(Some(options)).await?;
-        let mut environments = Vec::new();
+    /// Rebuilds an environment's image when its requirements file changed
+    /// since the last build, reusing cached layers, then recreates the
+    /// container on it with the same settings and data volume. Returns
+    /// whether anything was rebuilt.
+    pub async fn sync_environment(&self, name: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let container = self.environment_container(name).await?;
+        let details = self.docker.inspect_container(&container, None).await?;
+        let config = details.config.unwrap_or_default();
+        let labels = config.labels.clone().unwrap_or_default();
+        let lockfile = labels
+            .get(LOCKFILE_LABEL)
+            .map(PathBuf::from)
+            .ok_or_else(|| format!("Environment '{}' was created without a requirements file; there is nothing to sync", name))?;
+        if !self.is_stale(name, &lockfile).await? {
+            return Ok(false);
+        }
 
-        for image in images {
-            for tag in image.repo_tags {
-                if !tag.contains(':') || tag.ends_with(":latest") {
-                    environments.push(tag.replace(":latest", ""));
+        let base_image = labels.get(BASE_IMAGE_LABEL).cloned().unwrap_or_else(|| "python:3.11-slim".to_string());
+        // Only the platform the container runs is rebuilt
+        let platforms: Vec<String> = labels.get(PLATFORM_LABEL).cloned().into_iter().collect();
+        let target = BuildTarget { platforms: &platforms, push: None };
+        println!("{}", format!("🔄 {} changed; rebuilding '{}'...", lockfile.display(), name).cyan());
+        match labels.get(PROJECT_LABEL) {
+            Some(project) => {
+                if std::env::current_dir()?.canonicalize()? != Path::new(project) {
+                    return Err(format!("'{}' is a production build of {}; run 'sa docker sync {}' from there", name, project, name).into());
                 }
+                self.build_production_image(name, &base_image, &lockfile, &target).await?;
+            }
+            None => {
+                self.build_image(name, &base_image, Some(&lockfile.to_string_lossy()), &target).await?;
             }
         }
 
+        let running = details.state.and_then(|state| state.running).unwrap_or(false);
+        let options = RemoveContainerOptions { force: true, ..Default::default() };
+        self.docker.remove_container(&container, Some(options)).await?;
+        let config = Config {
+            hostname: None,
+            host_config: details.host_config,
+            ..Config::from(config)
+        };
+        let options = CreateContainerOptions { name: container.as_str(), platform: labels.get(PLATFORM_LABEL).map(String::as_str) };
+        self.docker.create_container(Some(options), config).await?;
+        if running {
+            self.docker.start_container(&container, None::<StartContainerOptions<String>>).await?;
+        }
+        Ok(true)
+    }
+
+    /// Whether an environment's requirements file changed since its image
+    /// was built
+    async fn is_stale(&self, name: &str, lockfile: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+        let current = sha256_file(lockfile).map_err(|e| format!("Failed to read {}: {}", lockfile.display(), e))?;
+        let built = self
+            .docker
+            .inspect_image(name)
+            .await
+            .ok()
+            .and_then(|image| image.config)
+            .and_then(|config| config.labels)
+            .and_then(|labels| labels.get(LOCKFILE_HASH_LABEL).cloned());
+        Ok(built.as_deref() != Some(current.as_str()))
+    }
+
+    /// Every environment SA created, running or not, sorted by name
+    pub async fn list_environments(&self) -> Result<Vec<DockerEnvironment>, Box<dyn std::error::Error>> {
+        let options = ListContainersOptions {
+            all: true,
+            filters: HashMap::from([("label", vec![ENVIRONMENT_LABEL])]),
+            ..Default::default()
+        };
+
+        let mut environments: Vec<DockerEnvironment> = self
+            .docker
+            .list_containers(Some(options))
+            .await?
+            .into_iter()
+            .filter_map(|container| {
+                let labels = container.labels.unwrap_or_default();
+                Some(DockerEnvironment {
+                    name: labels.get(ENVIRONMENT_LABEL)?.clone(),
+                    image: labels.get(BASE_IMAGE_LABEL).cloned().or(container.image).unwrap_or_default(),
+                    state: container.state.unwrap_or_default(),
+                    status: container.status.unwrap_or_default(),
+                    created: container.created.and_then(|created| DateTime::from_timestamp(created, 0)),
+                    lockfile: labels.get(LOCKFILE_LABEL).map(PathBuf::from),
+                    stale: false,
+                })
+            })
+            .collect();
+        for environment in &mut environments {
+            if let Some(lockfile) = &environment.lockfile {
+                // A missing lockfile is not a reason to rebuild
+                environment.stale = self.is_stale(&environment.name, lockfile).await.unwrap_or(false);
+            }
+        }
+        environments.sort_by(|a, b| a.name.cmp(&b.name));
         Ok(environments)
     }
 
-    pub async fn execute_in_environment(
+    pub async fn start_environment(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let container = self.environment_container(name).await?;
+        self.docker.start_container(&container, None::<StartContainerOptions<String>>).await?;
+        Ok(())
+    }
+
+    pub async fn stop_environment(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let container = self.environment_container(name).await?;
+        self.docker.stop_container(&container, Some(StopContainerOptions { t: 10 })).await?;
+        Ok(())
+    }
+
+    /// Removes an environment's container and image, and its data volume
+    /// unless `keep_data` is set
+    pub async fn remove_environment(&self, name: &str, keep_data: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let container = self.environment_container(name).await?;
+        let options = RemoveContainerOptions { force: true, ..Default::default() };
+        self.docker.remove_container(&container, Some(options)).await?;
+
+        if !keep_data {
+            self.docker.remove_volume(&volume_name(name), Some(RemoveVolumeOptions { force: true })).await?;
+        }
+
+        use bollard::image::RemoveImageOptions;
+        let options = RemoveImageOptions { force: true, ..Default::default() };
+        if let Err(e) = self.docker.remove_image(name, Some(options), None).await {
+            println!("{}", format!("Warning: could not remove image '{}': {}", name, e).yellow());
+        }
+        Ok(())
+    }
+
+    /// Runs a command in an environment's container, starting it if needed.
+    /// Commands share the container's devices, so with `gpus` the
+    /// environment must have been created with GPUs. Limits in `settings`
+    /// that differ from the container's are updated, a network it is not on
+    /// is connected, and its variables are set for the command; published
+    /// ports are fixed at creation and ignored here. SIGINT and SIGTERM are
+    /// forwarded to the command, and its exit code is returned.
+    pub async fn exec_in_environment(
         &self,
         name: &str,
         command: &[String],
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        use bollard::container::{CreateContainerOptions, Config, StartContainerOptions};
+        gpus: Option<&str>,
+        settings: &ContainerSettings,
+    ) -> Result<i64, Box<dyn std::error::Error>> {
+        let container = self.environment_container(name).await?;
+        let details = self.docker.inspect_container(&container, None).await?;
+        let (memory, cpu_quota) = resource_limits(settings)?;
+        // Links what was cached since, for environments that mount the cache
+        if self.is_local() {
+            if let Err(e) = PackageCache::new().and_then(|cache| cache.refresh_wheelhouse()) {
+                println!("{}", format!("Warning: not updating the SA cache's wheelhouse: {}", e).yellow());
+            }
+        }
+        let current = details.host_config.clone().unwrap_or_default();
+        let memory_changed = memory.is_some() && memory != current.memory;
+        let cpus_changed = cpu_quota.is_some() && cpu_quota != current.cpu_quota;
+        if memory_changed || cpus_changed {
+            use bollard::container::UpdateContainerOptions;
+            let options = UpdateContainerOptions::<String> {
+                memory,
+                // Docker's default swap allowance: as much again as the memory
+                memory_swap: memory.map(|memory| memory * 2),
+                cpu_period: cpu_quota.map(|_| CPU_PERIOD),
+                cpu_quota,
+                ..Default::default()
+            };
+            self.docker.update_container(&container, options).await?;
+        }
+        if let Some(network) = &settings.network {
+            let connected = details
+                .network_settings
+                .as_ref()
+                .and_then(|network_settings| network_settings.networks.as_ref())
+                .is_some_and(|networks| networks.contains_key(network));
+            if !connected {
+                use bollard::network::ConnectNetworkOptions;
+                let options = ConnectNetworkOptions { container: container.as_str(), endpoint_config: Default::default() };
+                self.docker.connect_network(network, options).await?;
+            }
+        }
+
+        if let Some(gpus) = gpus {
+            gpu_request(gpus)?;
+            let has_gpus = details
+                .host_config
+                .and_then(|host_config| host_config.device_requests)
+                .is_some_and(|requests| !requests.is_empty());
+            if !has_gpus {
+                return Err(format!(
+                    "Environment '{}' was created without GPUs, and a running container cannot gain them; recreate it with 'sa docker create {} --gpus {}'",
+                    name, name, gpus
+                ).into());
+            }
+        }
+        let running = details.state.and_then(|state| state.running).unwrap_or(false);
+        if !running {
+            self.docker.start_container(&container, None::<StartContainerOptions<String>>).await?;
+        }
+
+        // The engine can only signal a container's main process, so the
+        // command records its PID for signals to be forwarded to it
+        let pid_file = format!("/tmp/sa-exec-{}.pid", uuid::Uuid::new_v4());
+        let mut wrapped = vec!["sh".to_string(), "-c".to_string(), format!("echo $$ > {}; exec \"$@\"", pid_file), "sh".to_string()];
+        wrapped.extend(command.iter().cloned());
 
+        let exec = self
+            .docker
+            .create_exec(
+                &container,
+                CreateExecOptions {
+                    cmd: Some(wrapped),
+                    env: Some(environment_assignments(&settings.environment)),
+                    attach_stdout: Some(true),
+                    attach_stderr: Some(true),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        if let StartExecResults::Attached { mut output, .. } = self.docker.start_exec(&exec.id, None).await? {
+            let mut interrupts = terminal::Interrupts::new()?;
+            let mut interrupted = false;
+            loop {
+                tokio::select! {
+                    log = output.try_next() => match log? {
+                        Some(log) => print!("{}", log),
+                        None => break,
+                    },
+                    signal = interrupts.next() => {
+                        // A second interrupt stops waiting for a clean exit
+                        let signal = if interrupted { "SIGKILL" } else { signal };
+                        interrupted = true;
+                        let kill = format!("kill -{} \"$(cat {})\"", signal.trim_start_matches("SIG"), pid_file);
+                        self.exec_detached(&container, &kill).await?;
+                    }
+                }
+            }
+        }
+        self.exec_detached(&container, &format!("rm -f {}", pid_file)).await?;
+
+        match self.docker.inspect_exec(&exec.id).await?.exit_code {
+            Some(exit_code) => Ok(exit_code),
+            None => Err(format!("Could not get the exit status of the command in environment '{}'", name).into()),
+        }
+    }
+
+    /// Starts a shell command in a container without waiting for it
+    async fn exec_detached(&self, container: &str, script: &str) -> Result<(), Box<dyn std::error::Error>> {
+        use bollard::exec::StartExecOptions;
+        let options = CreateExecOptions { cmd: Some(vec!["sh", "-c", script]), ..Default::default() };
+        let exec = self.docker.create_exec(container, options).await?;
+        let options = StartExecOptions { detach: true, ..Default::default() };
+        self.docker.start_exec(&exec.id, Some(options)).await?;
+        Ok(())
+    }
+
+    /// Opens an interactive shell (or `command`) in a throwaway container of
+    /// an environment's image, with the project mounted at /app and the
+    /// environment's data volume at /data. The local terminal is attached to
+    /// the container's TTY and its size follows the window. Returns the
+    /// command's exit code.
+    pub async fn shell(&self, name: &str, project: &Path, command: &[String], gpus: Option<&str>) -> Result<i64, Box<dyn std::error::Error>> {
+        use std::io::IsTerminal;
+
+        self.environment_container(name).await?;
+        let device_requests = gpus.map(gpu_request).transpose()?.map(|request| vec![request]);
+        let project = project.canonicalize().map_err(|e| format!("Failed to resolve {}: {}", project.display(), e))?;
+        let tty = std::io::stdin().is_terminal() && std::io::stdout().is_terminal();
+        let command: Vec<String> = if command.is_empty() {
+            // bash where the image has it, else sh
+            ["sh", "-c", "command -v bash >/dev/null && exec bash || exec sh"].map(String::from).to_vec()
+        } else {
+            command.to_vec()
+        };
+        let term = std::env::var("TERM").unwrap_or_else(|_| "xterm".to_string());
+
+        let mut binds = vec![
+            format!("{}:{}", project.display(), PROJECT_MOUNT),
+            format!("{}:{}", volume_name(name), DATA_MOUNT),
+        ];
+        let mut env = vec![format!("TERM={}", term)];
+        if let Some((bind, variable)) = self.cache_mount() {
+            binds.push(bind);
+            env.push(variable);
+        }
+
+        let container = format!("sa-shell-{}", uuid::Uuid::new_v4());
+        let config = Config {
+            image: Some(name.to_string()),
+            cmd: Some(command),
+            working_dir: Some(PROJECT_MOUNT.to_string()),
+            env: Some(env),
+            tty: Some(tty),
+            open_stdin: Some(true),
+            stdin_once: Some(true),
+            attach_stdin: Some(true),
+            attach_stdout: Some(true),
+            attach_stderr: Some(true),
+            host_config: Some(HostConfig {
+                binds: Some(binds),
+                device_requests,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let options = CreateContainerOptions { name: container.as_str(), ..Default::default() };
+        self.docker.create_container(Some(options), config).await?;
+
+        let result = self.attach_terminal(&container, tty).await.map_err(|e| explain_start_error(e, gpus));
+
+        let options = RemoveContainerOptions { force: true, ..Default::default() };
+        self.docker.remove_container(&container, Some(options)).await?;
+        result
+    }
+
+    /// Streams the terminal to and from a created container until its
+    /// command exits, returning the exit code
+    async fn attach_terminal(&self, container: &str, tty: bool) -> Result<i64, Box<dyn std::error::Error>> {
+        use std::io::{Read, Write};
+        use bollard::container::{AttachContainerOptions, ResizeContainerTtyOptions};
+        use tokio::io::AsyncWriteExt;
+
+        let options = AttachContainerOptions::<String> {
+            stdin: Some(true),
+            stdout: Some(true),
+            stderr: Some(true),
+            stream: Some(true),
+            ..Default::default()
+        };
+        let attached = self.docker.attach_container(container, Some(options)).await?;
+        self.docker.start_container(container, None::<StartContainerOptions<String>>).await?;
+        let resize = |(width, height)| ResizeContainerTtyOptions { width, height };
+        if let (true, Some(size)) = (tty, terminal::size()) {
+            self.docker.resize_container_tty(container, resize(size)).await?;
+        }
+
+        // A plain thread reads stdin: tokio's stdin would hold up exiting
+        // until the next key press after the shell ends
+        let (keys, mut typed) = tokio::sync::mpsc::channel::<Vec<u8>>(16);
+        std::thread::spawn(move || {
+            let mut stdin = std::io::stdin();
+            let mut buffer = [0u8; 1024];
+            while let Ok(read) = stdin.read(&mut buffer) {
+                if read == 0 || keys.blocking_send(buffer[..read].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let _raw_mode = if tty { Some(terminal::RawMode::enable()?) } else { None };
+        let mut resized = terminal::ResizeEvents::new()?;
+        // With a TTY, Ctrl-C reaches the container as a key press instead, so
+        // only SIGTERM is forwarded
+        let mut interrupts = terminal::Interrupts::new()?;
+        let mut output = attached.output;
+        let mut input = Some(attached.input);
+        let mut stdout = std::io::stdout();
+        loop {
+            tokio::select! {
+                chunk = output.try_next() => match chunk? {
+                    Some(chunk) => {
+                        stdout.write_all(&chunk.into_bytes())?;
+                        stdout.flush()?;
+                    }
+                    None => break,
+                },
+                bytes = typed.recv(), if input.is_some() => match bytes {
+                    Some(bytes) => {
+                        if let Some(writer) = input.as_mut() {
+                            writer.write_all(&bytes).await?;
+                        }
+                    }
+                    // End of input: closing the stream ends the command's stdin
+                    None => {
+                        if let Some(mut writer) = input.take() {
+                            writer.shutdown().await?;
+                        }
+                    }
+                },
+                _ = resized.next(), if tty => {
+                    if let Some(size) = terminal::size() {
+                        self.docker.resize_container_tty(container, resize(size)).await?;
+                    }
+                }
+                signal = interrupts.next() => {
+                    if !tty || signal == "SIGTERM" {
+                        self.kill(container, signal).await;
+                    }
+                }
+            }
+        }
+        self.exit_code(container).await
+    }
+
+    /// Sends a signal to a container's main process; it may have exited already
+    async fn kill(&self, container: &str, signal: &str) {
+        use bollard::container::KillContainerOptions;
+        let _ = self.docker.kill_container(container, Some(KillContainerOptions { signal })).await;
+    }
+
+    /// Waits for a container to exit and returns its exit code
+    async fn exit_code(&self, container: &str) -> Result<i64, Box<dyn std::error::Error>> {
+        use bollard::container::WaitContainerOptions;
+        let mut wait = self.docker.wait_container(container, None::<WaitContainerOptions<String>>);
+        Ok(match wait.try_next().await {
+            Ok(Some(status)) => status.status_code,
+            // A non-zero exit comes back as an error carrying the code
+            Err(bollard::errors::Error::DockerContainerWaitError { code, .. }) => code,
+            Ok(None) => 0,
+            Err(e) => return Err(e.into()),
+        })
+    }
+
+    /// The container of an environment SA created
+    async fn environment_container(&self, name: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let container = container_name(name);
+        let managed = self
+            .docker
+            .inspect_container(&container, None)
+            .await
+            .ok()
+            .and_then(|details| details.config)
+            .and_then(|config| config.labels)
+            .is_some_and(|labels| labels.get(ENVIRONMENT_LABEL).is_some_and(|label| label == name));
+        if !managed {
+            return Err(format!("No Docker environment named '{}'; see 'sa docker list'", name).into());
+        }
+        Ok(container)
+    }
+
+    /// Runs a command in a throwaway container from an image. A project
+    /// directory is bind-mounted at /app, where the command then runs, so it
+    /// sees the live files. `gpus` (see `gpu_request`) passes GPUs through,
+    /// and `environment` is set in the container (e.g. from .env files).
+    /// SIGINT and SIGTERM are forwarded to the container, and the command's
+    /// exit code is returned.
+    pub async fn execute_in_image(
+        &self,
+        name: &str,
+        command: &[String],
+        project: Option<&Path>,
+        gpus: Option<&str>,
+        environment: &[(String, String)],
+    ) -> Result<i64, Box<dyn std::error::Error>> {
         let container_name = format!("sa-exec-{}", uuid::Uuid::new_v4());
 
+        let mut binds = Vec::new();
+        if let Some(dir) = project {
+            let dir = dir.canonicalize().map_err(|e| format!("Failed to resolve {}: {}", dir.display(), e))?;
+            binds.push(format!("{}:{}", dir.display(), PROJECT_MOUNT));
+        }
+        let mut env: Vec<String> = environment.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+        if let Some((bind, variable)) = self.cache_mount() {
+            binds.push(bind);
+            env.push(variable);
+        }
+        let host_config = HostConfig {
+            binds: Some(binds),
+            device_requests: gpus.map(gpu_request).transpose()?.map(|request| vec![request]),
+            ..Default::default()
+        };
         let config = Config {
             image: Some(name),
             cmd: Some(command.iter().map(|s| s.as_str()).collect()),
+            env: Some(env.iter().map(|s| s.as_str()).collect()),
+            working_dir: project.map(|_| PROJECT_MOUNT),
+            host_config: Some(host_config),
             attach_stdout: Some(true),
             attach_stderr: Some(true),
             ..Default::default()
@@ -128,9 +1148,65 @@ impl DockerManager {
 
         self.docker.create_container(Some(options), config).await?;
 
-        self.docker.start_container(&container_name, None::<StartContainerOptions<String>>).await?;
+        if let Err(e) = self.docker.start_container(&container_name, None::<StartContainerOptions<String>>).await {
+            let options = RemoveContainerOptions { force: true, ..Default::default() };
+            let _ = self.docker.remove_container(&container_name, Some(options)).await;
+            return Err(explain_start_error(e, gpus));
+        }
+
+        let exit_code = self.follow_logs(&container_name, None).await;
+
+        // Clean up container
+        let remove_options = RemoveContainerOptions {
+            force: true,
+            ..Default::default()
+        };
+
+        self.docker.remove_container(&container_name, Some(remove_options)).await?;
 
-        // Wait for container to finish and get logs
+        exit_code
+    }
+
+    /// Runs a shell script in a throwaway container of `image` for
+    /// `platform`, with the project directory mounted at /app. Each line of
+    /// output is prefixed with `label`, so several can run at once; the
+    /// script's exit code is returned.
+    pub async fn run_job(
+        &self,
+        image: &str,
+        platform: Option<&str>,
+        script: &str,
+        project: &Path,
+        label: &str,
+    ) -> Result<i64, Box<dyn std::error::Error>> {
+        let container_name = format!("sa-job-{}", uuid::Uuid::new_v4());
+        let project = project.canonicalize().map_err(|e| format!("Failed to resolve {}: {}", project.display(), e))?;
+        let config = Config {
+            image: Some(image),
+            cmd: Some(vec!["sh", "-c", script]),
+            working_dir: Some(PROJECT_MOUNT),
+            host_config: Some(HostConfig { binds: Some(vec![format!("{}:{}", project.display(), PROJECT_MOUNT)]), ..Default::default() }),
+            attach_stdout: Some(true),
+            attach_stderr: Some(true),
+            ..Default::default()
+        };
+        let options = CreateContainerOptions { name: container_name.as_str(), platform };
+        self.docker.create_container(Some(options), config).await?;
+
+        let remove_options = RemoveContainerOptions { force: true, ..Default::default() };
+        if let Err(e) = self.docker.start_container(&container_name, None::<StartContainerOptions<String>>).await {
+            let _ = self.docker.remove_container(&container_name, Some(remove_options)).await;
+            return Err(e.into());
+        }
+        let exit_code = self.follow_logs(&container_name, Some(label)).await;
+        self.docker.remove_container(&container_name, Some(remove_options)).await?;
+        exit_code
+    }
+
+    /// Prints a running container's output until it exits, forwarding
+    /// SIGINT and SIGTERM to it, and returns its exit code. With a `label`
+    /// the output is printed line by line, each prefixed with it.
+    async fn follow_logs(&self, container: &str, label: Option<&str>) -> Result<i64, Box<dyn std::error::Error>> {
         use bollard::container::LogsOptions;
 
         let logs_options = LogsOptions::<String> {
@@ -140,21 +1216,439 @@ impl DockerManager {
             ..Default::default()
         };
 
-        let mut logs_stream = self.docker.logs(&container_name, Some(logs_options));
+        let mut logs_stream = self.docker.logs(container, Some(logs_options));
+        let mut interrupts = terminal::Interrupts::new()?;
+        let mut interrupted = false;
+        let mut partial = String::new();
+        loop {
+            tokio::select! {
+                log = logs_stream.try_next() => match (log?, label) {
+                    (Some(log), None) => print!("{}", log),
+                    (Some(log), Some(label)) => {
+                        partial.push_str(&log.to_string());
+                        while let Some(end) = partial.find('\n') {
+                            println!("{} {}", label.dimmed(), partial[..end].trim_end_matches('\r'));
+                            partial.drain(..=end);
+                        }
+                    }
+                    (None, _) => break,
+                },
+                signal = interrupts.next() => {
+                    // A second interrupt stops waiting for a clean exit
+                    let signal = if interrupted { "SIGKILL" } else { signal };
+                    interrupted = true;
+                    self.kill(container, signal).await;
+                }
+            }
+        }
+        if let (Some(label), false) = (label, partial.is_empty()) {
+            println!("{} {}", label.dimmed(), partial);
+        }
+        self.exit_code(container).await
+    }
+}
 
-        while let Some(log) = logs_stream.try_next().await? {
-            print!("{}", log);
+fn container_name(name: &str) -> String {
+    format!("sa-env-{}", name)
+}
+
+fn volume_name(name: &str) -> String {
+    format!("sa-env-{}-data", name)
+}
+
+/// The device request for a `--gpus` value, in the forms Docker's CLI takes:
+/// `all`, a count (`2`, `count=2`) or `device=<id>[,<id>...]`
+pub fn gpu_request(spec: &str) -> Result<DeviceRequest, Box<dyn std::error::Error>> {
+    let spec = spec.trim().trim_matches(|c| c == '"' || c == '\'');
+    let mut request = DeviceRequest {
+        capabilities: Some(vec![vec!["gpu".to_string()]]),
+        ..Default::default()
+    };
+    let count = spec.strip_prefix("count=").unwrap_or(spec);
+    if spec == "all" || count == "all" {
+        request.count = Some(-1);
+    } else if let Some(count) = count.parse::<i64>().ok().filter(|count| *count > 0) {
+        request.count = Some(count);
+    } else if let Some(ids) = spec.strip_prefix("device=") {
+        let ids: Vec<String> = ids.split(',').map(str::trim).filter(|id| !id.is_empty()).map(str::to_string).collect();
+        if ids.is_empty() {
+            return Err("--gpus device= needs at least one GPU index or UUID".into());
         }
+        request.device_ids = Some(ids);
+    } else {
+        return Err(format!("Invalid --gpus value '{}'; use all, a count, or device=<id>[,<id>...]", spec).into());
+    }
+    Ok(request)
+}
 
-        // Clean up container
-        use bollard::container::RemoveContainerOptions;
-        let remove_options = RemoveContainerOptions {
-            force: true,
-            ..Default::default()
-        };
+/// Container settings from [tool.sa.docker] with `flags` on top: flags
+/// replace the memory, CPU and network settings and add ports and variables
+pub fn container_settings(flags: ContainerSettings) -> Result<ContainerSettings, Box<dyn std::error::Error>> {
+    let mut settings: ContainerSettings = match project_tool_section("docker")? {
+        Some(section) => section.try_into().map_err(|e| format!("Invalid [tool.sa.docker]: {}", e))?,
+        None => ContainerSettings::default(),
+    };
+    settings.memory = flags.memory.or(settings.memory);
+    settings.cpus = flags.cpus.or(settings.cpus);
+    settings.network = flags.network.or(settings.network);
+    settings.ports.extend(flags.ports);
+    settings.environment.extend(flags.environment);
+    if !flags.platforms.is_empty() {
+        settings.platforms = flags.platforms;
+    }
 
-        self.docker.remove_container(&container_name, Some(remove_options)).await?;
+    resource_limits(&settings)?;
+    for spec in &settings.ports {
+        parse_port(spec)?;
+    }
+    for platform in &settings.platforms {
+        check_platform(platform)?;
+    }
+    Ok(settings)
+}
 
-        Ok(())
+/// Checks a platform is written os/arch[/variant], e.g. linux/arm64
+pub fn check_platform(platform: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let parts: Vec<&str> = platform.split('/').collect();
+    if !(2..=3).contains(&parts.len()) || parts.iter().any(|part| part.trim().is_empty()) {
+        return Err(format!("Invalid platform '{}'; use os/arch[/variant], e.g. linux/amd64 or linux/arm64", platform).into());
+    }
+    Ok(())
+}
+
+/// The PyPA image of a manylinux or musllinux `policy` (e.g. manylinux_2_28)
+/// for a Linux platform: quay.io/pypa/manylinux_2_28_aarch64 for linux/arm64
+pub fn manylinux_image(policy: &str, platform: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let arch = match platform.split('/').collect::<Vec<_>>()[..] {
+        ["linux", "amd64" | "x86_64", ..] => "x86_64",
+        ["linux", "arm64" | "aarch64", ..] => "aarch64",
+        ["linux", "386" | "i686", ..] => "i686",
+        ["linux", "ppc64le", ..] => "ppc64le",
+        ["linux", "s390x", ..] => "s390x",
+        ["linux", "arm", "v7"] => "armv7l",
+        _ => return Err(format!("No {} image for platform {}", policy, platform).into()),
+    };
+    Ok(format!("quay.io/pypa/{}_{}", policy, arch))
+}
+
+/// The interpreter of a Python version (3.12, or 3.13t free-threaded) in
+/// the manylinux and musllinux images
+pub fn manylinux_python(version: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let (number, threading) = match version.strip_suffix('t') {
+        Some(number) => (number, "t"),
+        None => (version, ""),
+    };
+    match number.split_once('.') {
+        Some(("3", minor)) if !minor.is_empty() && minor.chars().all(|c| c.is_ascii_digit()) => {
+            Ok(format!("/opt/python/cp3{minor}-cp3{minor}{threading}/bin/python", minor = minor, threading = threading))
+        }
+        _ => Err(format!("Invalid Python version '{}' in --matrix; use versions like 3.12 (or 3.13t, free-threaded)", version).into()),
+    }
+}
+
+/// Variables from `--env` flags: `KEY=VALUE`, or `KEY` to pass on the value
+/// it has here (skipped when unset)
+pub fn parse_env_flags(flags: &[String]) -> Result<BTreeMap<String, String>, Box<dyn std::error::Error>> {
+    let mut environment = BTreeMap::new();
+    for flag in flags {
+        match flag.split_once('=') {
+            Some((key, _)) if key.trim().is_empty() => return Err(format!("Invalid --env '{}'; use KEY=VALUE", flag).into()),
+            Some((key, value)) => {
+                environment.insert(key.trim().to_string(), value.to_string());
+            }
+            None => {
+                if let Ok(value) = std::env::var(flag) {
+                    environment.insert(flag.clone(), value);
+                }
+            }
+        }
+    }
+    Ok(environment)
+}
+
+fn environment_assignments(environment: &BTreeMap<String, String>) -> Vec<String> {
+    environment.iter().map(|(key, value)| format!("{}={}", key, value)).collect()
+}
+
+/// Memory in bytes and CPU quota (per `CPU_PERIOD`) for the settings
+fn resource_limits(settings: &ContainerSettings) -> Result<(Option<i64>, Option<i64>), Box<dyn std::error::Error>> {
+    let memory = settings.memory.as_deref().map(parse_size).transpose()?.map(|bytes| bytes as i64);
+    let cpu_quota = match settings.cpus {
+        Some(cpus) if cpus <= 0.0 || !cpus.is_finite() => return Err(format!("Invalid CPU limit {}; use a positive number like 1.5", cpus).into()),
+        Some(cpus) => Some((cpus * CPU_PERIOD as f64).round() as i64),
+        None => None,
+    };
+    Ok((memory, cpu_quota))
+}
+
+/// The container port key (`8000/tcp`) and host binding of a published
+/// port, `[ip:]host:container[/protocol]` or just `container` for a random
+/// host port
+fn parse_port(spec: &str) -> Result<(String, PortBinding), Box<dyn std::error::Error>> {
+    let invalid = || format!("Invalid port '{}'; use [ip:]host:container[/protocol]", spec);
+    let (ports, protocol) = spec.split_once('/').unwrap_or((spec, "tcp"));
+    let parts: Vec<&str> = ports.split(':').collect();
+    let (host_ip, host_port, container_port) = match parts.as_slice() {
+        [container] => (None, None, *container),
+        [host, container] => (None, Some(*host), *container),
+        [ip, host, container] => (Some(*ip), Some(*host), *container),
+        _ => return Err(invalid().into()),
+    };
+    let is_port = |port: &str| port.parse::<u16>().is_ok_and(|port| port > 0);
+    if !is_port(container_port) || host_port.is_some_and(|port| !port.is_empty() && !is_port(port)) || !matches!(protocol, "tcp" | "udp" | "sctp") {
+        return Err(invalid().into());
+    }
+    let binding = PortBinding {
+        host_ip: host_ip.filter(|ip| !ip.is_empty()).map(str::to_string),
+        host_port: host_port.map(str::to_string),
+    };
+    Ok((format!("{}/{}", container_port, protocol), binding))
+}
+
+/// Points GPU failures at the engine host's setup
+fn explain_start_error(e: impl Into<Box<dyn std::error::Error>>, gpus: Option<&str>) -> Box<dyn std::error::Error> {
+    let e = e.into();
+    let message = e.to_string();
+    if gpus.is_some() && (message.contains("device driver") || message.contains("nvidia")) {
+        return format!("{}; GPU containers need the NVIDIA Container Toolkit on the engine host", message).into();
+    }
+    e
+}
+
+/// Splits `registry/repo:tag` into repository and tag, defaulting to latest
+fn split_reference(reference: &str) -> (&str, &str) {
+    match reference.rsplit_once(':') {
+        Some((repo, tag)) if !tag.contains('/') => (repo, tag),
+        _ => (reference, "latest"),
     }
 }
+
+fn environment_labels(name: &str, base_image: &str) -> HashMap<String, String> {
+    HashMap::from([
+        (ENVIRONMENT_LABEL.to_string(), name.to_string()),
+        (BASE_IMAGE_LABEL.to_string(), base_image.to_string()),
+    ])
+}
+
+/// A Dockerfile generated for the current project
+pub struct ProjectDockerfile {
+    pub content: String,
+    /// Pinned packages the lockfile installs
+    pub packages: usize,
+    /// Whether pip checks every package against the lockfile's hashes
+    pub hashed: bool,
+}
+
+/// The multi-stage Dockerfile for the current project and its lockfile,
+/// which must pin every package and lie inside the project directory.
+/// `cache_mounts` keeps pip's download cache between builds, which needs
+/// BuildKit or Podman.
+pub fn project_dockerfile(
+    requirements: &Path,
+    image: &str,
+    command: Option<&str>,
+    cache_mounts: bool,
+) -> Result<ProjectDockerfile, Box<dyn std::error::Error>> {
+    let pins = read_lockfile(requirements)?.ok_or_else(|| {
+        format!("{} has requirements without an exact pin; the image needs every package pinned (name==version)", requirements.display())
+    })?;
+    let project_dir = std::env::current_dir()?;
+    let lockfile = if requirements.is_absolute() {
+        requirements
+            .strip_prefix(&project_dir)
+            .map_err(|_| format!("{} must be inside the project directory, the build context", requirements.display()))?
+    } else {
+        requirements
+    };
+    let hashed = !pins.is_empty() && pins.iter().all(|pin| !pin.hashes.is_empty());
+    Ok(ProjectDockerfile {
+        content: generate_dockerfile(lockfile, image, command, hashed, cache_mounts),
+        packages: pins.len(),
+        hashed,
+    })
+}
+
+/// A multi-stage Dockerfile: the builder stage installs the pinned lockfile
+/// into /install, and the final stage copies only those packages and the
+/// project onto a clean base image, running as a non-root user. `lockfile`
+/// is relative to the build context.
+fn generate_dockerfile(lockfile: &Path, image: &str, command: Option<&str>, hashed: bool, cache_mounts: bool) -> String {
+    let lockfile = lockfile.to_string_lossy().replace('\\', "/");
+    let pip_options = if hashed { " --require-hashes" } else { "" };
+    let (pip_env, cache_mount) = if cache_mounts {
+        ("PIP_DISABLE_PIP_VERSION_CHECK=1", PIP_CACHE_MOUNT)
+    } else {
+        ("PIP_DISABLE_PIP_VERSION_CHECK=1 PIP_NO_CACHE_DIR=1", "")
+    };
+    let add_user = if image.contains("alpine") {
+        "adduser -D -u 10001 app"
+    } else {
+        "useradd --create-home --uid 10001 app"
+    };
+    let cmd: Vec<String> = command
+        .unwrap_or("python")
+        .split_whitespace()
+        .map(|arg| format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\"")))
+        .collect();
+
+    format!(
+        "# syntax=docker/dockerfile:1\n\
+         # Generated by `sa docker generate` from {lockfile}\n\
+         ARG PYTHON_IMAGE={image}\n\
+         \n\
+         FROM ${{PYTHON_IMAGE}} AS builder\n\
+         ENV {pip_env}\n\
+         WORKDIR /build\n\
+         COPY {lockfile} requirements.txt\n\
+         RUN {cache_mount}pip install{pip_options} --prefix=/install -r requirements.txt\n\
+         \n\
+         FROM ${{PYTHON_IMAGE}}\n\
+         ENV PYTHONDONTWRITEBYTECODE=1 PYTHONUNBUFFERED=1\n\
+         COPY --from=builder /install /usr/local\n\
+         RUN {add_user}\n\
+         WORKDIR /app\n\
+         COPY --chown=app . .\n\
+         USER app\n\
+         CMD [{cmd}]\n",
+        lockfile = lockfile,
+        image = image,
+        pip_env = pip_env,
+        cache_mount = cache_mount,
+        pip_options = pip_options,
+        add_user = add_user,
+        cmd = cmd.join(", "),
+    )
+}
+
+/// Keeps local environments, caches and VCS data out of the build context
+pub const DOCKERIGNORE: &str = ".git\n.sa_env\n.venv\nvenv\n__pycache__\n*.pyc\n.pytest_cache\nbuild\ndist\n*.egg-info\n.env\n";
+
+/// Adds the project directory to a build context under `prefix`, skipping
+/// what its .dockerignore (or else the default list) excludes
+fn append_build_context(tar_builder: &mut tar::Builder<Vec<u8>>, project_dir: &Path, prefix: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let ignore = fs::read_to_string(project_dir.join(".dockerignore")).unwrap_or_else(|_| DOCKERIGNORE.to_string());
+    let patterns: Vec<&str> = ignore
+        .lines()
+        .map(|line| line.trim().trim_start_matches('/').trim_end_matches('/'))
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+
+    let entries = walkdir::WalkDir::new(project_dir)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|entry| {
+            let relative = entry.path().strip_prefix(project_dir).unwrap_or(entry.path());
+            !is_ignored(relative, &patterns)
+        });
+    for entry in entries {
+        let entry = entry?;
+        let relative = entry.path().strip_prefix(project_dir)?;
+        if entry.file_type().is_file() {
+            tar_builder.append_path_with_name(entry.path(), prefix.join(relative))?;
+        }
+    }
+    Ok(())
+}
+
+/// Matches the simple .dockerignore forms: a name anywhere in the tree, a
+/// path from the root, or `*.ext`
+fn is_ignored(relative: &Path, patterns: &[&str]) -> bool {
+    let path = relative.to_string_lossy().replace('\\', "/");
+    let file_name = relative.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    patterns.iter().any(|pattern| match pattern.strip_prefix('*') {
+        Some(suffix) => file_name.ends_with(suffix),
+        None if pattern.contains('/') => path == *pattern || path.starts_with(&format!("{}/", pattern)),
+        None => relative.components().any(|component| component.as_os_str() == *pattern),
+    })
+}
+
+/// Where the project environment keeps its interpreter version, packages and
+/// scripts, as its own Python reports them
+struct EnvironmentLayout {
+    /// major.minor
+    python: String,
+    site_packages: Vec<PathBuf>,
+    scripts: PathBuf,
+}
+
+impl EnvironmentLayout {
+    async fn read() -> Result<Self, Box<dyn std::error::Error>> {
+        let env_dir = venv_dir();
+        let python = env_bin_path(&env_dir, "python");
+        if !python.exists() {
+            return Err("No project environment found. Create one with 'sa env create'".into());
+        }
+        let script = "import sys, sysconfig\nprint('%d.%d' % sys.version_info[:2])\nprint(sysconfig.get_path('purelib'))\nprint(sysconfig.get_path('platlib'))";
+        let output = tokio::process::Command::new(&python)
+            .args(["-c", script])
+            .output()
+            .await
+            .map_err(|e| format!("Failed to run {}: {}", python.display(), e))?;
+        if !output.status.success() {
+            return Err(format!("{} is not usable: {}", python.display(), String::from_utf8_lossy(&output.stderr).trim()).into());
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut lines = stdout.lines().map(str::trim);
+        let version = lines.next().unwrap_or_default().to_string();
+        let mut site_packages: Vec<PathBuf> = lines.map(PathBuf::from).filter(|dir| dir.is_dir()).collect();
+        site_packages.dedup();
+        Ok(EnvironmentLayout { python: version, site_packages, scripts: env_bin_dir(&env_dir) })
+    }
+
+    /// The image layer: packages in the base image's site-packages, console
+    /// scripts in /usr/local/bin pointed at its interpreter, and the project
+    /// (minus .dockerignore) in /app
+    fn layer(&self, project_dir: &Path) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut tar_builder = tar::Builder::new(Vec::new());
+        let site_packages = PathBuf::from(format!("usr/local/lib/python{}/site-packages", self.python));
+        for dir in &self.site_packages {
+            for entry in fs::read_dir(dir)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().to_string();
+                if is_base_image_package(&name) {
+                    continue;
+                }
+                let dest = site_packages.join(&name);
+                if entry.path().is_dir() {
+                    tar_builder.append_dir_all(&dest, entry.path())?;
+                } else {
+                    tar_builder.append_path_with_name(entry.path(), &dest)?;
+                }
+            }
+        }
+
+        for entry in fs::read_dir(&self.scripts)? {
+            let path = entry?.path();
+            let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+            if name.starts_with("python") || name.starts_with("activate") || is_base_image_package(&name) {
+                continue;
+            }
+            // Console scripts start with a shebang naming the environment's
+            // interpreter; anything else is not portable
+            let Ok(content) = fs::read_to_string(&path) else { continue };
+            let Some((shebang, body)) = content.split_once('\n') else { continue };
+            if !shebang.starts_with("#!") || !shebang.contains("python") {
+                continue;
+            }
+            let script = format!("#!/usr/local/bin/python\n{}", body);
+            let mut header = tar::Header::new_gnu();
+            header.set_size(script.len() as u64);
+            header.set_mode(0o755);
+            header.set_cksum();
+            tar_builder.append_data(&mut header, Path::new("usr/local/bin").join(&name), script.as_bytes())?;
+        }
+
+        append_build_context(&mut tar_builder, project_dir, Path::new(PROJECT_MOUNT.trim_start_matches('/')))?;
+        Ok(tar_builder.into_inner()?)
+    }
+}
+
+/// Whether a site-packages entry or script belongs to an installer the base
+/// image provides (`pip`, `pip-24.0.dist-info`, `pip3.11`, ...)
+fn is_base_image_package(name: &str) -> bool {
+    let name = name.to_lowercase();
+    BASE_IMAGE_PACKAGES.iter().any(|package| {
+        name == *package || name.strip_prefix(package).is_some_and(|rest| rest.starts_with(['-', '.']) || rest.starts_with(|c: char| c.is_ascii_digit()))
+    })
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::fs;
+
+pub const DEFAULT_ENV_FILE: &str = ".env";
+
+// .env file loading
+pub fn load_env_file(path: &Path) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read env file {}: {}", path.display(), e))?;
+
+    parse_env(&content).map_err(|e| format!("{}: {}", path.display(), e).into())
+}
+
+/// Parses KEY=VALUE lines. Supports comments, `export` prefixes, single quotes
+/// (literal) and double quotes (escapes and `$VAR`/`${VAR}` expansion); a
+/// quoted value may be followed by a comment.
+pub fn parse_env(content: &str) -> Result<Vec<(String, String)>, String> {
+    let mut vars: Vec<(String, String)> = Vec::new();
+    let mut defined: HashMap<String, String> = HashMap::new();
+
+    for (line_no, raw_line) in content.lines().enumerate() {
+        let line = raw_line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, raw_value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected KEY=VALUE", line_no + 1))?;
+
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("line {}: invalid variable name '{}'", line_no + 1, key));
+        }
+
+        let raw_value = raw_value.trim();
+        let value = if let Some(quote @ ('\'' | '"')) = raw_value.chars().next() {
+            let rest = &raw_value[1..];
+            let kind = if quote == '"' { "double" } else { "single" };
+            let end = closing_quote(rest, quote).ok_or_else(|| format!("line {}: unterminated {} quote", line_no + 1, kind))?;
+            // Only a comment may follow the closing quote
+            let trailing = rest[end + 1..].trim_start();
+            if !trailing.is_empty() && !trailing.starts_with('#') {
+                return Err(format!("line {}: unexpected '{}' after the closing {} quote", line_no + 1, trailing, kind));
+            }
+            let inner = &rest[..end];
+            if quote == '"' { expand(inner, &defined, true) } else { inner.to_string() }
+        } else {
+            // Unquoted values may carry a trailing comment
+            let value = match raw_value.find(" #") {
+                Some(idx) => raw_value[..idx].trim_end(),
+                None => raw_value,
+            };
+            expand(value, &defined, false)
+        };
+
+        defined.insert(key.to_string(), value.clone());
+        vars.retain(|(existing, _)| existing != key);
+        vars.push((key.to_string(), value));
+    }
+
+    Ok(vars)
+}
+
+/// Byte offset of the quote closing a value; inside double quotes a
+/// backslash escapes the next character
+fn closing_quote(value: &str, quote: char) -> Option<usize> {
+    let mut chars = value.char_indices();
+    while let Some((idx, c)) = chars.next() {
+        if c == quote {
+            return Some(idx);
+        }
+        if c == '\\' && quote == '"' {
+            chars.next();
+        }
+    }
+    None
+}
+
+/// Expands `$VAR` and `${VAR}` from earlier definitions, then the process
+/// environment. With `escapes` (double quotes) backslash escapes are read in
+/// the same pass, so `\$HOME` stays literal.
+fn expand(value: &str, defined: &HashMap<String, String>, escapes: bool) -> String {
+    let lookup = |name: &str| {
+        defined
+            .get(name)
+            .cloned()
+            .or_else(|| std::env::var(name).ok())
+            .unwrap_or_default()
+    };
+
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '\\' && escapes {
+            match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some('r') => result.push('\r'),
+                Some(other) => result.push(other),
+                None => result.push('\\'),
+            }
+            continue;
+        }
+        if c != '$' {
+            result.push(c);
+            continue;
+        }
+
+        if chars.peek() == Some(&'{') {
+            chars.next();
+            let name: String = chars.by_ref().take_while(|&c| c != '}').collect();
+            result.push_str(&lookup(&name));
+        } else {
+            let mut name = String::new();
+            while let Some(&next) = chars.peek() {
+                if next.is_ascii_alphanumeric() || next == '_' {
+                    name.push(next);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            if name.is_empty() {
+                result.push('$');
+            } else {
+                result.push_str(&lookup(&name));
+            }
+        }
+    }
+
+    result
+}
+
+/// Collects variables from the requested env files, falling back to `.env`
+/// in the working directory when present. Later files win.
+pub fn resolve_env_files(files: &[String], disabled: bool) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+    if disabled {
+        return Ok(Vec::new());
+    }
+
+    if files.is_empty() {
+        let default_path = Path::new(DEFAULT_ENV_FILE);
+        return if default_path.is_file() {
+            load_env_file(default_path)
+        } else {
+            Ok(Vec::new())
+        };
+    }
+
+    let mut vars: Vec<(String, String)> = Vec::new();
+    for file in files {
+        for (key, value) in load_env_file(Path::new(file))? {
+            vars.retain(|(existing, _)| *existing != key);
+            vars.push((key, value));
+        }
+    }
+    Ok(vars)
+}
@@ -0,0 +1,157 @@
+use std::path::{Path, PathBuf};
+use std::fs;
+use chrono::{Duration, Utc};
+use dirs::cache_dir;
+use sha2::{Digest, Sha256};
+use tokio::process::Command;
+use colored::*;
+use crate::modules::models::{EphemeralEnvInfo, SAConfig};
+use crate::modules::venv::{env_bin_path, find_interpreter, interpreter_version};
+
+const ENV_INFO_FILE: &str = "sa-ephemeral.json";
+
+// Cached throwaway environments for `sa run --with`
+pub struct EphemeralEnvs {
+    pub root: PathBuf,
+    pub max_age_days: i64,
+}
+
+impl EphemeralEnvs {
+    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        let root = cache_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("sa-cache")
+            .join("envs");
+
+        fs::create_dir_all(&root)?;
+        let max_age_days = SAConfig::load()?.venv.ephemeral_max_age_days;
+
+        Ok(EphemeralEnvs { root, max_age_days })
+    }
+
+    /// Normalized, order-independent key for a dependency set and interpreter
+    pub fn env_key(requirements: &[String], python: &str) -> String {
+        let mut normalized: Vec<String> = requirements
+            .iter()
+            .map(|req| req.trim().to_lowercase().replace(' ', ""))
+            .filter(|req| !req.is_empty())
+            .collect();
+        normalized.sort();
+        normalized.dedup();
+
+        let mut hasher = Sha256::new();
+        hasher.update(python.as_bytes());
+        for req in &normalized {
+            hasher.update(b"\n");
+            hasher.update(req.as_bytes());
+        }
+        hex::encode(hasher.finalize())[..16].to_string()
+    }
+
+    /// Returns an environment with the requirements installed, reusing a
+    /// previous one for the same dependency set when available
+    pub async fn prepare(&self, requirements: &[String], python: Option<&str>) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let interpreter = match python {
+            Some(spec) => find_interpreter(spec)?,
+            None => PathBuf::from("python3"),
+        };
+        let python_version = interpreter_version(&interpreter).await?;
+
+        let key = Self::env_key(requirements, &python_version);
+        let env_dir = self.root.join(&key);
+        let described = if requirements.is_empty() { "no packages".to_string() } else { requirements.join(", ") };
+
+        if let Some(mut info) = Self::read_info(&env_dir) {
+            println!("{}", format!("♻️  Reusing cached environment for {}", described).blue());
+            info.last_used = Utc::now();
+            Self::write_info(&env_dir, &info)?;
+            return Ok(env_dir);
+        }
+
+        // A directory without the info file is a leftover from an interrupted build
+        if env_dir.exists() {
+            fs::remove_dir_all(&env_dir)?;
+        }
+
+        println!("{}", format!("📦 Creating ephemeral environment for {}", described).cyan());
+        if let Err(e) = Self::build(&env_dir, &interpreter, requirements).await {
+            let _ = fs::remove_dir_all(&env_dir);
+            return Err(e);
+        }
+
+        let now = Utc::now();
+        Self::write_info(&env_dir, &EphemeralEnvInfo {
+            requirements: requirements.to_vec(),
+            python_version,
+            created_at: now,
+            last_used: now,
+        })?;
+
+        Ok(env_dir)
+    }
+
+    async fn build(env_dir: &Path, interpreter: &Path, requirements: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+        let status = Command::new(interpreter)
+            .args(["-m", "venv"])
+            .arg(env_dir)
+            .status()
+            .await?;
+
+        if !status.success() {
+            return Err("Failed to create ephemeral environment".into());
+        }
+
+        // A build needing nothing (an in-tree backend) gets a bare environment
+        if requirements.is_empty() {
+            return Ok(());
+        }
+        let status = Command::new(env_bin_path(env_dir, "python"))
+            .args(["-m", "pip", "install", "--quiet"])
+            .args(requirements)
+            .status()
+            .await?;
+
+        if !status.success() {
+            return Err(format!("Failed to install {}", requirements.join(", ")).into());
+        }
+        Ok(())
+    }
+
+    fn read_info(env_dir: &Path) -> Option<EphemeralEnvInfo> {
+        let content = fs::read_to_string(env_dir.join(ENV_INFO_FILE)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    fn write_info(env_dir: &Path, info: &EphemeralEnvInfo) -> Result<(), Box<dyn std::error::Error>> {
+        fs::write(env_dir.join(ENV_INFO_FILE), serde_json::to_string_pretty(info)?)?;
+        Ok(())
+    }
+
+    /// Removes environments not used within the configured age. Returns the number removed.
+    pub fn collect_garbage(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        let cutoff = Utc::now() - Duration::days(self.max_age_days);
+        let mut removed = 0;
+
+        for entry in fs::read_dir(&self.root)? {
+            let path = entry?.path();
+            if !path.is_dir() {
+                continue;
+            }
+
+            let expired = match Self::read_info(&path) {
+                Some(info) => info.last_used < cutoff,
+                // Unfinished builds are only swept once they are clearly abandoned
+                None => fs::metadata(&path)
+                    .and_then(|m| m.modified())
+                    .map(|modified| chrono::DateTime::<Utc>::from(modified) < cutoff)
+                    .unwrap_or(true),
+            };
+
+            if expired && fs::remove_dir_all(&path).is_ok() {
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::{HashMap, HashSet};
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use petgraph::visit::{Bfs, EdgeRef};
+use petgraph::Graph;
+
+/// Space around the drawing
+const MARGIN: f64 = 20.0;
+/// Horizontal space between neighbouring nodes of a layer
+const NODE_GAP: f64 = 24.0;
+/// Vertical space between layers, where edges and their labels go
+const LAYER_GAP: f64 = 56.0;
+/// Width reserved for an edge passing through a layer
+const DUMMY_WIDTH: f64 = 8.0;
+/// Ordering passes; more rarely helps dependency graphs
+const SWEEPS: usize = 4;
+
+// A layered (Sugiyama-style) drawing of a directed graph, top to bottom:
+// cycles are broken by reversing DFS back edges, nodes are layered by
+// longest path from the root, edges spanning several layers get a dummy
+// point per layer, layers are ordered by barycenters to reduce crossings,
+// and nodes are pulled under the nodes that point at them.
+
+/// A node's box; `x`/`y` are its top left corner
+pub struct NodeBox {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// Where an edge runs, from its source to its target
+pub struct EdgeRoute {
+    pub points: Vec<(f64, f64)>,
+    /// The edge closes a cycle, so it runs upwards
+    pub back: bool,
+}
+
+pub struct Layout {
+    pub nodes: HashMap<NodeIndex, NodeBox>,
+    pub edges: HashMap<EdgeIndex, EdgeRoute>,
+    pub width: f64,
+    pub height: f64,
+}
+
+struct Item {
+    node: Option<NodeIndex>,
+    width: f64,
+    height: f64,
+    layer: usize,
+    x: f64,
+}
+
+/// Lays out `graph` starting at `root`; `size` gives each node's width and height
+pub fn layered<N, E>(graph: &Graph<N, E>, root: NodeIndex, size: impl Fn(NodeIndex) -> (f64, f64)) -> Layout {
+    let back = back_edges(graph, root);
+    let layer_of = longest_path_layers(graph, root, &back);
+
+    // Nodes in breadth-first order from the root seed the layer order
+    let mut order = Vec::new();
+    let mut bfs = Bfs::new(graph, root);
+    while let Some(node) = bfs.next(graph) {
+        order.push(node);
+    }
+    let seen: HashSet<NodeIndex> = order.iter().copied().collect();
+    order.extend(graph.node_indices().filter(|node| !seen.contains(node)));
+
+    let mut items = Vec::new();
+    let mut item_of = HashMap::new();
+    for &node in &order {
+        let (width, height) = size(node);
+        item_of.insert(node, items.len());
+        items.push(Item { node: Some(node), width, height, layer: layer_of[&node], x: 0.0 });
+    }
+
+    // Chains of items each edge passes through, top to bottom
+    let mut chains: HashMap<EdgeIndex, Vec<usize>> = HashMap::new();
+    let mut links = Vec::new();
+    for edge in graph.edge_references() {
+        let (top, bottom) = if back.contains(&edge.id()) { (edge.target(), edge.source()) } else { (edge.source(), edge.target()) };
+        let mut chain = vec![item_of[&top]];
+        for layer in layer_of[&top] + 1..layer_of[&bottom] {
+            chain.push(items.len());
+            items.push(Item { node: None, width: DUMMY_WIDTH, height: 0.0, layer, x: 0.0 });
+        }
+        chain.push(item_of[&bottom]);
+        links.extend(chain.windows(2).map(|pair| (pair[0], pair[1])));
+        chains.insert(edge.id(), chain);
+    }
+
+    let layer_count = items.iter().map(|item| item.layer + 1).max().unwrap_or(0);
+    let mut layers: Vec<Vec<usize>> = vec![Vec::new(); layer_count];
+    for (id, item) in items.iter().enumerate() {
+        layers[item.layer].push(id);
+    }
+    let mut above: Vec<Vec<usize>> = vec![Vec::new(); items.len()];
+    let mut below: Vec<Vec<usize>> = vec![Vec::new(); items.len()];
+    for &(upper, lower) in &links {
+        if items[upper].layer + 1 == items[lower].layer {
+            above[lower].push(upper);
+            below[upper].push(lower);
+        }
+    }
+
+    order_layers(&mut layers, &above, &below, items.len());
+    place_layers(&mut items, &layers, &above);
+
+    // Vertical positions: each layer is as tall as its tallest node
+    let mut layer_top = Vec::with_capacity(layer_count);
+    let mut layer_height = Vec::with_capacity(layer_count);
+    let mut y = MARGIN;
+    for layer in &layers {
+        let height = layer.iter().map(|&id| items[id].height).fold(0.0, f64::max);
+        layer_top.push(y);
+        layer_height.push(height);
+        y += height + LAYER_GAP;
+    }
+    let height = (y - LAYER_GAP + MARGIN).max(2.0 * MARGIN);
+    let width = items.iter().map(|item| item.x + item.width).fold(0.0, f64::max) + MARGIN;
+
+    let mut nodes = HashMap::new();
+    for item in &items {
+        if let Some(node) = item.node {
+            let y = layer_top[item.layer] + (layer_height[item.layer] - item.height) / 2.0;
+            nodes.insert(node, NodeBox { x: item.x, y, width: item.width, height: item.height });
+        }
+    }
+
+    let mut edges = HashMap::new();
+    for (edge, chain) in chains {
+        let center = |id: usize| items[id].x + items[id].width / 2.0;
+        let mut points = Vec::with_capacity(chain.len());
+        let first = chain[0];
+        points.push((center(first), nodes[&items[first].node.expect("chains start at a node")].y + items[first].height));
+        for &id in &chain[1..chain.len() - 1] {
+            let layer = items[id].layer;
+            points.push((center(id), layer_top[layer] + layer_height[layer] / 2.0));
+        }
+        let last = chain[chain.len() - 1];
+        points.push((center(last), nodes[&items[last].node.expect("chains end at a node")].y));
+
+        let is_back = back.contains(&edge);
+        if is_back {
+            points.reverse();
+        }
+        edges.insert(edge, EdgeRoute { points, back: is_back });
+    }
+
+    Layout { nodes, edges, width, height }
+}
+
+/// Edges that close a cycle in a depth-first walk from `root`
+fn back_edges<N, E>(graph: &Graph<N, E>, root: NodeIndex) -> HashSet<EdgeIndex> {
+    // true while a node is on the DFS stack
+    fn visit<N, E>(graph: &Graph<N, E>, node: NodeIndex, on_stack: &mut HashMap<NodeIndex, bool>, back: &mut HashSet<EdgeIndex>) {
+        on_stack.insert(node, true);
+        for edge in graph.edges(node) {
+            match on_stack.get(&edge.target()) {
+                Some(true) => {
+                    back.insert(edge.id());
+                }
+                Some(false) => {}
+                None => visit(graph, edge.target(), on_stack, back),
+            }
+        }
+        on_stack.insert(node, false);
+    }
+
+    let mut on_stack = HashMap::new();
+    let mut back = HashSet::new();
+    for start in std::iter::once(root).chain(graph.node_indices()) {
+        if !on_stack.contains_key(&start) {
+            visit(graph, start, &mut on_stack, &mut back);
+        }
+    }
+    back
+}
+
+/// Each node's layer: the longest path to it, ignoring back edges
+fn longest_path_layers<N, E>(graph: &Graph<N, E>, root: NodeIndex, back: &HashSet<EdgeIndex>) -> HashMap<NodeIndex, usize> {
+    let mut incoming: HashMap<NodeIndex, usize> = graph.node_indices().map(|node| (node, 0)).collect();
+    for edge in graph.edge_references().filter(|edge| !back.contains(&edge.id())) {
+        *incoming.get_mut(&edge.target()).expect("node of this graph") += 1;
+    }
+
+    let mut layers: HashMap<NodeIndex, usize> = graph.node_indices().map(|node| (node, 0)).collect();
+    let mut ready: Vec<NodeIndex> = graph.node_indices().filter(|node| *node != root && incoming[node] == 0).collect();
+    if incoming[&root] == 0 {
+        ready.push(root);
+    }
+    while let Some(node) = ready.pop() {
+        for edge in graph.edges(node).filter(|edge| !back.contains(&edge.id())) {
+            let target = edge.target();
+            let layer = layers[&node] + 1;
+            let entry = layers.get_mut(&target).expect("node of this graph");
+            *entry = (*entry).max(layer);
+            let remaining = incoming.get_mut(&target).expect("node of this graph");
+            *remaining -= 1;
+            if *remaining == 0 {
+                ready.push(target);
+            }
+        }
+    }
+    layers
+}
+
+/// Reorders each layer by the mean position of its neighbours, sweeping
+/// down then up
+fn order_layers(layers: &mut [Vec<usize>], above: &[Vec<usize>], below: &[Vec<usize>], item_count: usize) {
+    let mut position = vec![0.0; item_count];
+    for layer in layers.iter() {
+        for (index, &id) in layer.iter().enumerate() {
+            position[id] = index as f64;
+        }
+    }
+
+    let reorder = |layer: &mut Vec<usize>, neighbours: &[Vec<usize>], position: &mut Vec<f64>| {
+        let key = |id: usize| {
+            let adjacent = &neighbours[id];
+            if adjacent.is_empty() {
+                position[id]
+            } else {
+                adjacent.iter().map(|&other| position[other]).sum::<f64>() / adjacent.len() as f64
+            }
+        };
+        let mut keyed: Vec<(f64, usize)> = layer.iter().map(|&id| (key(id), id)).collect();
+        keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+        *layer = keyed.into_iter().map(|(_, id)| id).collect();
+        for (index, &id) in layer.iter().enumerate() {
+            position[id] = index as f64;
+        }
+    };
+
+    for _ in 0..SWEEPS {
+        for layer in layers.iter_mut().skip(1) {
+            reorder(layer, above, &mut position);
+        }
+        let last = layers.len().saturating_sub(1);
+        for layer in layers[..last].iter_mut().rev() {
+            reorder(layer, below, &mut position);
+        }
+    }
+}
+
+/// Horizontal positions: the first layer side by side, then each node as
+/// close to the middle of its parents as the nodes left of it allow
+fn place_layers(items: &mut [Item], layers: &[Vec<usize>], above: &[Vec<usize>]) {
+    for layer in layers {
+        let mut next_free = MARGIN;
+        for &id in layer {
+            let parents = &above[id];
+            let wanted = if parents.is_empty() {
+                next_free
+            } else {
+                let center = parents.iter().map(|&parent| items[parent].x + items[parent].width / 2.0).sum::<f64>() / parents.len() as f64;
+                center - items[id].width / 2.0
+            };
+            items[id].x = wanted.max(next_free);
+            next_free = items[id].x + items[id].width + NODE_GAP;
+        }
+    }
+
+    // Wide lower layers push their parents right of the margin; recenter
+    // parents over their children where there is room
+    for index in (0..layers.len().saturating_sub(1)).rev() {
+        let layer = &layers[index];
+        for position in 0..layer.len() {
+            let id = layer[position];
+            let children: Vec<usize> = layers[index + 1].iter().copied().filter(|&child| above[child].contains(&id)).collect();
+            if children.is_empty() {
+                continue;
+            }
+            let center = children.iter().map(|&child| items[child].x + items[child].width / 2.0).sum::<f64>() / children.len() as f64;
+            let min = if position == 0 { MARGIN } else { items[layer[position - 1]].x + items[layer[position - 1]].width + NODE_GAP };
+            let max = layer.get(position + 1).map(|&next| items[next].x - NODE_GAP - items[id].width).unwrap_or(f64::MAX);
+            let wanted = center - items[id].width / 2.0;
+            if wanted > items[id].x {
+                items[id].x = wanted.min(max).max(items[id].x);
+            } else {
+                items[id].x = wanted.max(min).min(items[id].x);
+            }
+        }
+    }
+}
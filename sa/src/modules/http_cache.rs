@@ -0,0 +1,162 @@
+use std::path::PathBuf;
+use std::fs;
+use chrono::{Duration, Utc};
+use dirs::cache_dir;
+use reqwest::{Client, StatusCode};
+use reqwest::header::{ACCEPT, CACHE_CONTROL, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use crate::modules::models::HttpCacheEntry;
+use crate::modules::network;
+
+/// Body returned by the HTTP cache, flagged when no transfer was needed
+pub struct CachedResponse {
+    pub body: Vec<u8>,
+    pub from_cache: bool,
+}
+
+// On-disk cache for index pages and metadata responses, keyed by URL
+pub struct HttpCache {
+    pub dir: PathBuf,
+    pub client: Client,
+}
+
+impl HttpCache {
+    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_client(network::client()?)
+    }
+
+    pub fn with_client(client: Client) -> Result<Self, Box<dyn std::error::Error>> {
+        let dir = cache_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("sa-cache")
+            .join("http");
+
+        fs::create_dir_all(&dir)?;
+        Ok(HttpCache { dir, client })
+    }
+
+    fn key(url: &str, accept: Option<&str>) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(accept.unwrap_or("").as_bytes());
+        hasher.update(b" ");
+        hasher.update(url.as_bytes());
+        hex::encode(hasher.finalize())
+    }
+
+    fn paths(&self, key: &str) -> (PathBuf, PathBuf) {
+        (self.dir.join(format!("{}.json", key)), self.dir.join(format!("{}.body", key)))
+    }
+
+    fn load(&self, key: &str) -> Option<(HttpCacheEntry, Vec<u8>)> {
+        let (entry_path, body_path) = self.paths(key);
+        let entry: HttpCacheEntry = serde_json::from_str(&fs::read_to_string(entry_path).ok()?).ok()?;
+        let body = fs::read(body_path).ok()?;
+        Some((entry, body))
+    }
+
+    fn save(&self, key: &str, entry: &HttpCacheEntry, body: Option<&[u8]>) -> Result<(), Box<dyn std::error::Error>> {
+        let (entry_path, body_path) = self.paths(key);
+        if let Some(body) = body {
+            fs::write(body_path, body)?;
+        }
+        fs::write(entry_path, serde_json::to_string_pretty(entry)?)?;
+        Ok(())
+    }
+
+    /// GET with local caching: fresh entries are served without a request,
+    /// stale ones are revalidated with If-None-Match / If-Modified-Since, and a
+    /// cached copy is used when the network is unavailable
+    pub async fn get(&self, url: &str, accept: Option<&str>) -> Result<CachedResponse, Box<dyn std::error::Error>> {
+        let key = Self::key(url, accept);
+        let cached = self.load(&key);
+
+        if let Some((entry, body)) = &cached {
+            if entry.is_fresh() {
+                return Ok(CachedResponse { body: body.clone(), from_cache: true });
+            }
+        }
+
+        let mut request = self.client.get(url);
+        if let Some(accept) = accept {
+            request = request.header(ACCEPT, accept);
+        }
+        if let Some((entry, _)) = &cached {
+            if let Some(etag) = &entry.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &entry.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(e) => {
+                return match cached {
+                    Some((_, body)) => Ok(CachedResponse { body, from_cache: true }),
+                    None => Err(e.into()),
+                };
+            }
+        };
+
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v: &reqwest::header::HeaderValue| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let cache_control = header(CACHE_CONTROL).unwrap_or_default().to_lowercase();
+        let mut entry = HttpCacheEntry {
+            url: url.to_string(),
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+            stored_at: Utc::now(),
+            max_age: parse_max_age(&cache_control),
+            no_cache: cache_control.contains("no-cache"),
+        };
+
+        if response.status() == StatusCode::NOT_MODIFIED {
+            if let Some((previous, body)) = cached {
+                // 304s may omit validators; keep the ones we already have
+                entry.etag = entry.etag.or(previous.etag);
+                entry.last_modified = entry.last_modified.or(previous.last_modified);
+                self.save(&key, &entry, None)?;
+                return Ok(CachedResponse { body, from_cache: true });
+            }
+        }
+
+        if !response.status().is_success() {
+            return Err(format!("Request to {} failed with status {}", url, response.status()).into());
+        }
+
+        let body = response.bytes().await?.to_vec();
+        if !cache_control.contains("no-store") {
+            self.save(&key, &entry, Some(&body))?;
+        }
+
+        Ok(CachedResponse { body, from_cache: false })
+    }
+
+    pub async fn get_json(&self, url: &str) -> Result<Value, Box<dyn std::error::Error>> {
+        let response = self.get(url, Some("application/json")).await?;
+        Ok(serde_json::from_slice(&response.body)?)
+    }
+}
+
+impl HttpCacheEntry {
+    pub fn is_fresh(&self) -> bool {
+        match self.max_age {
+            Some(max_age) if !self.no_cache => Utc::now() < self.stored_at + Duration::seconds(max_age),
+            _ => false,
+        }
+    }
+}
+
+fn parse_max_age(cache_control: &str) -> Option<i64> {
+    cache_control
+        .split(',')
+        .filter_map(|directive| directive.trim().strip_prefix("max-age="))
+        .find_map(|value| value.trim().parse().ok())
+}
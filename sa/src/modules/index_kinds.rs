@@ -0,0 +1,66 @@
+use crate::modules::models::Mirror;
+
+// URL conventions of the private index servers SA knows about. A mirror's
+// `url` is always its simple index; the repository URL it was derived from
+// is where uploads go, and each server has its own status endpoint.
+//
+//   devpi        https://host/<user>/<index>/             simple: +simple/
+//   artifactory  https://host/artifactory/api/pypi/<repo>  simple: simple/
+//   nexus        https://host/repository/<repo>/           simple: simple/
+
+/// Path below a repository URL where its simple index lives
+fn simple_suffix(kind: &str) -> Option<&'static str> {
+    match kind {
+        "devpi" => Some("+simple/"),
+        "artifactory" | "nexus" => Some("simple/"),
+        _ => None,
+    }
+}
+
+/// The simple index URL for a repository URL, which may be given with or
+/// without the simple suffix
+pub fn index_url(kind: &str, url: &str) -> String {
+    match simple_suffix(kind) {
+        Some(suffix) => format!("{}{}", repository_url(kind, url), suffix),
+        None => url.to_string(),
+    }
+}
+
+/// A server's repository URL, i.e. its index URL without the simple suffix
+fn repository_url(kind: &str, url: &str) -> String {
+    let base = url.trim_end_matches('/');
+    let base = match simple_suffix(kind) {
+        Some(suffix) => base.strip_suffix(suffix.trim_end_matches('/')).unwrap_or(base),
+        None => base,
+    };
+    format!("{}/", base.trim_end_matches('/'))
+}
+
+/// Where `sa publish --repository` uploads to a mirror; plain simple
+/// indexes have no known upload endpoint
+pub fn upload_url(mirror: &Mirror) -> Option<String> {
+    match mirror.kind.as_str() {
+        "devpi" | "nexus" => Some(repository_url(&mirror.kind, &mirror.url)),
+        // Artifactory takes uploads at the repository URL without a trailing slash
+        "artifactory" => Some(repository_url(&mirror.kind, &mirror.url).trim_end_matches('/').to_string()),
+        _ => None,
+    }
+}
+
+/// Server endpoint answering 2xx while the server is healthy; plain simple
+/// indexes have none
+pub fn status_url(mirror: &Mirror) -> Option<String> {
+    let repository = repository_url(&mirror.kind, &mirror.url);
+    match mirror.kind.as_str() {
+        "devpi" => Some(repository),
+        "artifactory" => Some(match repository.split_once("/api/pypi/") {
+            Some((root, _)) => format!("{}/api/system/ping", root),
+            None => repository,
+        }),
+        "nexus" => Some(match repository.split_once("/repository/") {
+            Some((root, _)) => format!("{}/service/rest/v1/status", root),
+            None => repository,
+        }),
+        _ => None,
+    }
+}
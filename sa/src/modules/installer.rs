@@ -0,0 +1,481 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::fs;
+use std::process::Stdio;
+use futures_util::StreamExt;
+use serde_json::Value;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use walkdir::WalkDir;
+use crate::modules::credentials::authorize;
+use crate::modules::mirrors::MirrorManager;
+use crate::modules::models::InstallPlanItem;
+use crate::modules::venv::{bin_path, pip_command};
+use crate::modules::network;
+
+/// How files are materialized from the cache into an environment
+#[derive(Clone, Copy, PartialEq)]
+pub enum LinkMode {
+    /// Reflink where the filesystem supports it, then hardlink, then copy
+    Auto,
+    Reflink,
+    Hardlink,
+    Copy,
+}
+
+impl LinkMode {
+    pub fn parse(value: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        match value {
+            "auto" => Ok(LinkMode::Auto),
+            "reflink" | "clone" => Ok(LinkMode::Reflink),
+            "hardlink" => Ok(LinkMode::Hardlink),
+            "copy" => Ok(LinkMode::Copy),
+            other => Err(format!("Unknown link mode '{}'. Use auto, reflink, hardlink or copy", other).into()),
+        }
+    }
+}
+
+/// Resolves requirements against the environment with pip's dry-run report,
+/// returning only the distributions that still need installing. `None` means
+/// the available pip is too old to produce a report.
+pub async fn resolve_install_plan(requirements: &[String]) -> Result<Option<Vec<InstallPlanItem>>, Box<dyn std::error::Error>> {
+    let report = tempfile::NamedTempFile::new()?;
+
+    let output = pip_command()
+        .args(["install", "--dry-run", "--quiet", "--report"])
+        .arg(report.path())
+        .args(requirements)
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("no such option") {
+            return Ok(None);
+        }
+        return Err(format!("Dependency resolution failed: {}", stderr.trim()).into());
+    }
+
+    let report: Value = serde_json::from_str(&fs::read_to_string(report.path())?)?;
+    let mut plan = Vec::new();
+
+    for item in report["install"].as_array().into_iter().flatten() {
+        let download_info = &item["download_info"];
+        let archive_info = &download_info["archive_info"];
+
+        let sha256 = archive_info["hashes"]["sha256"]
+            .as_str()
+            .map(str::to_string)
+            .or_else(|| {
+                archive_info["hash"]
+                    .as_str()
+                    .and_then(|h| h.strip_prefix("sha256="))
+                    .map(str::to_string)
+            });
+
+        plan.push(InstallPlanItem {
+            name: item["metadata"]["name"].as_str().unwrap_or_default().to_string(),
+            version: item["metadata"]["version"].as_str().unwrap_or_default().to_string(),
+            url: download_info["url"].as_str().unwrap_or_default().to_string(),
+            sha256,
+            requested: item["requested"].as_bool().unwrap_or(false),
+        });
+    }
+
+    Ok(Some(plan))
+}
+
+/// File name component of an artifact URL, without query or fragment
+pub fn artifact_filename(url: &str) -> String {
+    url.split(['#', '?'])
+        .next()
+        .and_then(|u| u.rsplit('/').next())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Streams an artifact into `dest_dir`, returning the downloaded file path
+pub async fn download_artifact(url: &str, dest_dir: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    fs::create_dir_all(dest_dir)?;
+
+    let file_name = artifact_filename(url);
+    if file_name.is_empty() {
+        return Err(format!("Cannot determine file name for {}", url).into());
+    }
+    let dest = dest_dir.join(&file_name);
+    download_to(url, &dest).await?;
+    Ok(dest)
+}
+
+/// Downloads `url` to the file `dest`
+pub async fn download_to(url: &str, dest: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    // Local indexes and --find-links directories resolve to file:// URLs
+    if let Some(local) = url.strip_prefix("file://") {
+        fs::copy(local.split(['#', '?']).next().unwrap_or(local), dest)?;
+        return Ok(());
+    }
+
+    let mirrors = MirrorManager::new()?;
+    let mirror = mirrors.mirror_on_host(url);
+    let client = network::client_builder_for(mirror)?.build()?;
+    let (retries, bandwidth) = network::transfer_limits(mirror)?;
+    let request = authorize(client.get(url), mirrors.auth_for_url(url)?.as_ref());
+    let response = network::send_with_retries(request, retries).await?;
+    if !response.status().is_success() {
+        return Err(format!("Download of {} failed with status {}", url, response.status()).into());
+    }
+
+    let mut file = tokio::fs::File::create(dest).await?;
+    let mut stream = response.bytes_stream();
+    let mut throttle = network::Throttle::new(bandwidth);
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        file.write_all(&chunk).await?;
+        throttle.pace(chunk.len()).await;
+    }
+    file.flush().await?;
+
+    Ok(())
+}
+
+// Installs unpacked wheels from the cache into an environment
+pub struct WheelInstaller {
+    pub python: PathBuf,
+    pub site_packages: PathBuf,
+    pub scripts_dir: PathBuf,
+    pub data_dir: PathBuf,
+    pub include_dir: PathBuf,
+    pub link_mode: LinkMode,
+}
+
+impl WheelInstaller {
+    pub async fn for_active_env(link_mode: LinkMode) -> Result<Self, Box<dyn std::error::Error>> {
+        let python = std::path::absolute(bin_path("python"))?;
+
+        let output = Command::new(&python)
+            .args(["-c", "import json, sysconfig; print(json.dumps(sysconfig.get_paths()))"])
+            .output()
+            .await?;
+
+        if !output.status.success() {
+            return Err("Failed to query environment paths".into());
+        }
+
+        let paths: HashMap<String, String> = serde_json::from_slice(&output.stdout)?;
+        let path = |key: &str| -> Result<PathBuf, Box<dyn std::error::Error>> {
+            paths.get(key)
+                .map(PathBuf::from)
+                .ok_or_else(|| format!("Environment does not report a '{}' path", key).into())
+        };
+
+        Ok(WheelInstaller {
+            site_packages: path("purelib")?,
+            scripts_dir: path("scripts")?,
+            data_dir: path("data")?,
+            include_dir: path("include")?,
+            python,
+            link_mode,
+        })
+    }
+
+    /// dist-info directory of an installed distribution, if any
+    pub fn installed_dist_info(&self, name: &str) -> Option<PathBuf> {
+        let prefix = format!("{}-", normalize_dist_name(name));
+        fs::read_dir(&self.site_packages).ok()?.flatten().map(|e| e.path()).find(|path| {
+            let file_name = path.file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default();
+            file_name.ends_with(".dist-info") && file_name.starts_with(&prefix)
+        })
+    }
+
+    /// Uninstalls a previously installed version so files don't mix
+    pub async fn remove_existing(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if self.installed_dist_info(name).is_some() {
+            let status = pip_command()
+                .args(["uninstall", "--yes", "--quiet", name])
+                .status()
+                .await?;
+            if !status.success() {
+                return Err(format!("Failed to remove installed version of '{}'", name).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Links every file of an unpacked wheel into the environment and writes
+    /// the installer metadata. Returns the installed paths.
+    pub fn install(&self, unpacked: &Path, requested: bool) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        let dist_info = fs::read_dir(unpacked)?
+            .flatten()
+            .map(|e| e.path())
+            .find(|p| p.extension().is_some_and(|ext| ext == "dist-info"))
+            .ok_or_else(|| format!("No .dist-info directory in {}", unpacked.display()))?;
+        let dist_info_name = dist_info.file_name().unwrap_or_default().to_os_string();
+        let project_name = dist_info_name
+            .to_string_lossy()
+            .split('-')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+
+        let original_record = read_record(&dist_info.join("RECORD"));
+        let mut record = Vec::new();
+
+        for entry in WalkDir::new(unpacked).into_iter().filter_map(|e| e.ok()) {
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let rel = entry.path().strip_prefix(unpacked)?;
+            let rel_str = rel.to_string_lossy().replace('\\', "/");
+
+            if rel.parent() == Some(Path::new(&dist_info_name))
+                && matches!(rel.file_name().and_then(|n| n.to_str()), Some("RECORD" | "INSTALLER" | "REQUESTED"))
+            {
+                continue;
+            }
+
+            let Some((dest, is_script)) = self.destination(rel, &project_name) else {
+                continue;
+            };
+
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            let (hash, size) = if is_script {
+                self.install_script(entry.path(), &dest)?;
+                (String::new(), String::new())
+            } else {
+                link_file(entry.path(), &dest, self.link_mode)?;
+                original_record.get(&rel_str).cloned().unwrap_or_default()
+            };
+            record.push((dest, hash, size));
+        }
+
+        let installed_dist_info = self.site_packages.join(&dist_info_name);
+        for script in self.write_entry_points(&dist_info.join("entry_points.txt"))? {
+            record.push((script, String::new(), String::new()));
+        }
+
+        let installer_path = installed_dist_info.join("INSTALLER");
+        replace_file(&installer_path, "sa\n")?;
+        record.push((installer_path, String::new(), String::new()));
+
+        if requested {
+            let requested_path = installed_dist_info.join("REQUESTED");
+            replace_file(&requested_path, "")?;
+            record.push((requested_path, String::new(), String::new()));
+        }
+
+        let record_path = installed_dist_info.join("RECORD");
+        let mut lines: Vec<String> = record
+            .iter()
+            .map(|(path, hash, size)| {
+                format!("{},{},{}", relative_path(path, &self.site_packages).to_string_lossy().replace('\\', "/"), hash, size)
+            })
+            .collect();
+        lines.push(format!("{},,", relative_path(&record_path, &self.site_packages).to_string_lossy().replace('\\', "/")));
+        replace_file(&record_path, &(lines.join("\n") + "\n"))?;
+
+        Ok(record.into_iter().map(|(path, _, _)| path).collect())
+    }
+
+    /// Byte-compiles installed modules with the environment's compileall,
+    /// one worker per CPU. Failures (e.g. files targeting other Python
+    /// versions) are reported but don't fail the install, matching pip.
+    pub async fn compile(&self, files: &[PathBuf]) -> Result<usize, Box<dyn std::error::Error>> {
+        let modules: Vec<&PathBuf> = files
+            .iter()
+            .filter(|path| path.starts_with(&self.site_packages) && path.extension().is_some_and(|ext| ext == "py"))
+            .collect();
+        if modules.is_empty() {
+            return Ok(0);
+        }
+
+        let mut child = Command::new(&self.python)
+            .args(["-m", "compileall", "-q", "-j", "0", "-i", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()?;
+
+        let list: String = modules.iter().map(|path| format!("{}\n", path.display())).collect();
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(list.as_bytes()).await?;
+        }
+
+        if !child.wait().await?.success() {
+            return Err("Some modules could not be byte-compiled".into());
+        }
+        Ok(modules.len())
+    }
+
+    /// Maps a path inside the wheel to its install location, following the
+    /// `.data` scheme of the wheel spec. The flag marks scripts needing a shebang rewrite.
+    fn destination(&self, rel: &Path, project_name: &str) -> Option<(PathBuf, bool)> {
+        let mut components = rel.components();
+        let first = components.next()?.as_os_str().to_string_lossy().to_string();
+
+        if !first.ends_with(".data") {
+            return Some((self.site_packages.join(rel), false));
+        }
+
+        let category = components.next()?.as_os_str().to_string_lossy().to_string();
+        let rest: PathBuf = components.collect();
+
+        match category.as_str() {
+            "purelib" | "platlib" => Some((self.site_packages.join(rest), false)),
+            "scripts" => Some((self.scripts_dir.join(rest), true)),
+            "headers" => Some((self.include_dir.join(project_name).join(rest), false)),
+            "data" => Some((self.data_dir.join(rest), false)),
+            _ => None,
+        }
+    }
+
+    /// Copies a wheel script, pointing a `#!python` shebang at the environment interpreter
+    fn install_script(&self, src: &Path, dest: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let content = fs::read(src)?;
+        let content = match content.strip_prefix(b"#!python") {
+            Some(rest) => {
+                let line_end = rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+                let mut rewritten = format!("#!{}", self.python.display()).into_bytes();
+                rewritten.extend_from_slice(&rest[line_end..]);
+                rewritten
+            }
+            None => content,
+        };
+
+        replace_file(dest, &content)?;
+        make_executable(dest)?;
+        Ok(())
+    }
+
+    /// Generates console/gui script wrappers declared in entry_points.txt
+    fn write_entry_points(&self, entry_points: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        let Ok(content) = fs::read_to_string(entry_points) else {
+            return Ok(Vec::new());
+        };
+
+        let mut scripts = Vec::new();
+        let mut in_scripts_section = false;
+
+        for line in content.lines().map(str::trim) {
+            if line.starts_with('[') {
+                in_scripts_section = matches!(line, "[console_scripts]" | "[gui_scripts]");
+                continue;
+            }
+            if !in_scripts_section || line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((name, target)) = line.split_once('=') else {
+                continue;
+            };
+            // Drop any extras marker: "module:func [extra]"
+            let target = target.split('[').next().unwrap_or_default().trim();
+            let Some((module, attr)) = target.split_once(':') else {
+                continue;
+            };
+            let import_name = attr.split('.').next().unwrap_or(attr);
+
+            let script = format!(
+                "#!{python}\n\
+                 # -*- coding: utf-8 -*-\n\
+                 import re\n\
+                 import sys\n\
+                 from {module} import {import_name}\n\
+                 if __name__ == \"__main__\":\n    \
+                     sys.argv[0] = re.sub(r\"(-script\\.pyw|\\.exe)?$\", \"\", sys.argv[0])\n    \
+                     sys.exit({attr}())\n",
+                python = self.python.display(),
+                module = module.trim(),
+                import_name = import_name.trim(),
+                attr = attr.trim(),
+            );
+
+            let dest = self.scripts_dir.join(name.trim());
+            replace_file(&dest, script.as_bytes())?;
+            make_executable(&dest)?;
+            scripts.push(dest);
+        }
+
+        Ok(scripts)
+    }
+}
+
+/// Materializes `src` at `dst` according to the link mode
+pub fn link_file(src: &Path, dst: &Path, mode: LinkMode) -> Result<(), Box<dyn std::error::Error>> {
+    if dst.symlink_metadata().is_ok() {
+        fs::remove_file(dst)?;
+    }
+
+    match mode {
+        LinkMode::Reflink => reflink_copy::reflink(src, dst)?,
+        LinkMode::Hardlink => fs::hard_link(src, dst)?,
+        LinkMode::Copy => {
+            fs::copy(src, dst)?;
+        }
+        LinkMode::Auto => {
+            if reflink_copy::reflink(src, dst).is_err() && fs::hard_link(src, dst).is_err() {
+                fs::copy(src, dst)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes a fresh file, breaking any hardlink into the cache first
+fn replace_file(path: &Path, content: impl AsRef<[u8]>) -> Result<(), Box<dyn std::error::Error>> {
+    if path.symlink_metadata().is_ok() {
+        fs::remove_file(path)?;
+    }
+    fs::write(path, content)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    Ok(())
+}
+
+fn read_record(path: &Path) -> HashMap<String, (String, String)> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return HashMap::new();
+    };
+
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.rsplitn(3, ',');
+            let size = parts.next()?.to_string();
+            let hash = parts.next()?.to_string();
+            let path = parts.next()?.to_string();
+            Some((path, (hash, size)))
+        })
+        .collect()
+}
+
+/// PEP 503 normalization as used in dist-info directory names
+pub fn normalize_dist_name(name: &str) -> String {
+    name.to_lowercase().replace(['-', '.'], "_")
+}
+
+fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let path: Vec<Component> = path.components().collect();
+    let base: Vec<Component> = base.components().collect();
+    let common = path.iter().zip(base.iter()).take_while(|(a, b)| a == b).count();
+
+    let mut result = PathBuf::new();
+    for _ in common..base.len() {
+        result.push("..");
+    }
+    for component in &path[common..] {
+        result.push(component.as_os_str());
+    }
+    result
+}
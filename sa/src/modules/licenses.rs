@@ -0,0 +1,508 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use walkdir::WalkDir;
+use crate::modules::config::project_tool_section;
+use crate::modules::installer::normalize_dist_name;
+use crate::modules::models::{DistMetadata, LicenseFinding, LicensePolicy};
+use crate::modules::venv::venv_dir;
+
+/// SPDX identifiers recognised when parsing free-form license fields
+const KNOWN_LICENSES: &[&str] = &[
+    "0BSD", "AFL-3.0", "AGPL-3.0-only", "AGPL-3.0-or-later", "Apache-1.1", "Apache-2.0",
+    "Artistic-2.0", "BSD-1-Clause", "BSD-2-Clause", "BSD-3-Clause", "BSD-4-Clause", "BSL-1.0",
+    "CC-BY-4.0", "CC0-1.0", "CDDL-1.0", "EPL-1.0", "EPL-2.0", "EUPL-1.2", "GPL-2.0-only",
+    "GPL-2.0-or-later", "GPL-3.0-only", "GPL-3.0-or-later", "HPND", "ISC", "LGPL-2.0-only",
+    "LGPL-2.0-or-later", "LGPL-2.1-only", "LGPL-2.1-or-later", "LGPL-3.0-only", "LGPL-3.0-or-later",
+    "MIT", "MIT-0", "MPL-1.1", "MPL-2.0", "MS-PL", "NCSA", "OpenSSL", "PSF-2.0", "Python-2.0",
+    "Unlicense", "UPL-1.0", "WTFPL", "X11", "Zlib", "ZPL-2.1",
+];
+
+/// Common non-SPDX spellings from License fields and trove classifiers
+const LICENSE_ALIASES: &[(&str, &str)] = &[
+    ("mit license", "MIT"),
+    ("the mit license", "MIT"),
+    ("expat", "MIT"),
+    ("apache", "Apache-2.0"),
+    ("apache 2", "Apache-2.0"),
+    ("apache 2.0", "Apache-2.0"),
+    ("apache2", "Apache-2.0"),
+    ("apache-2", "Apache-2.0"),
+    ("apache license 2.0", "Apache-2.0"),
+    ("apache license, version 2.0", "Apache-2.0"),
+    ("apache license version 2.0", "Apache-2.0"),
+    ("apache software license", "Apache-2.0"),
+    ("apache software license 2.0", "Apache-2.0"),
+    ("asl 2.0", "Apache-2.0"),
+    ("bsd", "BSD-3-Clause"),
+    ("bsd license", "BSD-3-Clause"),
+    ("new bsd", "BSD-3-Clause"),
+    ("new bsd license", "BSD-3-Clause"),
+    ("modified bsd", "BSD-3-Clause"),
+    ("bsd-3", "BSD-3-Clause"),
+    ("3-clause bsd", "BSD-3-Clause"),
+    ("3-clause bsd license", "BSD-3-Clause"),
+    ("bsd 3-clause", "BSD-3-Clause"),
+    ("bsd 3-clause license", "BSD-3-Clause"),
+    ("simplified bsd", "BSD-2-Clause"),
+    ("bsd-2", "BSD-2-Clause"),
+    ("2-clause bsd", "BSD-2-Clause"),
+    ("bsd 2-clause", "BSD-2-Clause"),
+    ("iscl", "ISC"),
+    ("isc license", "ISC"),
+    ("psf", "PSF-2.0"),
+    ("psfl", "PSF-2.0"),
+    ("psf license", "PSF-2.0"),
+    ("python software foundation license", "PSF-2.0"),
+    ("mpl 2.0", "MPL-2.0"),
+    ("mpl-2", "MPL-2.0"),
+    ("mozilla public license 2.0", "MPL-2.0"),
+    ("lgpl", "LGPL-2.1-or-later"),
+    ("lgplv2", "LGPL-2.0-only"),
+    ("lgplv2+", "LGPL-2.0-or-later"),
+    ("lgplv3", "LGPL-3.0-only"),
+    ("lgplv3+", "LGPL-3.0-or-later"),
+    ("gpl", "GPL-2.0-or-later"),
+    ("gplv2", "GPL-2.0-only"),
+    ("gplv2+", "GPL-2.0-or-later"),
+    ("gplv3", "GPL-3.0-only"),
+    ("gplv3+", "GPL-3.0-or-later"),
+    ("agplv3", "AGPL-3.0-only"),
+    ("agplv3+", "AGPL-3.0-or-later"),
+    ("the unlicense", "Unlicense"),
+    ("public domain", "LicenseRef-Public-Domain"),
+    ("zlib/libpng license", "Zlib"),
+    ("historical permission notice and disclaimer", "HPND"),
+    ("eclipse public license 2.0", "EPL-2.0"),
+    ("cc0 1.0 universal", "CC0-1.0"),
+    ("boost software license 1.0", "BSL-1.0"),
+    ("other/proprietary license", "LicenseRef-Proprietary"),
+    ("proprietary", "LicenseRef-Proprietary"),
+];
+
+/// How much a license asks of the software that uses it, least first
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LicenseCategory {
+    Permissive,
+    WeakCopyleft,
+    StrongCopyleft,
+    Proprietary,
+    Unknown,
+}
+
+impl LicenseCategory {
+    /// The category of one SPDX identifier
+    pub fn of(id: &str) -> Self {
+        let upper = id.to_uppercase();
+        if upper.starts_with("GPL-") || upper.starts_with("AGPL-") {
+            LicenseCategory::StrongCopyleft
+        } else if ["LGPL-", "MPL-", "EPL-", "CDDL-", "EUPL-", "MS-PL"].iter().any(|prefix| upper.starts_with(prefix)) {
+            LicenseCategory::WeakCopyleft
+        } else if id == "LicenseRef-Proprietary" {
+            LicenseCategory::Proprietary
+        } else if id == "LicenseRef-Public-Domain" || KNOWN_LICENSES.iter().any(|known| known.eq_ignore_ascii_case(id)) {
+            LicenseCategory::Permissive
+        } else {
+            LicenseCategory::Unknown
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LicenseCategory::Permissive => "permissive",
+            LicenseCategory::WeakCopyleft => "weak copyleft",
+            LicenseCategory::StrongCopyleft => "strong copyleft",
+            LicenseCategory::Proprietary => "proprietary",
+            LicenseCategory::Unknown => "unknown",
+        }
+    }
+}
+
+/// A parsed SPDX license expression
+#[derive(Clone, Debug, PartialEq)]
+pub enum LicenseExpr {
+    Id(String),
+    With(String, String),
+    And(Vec<LicenseExpr>),
+    Or(Vec<LicenseExpr>),
+}
+
+impl LicenseExpr {
+    /// Parses expressions such as `MIT OR (Apache-2.0 AND BSD-3-Clause)` or
+    /// `GPL-2.0-or-later WITH Classpath-exception-2.0`
+    pub fn parse(value: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let spaced = value.replace('(', " ( ").replace(')', " ) ");
+        let tokens: Vec<&str> = spaced.split_whitespace().collect();
+        if tokens.is_empty() {
+            return Err("Empty license expression".into());
+        }
+
+        let mut parser = ExprParser { tokens: &tokens, pos: 0 };
+        let expr = parser.or()?;
+        if parser.pos != tokens.len() {
+            return Err(format!("Unexpected '{}' in license expression '{}'", tokens[parser.pos], value).into());
+        }
+        Ok(expr)
+    }
+
+    /// License identifiers in the expression, without exceptions
+    pub fn ids(&self) -> Vec<&str> {
+        match self {
+            LicenseExpr::Id(id) | LicenseExpr::With(id, _) => vec![id.as_str()],
+            LicenseExpr::And(parts) | LicenseExpr::Or(parts) => parts.iter().flat_map(LicenseExpr::ids).collect(),
+        }
+    }
+
+    /// The category of the least demanding way to comply: the best
+    /// alternative of an OR, the most demanding part of an AND
+    pub fn category(&self) -> LicenseCategory {
+        match self {
+            LicenseExpr::Id(id) | LicenseExpr::With(id, _) => LicenseCategory::of(id),
+            LicenseExpr::And(parts) => parts.iter().map(LicenseExpr::category).max().unwrap_or(LicenseCategory::Unknown),
+            LicenseExpr::Or(parts) => parts.iter().map(LicenseExpr::category).min().unwrap_or(LicenseCategory::Unknown),
+        }
+    }
+
+    /// Whether some choice of alternatives satisfies the policy
+    pub fn permitted(&self, policy: &LicensePolicy) -> bool {
+        match self {
+            LicenseExpr::Id(id) => license_permitted(policy, &[id.as_str()]),
+            LicenseExpr::With(id, exception) => {
+                license_permitted(policy, &[id.as_str(), &format!("{} WITH {}", id, exception)])
+            }
+            LicenseExpr::And(parts) => parts.iter().all(|part| part.permitted(policy)),
+            LicenseExpr::Or(parts) => parts.iter().any(|part| part.permitted(policy)),
+        }
+    }
+
+    fn canonicalize(self) -> Self {
+        match self {
+            LicenseExpr::Id(id) => LicenseExpr::Id(canonical_license(&id).unwrap_or(id)),
+            LicenseExpr::With(id, exception) => LicenseExpr::With(canonical_license(&id).unwrap_or(id), exception),
+            LicenseExpr::And(parts) => LicenseExpr::And(parts.into_iter().map(Self::canonicalize).collect()),
+            LicenseExpr::Or(parts) => LicenseExpr::Or(parts.into_iter().map(Self::canonicalize).collect()),
+        }
+    }
+}
+
+impl fmt::Display for LicenseExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |f: &mut fmt::Formatter<'_>, parts: &[LicenseExpr], op: &str| {
+            let parts: Vec<String> = parts
+                .iter()
+                .map(|part| match part {
+                    LicenseExpr::And(_) | LicenseExpr::Or(_) => format!("({})", part),
+                    _ => part.to_string(),
+                })
+                .collect();
+            write!(f, "{}", parts.join(op))
+        };
+        match self {
+            LicenseExpr::Id(id) => write!(f, "{}", id),
+            LicenseExpr::With(id, exception) => write!(f, "{} WITH {}", id, exception),
+            LicenseExpr::And(parts) => join(f, parts, " AND "),
+            LicenseExpr::Or(parts) => join(f, parts, " OR "),
+        }
+    }
+}
+
+// Recursive descent over `or := and (OR and)*`, `and := term (AND term)*`,
+// `term := '(' or ')' | id [WITH id]`; operators are accepted in any case
+struct ExprParser<'a> {
+    tokens: &'a [&'a str],
+    pos: usize,
+}
+
+impl ExprParser<'_> {
+    fn peek_is(&self, keyword: &str) -> bool {
+        self.tokens.get(self.pos).is_some_and(|t| t.eq_ignore_ascii_case(keyword))
+    }
+
+    fn or(&mut self) -> Result<LicenseExpr, Box<dyn std::error::Error>> {
+        let mut parts = vec![self.and()?];
+        while self.peek_is("OR") {
+            self.pos += 1;
+            parts.push(self.and()?);
+        }
+        Ok(if parts.len() == 1 { parts.remove(0) } else { LicenseExpr::Or(parts) })
+    }
+
+    fn and(&mut self) -> Result<LicenseExpr, Box<dyn std::error::Error>> {
+        let mut parts = vec![self.term()?];
+        while self.peek_is("AND") {
+            self.pos += 1;
+            parts.push(self.term()?);
+        }
+        Ok(if parts.len() == 1 { parts.remove(0) } else { LicenseExpr::And(parts) })
+    }
+
+    fn term(&mut self) -> Result<LicenseExpr, Box<dyn std::error::Error>> {
+        let token = *self.tokens.get(self.pos).ok_or("Incomplete license expression")?;
+        self.pos += 1;
+
+        if token == "(" {
+            let expr = self.or()?;
+            if self.tokens.get(self.pos) != Some(&")") {
+                return Err("Unbalanced parentheses in license expression".into());
+            }
+            self.pos += 1;
+            return Ok(expr);
+        }
+        if token == ")" || ["AND", "OR", "WITH"].iter().any(|op| token.eq_ignore_ascii_case(op)) {
+            return Err(format!("Unexpected '{}' in license expression", token).into());
+        }
+
+        if self.peek_is("WITH") {
+            self.pos += 1;
+            let exception = *self.tokens.get(self.pos).ok_or("Missing exception after WITH")?;
+            self.pos += 1;
+            return Ok(LicenseExpr::With(token.to_string(), exception.to_string()));
+        }
+        Ok(LicenseExpr::Id(token.to_string()))
+    }
+}
+
+/// SPDX identifier for a license name, e.g. "Apache Software License" ->
+/// "Apache-2.0"; a trailing "(LGPLv3)"-style abbreviation is tried as well
+pub fn canonical_license(name: &str) -> Option<String> {
+    let name = name.trim();
+    if let Some(id) = KNOWN_LICENSES.iter().find(|id| id.eq_ignore_ascii_case(name)) {
+        return Some(id.to_string());
+    }
+    if name.starts_with("LicenseRef-") {
+        return Some(name.to_string());
+    }
+
+    let lowered = name.to_lowercase();
+    if let Some((_, id)) = LICENSE_ALIASES.iter().find(|(alias, _)| *alias == lowered) {
+        return Some(id.to_string());
+    }
+
+    let (head, abbreviation) = lowered.strip_suffix(')')?.rsplit_once('(')?;
+    canonical_license(head).or_else(|| canonical_license(abbreviation))
+}
+
+/// Determines a distribution's license from License-Expression, then the
+/// License field, then its trove classifiers
+pub fn classify_license(dist: &DistMetadata) -> Option<LicenseExpr> {
+    if let Some(expression) = &dist.license_expression {
+        if let Ok(expr) = LicenseExpr::parse(expression) {
+            return Some(expr.canonicalize());
+        }
+    }
+
+    // License often holds the full license text; only short values are names
+    let license = dist.license.as_deref().unwrap_or("").trim();
+    if !license.is_empty() && !license.eq_ignore_ascii_case("UNKNOWN") && !license.contains('\n') && license.len() <= 100 {
+        if let Some(id) = canonical_license(license) {
+            return Some(LicenseExpr::Id(id));
+        }
+        if let Ok(expr) = LicenseExpr::parse(license) {
+            let expr = expr.canonicalize();
+            if expr.ids().iter().all(|id| canonical_license(id).is_some()) {
+                return Some(expr);
+            }
+        }
+    }
+
+    let mut from_classifiers: Vec<LicenseExpr> = Vec::new();
+    for classifier in &dist.classifiers {
+        let Some(name) = classifier.strip_prefix("License ::") else { continue };
+        let name = name.rsplit("::").next().unwrap_or(name);
+        if let Some(id) = canonical_license(name) {
+            let expr = LicenseExpr::Id(id);
+            if !from_classifiers.contains(&expr) {
+                from_classifiers.push(expr);
+            }
+        }
+    }
+    match from_classifiers.len() {
+        0 => None,
+        1 => from_classifiers.pop(),
+        _ => Some(LicenseExpr::Or(from_classifiers)),
+    }
+}
+
+fn policy_matches(pattern: &str, license: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => license.to_lowercase().starts_with(&prefix.to_lowercase()),
+        None => pattern.eq_ignore_ascii_case(license),
+    }
+}
+
+// A license (or its "X WITH exception" form) is denied if any spelling is
+// denied, and allowed if any spelling is allowed
+fn license_permitted(policy: &LicensePolicy, spellings: &[&str]) -> bool {
+    let denied = spellings.iter().any(|l| policy.deny.iter().any(|p| policy_matches(p, l)));
+    let allowed = policy.allow.is_empty() || spellings.iter().any(|l| policy.allow.iter().any(|p| policy_matches(p, l)));
+    !denied && allowed
+}
+
+/// Core metadata of every distribution installed in an environment
+pub fn read_installed_metadata(env_dir: &Path) -> Vec<DistMetadata> {
+    WalkDir::new(env_dir)
+        .max_depth(4)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_dir() && e.file_name().to_string_lossy().ends_with(".dist-info"))
+        .filter_map(|dist_info| {
+            let content = fs::read_to_string(dist_info.path().join("METADATA")).ok()?;
+            let mut dist = parse_metadata(&content)?;
+            dist.installed_size = record_size(&dist_info.path().join("RECORD"));
+            Some(dist)
+        })
+        .collect()
+}
+
+/// Sum of the file sizes listed in a RECORD (path,hash,size lines)
+fn record_size(path: &Path) -> Option<u64> {
+    let content = fs::read_to_string(path).ok()?;
+    Some(content.lines().filter_map(|line| line.rsplit(',').next()?.trim().parse::<u64>().ok()).sum())
+}
+
+/// Parses the RFC 822 style header block of a METADATA file
+pub fn parse_metadata(content: &str) -> Option<DistMetadata> {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in content.lines() {
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push('\n');
+                value.push_str(line.trim());
+            }
+        } else if let Some((key, value)) = line.split_once(':') {
+            headers.push((key.trim().to_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    let get = |key: &str| headers.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone());
+    let all = |key: &str| headers.iter().filter(|(k, _)| k == key).map(|(_, v)| v.clone()).collect::<Vec<_>>();
+
+    let requires = all("requires-dist")
+        .into_iter()
+        .filter(|requirement| !requirement.split_once(';').is_some_and(|(_, marker)| marker.contains("extra")))
+        .filter_map(|requirement| {
+            let end = requirement
+                .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+                .unwrap_or(requirement.len());
+            let name = &requirement[..end];
+            (!name.is_empty()).then(|| normalize_dist_name(name))
+        })
+        .collect();
+
+    Some(DistMetadata {
+        name: get("name")?,
+        version: get("version").unwrap_or_default(),
+        license_expression: get("license-expression"),
+        license: get("license"),
+        classifiers: all("classifier"),
+        requires,
+        requirements: all("requires-dist"),
+        installed_size: None,
+    })
+}
+
+// Checks installed distributions against the project's license policy
+pub struct LicenseChecker {
+    pub policy: LicensePolicy,
+    pub dists: Vec<DistMetadata>,
+}
+
+impl LicenseChecker {
+    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        let policy = match project_tool_section("licenses")? {
+            Some(section) => section
+                .try_into()
+                .map_err(|e| format!("Invalid [tool.sa.licenses]: {}", e))?,
+            None => LicensePolicy::default(),
+        };
+
+        let env_dir = venv_dir();
+        if !env_dir.is_dir() {
+            return Err("No project environment found. Create one with 'sa env create'".into());
+        }
+
+        let mut dists = read_installed_metadata(&env_dir);
+        dists.sort_by_key(|dist| normalize_dist_name(&dist.name));
+        Ok(LicenseChecker { policy, dists })
+    }
+
+    /// Classifies every distribution and flags policy violations. Packages
+    /// with an unknown license only violate a policy that has an allow list.
+    pub fn check(&self) -> Vec<LicenseFinding> {
+        let ignored: HashSet<String> = self.policy.ignore.iter().map(|name| normalize_dist_name(name)).collect();
+
+        self.dists
+            .iter()
+            .filter(|dist| !ignored.contains(&normalize_dist_name(&dist.name)))
+            .map(|dist| {
+                let expr = classify_license(dist);
+                let violation = match &expr {
+                    None if !self.policy.allow.is_empty() => Some("license could not be determined".to_string()),
+                    None => None,
+                    Some(expr) if expr.permitted(&self.policy) => None,
+                    Some(expr) => {
+                        let denied: Vec<&str> = expr
+                            .ids()
+                            .into_iter()
+                            .filter(|id| self.policy.deny.iter().any(|p| policy_matches(p, id)))
+                            .collect();
+                        Some(if denied.is_empty() {
+                            "not in the allow list".to_string()
+                        } else {
+                            format!("denied ({})", denied.join(", "))
+                        })
+                    }
+                };
+
+                LicenseFinding {
+                    name: dist.name.clone(),
+                    version: dist.version.clone(),
+                    license: expr.map(|expr| expr.to_string()),
+                    path: if violation.is_some() { self.dependency_path(&dist.name) } else { Vec::new() },
+                    violation,
+                }
+            })
+            .collect()
+    }
+
+    /// Shortest chain from a top-level distribution (one nothing else
+    /// requires) down to `name`
+    pub fn dependency_path(&self, name: &str) -> Vec<String> {
+        let names: HashMap<String, &str> = self
+            .dists
+            .iter()
+            .map(|dist| (normalize_dist_name(&dist.name), dist.name.as_str()))
+            .collect();
+        let required: HashSet<&String> = self.dists.iter().flat_map(|dist| &dist.requires).collect();
+        let target = normalize_dist_name(name);
+
+        let mut queue: VecDeque<String> = names.keys().filter(|n| !required.contains(n)).cloned().collect();
+        let mut parent: HashMap<String, Option<String>> = queue.iter().map(|n| (n.clone(), None)).collect();
+
+        while let Some(current) = queue.pop_front() {
+            if current == target {
+                let mut path = vec![current.clone()];
+                let mut node = current;
+                while let Some(Some(up)) = parent.get(&node) {
+                    path.push(up.clone());
+                    node = up.clone();
+                }
+                path.reverse();
+                return path.into_iter().map(|n| names.get(&n).map(|s| s.to_string()).unwrap_or(n)).collect();
+            }
+            let Some(dist) = self.dists.iter().find(|d| normalize_dist_name(&d.name) == current) else { continue };
+            for dep in &dist.requires {
+                if names.contains_key(dep) && !parent.contains_key(dep) {
+                    parent.insert(dep.clone(), Some(current.clone()));
+                    queue.push_back(dep.clone());
+                }
+            }
+        }
+
+        vec![name.to_string()]
+    }
+}
@@ -0,0 +1,80 @@
+use std::path::Path;
+use colored::*;
+use tokio::process::Command;
+use crate::modules::cache::PackageCache;
+use crate::modules::models::SAConfig;
+
+// Runs the external malware scanner configured as `security.artifact_scanner`
+// in ~/.config/sa/config.toml (e.g. "clamscan --no-summary {}") against each
+// artifact after download and before install. Arguments are split on
+// whitespace and `{}` is replaced by the artifact path, which is appended when
+// absent. A non-zero exit blocks the install. Verdicts are cached per
+// artifact hash and scanner command, so each file is scanned once.
+pub struct MalwareScanner {
+    command: Option<String>,
+}
+
+impl MalwareScanner {
+    pub fn from_config() -> Result<Self, Box<dyn std::error::Error>> {
+        let command = SAConfig::load()?.security.artifact_scanner.filter(|command| !command.trim().is_empty());
+        Ok(MalwareScanner { command })
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.command.is_some()
+    }
+
+    /// Scans one artifact (or reuses the cached verdict for its hash)
+    pub async fn check(&self, cache: &PackageCache, path: &Path, hash: &str, label: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(command) = &self.command else {
+            return Ok(());
+        };
+
+        let (passed, output) = match cache.scan_verdict(hash, command)? {
+            Some(verdict) => verdict,
+            None => {
+                let verdict = run_scanner(command, path).await?;
+                cache.record_scan_verdict(hash, command, verdict.0, &verdict.1)?;
+                if verdict.0 {
+                    println!("  {} {} passed the malware scan", "🛡️ ".green(), label);
+                }
+                verdict
+            }
+        };
+
+        if passed {
+            Ok(())
+        } else {
+            Err(format!("Malware scanner rejected {} (sha256 {}): {}", label, hash, output).into())
+        }
+    }
+}
+
+/// Runs the scanner, returning whether it passed and the tail of its output
+async fn run_scanner(command: &str, path: &Path) -> Result<(bool, String), Box<dyn std::error::Error>> {
+    let path = path.to_string_lossy();
+    let mut args: Vec<String> = command.split_whitespace().map(str::to_string).collect();
+    if args.iter().any(|arg| arg.contains("{}")) {
+        for arg in &mut args {
+            *arg = arg.replace("{}", &path);
+        }
+    } else {
+        args.push(path.to_string());
+    }
+
+    let output = Command::new(&args[0])
+        .args(&args[1..])
+        .output()
+        .await
+        .map_err(|e| format!("Could not run malware scanner '{}': {}", args[0], e))?;
+
+    let text = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    let lines: Vec<&str> = text.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+    let tail = lines[lines.len().saturating_sub(3)..].join("; ");
+    let summary = match output.status.code() {
+        Some(code) if !output.status.success() && tail.is_empty() => format!("exit {}", code),
+        Some(code) if !output.status.success() => format!("exit {}: {}", code, tail),
+        _ => tail,
+    };
+    Ok((output.status.success(), summary))
+}
@@ -1,13 +1,85 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
 use std::path::PathBuf;
 use std::fs;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use chrono::Utc;
+use colored::*;
 use dirs;
-use reqwest::Client;
-use crate::modules::models::Mirror;
+use futures_util::future::join_all;
+use regex::Regex;
+use reqwest::{Client, Url};
+use crate::modules::config::{parse_duration, project_tool_section};
+use crate::modules::credentials::{authorize, url_with_credentials, CredentialStore};
+use crate::modules::index_kinds::status_url;
+use crate::modules::installer::{artifact_filename, normalize_dist_name, resolve_install_plan};
+use crate::modules::models::{InstallPlanItem, Mirror, MirrorAuth, MirrorBenchmark, MirrorCredentials, MirrorOverrides, MirrorProbe, SAConfig, TlsOptions, TransferOptions};
+use crate::modules::pep440::compare_versions;
+use crate::modules::network;
+use crate::modules::simple_api::{self, IndexFile};
 
-// Mirror management
+/// Small, always-present project used to benchmark mirrors
+const BENCHMARK_PROJECT: &str = "six";
+const LATENCY_SAMPLES: usize = 3;
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Re-resolutions allowed while steering projects to their first index
+const MAX_PRECEDENCE_ROUNDS: usize = 5;
+/// Health checks kept per mirror for its rolling success rate
+const HEALTH_WINDOW: usize = 20;
+/// Project page requested by connectivity tests unless a mirror sets its own
+const DEFAULT_PROBE_PATH: &str = "pip/";
+/// Enough of a response to tell whether it is a simple index
+const PROBE_BYTES: usize = 64 * 1024;
+
+/// Mirrors in effect with the source of each entry
+type EffectiveMirrors = Vec<(Mirror, String)>;
+
+/// An index used in this run
+struct SessionIndex {
+    name: String,
+    /// URL with credentials embedded, for pip
+    url: String,
+    host: String,
+    primary: bool,
+    tls: TlsOptions,
+    transfer: TransferOptions,
+}
+
+/// Indexes used in this run, in precedence order. Empty leaves pip's own
+/// configuration alone.
+static SESSION_INDEXES: OnceLock<Vec<SessionIndex>> = OnceLock::new();
+
+/// Points a pip invocation at the indexes selected for this run, with their
+/// TLS and transfer settings
+pub fn apply_session_to_pip(command: &mut tokio::process::Command) {
+    let indexes = SESSION_INDEXES.get().map(Vec::as_slice).unwrap_or_default();
+    let primary = indexes.iter().find(|index| index.primary);
+    if let Some(primary) = primary {
+        let extras: Vec<&str> = indexes.iter().filter(|index| !index.primary).map(|index| index.url.as_str()).collect();
+        command.env("PIP_INDEX_URL", &primary.url);
+        command.env("PIP_EXTRA_INDEX_URL", extras.join(" "));
+    }
+    network::apply_transfer_to_pip(command, primary.map(|index| &index.transfer));
+    let tls: Vec<(String, TlsOptions)> = indexes.iter().map(|index| (index.host.clone(), index.tls.clone())).collect();
+    network::apply_tls_to_pip(command, &tls);
+}
+
+// Mirror management.
+//
+// The indexes used for installs come from, highest precedence first:
+//   1. SA_INDEX_URL and SA_EXTRA_INDEX_URLS (e.g. per CI job)
+//   2. index-url and extra-index-urls in [tool.sa.mirrors] of pyproject.toml
+//   3. ~/.config/sa/mirrors.json
+// The primary index and the supplemental ones are overridden separately, so
+// a project can add extra indexes while keeping the global primary.
 pub struct MirrorManager {
     pub config_path: PathBuf,
     pub mirrors: Vec<Mirror>,
+    pub credentials: CredentialStore,
+    /// The merged view when an override applies, with each entry's source
+    effective: Option<EffectiveMirrors>,
 }
 
 impl MirrorManager {
@@ -28,6 +100,14 @@ impl MirrorManager {
                     is_default: true,
                     last_tested: None,
                     is_active: true,
+                    priority: 0,
+                    role: "primary".to_string(),
+                    kind: "simple".to_string(),
+                    probe_path: None,
+                    json_api: None,
+                    tls: TlsOptions::default(),
+                    transfer: TransferOptions::default(),
+                    recent_checks: Vec::new(),
                 }
             ])
         } else {
@@ -38,27 +118,67 @@ impl MirrorManager {
                     is_default: true,
                     last_tested: None,
                     is_active: true,
+                    priority: 0,
+                    role: "primary".to_string(),
+                    kind: "simple".to_string(),
+                    probe_path: None,
+                    json_api: None,
+                    tls: TlsOptions::default(),
+                    transfer: TransferOptions::default(),
+                    recent_checks: Vec::new(),
                 }
             ]
         };
 
-        Ok(MirrorManager { config_path, mirrors })
+        let source = if config_path.exists() { "mirrors.json" } else { "built-in default" };
+        let effective = apply_overrides(&mirrors, source)?;
+        Ok(MirrorManager { config_path, mirrors, credentials: CredentialStore::load()?, effective })
+    }
+
+    /// Restricts this run to one mirror (`sa add --mirror`), used as the only
+    /// index for metadata and downloads
+    pub fn use_only(&mut self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let mirror = self
+            .effective_mirrors()
+            .into_iter()
+            .map(|(mirror, _)| mirror)
+            .chain(&self.mirrors)
+            .find(|mirror| mirror.name == name)
+            .ok_or_else(|| format!("Mirror '{}' not found; see 'sa mirror list'", name))?;
+        if !mirror.is_active {
+            return Err(format!("Mirror '{}' is inactive; check it with 'sa mirror test {}'", name, name).into());
+        }
+
+        let mut mirror = mirror.clone();
+        mirror.is_default = true;
+        mirror.role = "primary".to_string();
+        mirror.priority = 0;
+        self.effective = Some(vec![(mirror, "--mirror".to_string())]);
+        Ok(())
     }
 
-    pub fn add_mirror(&mut self, name: String, url: String, set_default: bool) -> Result<(), Box<dyn std::error::Error>> {
-        if set_default {
-            for mirror in &mut self.mirrors {
-                mirror.is_default = false;
+    /// The mirrors in use here, with where each came from
+    pub fn effective_mirrors(&self) -> Vec<(&Mirror, &str)> {
+        match &self.effective {
+            Some(effective) => effective.iter().map(|(mirror, source)| (mirror, source.as_str())).collect(),
+            None => self.mirrors.iter().map(|mirror| (mirror, "mirrors.json")).collect(),
+        }
+    }
+
+    pub fn add_mirror(&mut self, mirror: Mirror, credentials: Option<MirrorCredentials>) -> Result<(), Box<dyn std::error::Error>> {
+        if mirror.is_default && mirror.role != "primary" {
+            return Err("Only a primary mirror can be the default".into());
+        }
+        if mirror.is_default {
+            for existing in &mut self.mirrors {
+                existing.is_default = false;
             }
         }
 
-        self.mirrors.push(Mirror {
-            name,
-            url,
-            is_default: set_default,
-            last_tested: None,
-            is_active: true,
-        });
+        if let Some(credentials) = credentials {
+            self.credentials.set(&mirror.name, credentials)?;
+        }
+        self.mirrors.push(mirror);
 
         self.save_config()?;
         Ok(())
@@ -66,27 +186,419 @@ impl MirrorManager {
 
     pub fn remove_mirror(&mut self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
         self.mirrors.retain(|mirror| mirror.name != name);
+        self.credentials.remove(name)?;
         self.save_config()?;
         Ok(())
     }
 
-    #[allow(dead_code)]
     pub fn get_default_mirror(&self) -> Option<&Mirror> {
-        self.mirrors.iter().find(|mirror| mirror.is_default && mirror.is_active)
+        self.effective_mirrors().into_iter().map(|(mirror, _)| mirror).find(|mirror| mirror.is_default && mirror.is_active)
+    }
+
+    /// Active mirrors in effect, in precedence order (priority, then
+    /// configuration order)
+    pub fn by_precedence(&self) -> Vec<&Mirror> {
+        let mut mirrors: Vec<&Mirror> = self
+            .effective_mirrors()
+            .into_iter()
+            .map(|(mirror, _)| mirror)
+            .filter(|mirror| mirror.is_active)
+            .collect();
+        mirrors.sort_by_key(|mirror| mirror.priority);
+        mirrors
+    }
+
+    /// Name of the configured mirror an artifact URL was served by, matched
+    /// on host. Files from pypi.org live on files.pythonhosted.org.
+    pub fn mirror_for_url(&self, url: &str) -> String {
+        let Some(host) = url_host(url) else {
+            return "local".to_string();
+        };
+
+        self.mirrors
+            .iter()
+            .find(|mirror| {
+                let mirror_host = url_host(&mirror.url).unwrap_or_default();
+                mirror_host == host || (mirror_host == "pypi.org" && host == "files.pythonhosted.org")
+            })
+            .map(|mirror| mirror.name.clone())
+            .unwrap_or(host)
+    }
+
+    /// The configured mirror on the same host as an index or artifact URL
+    pub fn mirror_on_host(&self, url: &str) -> Option<&Mirror> {
+        let host = url_host(url)?;
+        self.effective_mirrors()
+            .into_iter()
+            .map(|(mirror, _)| mirror)
+            .chain(&self.mirrors)
+            .find(|mirror| url_host(&mirror.url).as_deref() == Some(host.as_str()))
+    }
+
+    /// Credentials for requests to an index or artifact URL, taken from the
+    /// configured mirror on the same host
+    pub fn auth_for_url(&self, url: &str) -> Result<Option<MirrorAuth>, Box<dyn std::error::Error>> {
+        match self.mirror_on_host(url) {
+            Some(mirror) => self.credentials.auth_for(mirror),
+            None => Ok(None),
+        }
+    }
+
+    /// Checks that a mirror answers like a simple index and whether it serves
+    /// the JSON form. The mirror's probe path (a project page, "pip/" by
+    /// default) is requested first, then the index root for indexes without
+    /// it. Index servers with a status endpoint must pass that too.
+    pub async fn test_mirror(&self, name: &str) -> Result<MirrorProbe, Box<dyn std::error::Error>> {
+        let mirror = self.mirrors.iter()
+            .find(|m| m.name == name)
+            .ok_or("Mirror not found")?;
+
+        let client = network::client_builder_for(Some(mirror))?.timeout(PROBE_TIMEOUT).build()?;
+        let auth = self.credentials.auth_for(mirror)?;
+
+        if let Some(status) = status_url(mirror) {
+            match authorize(client.get(&status), auth.as_ref()).send().await {
+                Ok(response) if response.status().is_success() => {}
+                Ok(response) => return Ok(failed_probe(format!("{} returned status {}", status, response.status()))),
+                Err(e) => return Ok(failed_probe(format!("{}: {}", status, e))),
+            }
+        }
+
+        let index = mirror.url.trim_end_matches('/');
+        let probe_path = mirror.probe_path.as_deref().unwrap_or(DEFAULT_PROBE_PATH).trim_start_matches('/');
+        let mut problem = None;
+        for url in [format!("{}/{}", index, probe_path), format!("{}/", index)] {
+            let request = authorize(client.get(&url), auth.as_ref()).header(reqwest::header::ACCEPT, simple_api::ACCEPT_JSON);
+            let mut response = match request.send().await {
+                Ok(response) => response,
+                Err(e) => return Ok(failed_probe(format!("{}: {}", url, e))),
+            };
+            if !response.status().is_success() {
+                problem = Some(format!("{} returned status {}", url, response.status()));
+                continue;
+            }
+
+            let content_type = response
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_lowercase();
+            let mut head = Vec::new();
+            while head.len() < PROBE_BYTES {
+                match response.chunk().await {
+                    Ok(Some(chunk)) => head.extend_from_slice(&chunk),
+                    Ok(None) => break,
+                    Err(e) => return Ok(failed_probe(format!("{}: {}", url, e))),
+                }
+            }
+            if !simple_api::looks_like_simple_index(&content_type, &head) {
+                return Ok(failed_probe(format!("{} does not look like a simple index", url)));
+            }
+            return Ok(MirrorProbe { problem: None, json_api: Some(simple_api::is_json(&content_type)) });
+        }
+        Ok(MirrorProbe { problem, json_api: None })
     }
 
-    pub async fn test_mirror(&self, name: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    /// Records the outcome of a health check, deactivating a mirror after
+    /// `deactivate_after` consecutive failures and reactivating it once it
+    /// passes again, and remembers whether it serves the JSON API. Only
+    /// persisted once mirrors have been configured.
+    pub fn record_check(&mut self, name: &str, probe: &MirrorProbe) -> Result<(), Box<dyn std::error::Error>> {
+        let passed = probe.problem.is_none();
+        let deactivate_after = SAConfig::load()?.mirror.deactivate_after;
+        let other_primaries = self
+            .mirrors
+            .iter()
+            .filter(|mirror| mirror.name != name && mirror.is_active && mirror.role == "primary")
+            .count();
+        let mirror = self.mirrors.iter_mut().find(|m| m.name == name).ok_or("Mirror not found")?;
+
+        mirror.last_tested = Some(Utc::now());
+        if probe.json_api.is_some() {
+            mirror.json_api = probe.json_api;
+        }
+        mirror.recent_checks.push(passed);
+        let excess = mirror.recent_checks.len().saturating_sub(HEALTH_WINDOW);
+        mirror.recent_checks.drain(..excess);
+
+        let failing = deactivate_after > 0
+            && mirror.recent_checks.len() >= deactivate_after
+            && mirror.recent_checks.iter().rev().take(deactivate_after).all(|passed| !passed);
+        if passed && !mirror.is_active {
+            mirror.is_active = true;
+            println!("{}", format!("✅ Mirror '{}' is reachable again and has been reactivated", name).green());
+        } else if failing && mirror.is_active {
+            // Never leave installs without a primary index
+            if mirror.role == "primary" && other_primaries == 0 {
+                println!("{}", format!("Warning: mirror '{}' failed its last {} health checks, but it is the only active primary mirror", name, deactivate_after).yellow());
+            } else {
+                mirror.is_active = false;
+                println!("{}", format!("⚠️  Mirror '{}' failed its last {} health checks and has been deactivated", name, deactivate_after).yellow());
+            }
+        }
+
+        if self.config_path.exists() {
+            self.save_config()?;
+        }
+        Ok(())
+    }
+
+    /// Re-tests every mirror whose last check is older than
+    /// `mirror.health_check`, at most once per interval
+    pub async fn check_health(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let interval = SAConfig::load()?.mirror.health_check;
+        if interval == "off" || !self.config_path.exists() {
+            return Ok(());
+        }
+        let interval = parse_duration(&interval)?;
+        let now = Utc::now();
+        let due: Vec<String> = self
+            .mirrors
+            .iter()
+            .filter(|mirror| mirror.last_tested.is_none_or(|tested| now - tested >= interval))
+            .map(|mirror| mirror.name.clone())
+            .collect();
+
+        let results = join_all(due.iter().map(|name| self.test_mirror(name))).await;
+        for (name, result) in due.iter().zip(results) {
+            let probe = result.unwrap_or_else(|e| failed_probe(e.to_string()));
+            self.record_check(name, &probe)?;
+        }
+        Ok(())
+    }
+
+    /// Measures a mirror's latency (best of a few index page requests) and
+    /// its throughput downloading a wheel of a small known project
+    pub async fn benchmark(&self, name: &str) -> Result<MirrorBenchmark, Box<dyn std::error::Error>> {
         let mirror = self.mirrors.iter()
             .find(|m| m.name == name)
             .ok_or("Mirror not found")?;
+        let client = network::client_builder_for(Some(mirror))?.timeout(PROBE_TIMEOUT).build()?;
+        let auth = self.credentials.auth_for(mirror)?;
 
-        let client = Client::new();
-        let test_url = format!("{}/pip/", mirror.url);
+        let mut result = MirrorBenchmark { name: mirror.name.clone(), latency: None, throughput: None, error: None };
+        match measure_latency(&client, mirror, auth.as_ref()).await {
+            Ok(latency) => result.latency = Some(latency),
+            Err(e) => {
+                result.error = Some(e.to_string());
+                return Ok(result);
+            }
+        }
+        match measure_throughput(&client, mirror, auth.as_ref()).await {
+            Ok(throughput) => result.throughput = Some(throughput),
+            Err(e) => result.error = Some(e.to_string()),
+        }
+        Ok(result)
+    }
 
-        match client.head(&test_url).send().await {
-            Ok(response) => Ok(response.status().is_success()),
-            Err(_) => Ok(false),
+    /// Picks the indexes for this run: one primary mirror according to
+    /// `mirror.preferred` in the SA config, plus every active supplemental
+    /// mirror. Only applies once mirrors have been configured; until then
+    /// pip keeps using its own index settings.
+    pub async fn select_session_mirror(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if SESSION_INDEXES.get().is_some() {
+            return Ok(());
+        }
+        let mut selected = Vec::new();
+        if self.config_path.exists() || self.effective.is_some() {
+            let primary = self
+                .preferred_mirror()
+                .await?
+                .ok_or("No active primary mirror; add one with 'sa mirror add <name> <url>'")?;
+            for mirror in self.by_precedence() {
+                if mirror.role == "primary" && mirror.name != primary.name {
+                    continue;
+                }
+                let auth = self.credentials.auth_for(mirror)?;
+                selected.push(SessionIndex {
+                    name: mirror.name.clone(),
+                    url: url_with_credentials(&mirror.url, auth.as_ref())?,
+                    host: url_host(&mirror.url).unwrap_or_default(),
+                    primary: mirror.role == "primary",
+                    tls: mirror.tls.clone(),
+                    transfer: mirror.transfer.clone(),
+                });
+            }
         }
+        let _ = SESSION_INDEXES.set(selected);
+        Ok(())
+    }
+
+    async fn preferred_mirror(&self) -> Result<Option<&Mirror>, Box<dyn std::error::Error>> {
+        let active: Vec<&Mirror> = self.by_precedence().into_iter().filter(|mirror| mirror.role == "primary").collect();
+        let fallback = self.get_default_mirror().filter(|mirror| mirror.role == "primary").or(active.first().copied());
+        // An overridden index is used as is
+        let overridden = self
+            .effective
+            .as_ref()
+            .is_some_and(|effective| effective.iter().any(|(mirror, source)| mirror.role == "primary" && is_override(source)));
+        if overridden {
+            return Ok(fallback);
+        }
+
+        match SAConfig::load()?.mirror.preferred.as_str() {
+            "default" => Ok(fallback),
+            "fastest" if active.len() <= 1 => Ok(fallback),
+            "fastest" => {
+                let mut probes = Vec::new();
+                for mirror in &active {
+                    let client = network::client_builder_for(Some(mirror))?.timeout(PROBE_TIMEOUT).build()?;
+                    probes.push((client, self.credentials.auth_for(mirror)?));
+                }
+                let latencies = join_all(
+                    active.iter().zip(&probes).map(|(mirror, (client, auth))| measure_latency(client, mirror, auth.as_ref())),
+                ).await;
+                let fastest = active
+                    .iter()
+                    .zip(latencies)
+                    .filter_map(|(mirror, latency)| latency.ok().map(|latency| (latency, *mirror)))
+                    .min_by_key(|(latency, _)| *latency);
+                match fastest {
+                    Some((latency, mirror)) => {
+                        println!("  {} using mirror '{}' ({} ms)", "🪞".cyan(), mirror.name, latency.as_millis());
+                        Ok(Some(mirror))
+                    }
+                    None => {
+                        println!("{}", "Warning: no mirror answered the latency probe; using the default mirror".yellow());
+                        Ok(fallback)
+                    }
+                }
+            }
+            name => active
+                .iter()
+                .find(|mirror| mirror.name == name)
+                .map(|mirror| Some(*mirror))
+                .ok_or_else(|| format!("Preferred mirror '{}' is not configured, inactive or not a primary mirror", name).into()),
+        }
+    }
+
+    /// Resolves like pip with the session's indexes, except that a project is
+    /// only taken from the first index (in precedence order) that has it.
+    /// pip merges all indexes and picks the highest version anywhere, so
+    /// versions a later index adds are excluded and the plan re-resolved.
+    pub async fn resolve_with_precedence(&self, requirements: &[String]) -> Result<Option<Vec<InstallPlanItem>>, Box<dyn std::error::Error>> {
+        let order: Vec<String> = SESSION_INDEXES
+            .get()
+            .map(|indexes| indexes.iter().map(|index| index.name.clone()).collect())
+            .unwrap_or_default();
+        if order.len() <= 1 {
+            return resolve_install_plan(requirements).await;
+        }
+
+        let mut pages: BTreeMap<(String, String), Option<ProjectPage>> = BTreeMap::new();
+        let mut excluded: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+
+        for _ in 0..MAX_PRECEDENCE_ROUNDS {
+            let mut args = requirements.to_vec();
+            let mut constraints = tempfile::Builder::new().suffix(".txt").tempfile()?;
+            for (project, versions) in &excluded {
+                let specifier: Vec<String> = versions.iter().map(|version| format!("!={}", version)).collect();
+                writeln!(constraints, "{}{}", project, specifier.join(","))?;
+            }
+            if !excluded.is_empty() {
+                args.push("-c".to_string());
+                args.push(constraints.path().to_string_lossy().to_string());
+            }
+            let Some(plan) = resolve_install_plan(&args).await? else {
+                return Ok(None);
+            };
+
+            let mut plan = plan;
+            let mut steered = false;
+            for item in plan.iter_mut() {
+                let served_by = self.mirror_for_url(&item.url);
+                let Some(rank) = order.iter().position(|name| *name == served_by) else {
+                    continue;
+                };
+                let project = normalize_dist_name(&item.name);
+
+                for owner in &order[..rank] {
+                    let Some(page) = self.project_page(&mut pages, owner, &project).await? else {
+                        continue;
+                    };
+                    // The earlier index has the project, so it alone may supply it
+                    if page.has_version(&item.version) {
+                        let published = page.version_hashes(&item.version);
+                        match item.sha256.as_ref().map(|hash| hash.to_lowercase()) {
+                            Some(hash) if published.contains(hash.as_str()) => break,
+                            Some(_) if !published.is_empty() => {
+                                return Err(format!(
+                                    "{}=={} from '{}' is not the file '{}' publishes; refusing it as possible dependency confusion",
+                                    item.name, item.version, served_by, owner
+                                ).into())
+                            }
+                            _ => {}
+                        }
+                        // No digests to compare: take the file from the owner
+                        let filename = artifact_filename(&item.url);
+                        let (url, sha256) = page.files.get(&filename).cloned().ok_or_else(|| {
+                            format!("'{}' publishes {}=={} but not {}, which only '{}' offers; pin another version", owner, item.name, item.version, filename, served_by)
+                        })?;
+                        item.url = url;
+                        item.sha256 = sha256;
+                        break;
+                    }
+                    let later = self.project_page(&mut pages, &served_by, &project).await?.unwrap_or_default();
+                    let skip = excluded.entry(project.clone()).or_default();
+                    skip.insert(item.version.clone());
+                    skip.extend(later.versions.iter().filter(|version| !page.has_version(version)).cloned());
+                    println!(
+                        "  {} {} is published on '{}'; ignoring the versions only '{}' offers",
+                        "🪞".cyan(), item.name, owner, served_by
+                    );
+                    steered = true;
+                    break;
+                }
+            }
+            if !steered {
+                return Ok(Some(plan));
+            }
+        }
+        Err("Could not settle which index supplies each package; pin the conflicting packages".into())
+    }
+
+    /// A project's simple index page on a mirror, or `None` if the mirror
+    /// does not have the project
+    async fn project_page(
+        &self,
+        pages: &mut BTreeMap<(String, String), Option<ProjectPage>>,
+        mirror: &str,
+        project: &str,
+    ) -> Result<Option<ProjectPage>, Box<dyn std::error::Error>> {
+        let key = (mirror.to_string(), project.to_string());
+        if let Some(page) = pages.get(&key) {
+            return Ok(page.clone());
+        }
+        let mirror = self.mirrors.iter().find(|m| m.name == key.0).ok_or("Mirror not found")?;
+        let page = self.fetch_project_files(mirror, project).await?.map(|files| ProjectPage::from_files(&files));
+        pages.insert(key, page.clone());
+        Ok(page)
+    }
+
+    /// The files on a project's page on a mirror, or None when the mirror
+    /// does not have the project. The JSON form is asked for unless the last
+    /// test found the mirror only serves HTML.
+    pub async fn fetch_project_files(&self, mirror: &Mirror, project: &str) -> Result<Option<Vec<IndexFile>>, Box<dyn std::error::Error>> {
+        let client = network::client_builder_for(Some(mirror))?.timeout(PROBE_TIMEOUT).build()?;
+        let auth = self.credentials.auth_for(mirror)?;
+        let (retries, _) = network::transfer_limits(Some(mirror))?;
+        let accept = if mirror.json_api == Some(false) { simple_api::ACCEPT_HTML } else { simple_api::ACCEPT_JSON };
+        let request = authorize(client.get(project_url(mirror, project)), auth.as_ref()).header(reqwest::header::ACCEPT, accept);
+        let response = network::send_with_retries(request, retries).await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = response.error_for_status()?;
+        let base = response.url().clone();
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        let body = response.text().await?;
+        Ok(Some(simple_api::parse_project_page(&base, &content_type, &body)?))
     }
 
     fn save_config(&self) -> Result<(), Box<dyn std::error::Error>> {
@@ -95,3 +607,191 @@ impl MirrorManager {
         Ok(())
     }
 }
+
+/// The mirror list after SA_INDEX_URL, SA_EXTRA_INDEX_URLS and
+/// [tool.sa.mirrors], or None if none of them is set
+fn apply_overrides(configured: &[Mirror], configured_source: &str) -> Result<Option<EffectiveMirrors>, Box<dyn std::error::Error>> {
+    let project: MirrorOverrides = match project_tool_section("mirrors")? {
+        Some(section) => section.try_into().map_err(|e| format!("Invalid [tool.sa.mirrors]: {}", e))?,
+        None => MirrorOverrides::default(),
+    };
+    let env = |name: &str| std::env::var(name).ok().filter(|value| !value.trim().is_empty());
+
+    let index = match env("SA_INDEX_URL") {
+        Some(url) => Some((url, "SA_INDEX_URL")),
+        None => project.index_url.map(|url| (url, "pyproject.toml")),
+    };
+    let extras = match env("SA_EXTRA_INDEX_URLS") {
+        Some(urls) => Some((urls.split([',', ' ', '\n']).filter(|url| !url.is_empty()).map(str::to_string).collect(), "SA_EXTRA_INDEX_URLS")),
+        None => project.extra_index_urls.map(|urls| (urls, "pyproject.toml")),
+    };
+    if index.is_none() && extras.is_none() {
+        return Ok(None);
+    }
+
+    let mut effective = Vec::new();
+    match index {
+        Some((url, source)) => effective.push((override_mirror(configured, &url, "primary", 0), source.to_string())),
+        None => effective.extend(
+            configured
+                .iter()
+                .filter(|mirror| mirror.role == "primary")
+                .map(|mirror| (mirror.clone(), configured_source.to_string())),
+        ),
+    }
+    match extras {
+        Some((urls, source)) => {
+            for (position, url) in urls.iter().enumerate() {
+                let mirror = override_mirror(configured, url, "supplemental", position as u32 + 1);
+                if !effective.iter().any(|(existing, _)| existing.url == mirror.url) {
+                    effective.push((mirror, source.to_string()));
+                }
+            }
+        }
+        None => effective.extend(
+            configured
+                .iter()
+                .filter(|mirror| mirror.role != "primary")
+                .map(|mirror| (mirror.clone(), configured_source.to_string())),
+        ),
+    }
+    Ok(Some(effective))
+}
+
+fn failed_probe(problem: String) -> MirrorProbe {
+    MirrorProbe { problem: Some(problem), json_api: None }
+}
+
+/// Whether an effective mirror replaces the configured ones
+fn is_override(source: &str) -> bool {
+    !matches!(source, "mirrors.json" | "built-in default")
+}
+
+/// A mirror for an override value: the configured mirror it names (or whose
+/// URL it is), keeping its credentials and TLS settings, or a new one
+fn override_mirror(configured: &[Mirror], value: &str, role: &str, priority: u32) -> Mirror {
+    let value = value.trim();
+    let existing = configured
+        .iter()
+        .find(|mirror| mirror.name == value || mirror.url.trim_end_matches('/') == value.trim_end_matches('/'));
+    let mut mirror = existing.cloned().unwrap_or_else(|| Mirror {
+        name: url_host(value).unwrap_or_else(|| value.to_string()),
+        url: value.to_string(),
+        is_default: false,
+        last_tested: None,
+        is_active: true,
+        priority: 0,
+        role: role.to_string(),
+        kind: "simple".to_string(),
+        probe_path: None,
+        json_api: None,
+        tls: TlsOptions::default(),
+        transfer: TransferOptions::default(),
+        recent_checks: Vec::new(),
+    });
+    mirror.is_default = role == "primary";
+    mirror.is_active = true;
+    mirror.priority = priority;
+    mirror.role = role.to_string();
+    mirror
+}
+
+/// Share of a mirror's recent health checks that passed
+pub fn success_rate(mirror: &Mirror) -> Option<f64> {
+    if mirror.recent_checks.is_empty() {
+        return None;
+    }
+    let passed = mirror.recent_checks.iter().filter(|passed| **passed).count();
+    Some(passed as f64 / mirror.recent_checks.len() as f64)
+}
+
+/// Versions and files listed on a project's simple index page
+#[derive(Clone, Default)]
+struct ProjectPage {
+    versions: BTreeSet<String>,
+    /// File name to URL and sha256, when published
+    files: BTreeMap<String, (String, Option<String>)>,
+}
+
+impl ProjectPage {
+    fn from_files(files: &[IndexFile]) -> Self {
+        ProjectPage {
+            versions: files.iter().filter_map(|file| filename_version(&file.filename)).collect(),
+            files: files.iter().map(|file| (file.filename.clone(), (file.url.to_string(), file.sha256.as_ref().map(|hash| hash.to_lowercase())))).collect(),
+        }
+    }
+
+    /// The sha256 digests published for a version's files
+    fn version_hashes(&self, version: &str) -> BTreeSet<&str> {
+        self.files
+            .iter()
+            .filter(|(filename, _)| filename_version(filename).is_some_and(|v| compare_versions(&v, version) == Ordering::Equal))
+            .filter_map(|(_, (_, sha256))| sha256.as_deref())
+            .collect()
+    }
+
+    fn has_version(&self, version: &str) -> bool {
+        self.versions.iter().any(|v| compare_versions(v, version) == Ordering::Equal)
+    }
+}
+
+/// Version part of a wheel or sdist file name
+pub fn filename_version(filename: &str) -> Option<String> {
+    if let Some(stem) = filename.strip_suffix(".whl") {
+        return stem.split('-').nth(1).map(str::to_string);
+    }
+    let stem = [".tar.gz", ".zip", ".tar.bz2", ".tgz"]
+        .iter()
+        .find_map(|extension| filename.strip_suffix(extension))?;
+    stem.rsplit_once('-').map(|(_, version)| version.to_string())
+}
+
+/// Simple index page of a project on a mirror
+fn project_url(mirror: &Mirror, project: &str) -> String {
+    format!("{}/{}/", mirror.url.trim_end_matches('/'), project)
+}
+
+/// Time until the mirror answers a project page request, best of a few
+async fn measure_latency(client: &Client, mirror: &Mirror, auth: Option<&MirrorAuth>) -> Result<Duration, Box<dyn std::error::Error>> {
+    let url = project_url(mirror, BENCHMARK_PROJECT);
+    let mut best: Option<Duration> = None;
+    for _ in 0..LATENCY_SAMPLES {
+        let start = Instant::now();
+        let response = authorize(client.get(&url), auth).send().await?;
+        let elapsed = start.elapsed();
+        if !response.status().is_success() {
+            return Err(format!("{} returned status {}", url, response.status()).into());
+        }
+        best = Some(best.map_or(elapsed, |best| best.min(elapsed)));
+    }
+    best.ok_or_else(|| "no latency samples".into())
+}
+
+/// Bytes per second downloading the newest wheel of the benchmark project
+async fn measure_throughput(client: &Client, mirror: &Mirror, auth: Option<&MirrorAuth>) -> Result<f64, Box<dyn std::error::Error>> {
+    let page_url = Url::parse(&project_url(mirror, BENCHMARK_PROJECT))?;
+    let page = authorize(client.get(page_url.clone()), auth).send().await?.error_for_status()?.text().await?;
+    let href = Regex::new(r#"href="([^"]+)""#)?
+        .captures_iter(&page)
+        .map(|captures| captures[1].replace("&amp;", "&"))
+        .filter(|href| href.split('#').next().unwrap_or_default().ends_with(".whl"))
+        .last()
+        .ok_or_else(|| format!("{} lists no wheels", page_url))?;
+
+    // Artifacts may live on another host, which must not see the credentials
+    let file_url = page_url.join(&href)?;
+    let file_auth = auth.filter(|_| file_url.host_str() == page_url.host_str());
+    let start = Instant::now();
+    let bytes = authorize(client.get(file_url), file_auth).send().await?.error_for_status()?.bytes().await?;
+    let seconds = start.elapsed().as_secs_f64().max(0.001);
+    Ok(bytes.len() as f64 / seconds)
+}
+
+/// Host of a URL, with the port when one is given, so that indexes sharing
+/// a host on different ports stay distinct
+fn url_host(url: &str) -> Option<String> {
+    let rest = url.split_once("://")?.1;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?;
+    (!host.is_empty()).then(|| host.to_lowercase())
+}
@@ -5,3 +5,37 @@ pub mod visualize;
 pub mod docker;
 pub mod models;
 
+pub mod config;
+pub mod venv;
+pub mod dotenv;
+pub mod ephemeral;
+pub mod installer;
+pub mod http_cache;
+pub mod pep440;
+pub mod licenses;
+pub mod attestations;
+pub mod typosquat;
+pub mod remediation;
+pub mod supply_chain;
+pub mod allowlist;
+pub mod malware;
+pub mod credentials;
+pub mod network;
+pub mod index_kinds;
+pub mod snapshot;
+pub mod simple_api;
+pub mod compose;
+pub mod container_runtime;
+pub mod terminal;
+pub mod registry;
+pub mod pep508;
+pub mod graph_layout;
+pub mod pep517;
+pub mod trusted_publishing;
+pub mod upload;
+pub mod dist_check;
+pub mod versioning;
+pub mod workspace;
+pub mod release;
+pub mod output;
+pub mod completions;
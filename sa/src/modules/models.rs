@@ -1,6 +1,7 @@
 use clap::{Parser, Subcommand};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::collections::BTreeMap;
 use chrono::{DateTime, Utc};
 
 /// SA - Super Accelerated Python Package Manager
@@ -16,9 +17,9 @@ pub struct Cli {
 pub enum Commands {
     /// Run a Python script with dependencies
     Run {
-        /// Dependency to install before running
-        #[arg(short, long)]
-        with: String,
+        /// Dependencies to run with, in a cached ephemeral environment (comma separated or repeated)
+        #[arg(short, long, value_delimiter = ',')]
+        with: Vec<String>,
         /// Script and arguments to pass to Python
         #[arg(
             trailing_var_arg = true,
@@ -32,6 +33,18 @@ pub enum Commands {
         /// Docker image to use (default: python:3.11-slim)
         #[arg(long, default_value = "python:3.11-slim")]
         docker_image: String,
+        /// GPUs for the container: all, a count, or device=<id>[,<id>...]
+        #[arg(long, requires = "docker")]
+        gpus: Option<String>,
+        /// Load environment variables from this file (default: .env if present)
+        #[arg(long = "env-file")]
+        env_files: Vec<String>,
+        /// Do not load any .env file
+        #[arg(long, conflicts_with = "env_files")]
+        no_env_file: bool,
+        /// Python interpreter to use (version like 3.11 or a path)
+        #[arg(long)]
+        python: Option<String>,
     },
     /// Install a Python package (like pip install) and show dependencies
     Install {
@@ -53,6 +66,18 @@ pub enum Commands {
         /// Force cache refresh
         #[arg(long)]
         refresh_cache: bool,
+        /// Byte-compile installed modules for faster first imports
+        #[arg(long)]
+        compile: bool,
+        /// Fail unless every artifact has a verified PEP 740 attestation
+        #[arg(long)]
+        require_attestations: bool,
+        /// Install look-alikes of popular packages without asking
+        #[arg(short, long)]
+        yes: bool,
+        /// Python interpreter to use (version like 3.11 or a path)
+        #[arg(long)]
+        python: Option<String>,
     },
     /// Remove a package from the environment
     Remove {
@@ -76,17 +101,147 @@ pub enum Commands {
         /// Output format (table, json, graph)
         #[arg(long, default_value = "table")]
         format: String,
+        /// Only packages with a newer version on the index, and that version
+        #[arg(long, conflicts_with = "tree")]
+        outdated: bool,
     },
     /// Build the project
+    #[command(group(clap::ArgGroup::new("containers").args(["docker", "matrix"]).multiple(true)))]
     Build {
         /// Use Docker for building
         #[arg(long)]
         docker: bool,
+        /// Python interpreter to use (version like 3.11 or a path)
+        #[arg(long, conflicts_with = "matrix")]
+        python: Option<String>,
+        /// Build in containers of these platforms, e.g. linux/amd64,linux/arm64
+        #[arg(long = "platform", value_delimiter = ',', requires = "containers")]
+        platforms: Vec<String>,
+        /// Build repaired wheels for each of these Python versions at once,
+        /// e.g. 3.9,3.10,3.11,3.12, in manylinux containers (for each
+        /// --platform)
+        #[arg(long, value_delimiter = ',', conflicts_with_all = ["sdist", "repair"])]
+        matrix: Vec<String>,
+        /// Image policy for --matrix: manylinux2014, manylinux_2_28,
+        /// musllinux_1_2, ...
+        #[arg(long, default_value = "manylinux_2_28", requires = "matrix")]
+        manylinux: String,
+        /// Build the wheel (alone, unless --sdist is given too)
+        #[arg(long)]
+        wheel: bool,
+        /// Build the source distribution (alone, unless --wheel is given too)
+        #[arg(long)]
+        sdist: bool,
+        /// Directory to write the distributions to
+        #[arg(long, default_value = "dist")]
+        out_dir: PathBuf,
+        /// Build every member of the workspace ([tool.sa.workspace]), each
+        /// after the members it depends on
+        #[arg(long)]
+        all: bool,
+        /// Repair binary wheels: bundle the shared libraries they link
+        /// against and retag them (manylinux, macOS), with auditwheel,
+        /// delocate or delvewheel
+        #[arg(long)]
+        repair: bool,
+        /// Platform tag to repair Linux wheels for (e.g. manylinux_2_28_x86_64)
+        #[arg(long, requires = "repair")]
+        plat: Option<String>,
     },
     /// Publish the project
-    Publish,
-    /// Show the current SA version
-    Version,
+    Publish {
+        /// Upload to this repository: "pypi" (the default), "testpypi", one
+        /// under [repositories] in the SA config, or a mirror (a devpi,
+        /// Artifactory or Nexus index)
+        #[arg(long)]
+        repository: Option<String>,
+        /// Upload to this URL (e.g. https://pypi.corp/legacy/)
+        #[arg(long, conflicts_with = "repository")]
+        repository_url: Option<String>,
+        /// Files the repository already has are always skipped now; kept so
+        /// existing scripts keep working
+        #[arg(long, hide = true)]
+        skip_existing: bool,
+        /// Only check the files in dist/ (metadata, description, file names,
+        /// versions), uploading nothing
+        #[arg(long)]
+        check: bool,
+        /// Check the files and the credentials and show what would be
+        /// uploaded, uploading nothing
+        #[arg(long, conflicts_with = "check")]
+        dry_run: bool,
+        /// Publish every member of the workspace ([tool.sa.workspace]), each
+        /// after the members it depends on
+        #[arg(long)]
+        all: bool,
+        /// Rebuild members with their path dependencies on other members
+        /// pinned to the versions just published
+        #[arg(long, requires = "all")]
+        pin_path_deps: bool,
+        /// Do not wait for the index to list the uploaded files and check
+        /// their hashes
+        #[arg(long)]
+        no_verify: bool,
+        /// How long to wait for the index to list the release (e.g. 90s, 10m)
+        #[arg(long, default_value = "5m")]
+        verify_timeout: String,
+        /// Then install the release from the index in a scratch environment
+        #[arg(long, conflicts_with = "no_verify")]
+        smoke_test: bool,
+    },
+    /// Release the project: bump the version, add a changelog section, build
+    /// and check the distributions, then commit, tag and publish
+    Release {
+        /// Which part to bump; auto picks it from the conventional commits
+        /// since the last tag (major for breaking changes, minor for features)
+        #[arg(value_parser = ["auto", "major", "minor", "patch", "pre"], default_value = "auto")]
+        part: String,
+        /// Pre-release phase to start: a, b or rc
+        #[arg(long, value_parser = ["a", "b", "rc"])]
+        pre: Option<String>,
+        /// Steps to leave out: bump, changelog, build, tag, publish
+        #[arg(long, value_delimiter = ',', value_parser = ["bump", "changelog", "build", "tag", "publish"])]
+        skip: Vec<String>,
+        /// Upload to this repository (see 'sa publish --repository')
+        #[arg(long)]
+        repository: Option<String>,
+        /// Upload to this URL
+        #[arg(long, conflicts_with = "repository")]
+        repository_url: Option<String>,
+        /// Push the release commit and tag to origin afterwards
+        #[arg(long)]
+        push: bool,
+        /// Release with uncommitted changes to tracked files
+        #[arg(long)]
+        allow_dirty: bool,
+        /// Show the new version, the changelog section and the steps,
+        /// changing nothing
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Check the environment (or a lockfile) against the vulnerability database
+    Audit {
+        /// Audit the pinned name==version lines of this requirements/lock file instead of the environment
+        #[arg(short, long)]
+        requirements: Option<PathBuf>,
+        /// Output format (table, json, sarif)
+        #[arg(long, default_value = "table", value_parser = ["table", "json", "sarif"])]
+        format: String,
+        /// Lowest severity that fails the command: exit 1 for low/medium, 2 for high, 3 for critical findings
+        #[arg(long, default_value = "low", value_parser = ["critical", "high", "medium", "low", "none"])]
+        fail_on: String,
+        /// Upgrade vulnerable packages to the lowest fixed versions the other pins allow
+        #[arg(long, conflicts_with = "format")]
+        fix: bool,
+        /// Apply the --fix plan without asking
+        #[arg(short, long, requires = "fix")]
+        yes: bool,
+    },
+    /// Show the current SA version, or manage the project's version
+    Version {
+        #[command(subcommand)]
+        action: Option<VersionAction>,
+    },
     /// Cache management commands
     Cache {
         #[command(subcommand)]
@@ -97,30 +252,93 @@ pub enum Commands {
         #[command(subcommand)]
         action: SecurityAction,
     },
+    /// License compliance commands
+    License {
+        #[command(subcommand)]
+        action: LicenseAction,
+    },
     /// Mirror configuration commands
     Mirror {
         #[command(subcommand)]
         action: MirrorAction,
     },
+    /// Store mirror and publish credentials in the OS keyring
+    Auth {
+        #[command(subcommand)]
+        action: AuthAction,
+    },
     /// Dependency visualization commands
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
     Visualize {
-        /// Package to visualize
-        package: String,
-        /// Output format (dot, svg, png)
+        #[command(subcommand)]
+        action: Option<VisualizeAction>,
+        /// Package to visualize, with any extras to include (e.g. requests[socks])
+        #[arg(required = true)]
+        package: Option<String>,
+        /// Output format (dot, mermaid, json, html, svg, png, tree)
         #[arg(long, default_value = "dot")]
         format: String,
         /// Output file
         #[arg(short, long)]
         output: Option<String>,
+        /// Characters the tree format draws branches with
+        #[arg(long, default_value = "utf8", value_parser = ["utf8", "ascii"])]
+        charset: String,
         /// Include transitive dependencies
         #[arg(long)]
         transitive: bool,
+        /// Take versions from this lockfile (name==version pins) instead of the environment
+        #[arg(short, long)]
+        requirements: Option<PathBuf>,
+        /// Show the installed packages that depend on the package instead
+        #[arg(long, conflicts_with = "requirements")]
+        reverse: bool,
+        /// Also list the N largest packages and the requirements pulling them in
+        #[arg(long, value_name = "N", conflicts_with = "reverse")]
+        top_sizes: Option<usize>,
+        /// Follow requirements at most N levels below the package (implies --transitive)
+        #[arg(long, value_name = "N", conflicts_with = "reverse")]
+        depth: Option<usize>,
+        /// Only show packages matching these globs and the requirements leading to them (comma separated or repeated)
+        #[arg(long, value_name = "GLOB", value_delimiter = ',', conflicts_with = "reverse")]
+        include: Vec<String>,
+        /// Leave out packages matching these globs, with whatever only they pull in (comma separated or repeated)
+        #[arg(long, value_name = "GLOB", value_delimiter = ',', conflicts_with = "reverse")]
+        exclude: Vec<String>,
+        /// Color packages by license category or by their worst known vulnerability
+        #[arg(long, value_parser = ["licenses", "vulns"])]
+        annotate: Option<String>,
     },
     /// Docker integration commands
     Docker {
         #[command(subcommand)]
         action: DockerAction,
     },
+    /// Spawn a subshell with the project environment activated
+    Shell {
+        /// Shell to launch (defaults to $SHELL)
+        #[arg(long)]
+        shell: Option<String>,
+    },
+    /// Virtual environment management commands
+    Env {
+        #[command(subcommand)]
+        action: EnvAction,
+    },
+    /// Show and change settings (~/.config/sa/config.toml, overridden per project under [tool.sa])
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Print a shell completion script (bash, zsh, fish or powershell)
+    Completions {
+        /// Shell to complete for
+        #[arg(value_parser = ["bash", "zsh", "fish", "powershell"], required_unless_present = "installed")]
+        shell: Option<String>,
+        /// List the packages installed in the environment, one per line (used by the scripts)
+        #[arg(long, hide = true, conflicts_with = "shell")]
+        installed: bool,
+    },
 }
 
 #[derive(Subcommand)]
@@ -128,11 +346,115 @@ pub enum CacheAction {
     /// Clear all cached packages
     Clear,
     /// Show cache statistics
-    Stats,
+    Stats {
+        /// Include a per-package breakdown
+        #[arg(long)]
+        packages: bool,
+    },
     /// Verify cache integrity
-    Verify,
+    Verify {
+        /// Prune orphaned entries and evict corrupt artifacts
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Evict artifacts by age and size budget (least recently used first)
+    Gc {
+        /// Size budget overriding the configured max_size (e.g. 10GB)
+        #[arg(long)]
+        max_size: Option<String>,
+        /// Age limit overriding the configured max_age (e.g. 90d)
+        #[arg(long)]
+        max_age: Option<String>,
+        /// Show what would be evicted without removing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Suppress output (used for background runs)
+        #[arg(long, hide = true)]
+        quiet: bool,
+    },
     /// Optimize cache storage
-    Optimize,
+    Optimize {
+        /// Keep artifacts not installed in any known project
+        #[arg(long)]
+        no_prune: bool,
+    },
+    /// Bundle the artifacts a project needs (.tar.zst, .tar.gz or .tar)
+    Export {
+        /// Bundle file to write
+        bundle: String,
+        /// Lockfile with pinned name==version lines (default: the project environment)
+        #[arg(short, long)]
+        requirements: Option<String>,
+        /// Export every cached artifact
+        #[arg(long, conflicts_with = "requirements")]
+        all: bool,
+    },
+    /// List cached packages
+    List {
+        /// Only show packages whose name contains this text
+        #[arg(long)]
+        name: Option<String>,
+        /// Only show packages cached longer ago than this (e.g. 30d)
+        #[arg(long)]
+        older_than: Option<String>,
+        /// Only show packages downloaded from this mirror
+        #[arg(long)]
+        mirror: Option<String>,
+    },
+    /// Protect a cached package from gc and clear
+    Pin {
+        /// Package as name==version (all cached versions when no version is given)
+        spec: String,
+    },
+    /// Allow a pinned package to be evicted again
+    Unpin {
+        /// Package as name==version (all cached versions when no version is given)
+        spec: String,
+    },
+    /// Manage trust-on-first-use digest records
+    Trust {
+        #[command(subcommand)]
+        action: TrustAction,
+    },
+    /// Remove every cached artifact that came from a mirror
+    Purge {
+        /// Mirror whose artifacts should be removed
+        #[arg(long)]
+        mirror: String,
+        /// Show what would be removed without removing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Restore artifacts from a bundle created by `sa cache export`
+    Import {
+        /// Bundle file to read
+        bundle: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum TrustAction {
+    /// Show recorded artifact digests
+    List {
+        /// Only show records for this package
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Forget recorded digests so the next download is trusted afresh
+    Forget {
+        /// Package as name==version (all versions when no version is given)
+        spec: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum LicenseAction {
+    /// Check installed packages against the [tool.sa.licenses] allow/deny lists
+    Check {
+        /// List every package with its license, not only violations
+        #[arg(long)]
+        verbose: bool,
+    },
 }
 
 #[derive(Subcommand)]
@@ -141,17 +463,50 @@ pub enum SecurityAction {
     Scan {
         /// Package to scan (all if not specified)
         package: Option<String>,
-        /// Output format (table, json)
-        #[arg(long, default_value = "table")]
+        /// Output format (table, json, sarif)
+        #[arg(long, default_value = "table", value_parser = ["table", "json", "sarif"])]
         format: String,
+        /// Lowest severity that fails the command: exit 1 for low/medium, 2 for high, 3 for critical findings
+        #[arg(long, default_value = "low", value_parser = ["critical", "high", "medium", "low", "none"])]
+        fail_on: String,
+    },
+    /// Accept a known finding until a date, so it stops failing scans and audits
+    Ignore {
+        /// Advisory ID or alias (e.g. PYSEC-2023-74 or CVE-2023-32681)
+        id: String,
+        /// Date (YYYY-MM-DD) after which the finding is reported again
+        #[arg(long)]
+        until: chrono::NaiveDate,
+        /// Why the finding is acceptable
+        #[arg(long)]
+        reason: String,
     },
+    /// Allow a package in allow-list mode and release it from quarantine
+    Approve {
+        /// Package name
+        name: String,
+        /// Version range to allow, e.g. ">=2.31,<3" (default: the quarantined versions)
+        #[arg(long)]
+        versions: Option<String>,
+        /// Why the package was approved
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// List install requests blocked by the allow-list
+    Quarantine,
     /// Update vulnerability database
-    Update,
+    Update {
+        /// Advisory feed to refresh
+        #[arg(long, default_value = "all", value_parser = ["pypa", "osv", "safetydb", "all"])]
+        source: String,
+    },
     /// Show security policy
     Policy,
 }
 
+// Parsed once per run, so the size of `Add` does not matter
 #[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)]
 pub enum MirrorAction {
     /// Add a new mirror
     Add {
@@ -162,6 +517,48 @@ pub enum MirrorAction {
         /// Set as default
         #[arg(long)]
         default: bool,
+        /// Precedence; lower numbers are consulted first
+        #[arg(long, default_value_t = 0)]
+        priority: u32,
+        /// Full index (primary) or extra index consulted alongside it (supplemental)
+        #[arg(long, default_value = "primary", value_parser = ["primary", "supplemental"])]
+        role: String,
+        /// Index server software, for its URL layout, health check and upload endpoint
+        #[arg(long, default_value = "simple", value_parser = ["simple", "devpi", "artifactory", "nexus"])]
+        kind: String,
+        /// Project page below the index used to test connectivity (default 'pip/')
+        #[arg(long)]
+        probe_path: Option<String>,
+        /// CA certificate bundle (PEM) for an index signed by a private CA
+        #[arg(long)]
+        ca_cert: Option<PathBuf>,
+        /// PEM file with a client certificate and its PKCS#8 key, for mutual TLS
+        #[arg(long)]
+        client_cert: Option<PathBuf>,
+        /// Do not verify the mirror's TLS certificate (insecure)
+        #[arg(long)]
+        insecure: bool,
+        /// Seconds to wait for a connection
+        #[arg(long)]
+        connect_timeout: Option<u64>,
+        /// Seconds to wait for data on an open connection
+        #[arg(long)]
+        read_timeout: Option<u64>,
+        /// Retries (with exponential backoff) of failed or rate-limited requests
+        #[arg(long)]
+        retries: Option<u32>,
+        /// Download rate cap, e.g. 5MB (per second)
+        #[arg(long)]
+        max_bandwidth: Option<String>,
+        /// Username for basic auth (may be an environment reference like '${ARTIFACTORY_USER}')
+        #[arg(long)]
+        username: Option<String>,
+        /// Password for basic auth (prefer an environment reference like '${ARTIFACTORY_PASSWORD}')
+        #[arg(long, requires = "username")]
+        password: Option<String>,
+        /// Bearer token (prefer an environment reference like '${INDEX_TOKEN}')
+        #[arg(long, conflicts_with_all = ["username", "password"])]
+        token: Option<String>,
     },
     /// Remove a mirror
     Remove {
@@ -169,11 +566,51 @@ pub enum MirrorAction {
         name: String,
     },
     /// List configured mirrors
-    List,
+    List {
+        /// Show the indexes actually used here, after SA_INDEX_URL, SA_EXTRA_INDEX_URLS and [tool.sa.mirrors]
+        #[arg(long)]
+        effective: bool,
+    },
     /// Test mirror connectivity
     Test {
         /// Mirror name (test all if not specified)
         name: Option<String>,
+        /// Measure latency and download throughput
+        #[arg(long)]
+        benchmark: bool,
+    },
+    /// Download every file of the pinned releases into a static PEP 503 index for offline installs
+    Snapshot {
+        /// Directory to write the index to
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Lockfile or requirements file to snapshot (unpinned requirements are resolved first)
+        #[arg(short, long, conflicts_with = "packages")]
+        requirements: Option<PathBuf>,
+        /// Packages to snapshot along with their dependencies
+        packages: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum AuthAction {
+    /// Save the password or token for a mirror (or with --publish, an upload repository) in the OS keyring
+    Login {
+        /// Mirror name, or repository name with --publish
+        name: String,
+        /// Username; without one the secret is used as a bearer token
+        #[arg(long, conflicts_with = "publish")]
+        username: Option<String>,
+        /// Store an upload token for `sa publish` (e.g. for "pypi")
+        #[arg(long)]
+        publish: bool,
+    },
+    /// Remove stored credentials
+    Logout {
+        /// Mirror name, or repository name with --publish
+        name: String,
+        #[arg(long)]
+        publish: bool,
     },
 }
 
@@ -186,27 +623,276 @@ pub enum DockerAction {
         /// Base image
         #[arg(long, default_value = "python:3.11-slim")]
         image: String,
-        /// Requirements file
+        /// Requirements file (the lockfile in production mode)
         #[arg(short, long)]
         requirements: Option<String>,
+        /// Build a slim multi-stage image of the project and its lockfile,
+        /// run by a non-root user
+        #[arg(long)]
+        production: bool,
+        /// GPUs for the container: all, a count, or device=<id>[,<id>...]
+        #[arg(long)]
+        gpus: Option<String>,
+        /// Memory limit, e.g. 2GB
+        #[arg(long)]
+        memory: Option<String>,
+        /// CPUs the container may use, e.g. 1.5
+        #[arg(long)]
+        cpus: Option<f64>,
+        /// Network to join, or host/none
+        #[arg(long)]
+        network: Option<String>,
+        /// Publish a port, [ip:]host:container[/protocol] (repeatable)
+        #[arg(short, long = "publish")]
+        ports: Vec<String>,
+        /// Set an environment variable, KEY=VALUE or KEY to pass it through (repeatable)
+        #[arg(short, long = "env")]
+        env: Vec<String>,
+        /// Platforms to build the image for, e.g. linux/amd64,linux/arm64
+        #[arg(long = "platform", value_delimiter = ',')]
+        platforms: Vec<String>,
+        /// Push the image as this reference; built for several platforms, as
+        /// a multi-arch manifest (uses the login stored by docker login)
+        #[arg(long, value_name = "REFERENCE")]
+        push: Option<String>,
     },
     /// List Docker environments
     List,
+    /// Rebuild an environment whose requirements file changed since it was built
+    Sync {
+        /// Environment name
+        name: String,
+    },
+    /// Start a stopped Docker environment
+    Start {
+        /// Environment name
+        name: String,
+    },
+    /// Stop a running Docker environment, keeping its state
+    Stop {
+        /// Environment name
+        name: String,
+    },
     /// Remove a Docker environment
     Remove {
         /// Environment name
         name: String,
+        /// Keep the environment's data volume
+        #[arg(long)]
+        keep_data: bool,
+    },
+    /// Write a multi-stage Dockerfile that installs the project's lockfile
+    Generate {
+        /// Fully pinned requirements lockfile to install
+        #[arg(short, long, default_value = "requirements.txt")]
+        requirements: PathBuf,
+        /// Base image for both stages
+        #[arg(long, default_value = "python:3.11-slim")]
+        image: String,
+        /// Command the image runs (default: python)
+        #[arg(long)]
+        cmd: Option<String>,
+        /// Where to write the Dockerfile
+        #[arg(short, long, default_value = "Dockerfile")]
+        output: PathBuf,
+        /// Overwrite an existing Dockerfile
+        #[arg(long)]
+        force: bool,
+    },
+    /// Write a compose file running the app with the services in [tool.sa.services]
+    Compose {
+        /// Tag of the app image (default: the project directory name)
+        #[arg(long)]
+        image: Option<String>,
+        /// Dockerfile the app image is built from
+        #[arg(long, default_value = "Dockerfile")]
+        dockerfile: String,
+        /// Where to write the compose file
+        #[arg(short, long, default_value = "compose.yaml")]
+        output: PathBuf,
+        /// Overwrite an existing compose file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Package the project environment and sources as an image, without a Dockerfile
+    Export {
+        /// Image reference to create, e.g. registry.example.com/team/app:1.0
+        tag: String,
+        /// Base image (default: python:<environment's Python>-slim)
+        #[arg(long)]
+        image: Option<String>,
+        /// Command the image runs
+        #[arg(long)]
+        cmd: Option<String>,
+        /// Also write the image to a tarball
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Push the image to its registry
+        #[arg(long)]
+        push: bool,
+        /// Registry user for --push; the password is prompted for (or read
+        /// from stdin). Default: the login stored by docker/podman login
+        #[arg(long, requires = "push")]
+        username: Option<String>,
+    },
+    /// Push an environment's image to a registry
+    Push {
+        /// Environment name
+        name: String,
+        /// Image reference to push as, e.g. registry.example.com/team/app:1.0
+        reference: String,
+        /// Registry user; the password is prompted for (or read from stdin).
+        /// Default: the login stored by docker/podman login
+        #[arg(long)]
+        username: Option<String>,
+    },
+    /// Open an interactive shell in a container of an environment's image, with the project mounted
+    Shell {
+        /// Environment name
+        name: String,
+        /// GPUs for the container: all, a count, or device=<id>[,<id>...]
+        #[arg(long)]
+        gpus: Option<String>,
+        /// Command to run instead of the shell
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
     },
     /// Execute command in Docker environment
     Exec {
         /// Environment name
         name: String,
-        /// Command to execute
+        /// Require GPU access; the environment must have been created with --gpus
+        #[arg(long)]
+        gpus: Option<String>,
+        /// Change the environment's memory limit, e.g. 2GB
+        #[arg(long)]
+        memory: Option<String>,
+        /// Change the CPUs the environment may use, e.g. 1.5
+        #[arg(long)]
+        cpus: Option<f64>,
+        /// Connect the environment to a network first
+        #[arg(long)]
+        network: Option<String>,
+        /// Set an environment variable for the command, KEY=VALUE or KEY (repeatable)
+        #[arg(short, long = "env")]
+        env: Vec<String>,
+        /// Command to execute, passed through as is from its first word (or
+        /// after `--`); options for SA go before it
+        #[arg(required = true, num_args = 1.., trailing_var_arg = true, allow_hyphen_values = true)]
         command: Vec<String>,
     },
 }
 
+#[derive(Subcommand)]
+pub enum VisualizeAction {
+    /// Draw what changed between two lockfiles: added, removed and upgraded packages
+    Diff {
+        /// Lockfile before the change (name==version pins)
+        old: PathBuf,
+        /// Lockfile after the change
+        new: PathBuf,
+        /// Output format (dot, mermaid, json, html, svg, png, tree)
+        #[arg(long, default_value = "dot")]
+        format: String,
+        /// Output file
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Characters the tree format draws branches with
+        #[arg(long, default_value = "utf8", value_parser = ["utf8", "ascii"])]
+        charset: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum VersionAction {
+    /// Bump project.version in pyproject.toml and the package's __version__
+    Bump {
+        /// Which part to bump (pre starts or continues a pre-release)
+        #[arg(value_parser = ["major", "minor", "patch", "pre"])]
+        part: String,
+        /// Pre-release phase to start: a, b or rc
+        #[arg(long, value_parser = ["a", "b", "rc"])]
+        pre: Option<String>,
+        /// Commit the changed files
+        #[arg(long)]
+        commit: bool,
+        /// Also tag the commit (implies --commit)
+        #[arg(long)]
+        tag: bool,
+        /// Show the new version and the files that would change
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum EnvAction {
+    /// Create the project virtual environment
+    Create {
+        /// Do not seed pip into the environment
+        #[arg(long)]
+        without_pip: bool,
+        /// Do not seed setuptools into the environment
+        #[arg(long)]
+        without_setuptools: bool,
+        /// Pin a seed package, e.g. pip==24.0 or setuptools==69.5.1
+        #[arg(long = "seed")]
+        seeds: Vec<String>,
+        /// Recreate the environment if it already exists
+        #[arg(long)]
+        clear: bool,
+        /// Python interpreter to use (version like 3.11 or a path)
+        #[arg(long)]
+        python: Option<String>,
+    },
+    /// Show how to activate the environment in the current shell
+    Activate {
+        /// Print the activation snippet (eval "$(sa env activate --print)")
+        #[arg(long)]
+        print: bool,
+        /// Target shell (bash, zsh, fish, powershell); detected when omitted
+        #[arg(long)]
+        shell: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigAction {
+    /// Print a setting's value, with the project's override if any
+    Get {
+        /// Setting, e.g. cache.max_size
+        key: String,
+    },
+    /// Change a setting in config.toml
+    Set {
+        /// Setting, e.g. cache.max_size
+        key: String,
+        value: String,
+        /// Override it for this project only, under [tool.sa] in pyproject.toml
+        #[arg(long)]
+        project: bool,
+    },
+    /// List every setting with its value and where the value comes from
+    List,
+    /// Open config.toml in $VISUAL or $EDITOR, and check it afterwards
+    Edit {
+        /// Open the project's pyproject.toml instead
+        #[arg(long)]
+        project: bool,
+    },
+}
+
 // Data structures for advanced features
+/// A distribution the resolver decided to install
+#[derive(Serialize, Deserialize, Clone)]
+pub struct InstallPlanItem {
+    pub name: String,
+    pub version: String,
+    pub url: String,
+    pub sha256: Option<String>,
+    pub requested: bool,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct CachedPackage {
     pub name: String,
@@ -216,6 +902,12 @@ pub struct CachedPackage {
     pub cached_at: DateTime<Utc>,
     pub file_path: PathBuf,
     pub metadata: PackageMetadata,
+    /// Original artifact file name (the blob itself is stored by digest)
+    #[serde(default)]
+    pub filename: String,
+    /// Name of the mirror the artifact was downloaded from
+    #[serde(default)]
+    pub mirror: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Default)]
@@ -228,15 +920,580 @@ pub struct PackageMetadata {
     pub home_page: String,
 }
 
+/// Output of `sa cache stats`
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct CacheStats {
+    pub packages: usize,
+    /// Entries protected from gc and clear
+    pub pinned: usize,
+    pub total_size: u64,
+    pub hits: u64,
+    pub misses: u64,
+    /// Artifact bytes installed from the cache instead of downloaded
+    pub bytes_from_cache: u64,
+    pub bytes_downloaded: u64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub per_package: Vec<PackageCacheStats>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PackageCacheStats {
+    pub name: String,
+    pub version: String,
+    /// Blob plus unpacked tree, in bytes
+    pub size: u64,
+    pub last_accessed: Option<DateTime<Utc>>,
+    pub hit_count: u64,
+    pub pinned: bool,
+}
+
+/// Selection for `sa cache list` and mirror purges
+#[derive(Clone, Default)]
+pub struct CacheListFilter {
+    pub name: Option<String>,
+    pub mirror: Option<String>,
+    /// Only entries cached before this instant
+    pub older_than: Option<DateTime<Utc>>,
+}
+
+/// One row of `sa cache list`
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CacheListEntry {
+    pub name: String,
+    pub version: String,
+    pub filename: String,
+    /// Blob plus unpacked tree, in bytes
+    pub size: u64,
+    pub download_url: String,
+    pub mirror: String,
+    pub cached_at: DateTime<Utc>,
+}
+
+/// First digest seen for an artifact; later downloads must match it
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TrustRecord {
+    pub name: String,
+    pub version: String,
+    pub filename: String,
+    pub sha256: String,
+    pub first_seen: DateTime<Utc>,
+    /// Mirror (or bundle) the artifact was first seen from
+    pub source: String,
+}
+
+/// Outcome of `sa cache verify`
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct CacheVerifyReport {
+    pub checked: usize,
+    /// name==version entries whose content no longer matches the stored hash
+    pub corrupt: Vec<String>,
+    /// name==version entries whose artifact file is missing
+    pub missing: Vec<String>,
+    /// Files and directories in the cache not referenced by any entry
+    pub orphaned_files: Vec<PathBuf>,
+    pub fixed: bool,
+}
+
+/// Outcome of `sa cache gc`
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct CacheGcReport {
+    /// name==version entries only referenced by projects that no longer exist
+    pub abandoned: Vec<String>,
+    /// name==version entries evicted for exceeding the age limit
+    pub expired: Vec<String>,
+    /// name==version entries evicted to fit the size budget
+    pub evicted: Vec<String>,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+/// Outcome of `sa cache optimize`
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct CacheOptimizeReport {
+    /// Identical files inside unpacked wheels merged into one copy
+    pub deduplicated_files: usize,
+    /// name==version entries removed because no known project uses them
+    pub pruned: Vec<String>,
+    /// Number of known projects consulted for pruning
+    pub known_projects: usize,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+/// Index stored as manifest.json inside a cache bundle
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CacheBundleManifest {
+    pub format_version: u32,
+    pub created_at: DateTime<Utc>,
+    pub packages: Vec<CachedPackage>,
+}
+
+/// Outcome of `sa cache export` / `sa cache import`
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct CacheBundleReport {
+    /// Entries written to (export) or indexed from (import) the bundle
+    pub packages: usize,
+    /// Artifacts in the bundle that were already present locally
+    pub already_cached: usize,
+    /// name==version entries requested but not available in the cache
+    pub missing: Vec<String>,
+    pub bytes: u64,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct SecurityVulnerability {
     pub id: String,
     pub package: String,
+    /// Specifier sets such as `>=1.0,<1.4`; alternatives are separated by ` || `
     pub version_range: String,
     pub severity: String,
     pub description: String,
     pub fixed_version: Option<String>,
     pub published_at: DateTime<Utc>,
+    /// Other identifiers for the same advisory (CVE, GHSA, PYSEC)
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// Explicitly enumerated affected versions, checked alongside version_range
+    #[serde(default)]
+    pub affected_versions: Vec<String>,
+    /// Feeds that reported this advisory (pypa, osv, safetydb)
+    #[serde(default)]
+    pub sources: Vec<String>,
+}
+
+/// Local vulnerability database (~/.cache/sa-cache/vulnerabilities.json)
+#[derive(Serialize, Deserialize, Default)]
+pub struct VulnerabilityDb {
+    /// Last successful refresh of each feed
+    #[serde(default)]
+    pub updated: BTreeMap<String, DateTime<Utc>>,
+    #[serde(default)]
+    pub advisories: Vec<SecurityVulnerability>,
+}
+
+/// A distribution `sa build` produced, as `sa build --json` lists it
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BuildArtifact {
+    /// sdist or wheel
+    pub kind: String,
+    /// Relative to the project directory when inside it
+    pub path: String,
+    pub filename: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// sa.lock, written by `sa build`: when the project was built and what came out
+#[derive(Serialize, Deserialize)]
+pub struct BuildLock {
+    pub build_time: String,
+    pub sa_version: String,
+    pub python_version: String,
+    pub platform: String,
+    #[serde(default)]
+    pub artifacts: Vec<BuildArtifact>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_deps: Option<BuildDeps>,
+}
+
+/// The build environment `sa build` used, so the next build of the same
+/// `[build-system]` installs exactly the same backend and plugins
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BuildDeps {
+    /// `build-system.requires` the pins were resolved from
+    pub requires: Vec<String>,
+    /// `name==version` of everything the environment held
+    pub pins: Vec<String>,
+}
+
+/// `[build-system]` in pyproject.toml (PEP 517 and 518)
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct BuildSystem {
+    /// Requirements of the build itself, installed in an isolated environment
+    pub requires: Vec<String>,
+    /// `module:object` of the backend, e.g. `setuptools.build_meta`
+    pub build_backend: Option<String>,
+    /// Directories in the project to import an in-tree backend from
+    #[serde(default)]
+    pub backend_path: Vec<String>,
+}
+
+/// `[tool.sa.licenses]` in pyproject.toml. Entries are SPDX identifiers; a
+/// trailing `*` matches a family (e.g. `BSD-*`).
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct LicensePolicy {
+    /// When non-empty, every package must be covered by one of these
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+    /// Packages exempt from the check
+    pub ignore: Vec<String>,
+}
+
+/// `[tool.sa.version]` in pyproject.toml, for `sa version bump`
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct VersionPolicy {
+    /// More files assigning `__version__` (or `version`), relative to the project
+    pub files: Vec<String>,
+    /// Tag name for --tag; `{version}` is replaced
+    pub tag_format: String,
+}
+
+/// `[tool.sa.release]` in pyproject.toml, for `sa release`
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ReleaseConfig {
+    /// The changelog each release adds a section to, relative to the project
+    pub changelog: String,
+    /// A release's section; `{version}`, `{previous}`, `{date}` and
+    /// `{changes}` (the commits, grouped by type) are replaced
+    pub template: String,
+    /// Steps always left out, as for --skip
+    pub skip: Vec<String>,
+}
+
+/// `[tool.sa.workspace]` in the pyproject.toml of a monorepo's root, for
+/// `sa build --all` and `sa publish --all`
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct WorkspaceConfig {
+    /// Member project directories, relative to the root; `*` and `?` match
+    /// within one path component (e.g. "packages/*")
+    pub members: Vec<String>,
+    /// Directories the member patterns match that are not members
+    pub exclude: Vec<String>,
+}
+
+/// `[tool.sa.security]` in pyproject.toml, consulted by `sa add`
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct SecurityPolicy {
+    /// Lowest severity that blocks installation (critical, high, medium, low or none)
+    pub block_severity: String,
+    /// Whether `--skip-security` may be used
+    pub allow_skip_security: bool,
+    pub exemptions: Vec<SecurityExemption>,
+    /// Accepted findings, hidden from scans and audits until they expire
+    pub ignore: Vec<VulnerabilityIgnore>,
+    /// Response to names resembling popular packages: warn, block or off
+    pub typosquatting: String,
+    pub supply_chain: SupplyChainPolicy,
+}
+
+/// `[tool.sa.security.supply-chain]`: red flags checked before installing
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct SupplyChainPolicy {
+    /// warn, block or off
+    pub action: String,
+    /// Projects first published more recently than this are flagged
+    pub min_age_days: u32,
+    /// Name prefixes of private packages, which should never come from public PyPI
+    pub internal_prefixes: Vec<String>,
+}
+
+/// `[tool.sa.allowlist]`: in allow-list mode only listed packages may be installed
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AllowListPolicy {
+    pub enabled: bool,
+    /// Shared allow-list (path or URL to a TOML file with a `packages` array)
+    pub source: Option<String>,
+    pub packages: Vec<AllowedPackage>,
+}
+
+/// A package approved for installation within a version range
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AllowedPackage {
+    pub name: String,
+    /// Specifiers such as `>=2.31,<3`; empty or `*` allows every version
+    #[serde(default)]
+    pub versions: String,
+    pub reason: Option<String>,
+    /// Date the entry was approved
+    pub approved: Option<String>,
+}
+
+/// An install request blocked in allow-list mode, awaiting approval
+#[derive(Serialize, Deserialize, Clone)]
+pub struct QuarantineEntry {
+    pub name: String,
+    pub version: String,
+    pub url: String,
+    /// Project that asked for it
+    pub project: PathBuf,
+    pub requested_at: DateTime<Utc>,
+}
+
+/// An advisory ID (or alias) accepted until `until`, with a justification
+#[derive(Serialize, Deserialize, Clone)]
+pub struct VulnerabilityIgnore {
+    pub id: String,
+    pub until: chrono::NaiveDate,
+    pub reason: String,
+}
+
+/// A package (optionally a single advisory) exempt from blocking until `expires`
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SecurityExemption {
+    pub package: String,
+    /// Advisory ID or alias; all advisories for the package when omitted
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub expires: Option<chrono::NaiveDate>,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// What `sa build` was asked to build, and how
+#[derive(Clone, Copy)]
+pub struct BuildOptions<'a> {
+    /// In a container rather than with the local interpreter
+    pub docker: bool,
+    pub python: Option<&'a str>,
+    /// Container platforms, with `docker`
+    pub platforms: &'a [String],
+    /// "sdist" and/or "wheel"
+    pub kinds: &'a [&'a str],
+    pub out_dir: &'a Path,
+    /// Progress on stderr, as JSON output wants
+    pub json: bool,
+    /// Repair binary wheels into ones indexes accept
+    pub repair: bool,
+    /// Platform tag to repair Linux wheels for, instead of the most compatible
+    pub plat: Option<&'a str>,
+    /// Python versions to build wheels for in parallel, in containers of
+    /// the `manylinux` image policy
+    pub matrix: &'a [String],
+    pub manylinux: &'a str,
+}
+
+/// Per-invocation switches for `sa add`
+#[derive(Clone, Copy, Default)]
+pub struct InstallOptions {
+    pub skip_security: bool,
+    pub compile: bool,
+    pub require_attestations: bool,
+    /// Answer yes to confirmation prompts
+    pub assume_yes: bool,
+    /// Leave requirements.txt untouched
+    pub no_save: bool,
+}
+
+/// Outcome of checking an artifact's PEP 740 provenance
+pub enum AttestationStatus {
+    /// Verified, with the trusted publisher identity
+    Verified(String),
+    /// The index publishes no attestations for the file
+    Missing,
+    Invalid(String),
+}
+
+/// Core metadata read from an installed distribution's METADATA file
+#[derive(Clone)]
+pub struct DistMetadata {
+    pub name: String,
+    pub version: String,
+    pub license_expression: Option<String>,
+    pub license: Option<String>,
+    pub classifiers: Vec<String>,
+    /// Names of unconditional (non-extra) requirements
+    pub requires: Vec<String>,
+    /// Requires-Dist entries as written, markers and extras included
+    pub requirements: Vec<String>,
+    /// Bytes taken by the files in RECORD, for installed distributions
+    pub installed_size: Option<u64>,
+}
+
+/// A package in a dependency graph
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GraphPackage {
+    pub name: String,
+    pub version: Option<String>,
+    /// Requirements whose markers hold here
+    pub requires: Vec<GraphRequirement>,
+    /// Where the metadata came from: installed, pypi, or missing when none
+    /// could be found
+    pub source: String,
+    /// SPDX expression, when one could be determined
+    pub license: Option<String>,
+    /// Bytes on disk when installed, else the download size of a release file
+    pub size: Option<u64>,
+    /// Versions published on PyPI, when the package was looked up there
+    /// without a pin; empty otherwise
+    pub releases: Vec<String>,
+    /// Other versions installed alongside `version`, or pinned by the
+    /// lockfile as well; the graph uses only `version`
+    pub other_versions: Vec<String>,
+}
+
+/// One requirement of a graph package
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GraphRequirement {
+    /// Normalized name of the required package
+    pub name: String,
+    pub extras: Vec<String>,
+    /// e.g. ">=1.21.1,<3"; empty when any version will do
+    pub specifier: String,
+    pub marker: Option<String>,
+}
+
+/// `sa visualize --format json` output, described under "Dependency graph
+/// JSON" in the README; bump `schema_version` on incompatible changes
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GraphDocument {
+    pub schema_version: u32,
+    /// Node id of the package the graph was drawn for
+    pub root: String,
+    /// The graph holds the packages depending on the root instead of its
+    /// dependencies; depths then count steps towards the root
+    pub reverse: bool,
+    /// What the nodes are colored by: `licenses` or `vulns` (`--annotate`),
+    /// or `changes` for a lockfile diff
+    pub annotate: Option<String>,
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+    pub conflicts: Vec<GraphConflict>,
+    /// Requirement cycles, each as node ids from its package nearest the
+    /// root around to the one requiring it back
+    pub cycles: Vec<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GraphNode {
+    /// Normalized package name
+    pub id: String,
+    pub name: String,
+    pub version: Option<String>,
+    /// root, direct (required by the root, or requiring it in a reverse
+    /// graph) or transitive
+    pub dependency: String,
+    /// Fewest requirement steps from the root
+    pub depth: usize,
+    pub source: String,
+    pub license: Option<String>,
+    /// permissive, weak copyleft, strong copyleft, proprietary or unknown
+    pub license_category: String,
+    pub size: Option<u64>,
+    /// The requirements on this package disagree; see `conflicts`
+    pub conflict: bool,
+    /// Advisories affecting this version, with `--annotate vulns`; null when
+    /// not checked
+    pub vulnerabilities: Option<Vec<GraphVulnerability>>,
+    /// In a lockfile diff: added, removed, upgraded, downgraded or unchanged
+    pub change: Option<String>,
+    /// In a lockfile diff, the version the old lockfile pinned
+    pub previous_version: Option<String>,
+    /// Other versions installed or pinned as well, which the graph leaves out
+    pub other_versions: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GraphVulnerability {
+    pub id: String,
+    pub severity: String,
+    pub fixed_version: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GraphEdge {
+    pub from: String,
+    pub to: String,
+    pub specifier: Option<String>,
+    pub extras: Vec<String>,
+    pub marker: Option<String>,
+    /// The required package is in conflict and its version fails this specifier
+    pub conflict: bool,
+    /// In a lockfile diff: added, removed or unchanged
+    pub change: Option<String>,
+}
+
+/// A package whose requirements cannot all be met
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GraphConflict {
+    pub package: String,
+    pub version: Option<String>,
+    pub constraints: Vec<GraphConstraint>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GraphConstraint {
+    pub required_by: String,
+    pub specifier: String,
+    /// Whether the package's version meets the specifier
+    pub satisfied: bool,
+}
+
+/// Result of checking one distribution for `sa license check`
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LicenseFinding {
+    pub name: String,
+    pub version: String,
+    /// Normalized SPDX expression, when one could be determined
+    pub license: Option<String>,
+    pub violation: Option<String>,
+    /// How the package is pulled in, from a top-level distribution
+    pub path: Vec<String>,
+}
+
+/// A distribution installed in an environment, as reported by `pip list`
+#[derive(Serialize, Deserialize, Clone)]
+pub struct InstalledPackage {
+    pub name: String,
+    pub version: String,
+}
+
+/// One version change proposed by `sa audit --fix`
+#[derive(Clone)]
+pub struct AuditFix {
+    pub name: String,
+    /// Current version; `None` for a dependency the upgrade newly pulls in
+    pub from: Option<String>,
+    pub to: String,
+    /// Advisories the change clears
+    pub fixes: Vec<String>,
+    pub sha256: Option<String>,
+}
+
+/// Upgrades that clear an audit's findings, and the findings they cannot
+#[derive(Default)]
+pub struct AuditFixPlan {
+    pub changes: Vec<AuditFix>,
+    /// Packages whose advisories have no usable fixed version, with the advisory IDs
+    pub unfixable: Vec<(String, Vec<String>)>,
+    /// Upgrades the resolver rejected, with its reason
+    pub blocked: Vec<(String, String)>,
+}
+
+/// Recorded in each cached `sa run --with` environment
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EphemeralEnvInfo {
+    pub requirements: Vec<String>,
+    pub python_version: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used: DateTime<Utc>,
+}
+
+/// Validators and freshness for a response held in the HTTP cache
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HttpCacheEntry {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub stored_at: DateTime<Utc>,
+    pub max_age: Option<i64>,
+    pub no_cache: bool,
+}
+
+/// Recorded in the environment directory when SA creates it
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EnvMetadata {
+    pub python: PathBuf,
+    pub python_version: String,
+    pub created_at: DateTime<Utc>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -246,13 +1503,300 @@ pub struct Mirror {
     pub is_default: bool,
     pub last_tested: Option<DateTime<Utc>>,
     pub is_active: bool,
+    /// Lower numbers are consulted first; a project is only ever taken from
+    /// the first index that has it
+    #[serde(default)]
+    pub priority: u32,
+    /// "primary" (a full index, one of which is pip's index URL) or
+    /// "supplemental" (an extra index consulted alongside it)
+    #[serde(default = "default_mirror_role")]
+    pub role: String,
+    /// "simple", "devpi", "artifactory" or "nexus"
+    #[serde(default = "default_mirror_kind")]
+    pub kind: String,
+    /// Page below the index requested by connectivity tests (default "pip/")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub probe_path: Option<String>,
+    /// Whether the index serves the PEP 691 JSON simple API, as found by the
+    /// last successful connectivity test; unknown until then
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json_api: Option<bool>,
+    /// Added to the global TLS settings for requests to this mirror
+    #[serde(default, flatten)]
+    pub tls: TlsOptions,
+    /// Override the global timeouts, retries and bandwidth cap
+    #[serde(default, flatten)]
+    pub transfer: TransferOptions,
+    /// Outcomes of the most recent health checks, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recent_checks: Vec<bool>,
 }
 
-#[derive(Serialize, Deserialize)]
+/// TLS settings for internal indexes, set globally under [network] and per
+/// mirror in mirrors.json
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct TlsOptions {
+    /// Extra CA certificates (PEM bundle) trusted alongside the system roots
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ca_cert: Option<PathBuf>,
+    /// PEM file holding the client certificate and its PKCS#8 private key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_cert: Option<PathBuf>,
+    /// Skip certificate verification entirely (warned about on every run)
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub insecure: bool,
+}
+
+/// Timeouts, retries and bandwidth limits, set globally under [network] and
+/// per mirror in mirrors.json
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct TransferOptions {
+    /// Seconds to wait for a connection
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_timeout: Option<u64>,
+    /// Seconds to wait for data on an open connection
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_timeout: Option<u64>,
+    /// Retries of failed connections, timeouts, 429 and 5xx responses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+    /// Download rate cap per transfer, e.g. "5MB" (per second)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_bandwidth: Option<String>,
+}
+
+fn default_mirror_role() -> String {
+    "primary".to_string()
+}
+
+fn default_mirror_kind() -> String {
+    "simple".to_string()
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct SAConfig {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     pub mirrors: Vec<Mirror>,
     pub cache_dir: PathBuf,
     pub security_enabled: bool,
     pub docker_enabled: bool,
     pub default_python_version: String,
+    pub venv: VenvConfig,
+    pub cache: CacheConfig,
+    pub security: SecurityConfig,
+    pub mirror: MirrorConfig,
+    pub network: NetworkConfig,
+    pub docker: DockerConfig,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub repositories: BTreeMap<String, PublishRepository>,
+}
+
+/// An upload target for `sa publish --repository <name>`, under
+/// `[repositories.<name>]`
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct PublishRepository {
+    /// Upload endpoint, e.g. "https://pypi.corp/legacy/"
+    pub url: String,
+    /// Defaults to "__token__", for a token as the password
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// Environment variable holding the token or password
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password_env: Option<String>,
+    /// The repository's simple index, to see which files it already has
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index_url: Option<String>,
+}
+
+/// Proxy, TLS and transfer settings for every request SA makes (and the pip
+/// runs it starts)
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct NetworkConfig {
+    /// e.g. "http://proxy.corp:3128" or "socks5h://proxy.corp:1080"; when
+    /// unset HTTP_PROXY, HTTPS_PROXY and NO_PROXY are honored
+    pub proxy: Option<String>,
+    /// Comma-separated hosts that bypass `proxy` (defaults to NO_PROXY)
+    pub no_proxy: Option<String>,
+    #[serde(flatten)]
+    pub tls: TlsOptions,
+    #[serde(flatten)]
+    pub transfer: TransferOptions,
+}
+
+/// `[tool.sa.mirrors]`: per-project indexes replacing those in mirrors.json.
+/// Values are URLs or names of configured mirrors.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct MirrorOverrides {
+    pub index_url: Option<String>,
+    pub extra_index_urls: Option<Vec<String>>,
+}
+
+/// How the index used for installs is chosen among the configured mirrors
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct MirrorConfig {
+    /// "default", "fastest" (lowest latency, measured once per run) or a mirror name
+    pub preferred: String,
+    /// How often mirrors are re-tested before an install (e.g. "1h"), or "off"
+    pub health_check: String,
+    /// Consecutive failed checks after which a mirror is deactivated
+    pub deactivate_after: usize,
+}
+
+/// Which container engine the Docker commands talk to
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct DockerConfig {
+    /// "auto" (Docker's socket, then Podman's), "docker" or "podman"
+    pub runtime: String,
+    /// Engine endpoint, e.g. "unix:///run/user/1000/podman/podman.sock",
+    /// "tcp://build-box:2375" or "ssh://user@build-box"; defaults to
+    /// DOCKER_HOST (CONTAINER_HOST for Podman), then the local socket
+    pub host: Option<String>,
+    /// Build with BuildKit (`docker build`) when buildx is installed, for
+    /// parallel stages and a pip cache kept between builds
+    pub buildkit: bool,
+}
+
+/// Credentials for one mirror, kept in ~/.config/sa/credentials.toml rather
+/// than mirrors.json. Values may reference environment variables as `${NAME}`.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct MirrorCredentials {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Bearer token; pip receives it as the basic-auth password
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// The password (with a username) or token is kept in the OS keyring
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub keyring: bool,
+}
+
+/// Mirror credentials with environment references resolved
+#[derive(Clone)]
+pub enum MirrorAuth {
+    Basic { username: String, password: Option<String> },
+    Bearer(String),
+}
+
+/// Result of `sa mirror test --benchmark` for one mirror
+#[derive(Clone)]
+pub struct MirrorBenchmark {
+    pub name: String,
+    /// Best of several index page requests
+    pub latency: Option<std::time::Duration>,
+    /// Bytes per second while downloading a small known artifact
+    pub throughput: Option<f64>,
+    pub error: Option<String>,
+}
+
+/// A backing service in [tool.sa.services]; known services (postgres, redis,
+/// mysql) need no keys at all
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ServiceSpec {
+    /// Full image reference, replacing the known service's default
+    pub image: Option<String>,
+    /// Tag of the known service's default image, e.g. "16"
+    pub version: Option<String>,
+    /// Published ports, e.g. "5432:5432" (default: the service's own port)
+    pub ports: Vec<String>,
+    /// Added to (or replacing) the service's default environment
+    pub environment: BTreeMap<String, String>,
+}
+
+/// Limits, networking and environment for Docker environment containers,
+/// from [tool.sa.docker] with command-line flags taking precedence
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ContainerSettings {
+    /// Memory limit, e.g. "2GB"
+    pub memory: Option<String>,
+    /// CPUs the container may use, e.g. 1.5
+    pub cpus: Option<f64>,
+    /// Network to join, or a mode such as "host" or "none"
+    pub network: Option<String>,
+    /// Published ports, "[ip:]host:container[/protocol]"
+    pub ports: Vec<String>,
+    pub environment: BTreeMap<String, String>,
+    /// Platforms the image is built for, e.g. "linux/amd64"; the container
+    /// runs the engine's own platform when listed, else the first
+    pub platforms: Vec<String>,
+}
+
+/// A container-based Docker environment
+pub struct DockerEnvironment {
+    pub name: String,
+    /// Base image the environment was built from
+    pub image: String,
+    /// Container state, e.g. "running" or "exited"
+    pub state: String,
+    /// Human-readable status, e.g. "Up 2 hours"
+    pub status: String,
+    pub created: Option<DateTime<Utc>>,
+    /// Requirements file the image was built from
+    pub lockfile: Option<PathBuf>,
+    /// Whether the requirements file changed since the image was built
+    pub stale: bool,
+}
+
+/// Outcome of a mirror connectivity test
+pub struct MirrorProbe {
+    /// What failed; None when the mirror is healthy
+    pub problem: Option<String>,
+    /// Whether the probe page came back as PEP 691 JSON; None when no
+    /// simple index page could be fetched
+    pub json_api: Option<bool>,
+}
+
+/// Machine-wide vulnerability database settings
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SecurityConfig {
+    /// Refresh the vulnerability database before scans and installs once it
+    /// is older than this, e.g. "24h" or "7d"
+    pub db_ttl: String,
+    /// External command run on each artifact before install, e.g.
+    /// "clamscan --no-summary {}" (`{}` is the file path; appended when absent)
+    pub artifact_scanner: Option<String>,
+}
+
+/// Retention policy for the package cache
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Size budget, e.g. "10GB" or "500MB"
+    pub max_size: String,
+    /// Evict artifacts not accessed for this long, e.g. "90d" or "12h"
+    pub max_age: String,
+    /// Run gc in the background when the cache exceeds its budget
+    pub auto_gc: bool,
+}
+
+/// Controls which seed packages end up in a freshly created virtual environment
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct VenvConfig {
+    /// Operate on an existing .venv/venv instead of creating .sa_env
+    pub reuse_existing: bool,
+    /// Create the environment without pip (installs go through the host pip)
+    pub without_pip: bool,
+    /// Remove setuptools if the interpreter seeded it
+    pub without_setuptools: bool,
+    /// Pin the seeded pip to this version
+    pub pip_version: Option<String>,
+    /// Pin (and install) setuptools at this version
+    pub setuptools_version: Option<String>,
+    /// Days an unused `sa run --with` environment is kept before removal
+    pub ephemeral_max_age_days: i64,
+    /// How cached files are placed into site-packages (auto, reflink, hardlink, copy)
+    pub link_mode: String,
 }
@@ -0,0 +1,81 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use serde::Serialize;
+
+// JSON output mode (`--json` or SA_OUTPUT=json): a command's result is one
+// JSON document on stdout and everything meant for people goes to stderr,
+// including the output of pip and build backends. Rather than each message
+// picking its stream, stdout itself is pointed at stderr for the run and
+// the original is kept for the results alone. Off Unix stdout stays as it
+// is, so messages and results share it.
+
+static JSON: AtomicBool = AtomicBool::new(false);
+/// The original stdout, while it points at stderr
+static RESULTS: OnceLock<Mutex<std::fs::File>> = OnceLock::new();
+/// Whether a result was printed, so a failure is not reported twice
+static PRINTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether SA_OUTPUT asks for JSON output
+pub fn json_requested() -> bool {
+    std::env::var("SA_OUTPUT").is_ok_and(|value| value.trim().eq_ignore_ascii_case("json"))
+}
+
+/// Switches to JSON output for the rest of the run
+pub fn enable_json() -> Result<(), Box<dyn std::error::Error>> {
+    if JSON.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    std::io::stdout().flush()?;
+    #[cfg(unix)]
+    {
+        use std::os::fd::FromRawFd;
+        // SAFETY: `saved` is a fresh descriptor owned by the File from here on
+        unsafe {
+            let saved = libc::dup(libc::STDOUT_FILENO);
+            if saved < 0 {
+                return Err(format!("Failed to set up JSON output: {}", std::io::Error::last_os_error()).into());
+            }
+            let results = std::fs::File::from_raw_fd(saved);
+            if libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) < 0 {
+                return Err(format!("Failed to set up JSON output: {}", std::io::Error::last_os_error()).into());
+            }
+            let _ = RESULTS.set(Mutex::new(results));
+        }
+    }
+    Ok(())
+}
+
+/// Whether results are printed as JSON
+pub fn json_mode() -> bool {
+    JSON.load(Ordering::SeqCst)
+}
+
+/// Reports a failure as `{"error": ...}`, unless the command printed its
+/// result already (which then tells what failed)
+pub fn print_error(error: &str) {
+    if !PRINTED.load(Ordering::SeqCst) {
+        let _ = print_json(&serde_json::json!({ "error": error }));
+    }
+}
+
+/// Prints a result as pretty JSON on the original stdout
+pub fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<(), Box<dyn std::error::Error>> {
+    print_result(&serde_json::to_string_pretty(value)?)
+}
+
+/// Prints a result (a report, a graph) on the original stdout, ending it
+/// with a newline
+pub fn print_result(text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    PRINTED.store(true, Ordering::SeqCst);
+    let newline = if text.ends_with('\n') { "" } else { "\n" };
+    match RESULTS.get() {
+        Some(results) => {
+            let mut results = results.lock().map_err(|_| "JSON output is unavailable")?;
+            write!(results, "{}{}", text, newline)?;
+            results.flush()?;
+        }
+        None => print!("{}{}", text, newline),
+    }
+    Ok(())
+}
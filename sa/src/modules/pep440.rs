@@ -0,0 +1,404 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Pre-release phase, ordered a < b < rc
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum PreRelease {
+    Alpha,
+    Beta,
+    Rc,
+}
+
+/// Local version label segment; numeric segments sort after alphanumeric ones
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum LocalSegment {
+    Text(String),
+    Number(u64),
+}
+
+type SuffixKey = ((i8, Option<(PreRelease, u64)>), Option<u64>, (i8, u64));
+
+/// A PEP 440 version, e.g. `1!2.0.1rc1.post2.dev3+ubuntu.1`
+#[derive(Clone, Debug)]
+pub struct Version {
+    pub epoch: u64,
+    pub release: Vec<u64>,
+    pub pre: Option<(PreRelease, u64)>,
+    pub post: Option<u64>,
+    pub dev: Option<u64>,
+    pub local: Vec<LocalSegment>,
+}
+
+impl Version {
+    /// Parses a version, accepting the alternative spellings PEP 440 normalizes
+    /// (`v1.0`, `1.0-alpha.1`, `1.0-1`, `1.0_dev2`, ...)
+    pub fn parse(value: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let invalid = || format!("Invalid version '{}'", value);
+        let lowered = value.trim().to_lowercase();
+        let (public, local) = match lowered.split_once('+') {
+            Some((public, local)) => (public, Some(local)),
+            None => (lowered.as_str(), None),
+        };
+        let mut rest = public.strip_prefix('v').unwrap_or(public);
+
+        let mut epoch = 0;
+        if let Some((e, tail)) = rest.split_once('!') {
+            epoch = e.parse().map_err(|_| invalid())?;
+            rest = tail;
+        }
+
+        let (release, mut rest) = take_release(rest).ok_or_else(invalid)?;
+
+        let mut pre = None;
+        let trimmed = rest.trim_start_matches(['.', '-', '_']);
+        for (spelling, phase) in [
+            ("alpha", PreRelease::Alpha),
+            ("beta", PreRelease::Beta),
+            ("preview", PreRelease::Rc),
+            ("pre", PreRelease::Rc),
+            ("rc", PreRelease::Rc),
+            ("a", PreRelease::Alpha),
+            ("b", PreRelease::Beta),
+            ("c", PreRelease::Rc),
+        ] {
+            if let Some(tail) = trimmed.strip_prefix(spelling) {
+                let (number, tail) = take_number(tail.trim_start_matches(['.', '-', '_']));
+                pre = Some((phase, number.unwrap_or(0)));
+                rest = tail;
+                break;
+            }
+        }
+
+        let mut post = None;
+        if let Some(tail) = rest.strip_prefix('-').filter(|t| t.starts_with(|c: char| c.is_ascii_digit())) {
+            // `1.0-1` is an implicit post release
+            let (number, tail) = take_number(tail);
+            post = number;
+            rest = tail;
+        } else {
+            let trimmed = rest.trim_start_matches(['.', '-', '_']);
+            for spelling in ["post", "rev", "r"] {
+                if let Some(tail) = trimmed.strip_prefix(spelling) {
+                    let (number, tail) = take_number(tail.trim_start_matches(['.', '-', '_']));
+                    post = Some(number.unwrap_or(0));
+                    rest = tail;
+                    break;
+                }
+            }
+        }
+
+        let mut dev = None;
+        let trimmed = rest.trim_start_matches(['.', '-', '_']);
+        if let Some(tail) = trimmed.strip_prefix("dev") {
+            let (number, tail) = take_number(tail.trim_start_matches(['.', '-', '_']));
+            dev = Some(number.unwrap_or(0));
+            rest = tail;
+        }
+
+        if !rest.is_empty() {
+            return Err(invalid().into());
+        }
+
+        let local = match local {
+            Some(local) => {
+                let segments: Vec<LocalSegment> = local
+                    .split(['.', '-', '_'])
+                    .map(|segment| match segment.parse() {
+                        Ok(number) => LocalSegment::Number(number),
+                        Err(_) => LocalSegment::Text(segment.to_string()),
+                    })
+                    .collect();
+                let valid = segments.iter().all(|segment| match segment {
+                    LocalSegment::Text(text) => !text.is_empty() && text.chars().all(|c| c.is_ascii_alphanumeric()),
+                    LocalSegment::Number(_) => true,
+                });
+                if !valid {
+                    return Err(invalid().into());
+                }
+                segments
+            }
+            None => Vec::new(),
+        };
+
+        Ok(Version { epoch, release, pre, post, dev, local })
+    }
+
+    pub fn is_prerelease(&self) -> bool {
+        self.pre.is_some() || self.dev.is_some()
+    }
+
+    pub fn is_postrelease(&self) -> bool {
+        self.post.is_some()
+    }
+
+    /// The version without its local label
+    pub fn public(&self) -> Version {
+        Version { local: Vec::new(), ..self.clone() }
+    }
+
+    /// Epoch and release only, e.g. `1.2` for `1.2rc1.post3`
+    pub fn base(&self) -> Version {
+        Version {
+            epoch: self.epoch,
+            release: self.release.clone(),
+            pre: None,
+            post: None,
+            dev: None,
+            local: Vec::new(),
+        }
+    }
+
+    fn release_at(&self, index: usize) -> u64 {
+        self.release.get(index).copied().unwrap_or(0)
+    }
+
+    // Sort key following PEP 440: a dev release without pre/post sorts before
+    // the pre-releases, a missing pre sorts after them, a missing dev after devs
+    fn suffix_key(&self) -> SuffixKey {
+        let pre = match self.pre {
+            None if self.post.is_none() && self.dev.is_some() => (-1, None),
+            None => (1, None),
+            Some(pre) => (0, Some(pre)),
+        };
+        let dev = match self.dev {
+            None => (1, 0),
+            Some(dev) => (0, dev),
+        };
+        (pre, self.post, dev)
+    }
+}
+
+fn take_release(value: &str) -> Option<(Vec<u64>, &str)> {
+    let mut release = Vec::new();
+    let mut rest = value;
+    loop {
+        let (number, tail) = take_number(rest);
+        release.push(number?);
+        rest = tail;
+        match rest.strip_prefix('.') {
+            Some(tail) if tail.starts_with(|c: char| c.is_ascii_digit()) => rest = tail,
+            _ => return Some((release, rest)),
+        }
+    }
+}
+
+fn take_number(value: &str) -> (Option<u64>, &str) {
+    let end = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    (value[..end].parse().ok(), &value[end..])
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let len = self.release.len().max(other.release.len());
+        self.epoch
+            .cmp(&other.epoch)
+            .then_with(|| (0..len).map(|i| self.release_at(i).cmp(&other.release_at(i))).find(|o| o.is_ne()).unwrap_or(Ordering::Equal))
+            .then_with(|| self.suffix_key().cmp(&other.suffix_key()))
+            .then_with(|| self.local.cmp(&other.local))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl fmt::Display for Version {
+    /// Normalized form, e.g. `1.0a1.post0.dev2+local.7`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.epoch != 0 {
+            write!(f, "{}!", self.epoch)?;
+        }
+        let release: Vec<String> = self.release.iter().map(u64::to_string).collect();
+        write!(f, "{}", release.join("."))?;
+        if let Some((phase, number)) = self.pre {
+            let phase = match phase {
+                PreRelease::Alpha => "a",
+                PreRelease::Beta => "b",
+                PreRelease::Rc => "rc",
+            };
+            write!(f, "{}{}", phase, number)?;
+        }
+        if let Some(post) = self.post {
+            write!(f, ".post{}", post)?;
+        }
+        if let Some(dev) = self.dev {
+            write!(f, ".dev{}", dev)?;
+        }
+        if !self.local.is_empty() {
+            let local: Vec<String> = self
+                .local
+                .iter()
+                .map(|segment| match segment {
+                    LocalSegment::Text(text) => text.clone(),
+                    LocalSegment::Number(number) => number.to_string(),
+                })
+                .collect();
+            write!(f, "+{}", local.join("."))?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares two version strings, falling back to plain string order when
+/// either is not a valid PEP 440 version
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    match (Version::parse(a), Version::parse(b)) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Operator {
+    Compatible,
+    Equal,
+    NotEqual,
+    LessEqual,
+    GreaterEqual,
+    Less,
+    Greater,
+    Arbitrary,
+}
+
+/// A single version clause such as `>=1.2`, `==2.*` or `~=1.4.2`
+#[derive(Clone, Debug)]
+pub struct Specifier {
+    pub operator: Operator,
+    pub version: String,
+    pub wildcard: bool,
+}
+
+impl Specifier {
+    pub fn parse(value: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let value = value.trim();
+        let (operator, version) = [
+            ("===", Operator::Arbitrary),
+            ("~=", Operator::Compatible),
+            ("==", Operator::Equal),
+            ("!=", Operator::NotEqual),
+            ("<=", Operator::LessEqual),
+            (">=", Operator::GreaterEqual),
+            ("<", Operator::Less),
+            (">", Operator::Greater),
+        ]
+        .into_iter()
+        .find_map(|(prefix, operator)| value.strip_prefix(prefix).map(|rest| (operator, rest.trim())))
+        .ok_or_else(|| format!("Invalid version specifier '{}'", value))?;
+
+        let wildcard = version.ends_with(".*");
+        if wildcard && !matches!(operator, Operator::Equal | Operator::NotEqual) {
+            return Err(format!("Wildcards are only allowed with == and != in '{}'", value).into());
+        }
+        if operator != Operator::Arbitrary {
+            let parsed = Version::parse(version.trim_end_matches(".*"))?;
+            if operator == Operator::Compatible && parsed.release.len() < 2 {
+                return Err(format!("~= needs at least two release segments in '{}'", value).into());
+            }
+        }
+
+        Ok(Specifier { operator, version: version.to_string(), wildcard })
+    }
+
+    pub fn contains(&self, candidate: &Version) -> bool {
+        if self.operator == Operator::Arbitrary {
+            return candidate.to_string().eq_ignore_ascii_case(&self.version);
+        }
+        // Validated in parse()
+        let Ok(spec) = Version::parse(self.version.trim_end_matches(".*")) else {
+            return false;
+        };
+
+        match self.operator {
+            Operator::Equal if self.wildcard => prefix_match(candidate, &spec),
+            Operator::NotEqual if self.wildcard => !prefix_match(candidate, &spec),
+            // A local label on the candidate is ignored unless the spec has one
+            Operator::Equal if spec.local.is_empty() => candidate.public() == spec,
+            Operator::Equal => *candidate == spec,
+            Operator::NotEqual if spec.local.is_empty() => candidate.public() != spec,
+            Operator::NotEqual => *candidate != spec,
+            Operator::LessEqual => candidate.public() <= spec,
+            Operator::GreaterEqual => candidate.public() >= spec,
+            // `<2.0` excludes 2.0 pre-releases and `>2.0` excludes 2.0 post
+            // releases, unless the spec itself is one
+            Operator::Less => {
+                let same_base = candidate.base() == spec.base();
+                *candidate < spec && (spec.is_prerelease() || !candidate.is_prerelease() || !same_base)
+            }
+            Operator::Greater => {
+                let same_base = candidate.base() == spec.base();
+                *candidate > spec
+                    && (spec.is_postrelease() || !candidate.is_postrelease() || !same_base)
+                    && (candidate.local.is_empty() || !same_base)
+            }
+            Operator::Compatible => {
+                let mut prefix = spec.base();
+                prefix.release.pop();
+                candidate.public() >= spec && prefix_match(candidate, &prefix)
+            }
+            Operator::Arbitrary => false,
+        }
+    }
+}
+
+// `==1.2.*`: same epoch and the candidate's release starts with 1.2
+fn prefix_match(candidate: &Version, prefix: &Version) -> bool {
+    candidate.epoch == prefix.epoch
+        && (0..prefix.release.len()).all(|i| candidate.release_at(i) == prefix.release[i])
+}
+
+impl fmt::Display for Specifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operator = match self.operator {
+            Operator::Compatible => "~=",
+            Operator::Equal => "==",
+            Operator::NotEqual => "!=",
+            Operator::LessEqual => "<=",
+            Operator::GreaterEqual => ">=",
+            Operator::Less => "<",
+            Operator::Greater => ">",
+            Operator::Arbitrary => "===",
+        };
+        write!(f, "{}{}", operator, self.version)
+    }
+}
+
+/// Comma-separated specifiers that must all match, e.g. `>=1.0,<2`
+#[derive(Clone, Debug, Default)]
+pub struct SpecifierSet {
+    pub specifiers: Vec<Specifier>,
+}
+
+impl SpecifierSet {
+    pub fn parse(value: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let specifiers = value
+            .split(',')
+            .map(str::trim)
+            .filter(|clause| !clause.is_empty())
+            .map(Specifier::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(SpecifierSet { specifiers })
+    }
+
+    /// Whether the version satisfies every clause. Pre-releases are not
+    /// filtered out, so a set describing affected versions covers them too.
+    pub fn contains(&self, version: &Version) -> bool {
+        self.specifiers.iter().all(|specifier| specifier.contains(version))
+    }
+}
+
+impl fmt::Display for SpecifierSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let clauses: Vec<String> = self.specifiers.iter().map(Specifier::to_string).collect();
+        write!(f, "{}", clauses.join(","))
+    }
+}
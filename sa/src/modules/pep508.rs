@@ -0,0 +1,278 @@
+use crate::modules::installer::normalize_dist_name;
+use crate::modules::pep440::{Specifier, Version};
+
+/// A PEP 508 dependency specification, e.g.
+/// `requests[socks] (>=2.28,<3) ; python_version >= "3.8"`
+#[derive(Clone, Debug)]
+pub struct Requirement {
+    pub name: String,
+    pub extras: Vec<String>,
+    /// Version specifiers as written (`>=2.28,<3`), or `@ <url>` for a
+    /// direct reference; empty when any version will do
+    pub specifier: String,
+    pub marker: Option<String>,
+}
+
+impl Requirement {
+    pub fn parse(value: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let (requirement, marker) = match value.split_once(';') {
+            Some((requirement, marker)) => (requirement.trim(), Some(marker.trim().to_string()).filter(|m| !m.is_empty())),
+            None => (value.trim(), None),
+        };
+        let end = requirement
+            .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+            .unwrap_or(requirement.len());
+        let name = &requirement[..end];
+        if name.is_empty() {
+            return Err(format!("Invalid requirement '{}'", value).into());
+        }
+
+        let mut rest = requirement[end..].trim_start();
+        let mut extras = Vec::new();
+        if let Some(after) = rest.strip_prefix('[') {
+            let (list, tail) = after.split_once(']').ok_or_else(|| format!("Unclosed extras in '{}'", value))?;
+            extras = list.split(',').map(str::trim).filter(|extra| !extra.is_empty()).map(normalize_dist_name).collect();
+            rest = tail.trim_start();
+        }
+        let specifier = match rest.strip_prefix('@') {
+            Some(url) => format!("@ {}", url.trim()),
+            None => rest.trim_start_matches('(').trim_end_matches(')').replace(' ', ""),
+        };
+
+        Ok(Requirement { name: name.to_string(), extras, specifier, marker })
+    }
+}
+
+/// Values environment markers are evaluated against
+pub struct MarkerEnvironment {
+    pub python_version: String,
+    pub python_full_version: String,
+    pub sys_platform: &'static str,
+    pub platform_system: &'static str,
+    pub os_name: &'static str,
+    pub platform_machine: &'static str,
+}
+
+impl MarkerEnvironment {
+    /// This machine, running Python `python_version` (e.g. 3.11.4)
+    pub fn current(python_version: &str) -> Self {
+        let short: Vec<&str> = python_version.split('.').take(2).collect();
+        let (sys_platform, platform_system) = match std::env::consts::OS {
+            "macos" => ("darwin", "Darwin"),
+            "windows" => ("win32", "Windows"),
+            "freebsd" => ("freebsd", "FreeBSD"),
+            _ => ("linux", "Linux"),
+        };
+        let platform_machine = match (std::env::consts::OS, std::env::consts::ARCH) {
+            ("macos", "aarch64") => "arm64",
+            ("windows", "x86_64") => "AMD64",
+            (_, arch) => arch,
+        };
+        MarkerEnvironment {
+            python_version: short.join("."),
+            python_full_version: python_version.to_string(),
+            sys_platform,
+            platform_system,
+            os_name: if cfg!(windows) { "nt" } else { "posix" },
+            platform_machine,
+        }
+    }
+
+    fn value(&self, variable: &str) -> Option<String> {
+        Some(match variable {
+            "python_version" => self.python_version.clone(),
+            "python_full_version" | "implementation_version" => self.python_full_version.clone(),
+            "sys_platform" => self.sys_platform.to_string(),
+            "platform_system" => self.platform_system.to_string(),
+            "os_name" => self.os_name.to_string(),
+            "platform_machine" => self.platform_machine.to_string(),
+            "implementation_name" => "cpython".to_string(),
+            "platform_python_implementation" => "CPython".to_string(),
+            _ => return None,
+        })
+    }
+}
+
+/// Whether a marker such as `python_version < "3.8" or extra == "socks"`
+/// holds in `env` with the given extras requested. Markers SA cannot
+/// evaluate (unknown variables, malformed expressions) are an error.
+pub fn evaluate_marker(marker: &str, env: &MarkerEnvironment, extras: &[String]) -> Result<bool, Box<dyn std::error::Error>> {
+    let tokens = tokenize(marker)?;
+    let mut parser = MarkerParser { tokens: &tokens, position: 0, env, extras };
+    let result = parser.or()?;
+    if parser.position != tokens.len() {
+        return Err(format!("Unexpected text in marker '{}'", marker).into());
+    }
+    Ok(result)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Text(String),
+    Word(String),
+    Operator(String),
+}
+
+fn tokenize(marker: &str) -> Result<Vec<Token>, Box<dyn std::error::Error>> {
+    let mut tokens = Vec::new();
+    let mut chars = marker.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            ' ' | '\t' => {
+                chars.next();
+            }
+            '(' | ')' => {
+                chars.next();
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            }
+            '"' | '\'' => {
+                chars.next();
+                let text: String = chars.by_ref().take_while(|&next| next != c).collect();
+                tokens.push(Token::Text(text));
+            }
+            '<' | '>' | '=' | '!' | '~' => {
+                let mut operator = String::new();
+                while let Some(&next) = chars.peek().filter(|next| matches!(next, '<' | '>' | '=' | '!' | '~')) {
+                    operator.push(next);
+                    chars.next();
+                }
+                tokens.push(Token::Operator(operator));
+            }
+            _ if c.is_ascii_alphanumeric() || c == '_' => {
+                let mut word = String::new();
+                while let Some(&next) = chars.peek().filter(|next| next.is_ascii_alphanumeric() || matches!(next, '_' | '.')) {
+                    word.push(next);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+            _ => return Err(format!("Unexpected '{}' in marker '{}'", c, marker).into()),
+        }
+    }
+    Ok(tokens)
+}
+
+struct MarkerParser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    env: &'a MarkerEnvironment,
+    extras: &'a [String],
+}
+
+impl MarkerParser<'_> {
+    fn peek_word(&self, word: &str) -> bool {
+        matches!(self.tokens.get(self.position), Some(Token::Word(w)) if w == word)
+    }
+
+    fn or(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        let mut result = self.and()?;
+        while self.peek_word("or") {
+            self.position += 1;
+            // Both sides are parsed, so errors anywhere are reported
+            let right = self.and()?;
+            result = result || right;
+        }
+        Ok(result)
+    }
+
+    fn and(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        let mut result = self.comparison()?;
+        while self.peek_word("and") {
+            self.position += 1;
+            let right = self.comparison()?;
+            result = result && right;
+        }
+        Ok(result)
+    }
+
+    fn comparison(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        if self.tokens.get(self.position) == Some(&Token::Open) {
+            self.position += 1;
+            let result = self.or()?;
+            if self.tokens.get(self.position) != Some(&Token::Close) {
+                return Err("Unclosed parenthesis in marker".into());
+            }
+            self.position += 1;
+            return Ok(result);
+        }
+
+        let left = self.operand()?;
+        let operator = match self.tokens.get(self.position) {
+            Some(Token::Operator(operator)) => operator.clone(),
+            Some(Token::Word(word)) if word == "in" => "in".to_string(),
+            Some(Token::Word(word)) if word == "not" && matches!(self.tokens.get(self.position + 1), Some(Token::Word(w)) if w == "in") => {
+                self.position += 1;
+                "not in".to_string()
+            }
+            _ => return Err("Expected a comparison in marker".into()),
+        };
+        self.position += 1;
+        let right = self.operand()?;
+
+        // `extra` holds each requested extra in turn
+        if let (Operand::Variable(variable), Operand::Literal(value)) | (Operand::Literal(value), Operand::Variable(variable)) = (&left, &right) {
+            if variable == "extra" {
+                let wanted = normalize_dist_name(value);
+                let matched = self.extras.contains(&wanted);
+                return match operator.as_str() {
+                    "==" => Ok(matched),
+                    "!=" => Ok(!matched),
+                    _ => Err(format!("Unsupported extra comparison '{}'", operator).into()),
+                };
+            }
+        }
+
+        let version_like = [&left, &right]
+            .iter()
+            .any(|operand| matches!(operand, Operand::Variable(v) if v.starts_with("python_") || v == "implementation_version"));
+        let left = self.resolve(&left)?;
+        let right = self.resolve(&right)?;
+        compare(&left, &operator, &right, version_like)
+    }
+
+    fn operand(&mut self) -> Result<Operand, Box<dyn std::error::Error>> {
+        let operand = match self.tokens.get(self.position) {
+            Some(Token::Text(text)) => Operand::Literal(text.clone()),
+            Some(Token::Word(word)) => Operand::Variable(word.clone()),
+            _ => return Err("Expected a value in marker".into()),
+        };
+        self.position += 1;
+        Ok(operand)
+    }
+
+    fn resolve(&self, operand: &Operand) -> Result<String, Box<dyn std::error::Error>> {
+        match operand {
+            Operand::Literal(text) => Ok(text.clone()),
+            Operand::Variable(variable) => self.env.value(variable).ok_or_else(|| format!("Unknown marker variable '{}'", variable).into()),
+        }
+    }
+}
+
+enum Operand {
+    Literal(String),
+    Variable(String),
+}
+
+fn compare(left: &str, operator: &str, right: &str, version_like: bool) -> Result<bool, Box<dyn std::error::Error>> {
+    match operator {
+        "in" => return Ok(right.contains(left)),
+        "not in" => return Ok(!right.contains(left)),
+        _ => {}
+    }
+    if version_like {
+        if let (Ok(specifier), Ok(version)) = (Specifier::parse(&format!("{}{}", operator, right)), Version::parse(left)) {
+            return Ok(specifier.contains(&version));
+        }
+    }
+    match operator {
+        "==" | "===" => Ok(left == right),
+        "!=" => Ok(left != right),
+        "<" => Ok(left < right),
+        "<=" => Ok(left <= right),
+        ">" => Ok(left > right),
+        ">=" => Ok(left >= right),
+        _ => Err(format!("Unsupported marker operator '{}'", operator).into()),
+    }
+}
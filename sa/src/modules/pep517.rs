@@ -0,0 +1,297 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use colored::*;
+use serde_json::{json, Value};
+use tempfile::TempDir;
+use tokio::process::Command;
+use crate::modules::cache::{installed_dists, PackageCache};
+use crate::modules::installer::normalize_dist_name;
+use crate::modules::models::{BuildDeps, BuildSystem};
+use crate::modules::pep440::{SpecifierSet, Version};
+use crate::modules::pep508::Requirement;
+use crate::modules::venv::{env_bin_path, find_interpreter};
+
+/// The backend PEP 517 assumes when pyproject.toml names none
+const LEGACY_BACKEND: &str = "setuptools.build_meta:__legacy__";
+/// And what it needs, for projects with no `[build-system]` at all
+const LEGACY_REQUIRES: [&str; 2] = ["setuptools>=40.8.0", "wheel"];
+
+/// Calls one hook of a backend: `hook backend backend-path args result-file`.
+/// The result goes to a file so the backend's output can go straight to
+/// the terminal. The project directory is only importable through
+/// `backend-path`, as PEP 517 requires.
+const HOOK_RUNNER: &str = r#"
+import importlib, json, os, sys
+hook, spec, backend_path, args, result_file = sys.argv[1:6]
+sys.path.pop(0)
+sys.path[:0] = [os.path.abspath(entry) for entry in json.loads(backend_path)]
+module, _, attribute = spec.partition(":")
+backend = importlib.import_module(module)
+for name in filter(None, attribute.split(".")):
+    backend = getattr(backend, name)
+function = getattr(backend, hook, None)
+result = {"unsupported": True} if function is None else {"return": function(*json.loads(args))}
+with open(result_file, "w") as out:
+    json.dump(result, out)
+"#;
+
+/// The project's `[build-system]`, or PEP 517's setuptools fallback for a
+/// project without one
+pub fn read_build_system(project_dir: &Path) -> Result<BuildSystem, Box<dyn std::error::Error>> {
+    let path = project_dir.join("pyproject.toml");
+    let table = if path.exists() {
+        let content = fs::read_to_string(&path)?;
+        let pyproject: toml::Value = toml::from_str(&content).map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+        pyproject.get("build-system").cloned()
+    } else if project_dir.join("setup.py").exists() {
+        None
+    } else {
+        return Err(format!("No pyproject.toml or setup.py in {}; nothing to build", project_dir.display()).into());
+    };
+
+    let mut system = match table {
+        Some(table) => table.try_into::<BuildSystem>().map_err(|e| format!("Invalid [build-system] in {}: {}", path.display(), e))?,
+        None => BuildSystem { requires: LEGACY_REQUIRES.iter().map(|r| r.to_string()).collect(), build_backend: None, backend_path: Vec::new() },
+    };
+    system.build_backend.get_or_insert_with(|| LEGACY_BACKEND.to_string());
+    Ok(system)
+}
+
+/// A project's PEP 517 build backend, run in a throwaway environment with
+/// just the build requirements, installed from the SA cache where it has
+/// them. The environment is deleted when the backend is dropped, so
+/// nothing a build installs reaches the project environment.
+pub struct BuildBackend {
+    project_dir: PathBuf,
+    system: BuildSystem,
+    env: TempDir,
+    /// Progress and the backend's output go to stderr, leaving stdout to the caller
+    quiet: bool,
+    /// The cached artifacts, for pip's --find-links
+    wheelhouse: Option<PathBuf>,
+}
+
+impl BuildBackend {
+    /// Installs the build requirements, or the pins sa.lock recorded for
+    /// them (`pinned`) while `[build-system]` still asks for the same
+    pub async fn new(project_dir: &Path, python: Option<&str>, quiet: bool, pinned: Option<&BuildDeps>) -> Result<Self, Box<dyn std::error::Error>> {
+        let system = read_build_system(project_dir)?;
+        let interpreter = match python {
+            Some(spec) => find_interpreter(spec)?,
+            None => PathBuf::from("python3"),
+        };
+        let env = tempfile::Builder::new().prefix("sa-build-env-").tempdir()?;
+        let wheelhouse = match PackageCache::new().and_then(|cache| cache.refresh_wheelhouse()) {
+            Ok(wheelhouse) => Some(wheelhouse),
+            Err(e) => {
+                eprintln!("{}", format!("Warning: not installing build requirements from the SA cache: {}", e).yellow());
+                None
+            }
+        };
+        let backend = BuildBackend { project_dir: project_dir.to_path_buf(), system, env, quiet, wheelhouse };
+        backend.status(format!("  {} backend {}", "•".blue(), backend.backend()).normal());
+
+        let status = Command::new(&interpreter)
+            .args(["-m", "venv"])
+            .arg(backend.env.path())
+            .stdout(backend.child_stdout())
+            .status()
+            .await
+            .map_err(|e| format!("Failed to run {}: {}", interpreter.display(), e))?;
+        if !status.success() {
+            return Err("Failed to create the build environment".into());
+        }
+
+        match pinned.filter(|pinned| same_requirements(&pinned.requires, &backend.system.requires)) {
+            Some(pinned) => {
+                backend.status(format!("  {} build requirements pinned by sa.lock", "•".blue()).normal());
+                backend.install(&pinned.pins, true).await?;
+            }
+            None => backend.install(&backend.system.requires, false).await?,
+        }
+        Ok(backend)
+    }
+
+    /// Builds a `wheel` or an `sdist` into `out_dir`, returning its path
+    pub async fn build(&mut self, kind: &str, out_dir: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        // Whatever else the backend needs for this kind of build, unless
+        // the environment already has a version of it that the requirement
+        // accepts (from the pins, or an earlier build)
+        let extra: Vec<String> = match self.call(&format!("get_requires_for_build_{}", kind), json!([{}])).await? {
+            Some(Value::Array(requires)) => requires.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+            _ => Vec::new(),
+        };
+        let installed = installed_dists(self.env.path());
+        let missing: Vec<String> = extra
+            .into_iter()
+            .filter(|requirement| {
+                let Ok(parsed) = Requirement::parse(requirement) else { return true };
+                let name = normalize_dist_name(&parsed.name);
+                !installed.iter().any(|(installed, version)| *installed == name && satisfies(&parsed.specifier, version))
+            })
+            .collect();
+        self.install(&missing, false).await?;
+
+        fs::create_dir_all(out_dir)?;
+        let out_dir = out_dir.canonicalize()?;
+        self.status(format!("🔨 Building {}...", kind).cyan());
+        let name = self
+            .call(&format!("build_{}", kind), json!([out_dir, {}]))
+            .await?
+            .ok_or_else(|| format!("Build backend '{}' has no build_{} hook", self.backend(), kind))?;
+        let name = name.as_str().ok_or_else(|| format!("The build backend returned no file name for the {}", kind))?;
+        Ok(out_dir.join(name))
+    }
+
+    /// Repairs a binary wheel the way indexes want it: the shared libraries
+    /// its extensions link against are copied into it and it is retagged for
+    /// the platforms it then runs on (manylinux with auditwheel, which `plat`
+    /// may name; macOS with delocate; Windows with delvewheel). The repaired
+    /// wheel replaces the original; pure wheels are left alone.
+    pub async fn repair(&self, wheel: &Path, plat: Option<&str>) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let filename = wheel.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        if filename.ends_with("-none-any.whl") {
+            self.status(format!("  {} {} is pure Python; nothing to repair", "•".blue(), filename).normal());
+            return Ok(wheel.to_path_buf());
+        }
+        let (tool, program, subcommand): (&str, &str, &[&str]) = match std::env::consts::OS {
+            "linux" => ("auditwheel", "auditwheel", &["repair"]),
+            "macos" => ("delocate", "delocate-wheel", &["-v"]),
+            "windows" => ("delvewheel", "delvewheel", &["repair"]),
+            other => return Err(format!("Repairing wheels is not supported on {}", other).into()),
+        };
+        if plat.is_some() && tool != "auditwheel" {
+            eprintln!("{}", "Warning: --plat only applies to Linux wheels; ignoring it".yellow());
+        }
+        // auditwheel rewrites library paths with patchelf
+        let tools: Vec<String> = if tool == "auditwheel" { vec![tool.to_string(), "patchelf".to_string()] } else { vec![tool.to_string()] };
+        self.install(&tools, false).await?;
+
+        self.status(format!("🩹 Repairing {} with {}...", filename, tool).cyan());
+        let repaired_dir = tempfile::Builder::new().prefix("sa-repair-").tempdir()?;
+        let mut command = Command::new(env_bin_path(self.env.path(), program));
+        command.args(subcommand).arg("-w").arg(repaired_dir.path());
+        if let Some(plat) = plat.filter(|_| tool == "auditwheel") {
+            command.args(["--plat", plat]);
+        }
+        let status = command.arg(wheel).stdout(self.child_stdout()).status().await?;
+        if !status.success() {
+            return Err(format!("{} could not repair {}", tool, filename).into());
+        }
+
+        let repaired = fs::read_dir(repaired_dir.path())?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .find(|path| path.extension().is_some_and(|extension| extension == "whl"))
+            .ok_or_else(|| format!("{} wrote no wheel for {}", tool, filename))?;
+        let target = wheel.with_file_name(repaired.file_name().unwrap_or_default());
+        // Copied, as the scratch directory may be on another file system
+        fs::copy(&repaired, &target)?;
+        if target != wheel {
+            fs::remove_file(wheel)?;
+        }
+        Ok(target)
+    }
+
+    /// What the environment holds now, for sa.lock to pin the next build to
+    pub fn build_deps(&self) -> BuildDeps {
+        let mut pins: Vec<String> = installed_dists(self.env.path())
+            .into_iter()
+            // pip comes with the environment, not from the requirements
+            .filter(|(name, _)| name != "pip")
+            .map(|(name, version)| format!("{}=={}", name, version))
+            .collect();
+        pins.sort();
+        BuildDeps { requires: self.system.requires.clone(), pins }
+    }
+
+    /// pip-installs `requirements` into the build environment; `exact` for
+    /// pins, whose dependencies are pinned alongside them
+    async fn install(&self, requirements: &[String], exact: bool) -> Result<(), Box<dyn std::error::Error>> {
+        if requirements.is_empty() {
+            return Ok(());
+        }
+        self.status(format!("📦 Installing build requirements: {}", requirements.join(", ")).cyan());
+        let mut command = Command::new(env_bin_path(self.env.path(), "python"));
+        command.args(["-m", "pip", "install", "--quiet", "--disable-pip-version-check"]);
+        if exact {
+            command.arg("--no-deps");
+        }
+        if let Some(wheelhouse) = &self.wheelhouse {
+            command.arg("--find-links").arg(wheelhouse);
+        }
+        let status = command.args(requirements).stdout(self.child_stdout()).status().await?;
+        if !status.success() {
+            return Err(format!("Failed to install the build requirements {}", requirements.join(", ")).into());
+        }
+        Ok(())
+    }
+
+    /// Prints a progress line where `quiet` wants it
+    fn status(&self, message: ColoredString) {
+        if self.quiet {
+            eprintln!("{}", message);
+        } else {
+            println!("{}", message);
+        }
+    }
+
+    /// Where a child process's stdout goes
+    fn child_stdout(&self) -> Stdio {
+        if self.quiet {
+            Stdio::from(std::io::stderr())
+        } else {
+            Stdio::inherit()
+        }
+    }
+
+    fn backend(&self) -> &str {
+        self.system.build_backend.as_deref().unwrap_or(LEGACY_BACKEND)
+    }
+
+    /// Runs `hook` with `args` in the build environment; `None` when the
+    /// backend does not define it (the optional hooks)
+    async fn call(&self, hook: &str, args: Value) -> Result<Option<Value>, Box<dyn std::error::Error>> {
+        let backend = self.backend();
+        let scratch = tempfile::Builder::new().prefix("sa-build-").tempdir()?;
+        let result_file = scratch.path().join("result.json");
+        let status = Command::new(env_bin_path(self.env.path(), "python"))
+            .arg("-c")
+            .arg(HOOK_RUNNER)
+            .args([hook, backend, &serde_json::to_string(&self.system.backend_path)?, &args.to_string()])
+            .arg(&result_file)
+            .current_dir(&self.project_dir)
+            .stdout(self.child_stdout())
+            .status()
+            .await?;
+        if !status.success() {
+            return Err(format!("Build backend '{}' failed in {}", backend, hook).into());
+        }
+
+        let result: Value = serde_json::from_str(&fs::read_to_string(&result_file)?)?;
+        Ok(result.get("return").cloned())
+    }
+}
+
+/// Whether two requirement lists ask for the same, in any order
+fn same_requirements(a: &[String], b: &[String]) -> bool {
+    let normalize = |requirements: &[String]| {
+        let mut normalized: Vec<String> = requirements.iter().map(|requirement| requirement.trim().to_lowercase().replace(' ', "")).collect();
+        normalized.sort();
+        normalized.dedup();
+        normalized
+    };
+    normalize(a) == normalize(b)
+}
+
+/// Whether an installed version meets a requirement's specifier; a direct
+/// reference is taken as met by whatever is installed
+fn satisfies(specifier: &str, version: &str) -> bool {
+    if specifier.is_empty() || specifier.starts_with('@') {
+        return true;
+    }
+    match (SpecifierSet::parse(specifier), Version::parse(version)) {
+        (Ok(set), Ok(version)) => set.contains(&version),
+        _ => false,
+    }
+}
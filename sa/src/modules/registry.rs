@@ -0,0 +1,145 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use base64::Engine;
+use bollard::auth::DockerCredentials;
+use serde_json::Value;
+use crate::modules::credentials::read_secret;
+
+/// Key Docker Hub's credentials are stored under
+const DOCKER_HUB_SERVER: &str = "https://index.docker.io/v1/";
+
+// Registry credentials for pushing images. Explicit credentials win;
+// otherwise they come from where `docker login` / `podman login` left them:
+// a credential helper named in ~/.docker/config.json (credHelpers for the
+// registry, else credsStore), then the base64 `auth` entries of that file
+// and of Podman's auth.json.
+
+/// The registry host of an image reference; Docker Hub for references
+/// without one (`team/app:1.0`)
+pub fn registry_host(reference: &str) -> String {
+    match reference.split_once('/') {
+        Some((first, _)) if first.contains('.') || first.contains(':') || first == "localhost" => first.to_string(),
+        _ => "docker.io".to_string(),
+    }
+}
+
+/// Credentials for pushing `reference`: `username` with a password read
+/// from the terminal (or stdin), else the stored login for its registry,
+/// else none (the engine then pushes anonymously)
+pub fn registry_credentials(reference: &str, username: Option<&str>) -> Result<Option<DockerCredentials>, Box<dyn std::error::Error>> {
+    let host = registry_host(reference);
+    let server = if host == "docker.io" { DOCKER_HUB_SERVER.to_string() } else { host.clone() };
+
+    if let Some(username) = username {
+        let password = read_secret(&format!("Password for {} at {}", username, host))?;
+        return Ok(Some(DockerCredentials {
+            username: Some(username.to_string()),
+            password: Some(password),
+            serveraddress: Some(server),
+            ..Default::default()
+        }));
+    }
+
+    for path in auth_files() {
+        let Ok(content) = std::fs::read_to_string(&path) else { continue };
+        let config: Value = serde_json::from_str(&content).map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+
+        let helper = config["credHelpers"]
+            .as_object()
+            .and_then(|helpers| helpers.iter().find(|(key, _)| server_host(key) == host))
+            .and_then(|(_, helper)| helper.as_str())
+            .or_else(|| config["credsStore"].as_str());
+        if let Some(helper) = helper {
+            if let Some(credentials) = from_helper(helper, &server)? {
+                return Ok(Some(credentials));
+            }
+        }
+
+        let entry = config["auths"]
+            .as_object()
+            .and_then(|auths| auths.iter().find(|(key, _)| server_host(key) == host))
+            .map(|(_, entry)| entry);
+        if let Some(entry) = entry {
+            if let Some(token) = entry["identitytoken"].as_str() {
+                return Ok(Some(DockerCredentials { identitytoken: Some(token.to_string()), serveraddress: Some(server), ..Default::default() }));
+            }
+            if let Some(auth) = entry["auth"].as_str() {
+                let decoded = base64::engine::general_purpose::STANDARD
+                    .decode(auth)
+                    .map_err(|_| format!("Invalid auth entry for {} in {}", host, path.display()))?;
+                let decoded = String::from_utf8_lossy(&decoded).to_string();
+                let (username, password) = decoded.split_once(':').unwrap_or((decoded.as_str(), ""));
+                return Ok(Some(DockerCredentials {
+                    username: Some(username.to_string()),
+                    password: Some(password.to_string()),
+                    serveraddress: Some(server),
+                    ..Default::default()
+                }));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Docker's config.json ($DOCKER_CONFIG or ~/.docker), then Podman's auth.json
+fn auth_files() -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    match std::env::var_os("DOCKER_CONFIG") {
+        Some(dir) => files.push(PathBuf::from(dir).join("config.json")),
+        None => files.extend(dirs::home_dir().map(|home| home.join(".docker").join("config.json"))),
+    }
+    if let Some(path) = std::env::var_os("REGISTRY_AUTH_FILE") {
+        files.push(PathBuf::from(path));
+    }
+    if let Some(runtime_dir) = std::env::var_os("XDG_RUNTIME_DIR") {
+        files.push(PathBuf::from(runtime_dir).join("containers").join("auth.json"));
+    }
+    files.extend(dirs::config_dir().map(|config| config.join("containers").join("auth.json")));
+    files
+}
+
+/// The host of a stored server key such as `https://index.docker.io/v1/`
+fn server_host(key: &str) -> String {
+    let host = key.split("://").last().unwrap_or(key).split('/').next().unwrap_or_default();
+    match host {
+        "index.docker.io" | "registry-1.docker.io" => "docker.io".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Asks a `docker-credential-<helper>` for a server's credentials; None when
+/// it has none or is not installed
+fn from_helper(helper: &str, server: &str) -> Result<Option<DockerCredentials>, Box<dyn std::error::Error>> {
+    let program = format!("docker-credential-{}", helper);
+    let Ok(mut child) = Command::new(&program)
+        .arg("get")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+    else {
+        return Ok(None);
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(server.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    let entry: Value = serde_json::from_slice(&output.stdout).map_err(|e| format!("Unexpected output from {}: {}", program, e))?;
+    let username = entry["Username"].as_str().unwrap_or_default();
+    let secret = entry["Secret"].as_str().unwrap_or_default().to_string();
+    // Helpers report identity tokens under this placeholder user name
+    if username == "<token>" {
+        return Ok(Some(DockerCredentials { identitytoken: Some(secret), serveraddress: Some(server.to_string()), ..Default::default() }));
+    }
+    Ok(Some(DockerCredentials {
+        username: Some(username.to_string()),
+        password: Some(secret),
+        serveraddress: Some(server.to_string()),
+        ..Default::default()
+    }))
+}
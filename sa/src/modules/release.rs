@@ -0,0 +1,237 @@
+use std::fs;
+use std::path::PathBuf;
+use regex::Regex;
+use tokio::process::Command;
+
+// `sa release` runs what a release takes, in an order that keeps mistakes
+// cheap: the version is bumped and the changelog written, the distributions
+// built and checked, and only then is anything committed, tagged or
+// uploaded. The changelog section lists the conventional commits
+// (`feat(cli): ...`, `fix!: ...`) made since the last tag, grouped by type;
+// the commits also pick the part to bump when none is given.
+
+/// Commit types listed in the changelog, in order, with their section titles;
+/// chore, ci, build, style, test and refactor commits are left out
+const SECTIONS: [(&str, &str); 5] = [("feat", "Features"), ("fix", "Bug fixes"), ("perf", "Performance"), ("revert", "Reverts"), ("docs", "Documentation")];
+const HIDDEN_TYPES: [&str; 6] = ["chore", "ci", "build", "style", "test", "refactor"];
+
+/// A commit, parsed as a conventional commit when it is one
+pub struct Commit {
+    pub hash: String,
+    /// feat, fix, ... (None when the subject does not follow the convention)
+    pub kind: Option<String>,
+    pub scope: Option<String>,
+    pub breaking: bool,
+    pub description: String,
+}
+
+impl Commit {
+    pub fn parse(hash: &str, subject: &str, body: &str) -> Commit {
+        let pattern = Regex::new(r"^([A-Za-z]+)(?:\(([^)]*)\))?(!)?:\s*(.+)$").expect("valid pattern");
+        let breaking_note = body.lines().any(|line| line.starts_with("BREAKING CHANGE:") || line.starts_with("BREAKING-CHANGE:"));
+        match pattern.captures(subject.trim()) {
+            Some(captures) => Commit {
+                hash: hash.to_string(),
+                kind: Some(captures[1].to_lowercase()),
+                scope: captures.get(2).map(|scope| scope.as_str().trim().to_string()).filter(|scope| !scope.is_empty()),
+                breaking: captures.get(3).is_some() || breaking_note,
+                description: captures[4].trim().to_string(),
+            },
+            None => Commit { hash: hash.to_string(), kind: None, scope: None, breaking: breaking_note, description: subject.trim().to_string() },
+        }
+    }
+
+    fn entry(&self) -> String {
+        let scope = self.scope.as_deref().map(|scope| format!("**{}:** ", scope)).unwrap_or_default();
+        format!("- {}{} ({})", scope, self.description, self.hash)
+    }
+}
+
+/// The part to bump for these commits: major for a breaking change, minor
+/// for a feature, otherwise patch
+pub fn suggested_part(commits: &[Commit]) -> &'static str {
+    if commits.iter().any(|commit| commit.breaking) {
+        "major"
+    } else if commits.iter().any(|commit| commit.kind.as_deref() == Some("feat")) {
+        "minor"
+    } else {
+        "patch"
+    }
+}
+
+/// The commits' changelog entries, grouped under `###` headings: breaking
+/// changes first, then by type, then the commits that are not conventional
+pub fn changes(commits: &[Commit]) -> String {
+    let mut groups: Vec<(&str, Vec<String>)> = Vec::new();
+    let breaking: Vec<String> = commits.iter().filter(|commit| commit.breaking).map(Commit::entry).collect();
+    groups.push(("Breaking changes", breaking));
+    for (kind, title) in SECTIONS {
+        let entries = commits.iter().filter(|commit| !commit.breaking && commit.kind.as_deref() == Some(kind)).map(Commit::entry).collect();
+        groups.push((title, entries));
+    }
+    let other = commits
+        .iter()
+        .filter(|commit| !commit.breaking && commit.kind.as_deref().is_none_or(|kind| !HIDDEN_TYPES.contains(&kind) && !SECTIONS.iter().any(|(known, _)| *known == kind)))
+        .filter(|commit| !commit.description.starts_with("Bump version:"))
+        .map(Commit::entry)
+        .collect();
+    groups.push(("Other changes", other));
+
+    let sections: Vec<String> =
+        groups.into_iter().filter(|(_, entries)| !entries.is_empty()).map(|(title, entries)| format!("### {}\n\n{}", title, entries.join("\n"))).collect();
+    if sections.is_empty() {
+        "No user-facing changes.".to_string()
+    } else {
+        sections.join("\n\n")
+    }
+}
+
+/// A release's changelog section from `template`, where `{version}`,
+/// `{previous}`, `{date}` and `{changes}` are replaced
+pub fn render_section(template: &str, version: &str, previous: &str, changes: &str) -> String {
+    let date = chrono::Local::now().format("%Y-%m-%d").to_string();
+    let section = template.replace("{version}", version).replace("{previous}", previous).replace("{date}", &date).replace("{changes}", changes);
+    format!("{}\n", section.trim_end())
+}
+
+/// Whether a heading of the changelog already names `version`, e.g. when
+/// its notes were written by hand
+pub fn has_section(changelog: &str, version: &str) -> bool {
+    let pattern = Regex::new(&format!(r"(?m)^#+.*[^\w.]{}($|[^\w.])", regex::escape(version))).expect("valid pattern");
+    pattern.is_match(changelog)
+}
+
+/// The changelog with `section` added above the latest release's, below an
+/// Unreleased section, or started when there is none yet
+pub fn insert_section(changelog: &str, section: &str) -> String {
+    if changelog.trim().is_empty() {
+        return format!("# Changelog\n\n{}", section);
+    }
+    let unreleased = Regex::new(r"(?i)^##\s*\[?unreleased\]?").expect("valid pattern");
+    let mut offset = 0;
+    for line in changelog.split_inclusive('\n') {
+        if line.starts_with("## ") && !unreleased.is_match(line) {
+            return format!("{}{}\n{}", &changelog[..offset], section, &changelog[offset..]);
+        }
+        offset += line.len();
+    }
+    format!("{}\n\n{}", changelog.trim_end(), section)
+}
+
+/// Files as they were before the release changed them, put back when
+/// dropped unless `keep` is called
+pub struct Snapshot {
+    files: Vec<(PathBuf, Option<Vec<u8>>)>,
+    kept: bool,
+}
+
+impl Snapshot {
+    pub fn take(paths: &[String]) -> Snapshot {
+        let files = paths.iter().map(|path| (PathBuf::from(path), fs::read(path).ok())).collect();
+        Snapshot { files, kept: false }
+    }
+
+    pub fn keep(mut self) {
+        self.kept = true;
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        if self.kept {
+            return;
+        }
+        for (path, content) in &self.files {
+            let _ = match content {
+                Some(content) => fs::write(path, content),
+                None => fs::remove_file(path),
+            };
+        }
+    }
+}
+
+/// Fails unless the current directory is in a git repository and, with
+/// `tag`, the tag does not exist yet
+pub async fn check_git(tag: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let inside = Command::new("git").args(["rev-parse", "--is-inside-work-tree"]).output().await;
+    if !inside.is_ok_and(|output| output.status.success()) {
+        return Err("Committing and tagging need a git repository".into());
+    }
+    if let Some(tag) = tag {
+        let tagged = Command::new("git").args(["rev-parse", "-q", "--verify"]).arg(format!("refs/tags/{}", tag)).output().await?;
+        if tagged.status.success() {
+            return Err(format!("Tag {} already exists", tag).into());
+        }
+    }
+    Ok(())
+}
+
+/// Tracked files with changes not committed yet
+pub async fn uncommitted_files() -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let output = Command::new("git").args(["status", "--porcelain", "--untracked-files=no"]).output().await?;
+    if !output.status.success() {
+        return Err("git status failed".into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).lines().filter_map(|line| line.get(3..)).map(str::to_string).collect())
+}
+
+/// Commits `paths` (adding them first) with `message`
+pub async fn git_commit(paths: &[String], message: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let added = Command::new("git").arg("add").arg("--").args(paths).status().await?;
+    let committed = added.success() && Command::new("git").args(["commit", "-q", "-m", message, "--"]).args(paths).status().await?.success();
+    if !committed {
+        return Err("git commit failed".into());
+    }
+    Ok(())
+}
+
+/// Tags the current commit with an annotated tag
+pub async fn git_tag(name: &str, message: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let status = Command::new("git").args(["tag", "-a", name, "-m", message]).status().await?;
+    if !status.success() {
+        return Err(format!("Failed to create tag {}", name).into());
+    }
+    Ok(())
+}
+
+/// Pushes the current branch and a tag to its upstream
+pub async fn git_push(tag: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut push = Command::new("git");
+    push.args(["push", "--atomic", "origin", "HEAD"]);
+    if let Some(tag) = tag {
+        push.arg(format!("refs/tags/{}", tag));
+    }
+    if !push.status().await?.success() {
+        return Err("git push failed".into());
+    }
+    Ok(())
+}
+
+/// The most recent tag reachable from HEAD
+pub async fn last_tag() -> Option<String> {
+    let output = Command::new("git").args(["describe", "--tags", "--abbrev=0"]).output().await.ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string()).filter(|tag| !tag.is_empty())
+}
+
+/// The commits after `since` (all of them without a tag), newest first
+pub async fn commits_since(since: Option<&str>) -> Result<Vec<Commit>, Box<dyn std::error::Error>> {
+    let mut log = Command::new("git");
+    log.args(["log", "--no-merges", "--format=%h%x1f%s%x1f%b%x1e"]);
+    if let Some(tag) = since {
+        log.arg(format!("{}..HEAD", tag));
+    }
+    let output = log.output().await?;
+    if !output.status.success() {
+        // A repository without commits yet
+        return Ok(Vec::new());
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    Ok(text
+        .split('\u{1e}')
+        .filter_map(|record| {
+            let mut fields = record.trim_start_matches('\n').splitn(3, '\u{1f}');
+            let (hash, subject) = (fields.next()?, fields.next()?);
+            (!hash.is_empty()).then(|| Commit::parse(hash, subject, fields.next().unwrap_or("")))
+        })
+        .collect())
+}
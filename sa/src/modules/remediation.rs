@@ -0,0 +1,290 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use chrono::Utc;
+use colored::*;
+use crate::modules::cache::{install_package_with_cache, PackageCache};
+use crate::modules::installer::{normalize_dist_name, resolve_install_plan};
+use crate::modules::mirrors::MirrorManager;
+use crate::modules::models::{AuditFix, AuditFixPlan, InstallOptions, InstallPlanItem, InstalledPackage, SecurityVulnerability};
+use crate::modules::pep440::Version;
+use crate::modules::security::{load_security_policy, SecurityScanner};
+
+/// Rounds of "upgrade, rescan" before a package's remaining advisories are
+/// reported as unfixable
+const MAX_FIX_ROUNDS: usize = 10;
+
+type Findings = [(InstalledPackage, Vec<SecurityVulnerability>)];
+
+// Plans and applies `sa audit --fix`.
+//
+// Each vulnerable package moves to the lowest version that clears its
+// advisories. The upgrades are then resolved by pip together with every other
+// audited package pinned at its current version, so nothing else moves; an
+// upgrade the other pins cannot accommodate is reported as blocked.
+pub struct Remediator<'a> {
+    scanner: &'a SecurityScanner,
+    /// Advisory IDs (and aliases) the project has accepted
+    ignored: Vec<String>,
+}
+
+impl<'a> Remediator<'a> {
+    pub fn new(scanner: &'a SecurityScanner) -> Result<Self, Box<dyn std::error::Error>> {
+        let today = Utc::now().date_naive();
+        let ignored = load_security_policy()?
+            .ignore
+            .into_iter()
+            .filter(|ignore| ignore.until >= today)
+            .map(|ignore| ignore.id)
+            .collect();
+        Ok(Remediator { scanner, ignored })
+    }
+
+    /// Computes the upgrades for an audit's findings. `lockfile` selects
+    /// resolution against the lock rather than the environment.
+    pub async fn plan(&self, findings: &Findings, lockfile: Option<&Path>) -> Result<AuditFixPlan, Box<dyn std::error::Error>> {
+        let mut plan = AuditFixPlan::default();
+        let mut upgrades: BTreeMap<String, (String, Vec<String>)> = BTreeMap::new();
+
+        for (package, vulns) in findings.iter().filter(|(_, vulns)| !vulns.is_empty()) {
+            let (target, unfixable) = self.lowest_fix(package, vulns);
+            if !unfixable.is_empty() {
+                plan.unfixable.push((package.name.clone(), unfixable));
+            }
+            if let Some(target) = target {
+                let remaining: Vec<String> = self.affecting(&package.name, &target).into_iter().map(|v| v.id).collect();
+                let fixes = vulns.iter().map(|v| v.id.clone()).filter(|id| !remaining.contains(id)).collect();
+                upgrades.insert(normalize_dist_name(&package.name), (target, fixes));
+            }
+        }
+        if upgrades.is_empty() {
+            return Ok(plan);
+        }
+
+        let current: BTreeMap<String, &InstalledPackage> = findings
+            .iter()
+            .map(|(package, _)| (normalize_dist_name(&package.name), package))
+            .collect();
+
+        // Try everything at once; if pip refuses, find out which upgrades the
+        // other pins cannot accommodate and drop them
+        let items = match resolve(&current, &upgrades, lockfile.is_some()).await {
+            Ok(items) => items,
+            Err(_) => {
+                let mut accepted = BTreeMap::new();
+                for (name, upgrade) in &upgrades {
+                    let single = BTreeMap::from([(name.clone(), upgrade.clone())]);
+                    match resolve(&current, &single, lockfile.is_some()).await {
+                        Ok(_) => {
+                            accepted.insert(name.clone(), upgrade.clone());
+                        }
+                        Err(e) => plan.blocked.push((current[name].name.clone(), resolver_reason(&e.to_string()))),
+                    }
+                }
+                upgrades = accepted;
+                if upgrades.is_empty() {
+                    return Ok(plan);
+                }
+                resolve(&current, &upgrades, lockfile.is_some()).await?
+            }
+        };
+
+        for item in items {
+            let name = normalize_dist_name(&item.name);
+            let from = current.get(&name).map(|package| package.version.clone());
+            if from.as_deref() == Some(item.version.as_str()) {
+                continue;
+            }
+            plan.changes.push(AuditFix {
+                fixes: upgrades.get(&name).map(|(_, fixes)| fixes.clone()).unwrap_or_default(),
+                name: current.get(&name).map(|package| package.name.clone()).unwrap_or(item.name),
+                from,
+                to: item.version,
+                sha256: item.sha256,
+            });
+        }
+        plan.changes.sort_by(|a, b| a.from.is_none().cmp(&b.from.is_none()).then_with(|| a.name.cmp(&b.name)));
+        Ok(plan)
+    }
+
+    /// The lowest version clearing a package's advisories (following fixed
+    /// versions until none remain), plus the advisories no upgrade clears
+    fn lowest_fix(&self, package: &InstalledPackage, vulns: &[SecurityVulnerability]) -> (Option<String>, Vec<String>) {
+        let Ok(current) = Version::parse(&package.version) else {
+            return (None, vulns.iter().map(|v| v.id.clone()).collect());
+        };
+
+        let mut target = current.clone();
+        let mut pending = vulns.to_vec();
+        let mut unfixable: Vec<String> = Vec::new();
+        for _ in 0..MAX_FIX_ROUNDS {
+            let mut next = target.clone();
+            for vuln in &pending {
+                match vuln.fixed_version.as_deref().and_then(|v| Version::parse(v).ok()) {
+                    Some(fixed) if fixed > target => next = next.max(fixed),
+                    // No fix, or the fix is for an older release line
+                    _ if !unfixable.contains(&vuln.id) => unfixable.push(vuln.id.clone()),
+                    _ => {}
+                }
+            }
+            if next == target {
+                break;
+            }
+            target = next;
+            pending = self
+                .affecting(&package.name, &target.to_string())
+                .into_iter()
+                .filter(|vuln| !unfixable.contains(&vuln.id))
+                .collect();
+            if pending.is_empty() {
+                break;
+            }
+        }
+
+        let target = (target > current).then(|| target.to_string());
+        (target, unfixable)
+    }
+
+    /// Advisories affecting a version, minus those the project has accepted
+    fn affecting(&self, name: &str, version: &str) -> Vec<SecurityVulnerability> {
+        self.scanner
+            .scan_package(name, version)
+            .into_iter()
+            .filter(|vuln| !self.ignored.iter().any(|id| *id == vuln.id || vuln.aliases.contains(id)))
+            .collect()
+    }
+}
+
+/// Runs pip's resolver over every audited package, pinned at its current
+/// version except for the upgrades
+async fn resolve(
+    current: &BTreeMap<String, &InstalledPackage>,
+    upgrades: &BTreeMap<String, (String, Vec<String>)>,
+    ignore_installed: bool,
+) -> Result<Vec<InstallPlanItem>, Box<dyn std::error::Error>> {
+    let mut requirements = tempfile::Builder::new().suffix(".txt").tempfile()?;
+    for (name, package) in current {
+        let version = upgrades.get(name).map(|(target, _)| target.as_str()).unwrap_or(&package.version);
+        writeln!(requirements, "{}=={}", package.name, version)?;
+    }
+
+    let mut args = vec!["-r".to_string(), requirements.path().to_string_lossy().to_string()];
+    if ignore_installed {
+        args.insert(0, "--ignore-installed".to_string());
+    }
+    resolve_install_plan(&args)
+        .await?
+        .ok_or_else(|| "sa audit --fix needs a pip that supports install reports (pip >= 22.2)".into())
+}
+
+/// The line of pip's resolver output that says what went wrong
+fn resolver_reason(error: &str) -> String {
+    error
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .find(|line| line.contains("conflict") || line.contains("Cannot install"))
+        .or_else(|| error.lines().map(str::trim).rfind(|line| !line.is_empty()))
+        .unwrap_or(error)
+        .to_string()
+}
+
+/// Rewrites the pinned lines of a requirements/lock file for the planned
+/// changes, refreshing `--hash` options where the file uses them. New
+/// dependencies are appended when `add_missing` is set. Returns the number of
+/// entries written.
+pub fn apply_to_requirements(path: &Path, changes: &[AuditFix], add_missing: bool) -> Result<usize, Box<dyn std::error::Error>> {
+    let content = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let hashed = content.contains("--hash=");
+    let by_name: BTreeMap<String, &AuditFix> = changes.iter().map(|c| (normalize_dist_name(&c.name), c)).collect();
+
+    // Group physical lines into entries joined by trailing backslashes
+    let mut entries: Vec<Vec<&str>> = Vec::new();
+    let mut continued = false;
+    for line in content.lines() {
+        match entries.last_mut() {
+            Some(entry) if continued => entry.push(line),
+            _ => entries.push(vec![line]),
+        }
+        continued = line.trim_end().ends_with('\\');
+    }
+
+    let mut output = Vec::new();
+    let mut written = 0;
+    let mut seen = Vec::new();
+    for entry in entries {
+        let joined: String = entry.iter().map(|line| line.trim().trim_end_matches('\\').trim()).collect::<Vec<_>>().join(" ");
+        let requirement = joined.split('#').next().unwrap_or("").split(" --").next().unwrap_or("").trim();
+        let pinned = requirement.split(';').next().unwrap_or("").split_once("==");
+        let change = pinned.and_then(|(name, version)| {
+            let name = normalize_dist_name(name.split('[').next().unwrap_or(name).trim());
+            by_name.get(&name).filter(|c| c.from.as_deref() == Some(version.trim())).copied()
+        });
+
+        let Some(change) = change else {
+            output.extend(entry.iter().map(|line| line.to_string()));
+            continue;
+        };
+        let from = change.from.as_deref().unwrap_or_default();
+        let mut line = requirement.replacen(&format!("=={}", from), &format!("=={}", change.to), 1);
+        if joined.contains("--hash=") {
+            line.push_str(&hash_option(change)?);
+        }
+        output.push(line);
+        seen.push(normalize_dist_name(&change.name));
+        written += 1;
+    }
+
+    if add_missing {
+        for change in changes.iter().filter(|c| c.from.is_none() && !seen.contains(&normalize_dist_name(&c.name))) {
+            let mut line = format!("{}=={}", change.name, change.to);
+            if hashed {
+                line.push_str(&hash_option(change)?);
+            }
+            output.push(line);
+            written += 1;
+        }
+    }
+
+    if written > 0 {
+        fs::write(path, output.join("\n") + "\n")?;
+    }
+    Ok(written)
+}
+
+fn hash_option(change: &AuditFix) -> Result<String, Box<dyn std::error::Error>> {
+    let hash = change
+        .sha256
+        .as_ref()
+        .ok_or_else(|| format!("The index publishes no sha256 for {}=={}; it cannot go in a hash-pinned lockfile", change.name, change.to))?;
+    Ok(format!(" \\\n    --hash=sha256:{}", hash))
+}
+
+/// Installs the planned versions into the project environment and updates
+/// any matching pins in requirements.txt
+pub async fn apply_to_environment(changes: &[AuditFix], scanner: &mut SecurityScanner) -> Result<(), Box<dyn std::error::Error>> {
+    let mut cache = PackageCache::new()?;
+    let mut mirror_manager = MirrorManager::new()?;
+    if !scanner.offline {
+        if let Err(e) = mirror_manager.check_health().await {
+            println!("{}", format!("Warning: mirror health check failed: {}", e).yellow());
+        }
+    }
+    let options = InstallOptions { assume_yes: true, no_save: true, ..InstallOptions::default() };
+
+    for change in changes {
+        let pinned = format!("{}=={}", change.name, change.to);
+        println!("{}", format!("📦 Installing {}", pinned).cyan());
+        install_package_with_cache(&pinned, &mut cache, &mirror_manager, scanner, options).await?;
+    }
+
+    let requirements = Path::new("requirements.txt");
+    if requirements.exists() {
+        let updated = apply_to_requirements(requirements, changes, false)?;
+        if updated > 0 {
+            println!("  {} updated {} pins in requirements.txt", "•".blue(), updated);
+        }
+    }
+    Ok(())
+}
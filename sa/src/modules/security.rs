@@ -1,126 +1,799 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::fs;
+use std::io::{Cursor, Read};
+use std::collections::{BTreeMap, BTreeSet};
+use chrono::{DateTime, NaiveDate, Utc};
+use toml_edit::{value, ArrayOfTables, Item, Table};
 use dirs::cache_dir;
-use reqwest::Client;
-use serde_json::Value;
+use serde_json::{json, Value};
 use colored::*;
-use crate::modules::models::SecurityVulnerability;
+use crate::modules::config::{parse_duration, project_tool_section, tool_table, update_pyproject};
+use crate::modules::models::{InstalledPackage, SAConfig, SecurityPolicy, SecurityVulnerability, VulnerabilityDb};
+use crate::modules::cache::lock_selection;
+use crate::modules::http_cache::HttpCache;
+use crate::modules::installer::normalize_dist_name;
+use crate::modules::pep440::{SpecifierSet, Version};
+use crate::modules::venv::{installed_packages, venv_dir};
+use crate::modules::network;
+
+const SAFETY_DB_URL: &str = "https://raw.githubusercontent.com/pyupio/safety-db/master/data/insecure_full.json";
+/// OSV's JSON export of the PyPI ecosystem; the PYSEC records in it are the
+/// PyPA advisory database (which upstream is kept as YAML)
+const PYPA_EXPORT_URL: &str = "https://osv-vulnerabilities.storage.googleapis.com/PyPI/all.zip";
+const OSV_API_URL: &str = "https://api.osv.dev/v1";
+/// OSV accepts at most this many queries per batch request
+const OSV_BATCH_SIZE: usize = 1000;
+
+/// Feeds accepted by `sa security update --source`
+pub const VULNERABILITY_SOURCES: [&str; 3] = ["pypa", "osv", "safetydb"];
 
 // Security scanner implementation
 pub struct SecurityScanner {
     pub vulnerability_db: Vec<SecurityVulnerability>,
+    /// Last successful refresh of each feed
+    pub updated: BTreeMap<String, DateTime<Utc>>,
     pub db_path: PathBuf,
+    /// Never touch the network; scan with whatever the local database has
+    pub offline: bool,
 }
 
 impl SecurityScanner {
-    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn new(offline: bool) -> Result<Self, Box<dyn std::error::Error>> {
         let cache_dir = cache_dir()
             .unwrap_or_else(|| PathBuf::from("."))
             .join("sa-cache");
 
         let db_path = cache_dir.join("vulnerabilities.json");
 
-        let vulnerability_db = if db_path.exists() {
+        let db = if db_path.exists() {
             let content = fs::read_to_string(&db_path)?;
-            serde_json::from_str(&content).unwrap_or_default()
+            // Older versions stored a bare list of advisories
+            serde_json::from_str(&content)
+                .or_else(|_| serde_json::from_str(&content).map(|advisories| VulnerabilityDb { advisories, ..Default::default() }))
+                .unwrap_or_default()
         } else {
-            Vec::new()
+            VulnerabilityDb::default()
         };
 
-        Ok(SecurityScanner { vulnerability_db, db_path })
+        Ok(SecurityScanner { vulnerability_db: db.advisories, updated: db.updated, db_path, offline })
     }
 
-    pub async fn update_vulnerability_db(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    /// Refreshes one feed, or every feed when `source` is "all"
+    pub async fn update_vulnerability_db(&mut self, source: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if self.offline {
+            return Err("Cannot update the vulnerability database with --offline".into());
+        }
         println!("{}", "🔄 Updating vulnerability database...".yellow());
 
-        let client = Client::new();
-
-        // Fetch from PyUp.io safety database (mock implementation)
-        let response = client
-            .get("https://raw.githubusercontent.com/pyupio/safety-db/master/data/insecure_full.json")
-            .send()
-            .await?;
-
-        if response.status().is_success() {
-            let vulnerabilities_data: Value = response.json().await?;
-
-            // Parse and convert to our format (simplified)
-            let mut vulnerabilities = Vec::new();
-
-            if let Some(packages) = vulnerabilities_data.as_object() {
-                for (package_name, vulns) in packages {
-                    if let Some(vuln_array) = vulns.as_array() {
-                        for vuln in vuln_array {
-                            if let Some(vuln_obj) = vuln.as_object() {
-                                let vulnerability = SecurityVulnerability {
-                                    id: vuln_obj.get("id")
-                                        .and_then(|v| v.as_str())
-                                        .unwrap_or("unknown")
-                                        .to_string(),
-                                    package: package_name.clone(),
-                                    version_range: vuln_obj.get("specs")
-                                        .and_then(|v| v.as_array())
-                                        .and_then(|arr| arr.first())
-                                        .and_then(|v| v.as_str())
-                                        .unwrap_or("*")
-                                        .to_string(),
-                                    severity: "medium".to_string(),
-                                    description: vuln_obj.get("advisory")
-                                        .and_then(|v| v.as_str())
-                                        .unwrap_or("No description available")
-                                        .to_string(),
-                                    fixed_version: None,
-                                    published_at: chrono::Utc::now(),
-                                };
-                                vulnerabilities.push(vulnerability);
-                            }
-                        }
+        if source == "all" {
+            let mut failed = 0;
+            for source in VULNERABILITY_SOURCES {
+                match self.update_source(source).await {
+                    Ok(summary) => println!("  {} {}: {}", "•".blue(), source, summary),
+                    Err(e) => {
+                        println!("  {} {}: {}", "✗".red(), source, e);
+                        failed += 1;
                     }
                 }
             }
+            self.save()?;
+            if failed == VULNERABILITY_SOURCES.len() {
+                return Err("Could not update any vulnerability source".into());
+            }
+        } else {
+            let result = self.update_source(source).await;
+            self.save()?;
+            println!("  {} {}: {}", "•".blue(), source, result?);
+        }
 
-            self.vulnerability_db = vulnerabilities;
+        println!("{}", "✅ Vulnerability database updated successfully".green());
+        Ok(())
+    }
 
-            // Save to local database
-            let json_content = serde_json::to_string_pretty(&self.vulnerability_db)?;
-            fs::write(&self.db_path, json_content)?;
+    /// Refreshes one feed, returning a one-line summary of what changed
+    async fn update_source(&mut self, source: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let http = HttpCache::new()?;
 
-            println!("{}", "✅ Vulnerability database updated successfully".green());
+        let summary = match source {
+            "safetydb" => {
+                let response = http.get(SAFETY_DB_URL, Some("application/json")).await?;
+                if response.from_cache && self.updated.contains_key("safetydb") {
+                    "already up to date".to_string()
+                } else {
+                    let advisories = parse_safety_db(&serde_json::from_slice(&response.body)?);
+                    let summary = format!("{} advisories", advisories.len());
+                    self.merge_advisories(advisories);
+                    summary
+                }
+            }
+            "pypa" => {
+                let response = http.get(PYPA_EXPORT_URL, None).await?;
+                if response.from_cache && self.updated.contains_key("pypa") {
+                    "already up to date".to_string()
+                } else {
+                    let advisories = parse_pypa_export(&response.body)?;
+                    let summary = format!("{} advisories", advisories.len());
+                    self.merge_advisories(advisories);
+                    summary
+                }
+            }
+            "osv" => {
+                // OSV has no bulk API, so it is queried for what the project has installed
+                if !venv_dir().exists() {
+                    return Ok("skipped, no environment to query for".to_string());
+                }
+                let installed = installed_packages().await?;
+                let advisories = self.query_osv(&installed).await?;
+                let summary = format!("{} advisories for {} installed packages", advisories.len(), installed.len());
+                self.merge_advisories(advisories);
+                summary
+            }
+            other => return Err(format!("Unknown vulnerability source '{}'", other).into()),
+        };
+
+        self.updated.insert(source.to_string(), Utc::now());
+        Ok(summary)
+    }
+
+    /// Refreshes the bulk feeds last updated longer ago than the configured
+    /// TTL (`security.db_ttl`, default 24h). Failures only warn, since scans
+    /// can still use the local copy; with --offline staleness is just noted.
+    pub async fn refresh_if_stale(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let ttl = parse_duration(&SAConfig::load()?.security.db_ttl)?;
+        let now = Utc::now();
+        let stale: Vec<&str> = ["pypa", "safetydb"]
+            .into_iter()
+            .filter(|source| self.updated.get(*source).is_none_or(|updated| now - *updated > ttl))
+            .collect();
+        if stale.is_empty() {
+            return Ok(());
         }
 
+        if self.offline {
+            eprintln!("{}", format!("Warning: vulnerability data for {} is missing or older than {} (--offline, not refreshing)", stale.join(", "), format_ttl(ttl)).yellow());
+            return Ok(());
+        }
+
+        eprintln!("{}", format!("🔄 Vulnerability data is missing or older than {}; refreshing {}...", format_ttl(ttl), stale.join(", ")).yellow());
+        for source in stale {
+            match self.update_source(source).await {
+                Ok(summary) => eprintln!("  {} {}: {}", "•".blue(), source, summary),
+                Err(e) => eprintln!("{}", format!("Warning: could not refresh {} ({}); using the local database", source, e).yellow()),
+            }
+        }
+        self.save()
+    }
+
+    /// Looks up advisories for the given packages with the OSV batch API,
+    /// then fetches each referenced advisory (through the HTTP cache)
+    pub async fn query_osv(&self, packages: &[InstalledPackage]) -> Result<Vec<SecurityVulnerability>, Box<dyn std::error::Error>> {
+        let client = network::client()?;
+        let mut ids = BTreeSet::new();
+
+        for chunk in packages.chunks(OSV_BATCH_SIZE) {
+            let queries: Vec<Value> = chunk
+                .iter()
+                .map(|package| json!({
+                    "package": { "name": package.name, "ecosystem": "PyPI" },
+                    "version": package.version,
+                }))
+                .collect();
+
+            let response: Value = client
+                .post(format!("{}/querybatch", OSV_API_URL))
+                .json(&json!({ "queries": queries }))
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+
+            let results = response["results"].as_array().cloned().unwrap_or_default();
+            for result in results {
+                let vulns = result["vulns"].as_array().cloned().unwrap_or_default();
+                ids.extend(vulns.iter().filter_map(|v| v["id"].as_str()).map(str::to_string));
+            }
+        }
+
+        let http = HttpCache::new()?;
+        let mut advisories = Vec::new();
+        for id in ids {
+            let record = http.get_json(&format!("{}/vulns/{}", OSV_API_URL, id)).await?;
+            advisories.extend(parse_osv_record(&record, "osv"));
+        }
+
+        Ok(advisories)
+    }
+
+    /// Refreshes stale feeds and OSV data for the packages (falling back to
+    /// the local database when offline) and returns the advisories affecting each,
+    /// minus those the project has ignored
+    pub async fn scan_installed(&mut self, packages: &[InstalledPackage]) -> Result<Vec<(InstalledPackage, Vec<SecurityVulnerability>)>, Box<dyn std::error::Error>> {
+        self.refresh_if_stale().await?;
+        if !self.offline {
+            match self.query_osv(packages).await {
+                Ok(advisories) => {
+                    self.merge_advisories(advisories);
+                    self.updated.insert("osv".to_string(), Utc::now());
+                    self.save()?;
+                }
+                Err(e) => eprintln!("{}", format!("Warning: could not query OSV ({}); using the local database", e).yellow()),
+            }
+        }
+
+        let ignores = load_security_policy()?.ignore;
+        let today = Utc::now().date_naive();
+        Ok(packages
+            .iter()
+            .map(|package| {
+                let mut vulns = self.scan_package(&package.name, &package.version);
+                vulns.retain(|vuln| {
+                    let Some(ignore) = ignores.iter().find(|i| i.id == vuln.id || vuln.aliases.contains(&i.id)) else {
+                        return true;
+                    };
+                    if ignore.until < today {
+                        eprintln!("{}", format!("Warning: ignore for {} expired on {}; reporting it again", ignore.id, ignore.until).yellow());
+                        return true;
+                    }
+                    eprintln!("  {} {} ignored until {}: {}", "•".blue(), vuln.id, ignore.until, ignore.reason);
+                    false
+                });
+                (package.clone(), vulns)
+            })
+            .collect())
+    }
+
+    /// Checks packages about to be installed against the project's security
+    /// policy, failing when an unexempted advisory reaches the block threshold
+    pub async fn enforce_policy(&mut self, packages: &[InstalledPackage], skip_security: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let policy = load_security_policy()?;
+        if skip_security {
+            if !policy.allow_skip_security {
+                return Err("--skip-security is not permitted by the project's security policy".into());
+            }
+            println!("{}", "Warning: skipping security checks".yellow());
+            return Ok(());
+        }
+
+        let threshold = block_threshold(&policy.block_severity)?;
+        let today = Utc::now().date_naive();
+        let mut blocking = Vec::new();
+
+        for (package, vulns) in self.scan_installed(packages).await? {
+            for vuln in vulns {
+                let label = format!("{}=={} {} ({})", package.name, package.version, vuln.id, vuln.severity);
+                let exemption = policy.exemptions.iter().find(|exemption| {
+                    normalize_dist_name(&exemption.package) == normalize_dist_name(&package.name)
+                        && exemption.id.as_ref().is_none_or(|id| *id == vuln.id || vuln.aliases.contains(id))
+                });
+
+                match exemption {
+                    Some(exemption) if exemption.expires.is_none_or(|expires| expires >= today) => {
+                        println!("  {} {} is exempt by policy{}", "•".blue(), label,
+                            exemption.reason.as_ref().map(|r| format!(": {}", r)).unwrap_or_default());
+                        continue;
+                    }
+                    Some(exemption) => {
+                        println!("{}", format!("Warning: exemption for {} expired on {}", exemption.package,
+                            exemption.expires.map(|d| d.to_string()).unwrap_or_default()).yellow());
+                    }
+                    None => {}
+                }
+
+                if threshold.is_some_and(|threshold| severity_rank(&vuln.severity) >= threshold) {
+                    blocking.push(label);
+                } else {
+                    println!("{}", format!("Warning: {}: {}", label, vuln.description.lines().next().unwrap_or_default()).yellow());
+                }
+            }
+        }
+
+        if !blocking.is_empty() {
+            return Err(format!("Installation blocked by security policy: {}", blocking.join(", ")).into());
+        }
+        Ok(())
+    }
+
+    /// Adds advisories to the database. Records with the same ID are
+    /// refreshed; records sharing an alias (e.g. a CVE reported by several
+    /// feeds) are merged into one entry.
+    pub fn merge_advisories(&mut self, advisories: Vec<SecurityVulnerability>) {
+        for advisory in advisories {
+            let package = normalize_dist_name(&advisory.package);
+            let existing = self.vulnerability_db.iter_mut().find(|vuln| {
+                normalize_dist_name(&vuln.package) == package
+                    && (vuln.id == advisory.id
+                        || vuln.aliases.contains(&advisory.id)
+                        || advisory.aliases.contains(&vuln.id)
+                        || vuln.aliases.iter().any(|alias| advisory.aliases.contains(alias)))
+            });
+
+            let Some(existing) = existing else {
+                self.vulnerability_db.push(advisory);
+                continue;
+            };
+
+            let same_record = existing.id == advisory.id;
+            // OSV-format ranges are structured, so they win over scraped specs
+            if same_record || advisory.sources.iter().any(|s| s != "safetydb") {
+                existing.version_range = advisory.version_range;
+                if advisory.severity != "unknown" {
+                    existing.severity = advisory.severity;
+                }
+            }
+            if same_record {
+                existing.description = advisory.description;
+                existing.affected_versions = advisory.affected_versions;
+                existing.fixed_version = advisory.fixed_version.or(existing.fixed_version.take());
+            } else {
+                existing.fixed_version = existing.fixed_version.take().or(advisory.fixed_version);
+                for version in advisory.affected_versions {
+                    if !existing.affected_versions.contains(&version) {
+                        existing.affected_versions.push(version);
+                    }
+                }
+                if !existing.aliases.contains(&advisory.id) {
+                    existing.aliases.push(advisory.id);
+                }
+            }
+            for alias in advisory.aliases {
+                if alias != existing.id && !existing.aliases.contains(&alias) {
+                    existing.aliases.push(alias);
+                }
+            }
+            for source in advisory.sources {
+                if !existing.sources.contains(&source) {
+                    existing.sources.push(source);
+                }
+            }
+        }
+    }
+
+    fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(parent) = self.db_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let db = VulnerabilityDb {
+            updated: self.updated.clone(),
+            advisories: self.vulnerability_db.clone(),
+        };
+        let json_content = serde_json::to_string_pretty(&db)?;
+        fs::write(&self.db_path, json_content)?;
         Ok(())
     }
 
     pub fn scan_package(&self, package_name: &str, version: &str) -> Vec<SecurityVulnerability> {
+        let package_name = normalize_dist_name(package_name);
         self.vulnerability_db
             .iter()
             .filter(|vuln| {
-                vuln.package == package_name && self.version_matches(version, &vuln.version_range)
+                normalize_dist_name(&vuln.package) == package_name && self.is_affected(vuln, version)
             })
             .cloned()
             .collect()
     }
 
-    fn version_matches(&self, version: &str, range: &str) -> bool {
-        // Simplified version matching - in production use semver crate
-        if range == "*" {
+    fn is_affected(&self, vuln: &SecurityVulnerability, version: &str) -> bool {
+        let Ok(version) = Version::parse(version) else {
+            // Not PEP 440 (e.g. a VCS checkout): only exact listings can match
+            return vuln.affected_versions.iter().any(|affected| affected == version);
+        };
+
+        if vuln.affected_versions.iter().any(|affected| Version::parse(affected).is_ok_and(|affected| affected == version)) {
             return true;
         }
 
-        if range.starts_with(">=") {
-            let range_version = &range[2..];
-            version >= range_version
-        } else if range.starts_with("<=") {
-            let range_version = &range[2..];
-            version <= range_version
-        } else if range.starts_with('<') {
-            let range_version = &range[1..];
-            version < range_version
-        } else if range.starts_with('>') {
-            let range_version = &range[1..];
-            version > range_version
-        } else {
-            version == range
+        // Alternatives are OR-ed, the comma-separated clauses within one are AND-ed
+        vuln.version_range.split("||").any(|set| {
+            let set = set.trim();
+            match set {
+                "" => false,
+                "*" => true,
+                _ => SpecifierSet::parse(set).is_ok_and(|set| set.contains(&version)),
+            }
+        })
+    }
+}
+
+/// Severity ordering used for sorting and exit codes; unrated advisories
+/// are treated like medium ones
+pub fn severity_rank(severity: &str) -> u8 {
+    match severity.to_lowercase().as_str() {
+        "critical" => 4,
+        "high" => 3,
+        "low" => 1,
+        _ => 2,
+    }
+}
+
+/// Severity rank at which installs are blocked; None for "none"
+pub fn block_threshold(severity: &str) -> Result<Option<u8>, Box<dyn std::error::Error>> {
+    match severity.to_lowercase().as_str() {
+        "none" => Ok(None),
+        "critical" | "high" | "medium" | "low" => Ok(Some(severity_rank(severity))),
+        other => Err(format!("Unknown block-severity '{}'. Use critical, high, medium, low or none", other).into()),
+    }
+}
+
+/// `[tool.sa.security]` from pyproject.toml, or the defaults
+pub fn load_security_policy() -> Result<SecurityPolicy, Box<dyn std::error::Error>> {
+    match project_tool_section("security")? {
+        Some(section) => Ok(section
+            .try_into()
+            .map_err(|e| format!("Invalid [tool.sa.security]: {}", e))?),
+        None => Ok(SecurityPolicy::default()),
+    }
+}
+
+/// Records an accepted finding under `[[tool.sa.security.ignore]]` in
+/// pyproject.toml, replacing an earlier entry for the same ID
+pub fn add_vulnerability_ignore(id: &str, until: NaiveDate, reason: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut replaced = false;
+    update_pyproject(|doc| {
+        let security = tool_table(doc, "security")?;
+        let ignores = security
+            .entry("ignore")
+            .or_insert(Item::ArrayOfTables(ArrayOfTables::new()))
+            .as_array_of_tables_mut()
+            .ok_or("[tool.sa.security] ignore must be an array of tables")?;
+
+        let before = ignores.len();
+        ignores.retain(|entry| entry.get("id").and_then(|v| v.as_str()) != Some(id));
+        replaced = ignores.len() != before;
+
+        let mut entry = Table::new();
+        entry["id"] = value(id);
+        entry["until"] = value(until.to_string());
+        entry["reason"] = value(reason);
+        ignores.push(entry);
+        Ok(())
+    })?;
+    Ok(replaced)
+}
+
+/// Exit code for `sa audit` and `sa security scan`: 0 when nothing at or
+/// above `fail_on` was found, otherwise the worst counted finding decides
+/// (1 low/medium/unrated, 2 high, 3 critical). `fail_on = "none"` never fails.
+pub fn audit_exit_code(findings: &[(InstalledPackage, Vec<SecurityVulnerability>)], fail_on: &str) -> i32 {
+    let Ok(Some(threshold)) = block_threshold(fail_on) else {
+        return 0;
+    };
+    findings
+        .iter()
+        .flat_map(|(_, vulns)| vulns)
+        .map(|vuln| severity_rank(&vuln.severity))
+        .filter(|rank| *rank >= threshold)
+        .max()
+        .map(|rank| match rank {
+            4 => 3,
+            3 => 2,
+            _ => 1,
+        })
+        .unwrap_or(0)
+}
+
+/// Findings as a JSON document for CI pipelines
+pub fn json_report(target: &str, findings: &[(InstalledPackage, Vec<SecurityVulnerability>)], fail_on: &str) -> Value {
+    let vulnerabilities: Vec<Value> = findings
+        .iter()
+        .flat_map(|(package, vulns)| vulns.iter().map(move |vuln| (package, vuln)))
+        .map(|(package, vuln)| {
+            json!({
+                "package": package.name,
+                "version": package.version,
+                "id": vuln.id,
+                "aliases": vuln.aliases,
+                "severity": vuln.severity.to_lowercase(),
+                "description": vuln.description,
+                "affected": vuln.version_range,
+                "fixed_version": vuln.fixed_version,
+                "sources": vuln.sources,
+            })
+        })
+        .collect();
+
+    let mut summary = serde_json::Map::new();
+    for label in ["critical", "high", "medium", "low"] {
+        let count = vulnerabilities
+            .iter()
+            .filter(|v| severity_rank(v["severity"].as_str().unwrap_or_default()) == severity_rank(label))
+            .count();
+        summary.insert(label.to_string(), json!(count));
+    }
+
+    json!({
+        "target": target,
+        "packages_scanned": findings.len(),
+        "vulnerabilities": vulnerabilities,
+        "summary": summary,
+        "fail_on": fail_on,
+        "exit_code": audit_exit_code(findings, fail_on),
+    })
+}
+
+/// Findings as a SARIF 2.1.0 log for GitHub code scanning. Results point at
+/// the requirement line for each package in `artifact` when one is found.
+pub fn sarif_report(artifact: &Path, findings: &[(InstalledPackage, Vec<SecurityVulnerability>)]) -> Value {
+    let manifest = fs::read_to_string(artifact).unwrap_or_default();
+    let uri = artifact.to_string_lossy().replace('\\', "/");
+
+    let mut rules: BTreeMap<&str, Value> = BTreeMap::new();
+    let mut results = Vec::new();
+    for (package, vuln) in findings.iter().flat_map(|(package, vulns)| vulns.iter().map(move |vuln| (package, vuln))) {
+        let summary = vuln.description.lines().next().unwrap_or_default();
+        let (level, score) = match severity_rank(&vuln.severity) {
+            4 => ("error", "9.5"),
+            3 => ("error", "7.5"),
+            1 => ("note", "2.0"),
+            _ => ("warning", "5.0"),
+        };
+
+        rules.entry(&vuln.id).or_insert_with(|| {
+            json!({
+                "id": vuln.id,
+                "name": vuln.id,
+                "shortDescription": { "text": format!("{} in {}", vuln.id, vuln.package) },
+                "fullDescription": { "text": vuln.description },
+                "helpUri": format!("https://osv.dev/vulnerability/{}", vuln.id),
+                "help": { "text": summary },
+                "defaultConfiguration": { "level": level },
+                "properties": {
+                    "tags": ["security", "vulnerability"],
+                    "security-severity": score,
+                    "aliases": vuln.aliases,
+                },
+            })
+        });
+
+        let fix = vuln
+            .fixed_version
+            .as_ref()
+            .map(|v| format!(" Upgrade to {} or later.", v))
+            .unwrap_or_default();
+        let mut location = json!({ "artifactLocation": { "uri": uri } });
+        if let Some(line) = manifest_line(&manifest, &package.name) {
+            location["region"] = json!({ "startLine": line });
+        }
+        results.push(json!({
+            "ruleId": vuln.id,
+            "level": level,
+            "message": { "text": format!("{} {} is affected by {}: {}.{}", package.name, package.version, vuln.id, summary.trim_end_matches('.'), fix) },
+            "locations": [{ "physicalLocation": location }],
+            "partialFingerprints": { "package": format!("{}/{}", normalize_dist_name(&package.name), vuln.id) },
+        }));
+    }
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "sa",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": "https://github.com/isathish/SAPythonBackageManager",
+                    "rules": rules.into_values().collect::<Vec<_>>(),
+                }
+            },
+            "results": results,
+        }]
+    })
+}
+
+/// 1-based line of the requirement naming `package` in a requirements file
+/// or pyproject.toml
+fn manifest_line(manifest: &str, package: &str) -> Option<usize> {
+    let wanted = normalize_dist_name(package);
+    manifest.lines().position(|line| {
+        let line = line.trim().trim_start_matches(['"', '\'']);
+        let end = line
+            .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+            .unwrap_or(line.len());
+        end > 0 && normalize_dist_name(&line[..end]) == wanted
+    }).map(|index| index + 1)
+}
+
+/// Packages to audit: the pinned lines of a lockfile, or the environment
+pub async fn audit_targets(requirements: Option<&Path>) -> Result<Vec<InstalledPackage>, Box<dyn std::error::Error>> {
+    if let Some(requirements) = requirements {
+        let mut packages: Vec<InstalledPackage> = lock_selection(Some(requirements))?
+            .into_iter()
+            .map(|(name, version)| InstalledPackage { name, version })
+            .collect();
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+        return Ok(packages);
+    }
+
+    if !venv_dir().exists() {
+        return Err("No project environment found; create one or pass --requirements".into());
+    }
+    installed_packages().await
+}
+
+/// A TTL as it appears in messages, e.g. "24h" or "7d"
+fn format_ttl(ttl: chrono::Duration) -> String {
+    if ttl.num_minutes() % 60 != 0 {
+        format!("{}m", ttl.num_minutes())
+    } else if ttl.num_hours() >= 48 && ttl.num_hours() % 24 == 0 {
+        format!("{}d", ttl.num_days())
+    } else {
+        format!("{}h", ttl.num_hours())
+    }
+}
+
+/// Converts the safety-db dump (package -> advisories with version specs)
+fn parse_safety_db(data: &Value) -> Vec<SecurityVulnerability> {
+    let mut vulnerabilities = Vec::new();
+
+    let Some(packages) = data.as_object() else {
+        return vulnerabilities;
+    };
+
+    for (package_name, vulns) in packages {
+        let Some(vuln_array) = vulns.as_array() else { continue };
+        for vuln_obj in vuln_array.iter().filter_map(|v| v.as_object()) {
+            let specs: Vec<&str> = vuln_obj.get("specs")
+                .and_then(|v| v.as_array())
+                .map(|arr| arr.iter().filter_map(|v| v.as_str()).collect())
+                .unwrap_or_default();
+
+            vulnerabilities.push(SecurityVulnerability {
+                id: vuln_obj.get("id")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("unknown")
+                    .to_string(),
+                package: package_name.clone(),
+                version_range: if specs.is_empty() { "*".to_string() } else { specs.join(" || ") },
+                severity: "medium".to_string(),
+                description: vuln_obj.get("advisory")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("No description available")
+                    .to_string(),
+                fixed_version: None,
+                published_at: chrono::Utc::now(),
+                aliases: vuln_obj.get("cve")
+                    .and_then(|v| v.as_str())
+                    .filter(|cve| !cve.is_empty())
+                    .map(|cve| cve.split(',').map(|c| c.trim().to_string()).collect())
+                    .unwrap_or_default(),
+                affected_versions: Vec::new(),
+                sources: vec!["safetydb".to_string()],
+            });
         }
     }
+
+    vulnerabilities
+}
+
+/// Reads the PYSEC (PyPA) advisories out of the OSV PyPI export archive
+fn parse_pypa_export(archive: &[u8]) -> Result<Vec<SecurityVulnerability>, Box<dyn std::error::Error>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(archive))
+        .map_err(|e| format!("Invalid advisory archive: {}", e))?;
+
+    let mut advisories = Vec::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if !file.name().starts_with("PYSEC-") {
+            continue;
+        }
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        match serde_json::from_str::<Value>(&content) {
+            Ok(record) => advisories.extend(parse_osv_record(&record, "pypa")),
+            Err(e) => println!("{}", format!("Warning: skipping {}: {}", file.name(), e).yellow()),
+        }
+    }
+
+    Ok(advisories)
+}
+
+/// Converts one OSV-format advisory into a record per affected PyPI package
+fn parse_osv_record(record: &Value, source: &str) -> Vec<SecurityVulnerability> {
+    if !record["withdrawn"].is_null() {
+        return Vec::new();
+    }
+
+    let text = |value: &Value| value.as_str().map(str::trim).filter(|s| !s.is_empty()).map(str::to_string);
+    let strings = |value: &Value| -> Vec<String> {
+        value
+            .as_array()
+            .map(|arr| arr.iter().filter_map(|v| v.as_str()).map(str::to_string).collect())
+            .unwrap_or_default()
+    };
+
+    let id = text(&record["id"]).unwrap_or_else(|| "unknown".to_string());
+    let description = text(&record["summary"])
+        .or_else(|| text(&record["details"]))
+        .unwrap_or_else(|| "No description available".to_string());
+    let published_at = record["published"]
+        .as_str()
+        .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+        .map(|t| t.with_timezone(&chrono::Utc))
+        .unwrap_or_else(chrono::Utc::now);
+    let severity = osv_severity(record);
+
+    let affected = record["affected"].as_array().cloned().unwrap_or_default();
+    affected
+        .iter()
+        .filter(|entry| entry["package"]["ecosystem"].as_str() == Some("PyPI"))
+        .filter_map(|entry| {
+            let package = entry["package"]["name"].as_str()?.to_string();
+            let mut ranges = Vec::new();
+            let mut fixed_version = None;
+
+            let range_list = entry["ranges"].as_array().cloned().unwrap_or_default();
+            for range in range_list.iter().filter(|r| r["type"].as_str() == Some("ECOSYSTEM")) {
+                let mut lower: Option<String> = None;
+                for event in range["events"].as_array().cloned().unwrap_or_default() {
+                    if let Some(introduced) = event["introduced"].as_str() {
+                        lower = Some(introduced.to_string());
+                    } else if let Some(fixed) = event["fixed"].as_str() {
+                        ranges.push(specifier_set(lower.take(), Some(format!("<{}", fixed))));
+                        fixed_version.get_or_insert_with(|| fixed.to_string());
+                    } else if let Some(last) = event["last_affected"].as_str() {
+                        ranges.push(specifier_set(lower.take(), Some(format!("<={}", last))));
+                    }
+                }
+                if lower.is_some() {
+                    ranges.push(specifier_set(lower, None));
+                }
+            }
+
+            let affected_versions = strings(&entry["versions"]);
+            let version_range = if ranges.is_empty() && affected_versions.is_empty() {
+                "*".to_string()
+            } else {
+                ranges.join(" || ")
+            };
+
+            Some(SecurityVulnerability {
+                id: id.clone(),
+                package,
+                version_range,
+                severity: severity.clone(),
+                description: description.clone(),
+                fixed_version,
+                published_at,
+                aliases: strings(&record["aliases"]),
+                affected_versions,
+                sources: vec![source.to_string()],
+            })
+        })
+        .collect()
+}
+
+fn specifier_set(introduced: Option<String>, upper: Option<String>) -> String {
+    let mut clauses = Vec::new();
+    if let Some(introduced) = introduced.filter(|v| v != "0") {
+        clauses.push(format!(">={}", introduced));
+    }
+    clauses.extend(upper);
+    if clauses.is_empty() {
+        "*".to_string()
+    } else {
+        clauses.join(",")
+    }
+}
+
+/// Severity label from the GHSA-style `database_specific.severity` field
+fn osv_severity(record: &Value) -> String {
+    let label = record["database_specific"]["severity"]
+        .as_str()
+        .or_else(|| {
+            record["affected"]
+                .as_array()?
+                .iter()
+                .find_map(|entry| entry["database_specific"]["severity"].as_str())
+        })
+        .unwrap_or("")
+        .to_lowercase();
+
+    match label.as_str() {
+        "critical" => "critical",
+        "high" => "high",
+        "moderate" | "medium" => "medium",
+        "low" => "low",
+        _ => "unknown",
+    }
+    .to_string()
 }
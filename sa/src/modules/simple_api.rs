@@ -0,0 +1,122 @@
+use regex::Regex;
+use reqwest::Url;
+use serde_json::Value;
+use crate::modules::installer::artifact_filename;
+
+// Project pages of the simple repository API, in the PEP 503 HTML form or the
+// PEP 691 JSON form. Mirrors are asked for JSON unless they are known not to
+// serve it; whichever form comes back is parsed into the same file list.
+
+/// Accept header preferring the JSON form, falling back to HTML
+pub const ACCEPT_JSON: &str = "application/vnd.pypi.simple.v1+json, application/vnd.pypi.simple.v1+html;q=0.2, text/html;q=0.01";
+/// Accept header for mirrors known to serve HTML only
+pub const ACCEPT_HTML: &str = "application/vnd.pypi.simple.v1+html, text/html;q=0.01";
+
+/// One file listed on a project page
+pub struct IndexFile {
+    pub url: Url,
+    pub filename: String,
+    pub sha256: Option<String>,
+    pub requires_python: Option<String>,
+    /// The reason (possibly empty) when the file is yanked
+    pub yanked: Option<String>,
+}
+
+/// Whether a response carries the JSON form
+pub fn is_json(content_type: &str) -> bool {
+    content_type.to_lowercase().contains("application/vnd.pypi.simple.v1+json")
+}
+
+/// Files on a project page, parsed according to the response content type.
+/// Relative URLs are resolved against `base`, the page's own URL.
+pub fn parse_project_page(base: &Url, content_type: &str, body: &str) -> Result<Vec<IndexFile>, Box<dyn std::error::Error>> {
+    if is_json(content_type) {
+        parse_json(base, body)
+    } else {
+        parse_html(base, body)
+    }
+}
+
+/// Whether the start of a response is a PEP 503 page or a PEP 691 document
+pub fn looks_like_simple_index(content_type: &str, head: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(head).to_lowercase();
+    if content_type.contains("json") {
+        return text.contains("\"meta\"") && text.contains("api-version");
+    }
+    text.contains("<a ") || text.contains("pypi:repository-version")
+}
+
+pub fn parse_html(base: &Url, html: &str) -> Result<Vec<IndexFile>, Box<dyn std::error::Error>> {
+    let anchor = Regex::new(r"(?is)<a\s([^>]*)>")?;
+    let attribute = Regex::new(r#"([\w-]+)\s*=\s*"([^"]*)""#)?;
+
+    let mut files = Vec::new();
+    for captures in anchor.captures_iter(html) {
+        let mut href = None;
+        let mut requires_python = None;
+        let mut yanked = None;
+        for pair in attribute.captures_iter(&captures[1]) {
+            let value = unescape(&pair[2]);
+            match pair[1].to_lowercase().as_str() {
+                "href" => href = Some(value),
+                "data-requires-python" => requires_python = Some(value).filter(|v| !v.is_empty()),
+                "data-yanked" => yanked = Some(value),
+                _ => {}
+            }
+        }
+        let Some(href) = href else { continue };
+        let filename = artifact_filename(&href);
+        if filename.is_empty() {
+            continue;
+        }
+        files.push(IndexFile {
+            url: base.join(&href)?,
+            sha256: href.split_once("#sha256=").map(|(_, hash)| hash.to_lowercase()),
+            filename,
+            requires_python,
+            yanked,
+        });
+    }
+    Ok(files)
+}
+
+fn parse_json(base: &Url, body: &str) -> Result<Vec<IndexFile>, Box<dyn std::error::Error>> {
+    let page: Value = serde_json::from_str(body).map_err(|e| format!("Invalid JSON project page {}: {}", base, e))?;
+    let mut files = Vec::new();
+    for file in page["files"].as_array().into_iter().flatten() {
+        let (Some(filename), Some(url)) = (file["filename"].as_str(), file["url"].as_str()) else {
+            continue;
+        };
+        let yanked = match &file["yanked"] {
+            Value::Bool(true) => Some(String::new()),
+            Value::String(reason) => Some(reason.clone()),
+            _ => None,
+        };
+        files.push(IndexFile {
+            url: base.join(url)?,
+            filename: filename.to_string(),
+            sha256: file["hashes"]["sha256"].as_str().map(str::to_lowercase),
+            requires_python: file["requires-python"].as_str().filter(|v| !v.is_empty()).map(str::to_string),
+            yanked,
+        });
+    }
+    Ok(files)
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Escapes text for an HTML attribute value
+pub fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
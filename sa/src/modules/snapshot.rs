@@ -0,0 +1,196 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use colored::*;
+use reqwest::Url;
+use crate::modules::cache::sha256_file;
+use crate::modules::installer::{download_to, normalize_dist_name};
+use crate::modules::mirrors::{filename_version, MirrorManager};
+use crate::modules::pep440::compare_versions;
+use crate::modules::simple_api::{self, IndexFile};
+
+// Offline snapshots for `sa mirror snapshot`: every file of the pinned
+// releases, copied into a PEP 503 simple index that any static file server
+// (or pip itself, through a file:// index URL) can serve.
+//
+//   <output>/index.html              links to every project
+//   <output>/<project>/index.html    links to the project's files
+//   <output>/<project>/<file>
+//
+// Snapshots are incremental: files already present with the right digest are
+// kept, and project pages are merged with what earlier runs wrote.
+pub struct SnapshotBuilder<'a> {
+    mirrors: &'a MirrorManager,
+    output: PathBuf,
+}
+
+/// A release to copy, with the digests a hash-pinned lockfile allows
+pub struct SnapshotPin {
+    pub name: String,
+    pub version: String,
+    pub hashes: Vec<String>,
+}
+
+impl<'a> SnapshotBuilder<'a> {
+    pub fn new(mirrors: &'a MirrorManager, output: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        fs::create_dir_all(output).map_err(|e| format!("Failed to create {}: {}", output.display(), e))?;
+        Ok(SnapshotBuilder { mirrors, output: output.to_path_buf() })
+    }
+
+    /// Copies every file of a release from the first index (in precedence
+    /// order) that has it, returning how many files were downloaded
+    pub async fn add(&self, pin: &SnapshotPin) -> Result<usize, Box<dyn std::error::Error>> {
+        let project = normalize_dist_name(&pin.name);
+        let mut source = None;
+        for mirror in self.mirrors.by_precedence() {
+            let Some(files) = self.mirrors.fetch_project_files(mirror, &project).await? else {
+                continue;
+            };
+            let files: Vec<IndexFile> = files
+                .into_iter()
+                .filter(|file| {
+                    filename_version(&file.filename).is_some_and(|version| compare_versions(&version, &pin.version) == Ordering::Equal)
+                })
+                .collect();
+            if !files.is_empty() {
+                source = Some(files);
+                break;
+            }
+        }
+        let files = source.ok_or_else(|| format!("{}=={} is not on any configured mirror", pin.name, pin.version))?;
+
+        let project_dir = self.output.join(&project);
+        let mut page = read_project_page(&project_dir)?;
+        let mut downloaded = 0;
+        for file in files {
+            if file.sha256.as_ref().is_some_and(|hash| !pin.hashes.is_empty() && !pin.hashes.contains(hash)) {
+                continue;
+            }
+            // The name comes from the index; it must not leave the snapshot
+            if file.filename.is_empty() || file.filename.contains(['/', '\\']) || file.filename.contains("..") {
+                return Err(format!("Refusing file name '{}' from the index for {}", file.filename, pin.name).into());
+            }
+            let dest = project_dir.join(&file.filename);
+            let expected = file.sha256.clone().or_else(|| (pin.hashes.len() == 1).then(|| pin.hashes[0].clone()));
+            let present = dest.exists() && expected.as_ref().is_some_and(|hash| sha256_file(&dest).is_ok_and(|actual| actual == *hash));
+            if !present {
+                fs::create_dir_all(&project_dir)?;
+                download_to(file.url.as_str(), &dest).await?;
+                downloaded += 1;
+            }
+
+            let actual = sha256_file(&dest)?;
+            let mismatch = match &file.sha256 {
+                Some(hash) => *hash != actual,
+                None => !pin.hashes.is_empty() && !pin.hashes.contains(&actual),
+            };
+            if mismatch {
+                fs::remove_file(&dest)?;
+                return Err(format!("{} does not match its published digest", file.filename).into());
+            }
+            page.insert(file.filename.clone(), IndexFile { sha256: Some(actual), ..file });
+        }
+
+        if page.is_empty() {
+            return Err(format!("No file of {}=={} matches the lockfile hashes", pin.name, pin.version).into());
+        }
+        write_project_page(&project_dir, &project, &page)?;
+        Ok(downloaded)
+    }
+
+    /// Rewrites the root page from the project directories present, returning
+    /// the number of projects in the snapshot
+    pub fn write_root_index(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut projects: Vec<String> = fs::read_dir(&self.output)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().join("index.html").is_file())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect();
+        projects.sort();
+
+        let body: String = projects
+            .iter()
+            .map(|project| format!("    <a href=\"{}/\">{}</a><br/>\n", project, project))
+            .collect();
+        fs::write(self.output.join("index.html"), page_html("Simple index", &body))?;
+        Ok(projects.len())
+    }
+}
+
+/// The pins of a lockfile (name==version lines, with any `--hash` options),
+/// or None if some requirement is not pinned and needs resolving first
+pub fn read_lockfile(path: &Path) -> Result<Option<Vec<SnapshotPin>>, Box<dyn std::error::Error>> {
+    let content = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let joined = content.replace("\\\r\n", " ").replace("\\\n", " ");
+
+    let mut pins = Vec::new();
+    for line in joined.lines() {
+        let line = line.split(" #").next().unwrap_or("").trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('-') {
+            continue;
+        }
+        let requirement = line.split(" --").next().unwrap_or("").split(';').next().unwrap_or("").trim();
+        let Some((name, version)) = requirement.split_once("==") else {
+            return Ok(None);
+        };
+        let hashes = line
+            .split_whitespace()
+            .filter_map(|option| option.strip_prefix("--hash=sha256:"))
+            .map(str::to_lowercase)
+            .collect();
+        pins.push(SnapshotPin {
+            name: name.split('[').next().unwrap_or(name).trim().to_string(),
+            version: version.trim().to_string(),
+            hashes,
+        });
+    }
+    Ok(Some(pins))
+}
+
+/// Files on the page an earlier snapshot wrote, by file name
+fn read_project_page(project_dir: &Path) -> Result<BTreeMap<String, IndexFile>, Box<dyn std::error::Error>> {
+    let page = project_dir.join("index.html");
+    if !page.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let base = Url::from_directory_path(project_dir.canonicalize()?).map_err(|_| format!("Invalid snapshot path {}", project_dir.display()))?;
+    let files = simple_api::parse_html(&base, &fs::read_to_string(&page)?)?;
+    Ok(files
+        .into_iter()
+        .filter(|file| project_dir.join(&file.filename).is_file())
+        .map(|file| (file.filename.clone(), file))
+        .collect())
+}
+
+fn write_project_page(project_dir: &Path, project: &str, files: &BTreeMap<String, IndexFile>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut body = String::new();
+    for file in files.values() {
+        let hash = file.sha256.as_ref().map(|hash| format!("#sha256={}", hash)).unwrap_or_default();
+        let mut attributes = String::new();
+        if let Some(requires_python) = &file.requires_python {
+            attributes.push_str(&format!(" data-requires-python=\"{}\"", simple_api::escape(requires_python)));
+        }
+        if let Some(reason) = &file.yanked {
+            attributes.push_str(&format!(" data-yanked=\"{}\"", simple_api::escape(reason)));
+        }
+        body.push_str(&format!("    <a href=\"{}{}\"{}>{}</a><br/>\n", file.filename, hash, attributes, file.filename));
+    }
+    fs::write(project_dir.join("index.html"), page_html(&format!("Links for {}", project), &body))?;
+    Ok(())
+}
+
+fn page_html(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n  <head>\n    <meta name=\"pypi:repository-version\" content=\"1.0\">\n    <title>{}</title>\n  </head>\n  <body>\n    <h1>{}</h1>\n{}  </body>\n</html>\n",
+        title, title, body
+    )
+}
+
+/// Prints how to point SA or pip at a finished snapshot
+pub fn print_usage_hint(output: &Path) {
+    let path = output.canonicalize().unwrap_or_else(|_| output.to_path_buf());
+    println!("  Serve it with any static file server, e.g.:");
+    println!("    {}", format!("python -m http.server -d {} 8000", path.display()).bold());
+    println!("    {}", "sa mirror add --default snapshot http://<host>:8000/".bold());
+}
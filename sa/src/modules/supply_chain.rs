@@ -0,0 +1,215 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use chrono::{DateTime, Utc};
+use colored::*;
+use flate2::read::GzDecoder;
+use regex::Regex;
+use serde_json::Value;
+use crate::modules::attestations::AttestationVerifier;
+use crate::modules::http_cache::HttpCache;
+use crate::modules::installer::{download_artifact, normalize_dist_name};
+use crate::modules::models::{InstallPlanItem, SupplyChainPolicy};
+use crate::modules::pep440::compare_versions;
+use crate::modules::security::load_security_policy;
+
+const PYPI_JSON_URL: &str = "https://pypi.org/pypi";
+
+/// Things a setup.py has no business doing while being installed
+const SUSPICIOUS_SETUP_CALLS: &[(&str, &str)] = &[
+    (r"\bsubprocess\.|\bos\.(system|popen|exec)", "runs shell commands"),
+    (r"\burllib\.request|\burlopen\(|\brequests\.(get|post)\(|\bsocket\.|\bhttp\.client", "makes network requests"),
+    (r"\bb64decode\(|\bzlib\.decompress\(|\bmarshal\.loads\(|\bcodecs\.decode\(", "decodes embedded payloads"),
+    (r"\beval\(|__import__\(", "evaluates dynamic code"),
+    (r#"cmdclass\s*=\s*\{[^}]*['"](install|develop|egg_info)['"]"#, "overrides the install command"),
+];
+
+// Install-time red flags beyond known vulnerabilities, configured by
+// [tool.sa.security.supply-chain]: projects first published within
+// `min-age-days`, a release whose author/maintainer contacts share nothing
+// with the previous release, sdists whose setup.py does suspicious things, and
+// names matching `internal-prefixes` that resolve on public PyPI.
+pub struct SupplyChainChecker {
+    http: HttpCache,
+    policy: SupplyChainPolicy,
+}
+
+impl SupplyChainChecker {
+    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(SupplyChainChecker { http: HttpCache::new()?, policy: load_security_policy()?.supply_chain })
+    }
+
+    /// Reports red flags for the distributions about to be installed,
+    /// failing when the policy action is `block`
+    pub async fn check(&self, plan: &[InstallPlanItem]) -> Result<(), Box<dyn std::error::Error>> {
+        match self.policy.action.as_str() {
+            "off" => return Ok(()),
+            "warn" | "block" => {}
+            other => return Err(format!("Unknown supply-chain action '{}'. Use warn, block or off", other).into()),
+        }
+
+        let mut flagged = 0;
+        for item in plan {
+            let pinned = format!("{}=={}", item.name, item.version);
+            for reason in self.inspect(item).await {
+                println!("{}", format!("⚠️  {}: {}", pinned, reason).yellow());
+                flagged += 1;
+            }
+        }
+
+        if flagged > 0 && self.policy.action == "block" {
+            return Err(format!("{} supply-chain red flags; blocked by [tool.sa.security.supply-chain]", flagged).into());
+        }
+        Ok(())
+    }
+
+    async fn inspect(&self, item: &InstallPlanItem) -> Vec<String> {
+        let mut reasons = Vec::new();
+        let name = normalize_dist_name(&item.name);
+        let project = self.http.get_json(&format!("{}/{}/json", PYPI_JSON_URL, name)).await.ok();
+        let from_pypi = AttestationVerifier::supports(&item.url);
+
+        if let Some(prefix) = self
+            .policy
+            .internal_prefixes
+            .iter()
+            .find(|prefix| name.starts_with(&normalize_dist_name(prefix)))
+        {
+            if from_pypi {
+                reasons.push(format!("looks internal (matches '{}') but was resolved from public PyPI; possible dependency confusion", prefix));
+            } else if project.is_some() {
+                reasons.push(format!("looks internal (matches '{}') and the name is also taken on public PyPI; pin its index", prefix));
+            }
+        }
+
+        // Release history only describes this artifact if it came from PyPI
+        if let Some(project) = project.as_ref().filter(|_| from_pypi) {
+            if let Some(first) = first_published(project) {
+                let age = (Utc::now() - first).num_days();
+                if age < i64::from(self.policy.min_age_days) {
+                    reasons.push(format!("project was first published {} days ago", age));
+                }
+            }
+            if let Some(change) = self.maintainer_change(&name, &item.version, project).await {
+                reasons.push(change);
+            }
+        }
+
+        if !item.url.ends_with(".whl") {
+            match self.setup_script_findings(item).await {
+                Ok(findings) if !findings.is_empty() => reasons.push(format!("setup.py {} at install time", findings.join(", "))),
+                Ok(_) => {}
+                Err(e) => println!("{}", format!("Warning: could not inspect the sdist of {}: {}", item.name, e).yellow()),
+            }
+        }
+        reasons
+    }
+
+    /// Describes a release whose contacts share nothing with the previous one
+    async fn maintainer_change(&self, name: &str, version: &str, project: &Value) -> Option<String> {
+        let mut versions: Vec<&String> = project["releases"]
+            .as_object()?
+            .iter()
+            .filter(|(_, files)| files.as_array().is_some_and(|files| !files.is_empty()))
+            .map(|(version, _)| version)
+            .collect();
+        versions.sort_by(|a, b| compare_versions(a, b));
+        let index = versions.iter().position(|v| v.as_str() == version)?;
+        let previous = versions.get(index.checked_sub(1)?)?;
+
+        let current = self.contacts(name, version).await?;
+        let before = self.contacts(name, previous).await?;
+        if current.is_disjoint(&before) {
+            Some(format!(
+                "maintainers changed since {} ({} → {})",
+                previous,
+                before.into_iter().collect::<Vec<_>>().join(", "),
+                current.into_iter().collect::<Vec<_>>().join(", ")
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Author and maintainer e-mail addresses (or names) of one release
+    async fn contacts(&self, name: &str, version: &str) -> Option<BTreeSet<String>> {
+        let release = self.http.get_json(&format!("{}/{}/{}/json", PYPI_JSON_URL, name, version)).await.ok()?;
+        let info = &release["info"];
+        let collect = |fields: [&str; 2]| -> BTreeSet<String> {
+            fields
+                .iter()
+                .filter_map(|field| info[*field].as_str())
+                .flat_map(|value| value.split(','))
+                .map(|value| value.trim().to_lowercase())
+                .filter(|value| !value.is_empty())
+                .collect()
+        };
+        let emails = collect(["author_email", "maintainer_email"]);
+        let contacts = if emails.is_empty() { collect(["author", "maintainer"]) } else { emails };
+        (!contacts.is_empty()).then_some(contacts)
+    }
+
+    /// Downloads an sdist and lists what its setup.py does that install
+    /// scripts should not
+    async fn setup_script_findings(&self, item: &InstallPlanItem) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let archive = download_artifact(&item.url, dir.path()).await?;
+        let Some(script) = read_setup_script(&archive)? else {
+            return Ok(Vec::new());
+        };
+
+        let mut findings = Vec::new();
+        for (pattern, description) in SUSPICIOUS_SETUP_CALLS {
+            if Regex::new(pattern)?.is_match(&script) {
+                findings.push(description.to_string());
+            }
+        }
+        Ok(findings)
+    }
+}
+
+/// Earliest upload time across all of a project's releases
+fn first_published(project: &Value) -> Option<DateTime<Utc>> {
+    project["releases"]
+        .as_object()?
+        .values()
+        .flat_map(|files| files.as_array().into_iter().flatten())
+        .filter_map(|file| file["upload_time_iso_8601"].as_str())
+        .filter_map(|time| DateTime::parse_from_rfc3339(time).ok())
+        .map(|time| time.with_timezone(&Utc))
+        .min()
+}
+
+/// The top-level setup.py of a .tar.gz or .zip sdist, if it has one
+fn read_setup_script(archive: &Path) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let is_setup = |path: &str| {
+        let parts: Vec<&str> = path.trim_start_matches("./").split('/').collect();
+        parts.len() == 2 && parts[1] == "setup.py"
+    };
+    let file_name = archive.file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default();
+
+    if file_name.ends_with(".zip") {
+        let mut zip = zip::ZipArchive::new(fs::File::open(archive)?)?;
+        for index in 0..zip.len() {
+            let mut entry = zip.by_index(index)?;
+            if is_setup(entry.name()) {
+                let mut script = String::new();
+                entry.read_to_string(&mut script)?;
+                return Ok(Some(script));
+            }
+        }
+        return Ok(None);
+    }
+
+    let mut tar = tar::Archive::new(GzDecoder::new(fs::File::open(archive)?));
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        if is_setup(&entry.path()?.to_string_lossy()) {
+            let mut bytes = Vec::new();
+            entry.read_to_end(&mut bytes)?;
+            return Ok(Some(String::from_utf8_lossy(&bytes).into_owned()));
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,142 @@
+// The local terminal while it is attached to a container: raw mode, so keys
+// reach the container's TTY untranslated, the window size, which the
+// container's TTY follows, and the signals to forward to the container.
+
+/// Puts stdin's terminal in raw mode until dropped; does nothing when stdin
+/// is not a terminal
+pub struct RawMode {
+    #[cfg(unix)]
+    saved: Option<libc::termios>,
+}
+
+impl RawMode {
+    pub fn enable() -> Result<Self, Box<dyn std::error::Error>> {
+        #[cfg(unix)]
+        {
+            use std::io::IsTerminal;
+            if !std::io::stdin().is_terminal() {
+                return Ok(RawMode { saved: None });
+            }
+            // SAFETY: termios is plain data, filled in by tcgetattr before use
+            unsafe {
+                let mut saved: libc::termios = std::mem::zeroed();
+                if libc::tcgetattr(libc::STDIN_FILENO, &mut saved) != 0 {
+                    return Err(format!("Failed to read terminal settings: {}", std::io::Error::last_os_error()).into());
+                }
+                let mut raw = saved;
+                libc::cfmakeraw(&mut raw);
+                if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                    return Err(format!("Failed to switch the terminal to raw mode: {}", std::io::Error::last_os_error()).into());
+                }
+                Ok(RawMode { saved: Some(saved) })
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            Ok(RawMode {})
+        }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(saved) = &self.saved {
+            // SAFETY: restores settings tcgetattr returned
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved);
+            }
+        }
+    }
+}
+
+/// Columns and rows of the terminal on stdout, if it is one
+pub fn size() -> Option<(u16, u16)> {
+    #[cfg(unix)]
+    {
+        use std::io::IsTerminal;
+        if !std::io::stdout().is_terminal() {
+            return None;
+        }
+        // SAFETY: winsize is plain data, filled in by the ioctl
+        unsafe {
+            let mut size: libc::winsize = std::mem::zeroed();
+            if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) != 0 || size.ws_col == 0 {
+                return None;
+            }
+            Some((size.ws_col, size.ws_row))
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+/// Resolves whenever the terminal window is resized (never, off Unix)
+pub struct ResizeEvents {
+    #[cfg(unix)]
+    signal: tokio::signal::unix::Signal,
+}
+
+impl ResizeEvents {
+    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            Ok(ResizeEvents { signal: signal(SignalKind::window_change())? })
+        }
+        #[cfg(not(unix))]
+        {
+            Ok(ResizeEvents {})
+        }
+    }
+
+    pub async fn next(&mut self) {
+        #[cfg(unix)]
+        self.signal.recv().await;
+        #[cfg(not(unix))]
+        std::future::pending::<()>().await;
+    }
+}
+
+/// SIGINT and SIGTERM received while a container runs, to forward to it
+/// rather than letting them end SA with the container still running
+pub struct Interrupts {
+    #[cfg(unix)]
+    interrupt: tokio::signal::unix::Signal,
+    #[cfg(unix)]
+    terminate: tokio::signal::unix::Signal,
+}
+
+impl Interrupts {
+    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            Ok(Interrupts { interrupt: signal(SignalKind::interrupt())?, terminate: signal(SignalKind::terminate())? })
+        }
+        #[cfg(not(unix))]
+        {
+            Ok(Interrupts {})
+        }
+    }
+
+    /// The name of the next signal received, e.g. "SIGINT"
+    pub async fn next(&mut self) -> &'static str {
+        #[cfg(unix)]
+        {
+            tokio::select! {
+                _ = self.interrupt.recv() => "SIGINT",
+                _ = self.terminate.recv() => "SIGTERM",
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            if tokio::signal::ctrl_c().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+            "SIGINT"
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use colored::*;
+use reqwest::Url;
+use serde_json::{json, Value};
+use crate::modules::network;
+
+/// Variable a GitLab job receives its ID token in, declared under
+/// `id_tokens:` in .gitlab-ci.yml
+const GITLAB_TOKEN_VARIABLE: &str = "PYPI_ID_TOKEN";
+
+// PyPI trusted publishing. A CI job proves which workflow it is with an
+// OIDC token from its provider; PyPI exchanges that for an API token valid
+// for a few minutes and scoped to the projects that trust the workflow, so
+// no long-lived secret is stored anywhere. GitHub Actions hands out tokens
+// on request to jobs with `id-token: write`; GitLab puts them in a variable.
+
+#[derive(Clone, Copy)]
+enum Provider {
+    GitHub,
+    GitLab,
+}
+
+impl Provider {
+    fn detect() -> Option<Self> {
+        if std::env::var_os("ACTIONS_ID_TOKEN_REQUEST_URL").is_some() {
+            Some(Provider::GitHub)
+        } else if std::env::var_os("GITLAB_CI").is_some() && std::env::var_os(GITLAB_TOKEN_VARIABLE).is_some() {
+            Some(Provider::GitLab)
+        } else {
+            None
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Provider::GitHub => "GitHub Actions",
+            Provider::GitLab => "GitLab CI",
+        }
+    }
+
+    /// An ID token for `audience`
+    async fn identity(self, client: &reqwest::Client, audience: &str) -> Result<String, Box<dyn std::error::Error>> {
+        match self {
+            Provider::GitHub => {
+                let mut url = Url::parse(&std::env::var("ACTIONS_ID_TOKEN_REQUEST_URL")?)?;
+                url.query_pairs_mut().append_pair("audience", audience);
+                let request_token = std::env::var("ACTIONS_ID_TOKEN_REQUEST_TOKEN")
+                    .map_err(|_| "ACTIONS_ID_TOKEN_REQUEST_TOKEN is not set; the job needs the 'id-token: write' permission")?;
+                let response: Value = client.get(url).bearer_auth(request_token).send().await?.error_for_status()?.json().await?;
+                response["value"]
+                    .as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| "GitHub Actions returned no ID token".into())
+            }
+            Provider::GitLab => Ok(std::env::var(GITLAB_TOKEN_VARIABLE)?),
+        }
+    }
+}
+
+/// The PyPI instance an upload URL belongs to, for the indexes that
+/// support trusted publishing
+pub fn trusted_index(upload_url: &str) -> Option<&'static str> {
+    match Url::parse(upload_url).ok()?.host_str()? {
+        "upload.pypi.org" | "pypi.org" => Some("https://pypi.org"),
+        "test.pypi.org" => Some("https://test.pypi.org"),
+        _ => None,
+    }
+}
+
+/// What a CI job is missing to publish to `index` without a token, when
+/// it runs somewhere trusted publishing would work
+pub fn setup_hint(index: &str) -> Option<String> {
+    if std::env::var_os("GITHUB_ACTIONS").is_some() {
+        Some("or grant the job 'id-token: write' for trusted publishing".to_string())
+    } else if std::env::var_os("GITLAB_CI").is_some() {
+        let audience = if index.contains("test.pypi.org") { "testpypi" } else { "pypi" };
+        Some(format!("or declare an ID token {} with aud: {} for trusted publishing", GITLAB_TOKEN_VARIABLE, audience))
+    } else {
+        None
+    }
+}
+
+/// A short-lived upload token for `index` (e.g. https://pypi.org) in
+/// exchange for the CI job's identity; `None` outside a CI job that can
+/// prove one
+pub async fn mint_token(index: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let Some(provider) = Provider::detect() else {
+        return Ok(None);
+    };
+    println!("  {} trusted publishing from {}", "•".blue(), provider.name());
+    let client = network::client()?;
+
+    let audience: Value = client
+        .get(format!("{}/_/oidc/audience", index))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("Could not get the trusted publishing audience from {}: {}", index, e))?
+        .json()
+        .await?;
+    let audience = audience["audience"].as_str().ok_or_else(|| format!("{} returned no OIDC audience", index))?;
+    let identity = provider.identity(&client, audience).await?;
+
+    let response = client
+        .post(format!("{}/_/oidc/mint-token", index))
+        .json(&json!({ "token": identity }))
+        .send()
+        .await?;
+    let status = response.status();
+    let body: Value = response.json().await.unwrap_or(Value::Null);
+    if !status.is_success() {
+        let reasons: Vec<String> = body["errors"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|error| format!("{} ({})", error["description"].as_str().unwrap_or("unknown error"), error["code"].as_str().unwrap_or("?")))
+            .collect();
+        let reasons = if reasons.is_empty() { status.to_string() } else { reasons.join("; ") };
+        return Err(format!("{} refused the {} identity: {}. Check the project's trusted publisher settings", index, provider.name(), reasons).into());
+    }
+    let token = body["token"].as_str().ok_or_else(|| format!("{} returned no upload token", index))?;
+    Ok(Some(token.to_string()))
+}
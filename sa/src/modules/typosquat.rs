@@ -0,0 +1,177 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+use colored::*;
+use crate::modules::http_cache::HttpCache;
+use crate::modules::installer::normalize_dist_name;
+
+const TOP_PACKAGES_URL: &str = "https://hugovk.github.io/top-pypi-packages/top-pypi-packages-30-days.min.json";
+/// How many of the most downloaded projects are treated as squatting targets
+const TOP_PACKAGES_LIMIT: usize = 5000;
+
+/// Fallback when the download statistics cannot be fetched
+const POPULAR_PACKAGES: &[&str] = &[
+    "aiobotocore", "aiohttp", "aiosignal", "alembic", "annotated-types", "anyio", "argcomplete",
+    "arrow", "asn1crypto", "async-timeout", "attrs", "azure-core", "azure-identity",
+    "azure-storage-blob", "babel", "backoff", "bcrypt", "beautifulsoup4", "black", "bleach",
+    "boto3", "botocore", "build", "cachetools", "celery", "certifi", "cffi", "chardet",
+    "charset-normalizer", "click", "cloudpickle", "colorama", "coverage", "cryptography", "cycler",
+    "cython", "dask", "decorator", "defusedxml", "deprecated", "dill", "distlib", "django",
+    "djangorestframework", "dnspython", "docker", "docutils", "et-xmlfile", "exceptiongroup",
+    "fastapi", "filelock", "flake8", "flask", "fonttools", "frozenlist", "fsspec", "gitdb",
+    "gitpython", "google-api-core", "google-auth", "google-cloud-storage", "googleapis-common-protos",
+    "greenlet", "grpcio", "grpcio-status", "gunicorn", "h11", "httpcore", "httplib2", "httpx",
+    "huggingface-hub", "idna", "importlib-metadata", "importlib-resources", "iniconfig", "isodate",
+    "isort", "itsdangerous", "jinja2", "jmespath", "joblib", "jsonpointer", "jsonschema",
+    "jupyter", "keras", "kiwisolver", "lxml", "markdown", "markupsafe", "marshmallow",
+    "matplotlib", "mccabe", "more-itertools", "msgpack", "multidict", "mypy", "mypy-extensions",
+    "networkx", "nltk", "numpy", "oauthlib", "openai", "openpyxl", "opentelemetry-api",
+    "packaging", "pandas", "paramiko", "pathspec", "pexpect", "pillow", "pip", "platformdirs",
+    "pluggy", "poetry", "prometheus-client", "prompt-toolkit", "protobuf", "psutil",
+    "psycopg2", "psycopg2-binary", "ptyprocess", "pyarrow", "pyasn1", "pyasn1-modules",
+    "pycodestyle", "pycparser", "pydantic", "pydantic-core", "pyflakes", "pygments", "pyjwt",
+    "pylint", "pymongo", "pymysql", "pynacl", "pyopenssl", "pyparsing", "pyproject-hooks",
+    "pyrsistent", "pyspark", "pytest", "pytest-cov", "pytest-mock", "python-dateutil",
+    "python-dotenv", "pytz", "pyyaml", "pyzmq", "redis", "regex", "requests",
+    "requests-oauthlib", "requests-toolbelt", "rich", "rsa", "ruamel-yaml", "ruff", "s3fs",
+    "s3transfer", "scikit-learn", "scipy", "seaborn", "selenium", "setuptools", "six",
+    "smmap", "sniffio", "soupsieve", "sqlalchemy", "sqlparse", "starlette", "sympy",
+    "tabulate", "tenacity", "tensorflow", "threadpoolctl", "tokenizers", "toml", "tomli",
+    "tomlkit", "torch", "tornado", "tqdm", "traitlets", "transformers", "typer",
+    "typing-extensions", "tzdata", "urllib3", "uvicorn", "virtualenv", "websocket-client",
+    "websockets", "werkzeug", "wheel", "wrapt", "xlrd", "yarl", "zipp",
+];
+
+/// Most downloaded PyPI projects (normalized), from cached download
+/// statistics or the built-in list
+pub async fn popular_packages() -> Vec<String> {
+    let fetched = match HttpCache::new() {
+        Ok(http) => http.get_json(TOP_PACKAGES_URL).await.ok(),
+        Err(_) => None,
+    };
+
+    let rows = fetched
+        .as_ref()
+        .and_then(|data| data["rows"].as_array())
+        .map(|rows| {
+            rows.iter()
+                .take(TOP_PACKAGES_LIMIT)
+                .filter_map(|row| row["project"].as_str())
+                .map(normalize_dist_name)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    if rows.is_empty() {
+        POPULAR_PACKAGES.iter().map(|name| normalize_dist_name(name)).collect()
+    } else {
+        rows
+    }
+}
+
+/// The popular project `name` most likely imitates, if any: a one or two
+/// character typo (transpositions count once), or a name that only differs
+/// by separators or look-alike characters
+pub fn find_lookalike(name: &str, popular: &[String]) -> Option<String> {
+    let name = normalize_dist_name(name);
+    if popular.contains(&name) {
+        return None;
+    }
+
+    let skeleton = confusable_skeleton(&name);
+    let max_distance = if name.len() <= 5 { 1 } else { 2 };
+
+    popular
+        .iter()
+        .filter(|candidate| candidate.len() >= 4)
+        .map(|candidate| {
+            let distance = if confusable_skeleton(candidate) == skeleton {
+                0
+            } else {
+                edit_distance(&name, candidate)
+            };
+            (distance, candidate)
+        })
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.clone())
+}
+
+/// Damerau-Levenshtein distance (optimal string alignment variant)
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// Collapses separators and characters that read alike (rn/m, 1/l, 0/o)
+fn confusable_skeleton(name: &str) -> String {
+    name.replace(['-', '_', '.'], "")
+        .replace("rn", "m")
+        .replace("vv", "w")
+        .replace(['1', 'i'], "l")
+        .replace('0', "o")
+}
+
+/// Asks the user whether to continue; non-interactive sessions decline
+pub fn confirm(prompt: &str) -> bool {
+    if !io::stdin().is_terminal() {
+        return false;
+    }
+    print!("{} [y/N] ", prompt);
+    if io::stdout().flush().is_err() {
+        return false;
+    }
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Warns about (or blocks) an install of a look-alike of a popular package.
+/// `mode` is the project's `typosquatting` policy: warn, block or off.
+pub async fn check_typosquatting(name: &str, mode: &str, assume_yes: bool) -> Result<(), Box<dyn std::error::Error>> {
+    match mode {
+        "off" => return Ok(()),
+        "warn" | "block" => {}
+        other => return Err(format!("Unknown typosquatting policy '{}'. Use warn, block or off", other).into()),
+    }
+
+    let popular = popular_packages().await;
+    let Some(target) = find_lookalike(name, &popular) else {
+        return Ok(());
+    };
+
+    let message = format!("'{}' looks like the popular package '{}'", name, target);
+    match mode {
+        "block" => Err(format!("{}; blocked by the project's security policy", message).into()),
+        _ if assume_yes => {
+            println!("{}", format!("Warning: {}", message).yellow());
+            Ok(())
+        }
+        _ => {
+            println!("{}", format!("⚠️  {}. It may be a typosquatting attempt.", message).yellow());
+            if confirm(&format!("Install '{}' anyway?", name)) {
+                Ok(())
+            } else {
+                Err(format!("Not installing '{}' (did you mean '{}'? pass --yes to install anyway)", name, target).into())
+            }
+        }
+    }
+}
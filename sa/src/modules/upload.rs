@@ -0,0 +1,349 @@
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use blake2::digest::consts::U32;
+use blake2::Blake2b;
+use colored::*;
+use flate2::read::GzDecoder;
+use reqwest::multipart::{Form, Part};
+use reqwest::{Client, StatusCode};
+use sha2::{Digest, Sha256};
+use crate::modules::installer::normalize_dist_name;
+use crate::modules::network;
+use crate::modules::simple_api::{self, IndexFile};
+use crate::modules::visualize::format_size;
+
+type Blake2b256 = Blake2b<U32>;
+
+/// Time between looks at the index while waiting for an upload to show
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+// Uploads through the legacy upload API that PyPI, TestPyPI, devpi,
+// Artifactory and Nexus all accept: one multipart POST per file carrying
+// the file, its digests and its core metadata as form fields. Failed
+// connections, timeouts, 429 and 5xx responses are retried. Indexes refuse
+// a file name they already have, even with the same content, so a file the
+// index reports as already present is skipped, or after a retry may be the
+// earlier attempt having got through. Either way only the SHA-256 the
+// index lists tells whether it is this file; publishing a release again
+// then resumes it rather than failing.
+
+/// A wheel or sdist ready to upload
+pub struct Distribution {
+    pub path: PathBuf,
+    pub filename: String,
+    /// "bdist_wheel" or "sdist"
+    pub filetype: &'static str,
+    /// The wheel's Python tag, "source" for an sdist
+    pub pyversion: String,
+    pub name: String,
+    pub version: String,
+    /// Core metadata headers (lowercase) and values, in file order
+    pub fields: Vec<(String, String)>,
+    /// The long description, the body of the metadata file
+    pub description: Option<String>,
+}
+
+impl Distribution {
+    pub fn read(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let filename = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        let (filetype, pyversion, content) = if filename.ends_with(".whl") {
+            // name-version(-build)?-python-abi-platform.whl
+            let parts: Vec<&str> = filename.trim_end_matches(".whl").split('-').collect();
+            if parts.len() < 5 {
+                return Err(format!("{} is not a valid wheel file name", filename).into());
+            }
+            ("bdist_wheel", parts[parts.len() - 3].to_string(), wheel_metadata(path)?)
+        } else if filename.ends_with(".tar.gz") || filename.ends_with(".zip") {
+            ("sdist", "source".to_string(), sdist_metadata(path)?)
+        } else {
+            return Err(format!("{} is not a wheel or an sdist", filename).into());
+        };
+        let content = content.ok_or_else(|| format!("{} has no core metadata (METADATA or PKG-INFO)", filename))?;
+
+        let (fields, description) = parse_fields(&content);
+        let get = |key: &str| fields.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone());
+        let name = get("name").ok_or_else(|| format!("The metadata of {} has no Name", filename))?;
+        let version = get("version").ok_or_else(|| format!("The metadata of {} has no Version", filename))?;
+        Ok(Distribution { path: path.to_path_buf(), filename, filetype, pyversion, name, version, fields, description })
+    }
+}
+
+/// The wheels and sdists in `dir`, wheels first so an index never offers a
+/// release as sdist only while its wheels are still uploading
+pub fn find_distributions(dir: &Path) -> Result<Vec<Distribution>, Box<dyn std::error::Error>> {
+    if !dir.is_dir() {
+        return Err(format!("No {} directory; run 'sa build' first", dir.display()).into());
+    }
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            let name = path.to_string_lossy();
+            name.ends_with(".whl") || name.ends_with(".tar.gz") || name.ends_with(".zip")
+        })
+        .collect();
+    paths.sort_by_key(|path| (!path.to_string_lossy().ends_with(".whl"), path.clone()));
+    if paths.is_empty() {
+        return Err(format!("No wheels or sdists in {}; run 'sa build' first", dir.display()).into());
+    }
+    paths.iter().map(|path| Distribution::read(path)).collect()
+}
+
+/// `*.dist-info/METADATA` at the top of a wheel
+fn wheel_metadata(path: &Path) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let mut archive = zip::ZipArchive::new(fs::File::open(path)?).map_err(|e| format!("Invalid wheel {}: {}", path.display(), e))?;
+    let Some(name) = archive
+        .file_names()
+        .find(|name| name.split('/').count() == 2 && name.split('/').next().is_some_and(|dir| dir.ends_with(".dist-info")) && name.ends_with("/METADATA"))
+        .map(str::to_string)
+    else {
+        return Ok(None);
+    };
+    let mut content = String::new();
+    archive.by_name(&name)?.read_to_string(&mut content)?;
+    Ok(Some(content))
+}
+
+/// `<name>-<version>/PKG-INFO` in a .tar.gz or .zip sdist
+fn sdist_metadata(path: &Path) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let is_pkg_info = |name: &str| {
+        let parts: Vec<&str> = name.trim_start_matches("./").split('/').collect();
+        parts.len() == 2 && parts[1] == "PKG-INFO"
+    };
+    let mut content = String::new();
+    if path.to_string_lossy().ends_with(".zip") {
+        let mut archive = zip::ZipArchive::new(fs::File::open(path)?).map_err(|e| format!("Invalid sdist {}: {}", path.display(), e))?;
+        let Some(name) = archive.file_names().find(|name| is_pkg_info(name)).map(str::to_string) else {
+            return Ok(None);
+        };
+        archive.by_name(&name)?.read_to_string(&mut content)?;
+        return Ok(Some(content));
+    }
+
+    let mut archive = tar::Archive::new(GzDecoder::new(fs::File::open(path)?));
+    for entry in archive.entries().map_err(|e| format!("Invalid sdist {}: {}", path.display(), e))? {
+        let mut entry = entry?;
+        if is_pkg_info(&entry.path()?.to_string_lossy()) {
+            entry.read_to_string(&mut content)?;
+            return Ok(Some(content));
+        }
+    }
+    Ok(None)
+}
+
+/// The headers of a metadata file and its body. Continuation lines are
+/// joined to their header, as the old multi-line Description was written.
+fn parse_fields(content: &str) -> (Vec<(String, String)>, Option<String>) {
+    let mut fields: Vec<(String, String)> = Vec::new();
+    let mut lines = content.split_inclusive('\n');
+    for line in lines.by_ref() {
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = fields.last_mut() {
+                value.push('\n');
+                value.push_str(line.trim_start().trim_start_matches('|'));
+            }
+        } else if let Some((key, value)) = line.split_once(':') {
+            fields.push((key.trim().to_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    let body: String = lines.collect();
+    let header = fields.iter().position(|(key, _)| key == "description").map(|index| fields.remove(index).1);
+    let description = Some(body).filter(|body| !body.trim().is_empty()).or(header);
+    (fields, description)
+}
+
+/// The upload form field a metadata header goes in
+fn form_field(header: &str) -> String {
+    match header {
+        "classifier" => "classifiers".to_string(),
+        "project-url" => "project_urls".to_string(),
+        _ => header.replace('-', "_"),
+    }
+}
+
+/// Whether a rejected upload means the file is already on the index; each
+/// index says so differently
+fn already_exists(status: StatusCode, text: &str) -> bool {
+    match status {
+        StatusCode::CONFLICT => true,
+        StatusCode::BAD_REQUEST => {
+            ["File already exists", "Repository does not allow updating assets", "cannot be updated"].iter().any(|message| text.contains(message))
+        }
+        StatusCode::FORBIDDEN => text.contains("overwrite artifact"),
+        _ => false,
+    }
+}
+
+/// The readable part of an error page, for error messages
+fn error_text(body: &str) -> String {
+    let without_tags = regex::Regex::new(r"<[^>]*>").map(|tags| tags.replace_all(body, " ").to_string()).unwrap_or_else(|_| body.to_string());
+    let text = without_tags.split_whitespace().collect::<Vec<_>>().join(" ");
+    match text.char_indices().nth(300) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text,
+    }
+}
+
+pub enum UploadOutcome {
+    Uploaded,
+    /// The index already has a file of that name, from an earlier run or
+    /// with other content; only the SHA-256 it lists tells which
+    Skipped,
+    /// A retry found the file already there: the attempt before it may have
+    /// got through, or the index holds another file of that name. Only the
+    /// index listing the same SHA-256 tells which.
+    Unconfirmed,
+}
+
+pub struct Uploader {
+    client: Client,
+    url: String,
+    username: String,
+    password: String,
+    retries: u32,
+}
+
+impl Uploader {
+    /// `client` should not follow redirects: an upload redirected to
+    /// another URL would arrive there as a GET
+    pub fn new(client: Client, url: &str, username: String, password: String, retries: u32) -> Self {
+        Uploader { client, url: url.to_string(), username, password, retries }
+    }
+
+    /// Whether the repository accepts the credentials, found with an upload
+    /// carrying no file: indexes refuse unknown credentials with 401 or 403
+    /// before they look at the form, which they then reject as incomplete
+    pub async fn verify_credentials(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let form = Form::new().text(":action", "file_upload").text("protocol_version", "1");
+        let request = self.client.post(&self.url).basic_auth(&self.username, Some(&self.password)).multipart(form);
+        let response = network::send_with_retries(request, self.retries)
+            .await
+            .map_err(|e| format!("Could not reach {}: {}", self.url, e))?;
+        match response.status() {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                let text = error_text(&response.text().await.unwrap_or_default());
+                Err(format!("{} rejected the credentials for '{}': {}", self.url, self.username, text).into())
+            }
+            status if status.is_redirection() => Err(format!("{} redirects uploads elsewhere; use the upload URL itself", self.url).into()),
+            status if status.is_server_error() => Err(format!("{} failed: {}", self.url, status).into()),
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn upload(&self, dist: &Distribution) -> Result<UploadOutcome, Box<dyn std::error::Error>> {
+        let content = fs::read(&dist.path)?;
+        println!("  {} {} ({})", "⬆".cyan(), dist.filename, format_size(content.len() as u64));
+        let sha256 = hex::encode(Sha256::digest(&content));
+        let blake2 = hex::encode(Blake2b256::digest(&content));
+
+        let mut attempt = 0;
+        loop {
+            let mut form = Form::new()
+                .text(":action", "file_upload")
+                .text("protocol_version", "1")
+                .text("filetype", dist.filetype)
+                .text("pyversion", dist.pyversion.clone())
+                .text("sha256_digest", sha256.clone())
+                .text("blake2_256_digest", blake2.clone());
+            for (header, value) in &dist.fields {
+                form = form.text(form_field(header), value.clone());
+            }
+            if let Some(description) = &dist.description {
+                form = form.text("description", description.clone());
+            }
+            let part = Part::bytes(content.clone()).file_name(dist.filename.clone()).mime_str("application/octet-stream")?;
+            form = form.part("content", part);
+
+            let sent = self
+                .client
+                .post(&self.url)
+                .basic_auth(&self.username, Some(&self.password))
+                .multipart(form)
+                .send()
+                .await;
+            let delay = match sent {
+                Ok(response) if attempt < self.retries && network::is_retryable(&response) => network::retry_delay(attempt, Some(&response)),
+                Ok(response) if response.status().is_success() => return Ok(UploadOutcome::Uploaded),
+                Ok(response) if response.status().is_redirection() => {
+                    let location = response.headers().get(reqwest::header::LOCATION).and_then(|value| value.to_str().ok()).unwrap_or("elsewhere").to_string();
+                    return Err(format!("{} redirected the upload to {}; use the upload URL itself", self.url, location).into());
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    let text = error_text(&response.text().await.unwrap_or_default());
+                    if already_exists(status, &text) {
+                        return Ok(if attempt == 0 { UploadOutcome::Skipped } else { UploadOutcome::Unconfirmed });
+                    }
+                    let hint = match status {
+                        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => "; check the credentials",
+                        _ => "",
+                    };
+                    return Err(format!("Upload of {} failed: {} {}{}", dist.filename, status, text, hint).into());
+                }
+                Err(e) if attempt < self.retries && network::is_transient(&e) => network::retry_delay(attempt, None),
+                Err(e) => return Err(format!("Upload of {} failed: {}", dist.filename, e).into()),
+            };
+            eprintln!("{}", format!("Warning: uploading {} failed; retrying in {:.1}s", dist.filename, delay.as_secs_f64()).yellow());
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// The files the simple index at `index_url` lists for `project`; none when
+/// it does not have the project yet. `auth` is for private indexes.
+pub async fn index_files(client: &Client, index_url: &str, project: &str, auth: Option<(&str, &str)>, retries: u32) -> Result<Vec<IndexFile>, Box<dyn std::error::Error>> {
+    // The PEP 503 name, which every index serves without a redirect
+    let url = format!("{}/{}/", index_url.trim_end_matches('/'), normalize_dist_name(project).replace('_', "-"));
+    // What the index has now, not what a cache in between remembers
+    let mut request = client.get(&url).header(reqwest::header::ACCEPT, simple_api::ACCEPT_JSON).header(reqwest::header::CACHE_CONTROL, "max-age=0");
+    if let Some((username, password)) = auth {
+        request = request.basic_auth(username, Some(password));
+    }
+    let response = network::send_with_retries(request, retries).await.map_err(|e| format!("Could not read {}: {}", url, e))?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(Vec::new());
+    }
+    let response = response.error_for_status().map_err(|e| format!("Could not read {}: {}", url, e))?;
+    let base = response.url().clone();
+    let content_type = response.headers().get(reqwest::header::CONTENT_TYPE).and_then(|value| value.to_str().ok()).unwrap_or_default().to_string();
+    let body = response.text().await?;
+    simple_api::parse_project_page(&base, &content_type, &body)
+}
+
+/// Polls the index until `project`'s page lists every file in `filenames`,
+/// for at most `timeout`: an index (or the CDN in front of it) can take a
+/// while to show a new upload. Returns the files listed then.
+pub async fn wait_for_files(
+    client: &Client,
+    index_url: &str,
+    project: &str,
+    auth: Option<(&str, &str)>,
+    filenames: &[&str],
+    timeout: Duration,
+) -> Result<Vec<IndexFile>, Box<dyn std::error::Error>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        // A failed poll is retried like a page not updated yet
+        let (files, failure) = match index_files(client, index_url, project, auth, 0).await {
+            Ok(files) => (files, None),
+            Err(e) => (Vec::new(), Some(e)),
+        };
+        let missing: Vec<&str> = filenames.iter().copied().filter(|filename| !files.iter().any(|file| file.filename == *filename)).collect();
+        if missing.is_empty() {
+            return Ok(files);
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            let reason = failure.map(|e| format!(" (last attempt: {})", e)).unwrap_or_default();
+            return Err(format!("{} still does not list {} after {}s{}", index_url, missing.join(", "), timeout.as_secs(), reason).into());
+        }
+        tokio::time::sleep(POLL_INTERVAL.min(deadline - now)).await;
+    }
+}
@@ -0,0 +1,372 @@
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::fs;
+use chrono::Utc;
+use tokio::process::Command;
+use colored::*;
+use crate::modules::mirrors::apply_session_to_pip;
+use crate::modules::models::{SAConfig, VenvConfig, EnvMetadata, InstalledPackage};
+use crate::modules::network;
+
+pub const VENV_DIR: &str = ".sa_env";
+const ENV_METADATA_FILE: &str = "sa-env.json";
+
+/// Environments created by other tools that SA can operate on
+const EXISTING_VENV_DIRS: &[&str] = &[".venv", "venv"];
+
+static ACTIVE_VENV: OnceLock<PathBuf> = OnceLock::new();
+
+// Virtual environment management
+pub fn venv_dir() -> PathBuf {
+    ACTIVE_VENV.get_or_init(resolve_venv_dir).clone()
+}
+
+fn resolve_venv_dir() -> PathBuf {
+    if Path::new(VENV_DIR).exists() {
+        return PathBuf::from(VENV_DIR);
+    }
+
+    let reuse_existing = SAConfig::load()
+        .map(|config| config.venv.reuse_existing)
+        .unwrap_or(true);
+
+    if reuse_existing {
+        if let Some(existing) = detect_existing_venv() {
+            return existing;
+        }
+    }
+
+    PathBuf::from(VENV_DIR)
+}
+
+/// Finds a virtualenv in the project directory created by another tool
+pub fn detect_existing_venv() -> Option<PathBuf> {
+    EXISTING_VENV_DIRS
+        .iter()
+        .map(PathBuf::from)
+        .find(|dir| dir.join("pyvenv.cfg").is_file())
+}
+
+/// Executables directory of an environment (bin/ on Unix, Scripts/ on Windows)
+pub fn env_bin_dir(env_dir: &Path) -> PathBuf {
+    env_dir.join(if cfg!(windows) { "Scripts" } else { "bin" })
+}
+
+pub fn env_bin_path(env_dir: &Path, name: &str) -> PathBuf {
+    env_bin_dir(env_dir).join(name)
+}
+
+pub fn bin_dir() -> PathBuf {
+    env_bin_dir(&venv_dir())
+}
+
+pub fn bin_path(name: &str) -> PathBuf {
+    bin_dir().join(name)
+}
+
+pub fn has_pip() -> bool {
+    bin_path("pip").exists()
+}
+
+/// Builds a pip invocation targeting the environment. Environments created
+/// without pip are driven by the host pip through `--python`. The mirrors
+/// selected for this run, if any, become pip's index and extra indexes.
+pub fn pip_command() -> Command {
+    let mut cmd = if has_pip() {
+        Command::new(bin_path("pip"))
+    } else {
+        let mut cmd = Command::new("python3");
+        cmd.args(["-m", "pip", "--python"]).arg(bin_path("python"));
+        cmd
+    };
+    network::apply_to_pip(&mut cmd);
+    apply_session_to_pip(&mut cmd);
+    cmd
+}
+
+/// Distributions installed in the project environment
+pub async fn installed_packages() -> Result<Vec<InstalledPackage>, Box<dyn std::error::Error>> {
+    let output = pip_command()
+        .args(["list", "--format", "json", "--disable-pip-version-check"])
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Err("Failed to list installed packages".into());
+    }
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+pub async fn ensure_venv_exists() -> Result<(), Box<dyn std::error::Error>> {
+    ensure_venv_with_python(None).await
+}
+
+/// Creates the environment with the requested interpreter, or validates that an
+/// existing environment was built with it
+pub async fn ensure_venv_with_python(python: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    if !venv_dir().exists() {
+        let config = SAConfig::load()?;
+        return create_venv(&config.venv, python).await;
+    }
+
+    if let Some(spec) = python {
+        validate_interpreter(spec).await?;
+    }
+    Ok(())
+}
+
+pub async fn create_venv(settings: &VenvConfig, python: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let interpreter = match python {
+        Some(spec) => find_interpreter(spec)?,
+        None => PathBuf::from("python3"),
+    };
+    let python_version = interpreter_version(&interpreter).await?;
+
+    println!("Creating virtual environment (Python {})...", python_version);
+
+    let mut cmd = Command::new(&interpreter);
+    cmd.args(["-m", "venv"]);
+    if settings.without_pip {
+        cmd.arg("--without-pip");
+    }
+    cmd.arg(venv_dir());
+
+    let status = cmd.status().await?;
+    if !status.success() {
+        return Err("Failed to create virtual environment".into());
+    }
+
+    write_env_metadata(&EnvMetadata {
+        python: interpreter,
+        python_version,
+        created_at: Utc::now(),
+    })?;
+
+    seed_venv(settings).await
+}
+
+/// Locates an interpreter from a path (`/usr/bin/python3.12`), a version
+/// (`3.11`) or an executable name (`python3.12`)
+pub fn find_interpreter(spec: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let as_path = Path::new(spec);
+    if as_path.components().count() > 1 || as_path.is_file() {
+        return if as_path.is_file() {
+            Ok(as_path.to_path_buf())
+        } else {
+            Err(format!("Python interpreter '{}' does not exist", spec).into())
+        };
+    }
+
+    let is_version = spec.chars().all(|c| c.is_ascii_digit() || c == '.');
+    let candidates = if is_version {
+        vec![format!("python{}", spec)]
+    } else {
+        vec![spec.to_string()]
+    };
+
+    candidates
+        .iter()
+        .find_map(|name| find_on_path(name))
+        .ok_or_else(|| format!("Could not find a Python interpreter for '{}' on PATH", spec).into())
+}
+
+fn find_on_path(name: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path).find_map(|dir| {
+        let candidate = dir.join(name);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+        let exe = candidate.with_extension("exe");
+        exe.is_file().then_some(exe)
+    })
+}
+
+pub async fn interpreter_version(python: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let output = Command::new(python)
+        .args(["-c", "import platform; print(platform.python_version())"])
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run Python interpreter '{}': {}", python.display(), e))?;
+
+    if !output.status.success() {
+        return Err(format!("Python interpreter '{}' is not usable", python.display()).into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn metadata_path() -> PathBuf {
+    venv_dir().join(ENV_METADATA_FILE)
+}
+
+pub fn read_env_metadata() -> Option<EnvMetadata> {
+    let content = fs::read_to_string(metadata_path()).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+fn write_env_metadata(metadata: &EnvMetadata) -> Result<(), Box<dyn std::error::Error>> {
+    fs::write(metadata_path(), serde_json::to_string_pretty(metadata)?)?;
+    Ok(())
+}
+
+/// Python version of the active environment, from SA metadata or pyvenv.cfg
+pub fn env_python_version() -> Option<String> {
+    if let Some(metadata) = read_env_metadata() {
+        return Some(metadata.python_version);
+    }
+
+    let pyvenv = fs::read_to_string(venv_dir().join("pyvenv.cfg")).ok()?;
+    pyvenv.lines().find_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        matches!(key.trim(), "version" | "version_info").then(|| value.trim().to_string())
+    })
+}
+
+async fn validate_interpreter(spec: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(env_version) = env_python_version() else {
+        return Ok(());
+    };
+
+    let is_version = spec.chars().all(|c| c.is_ascii_digit() || c == '.');
+    let requested = if is_version {
+        spec.to_string()
+    } else {
+        interpreter_version(&find_interpreter(spec)?).await?
+    };
+
+    if env_version == requested || env_version.starts_with(&format!("{}.", requested)) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Environment '{}' uses Python {}, but Python {} was requested. Recreate it with 'sa env create --clear --python {}'",
+            venv_dir().display(), env_version, requested, spec
+        ).into())
+    }
+}
+
+async fn seed_venv(settings: &VenvConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let mut pins = Vec::new();
+    if let (false, Some(version)) = (settings.without_pip, &settings.pip_version) {
+        pins.push(format!("pip=={}", version));
+    }
+    if let Some(version) = &settings.setuptools_version {
+        pins.push(format!("setuptools=={}", version));
+    }
+
+    if !pins.is_empty() {
+        println!("{}", format!("🌱 Seeding {}", pins.join(", ")).cyan());
+        let status = pip_command()
+            .args(["install", "--quiet"])
+            .args(&pins)
+            .status()
+            .await?;
+
+        if !status.success() {
+            return Err("Failed to install seed packages".into());
+        }
+    }
+
+    if settings.without_setuptools && settings.setuptools_version.is_none() {
+        // Older interpreters seed setuptools together with pip
+        let _ = pip_command()
+            .args(["uninstall", "--yes", "--quiet", "setuptools"])
+            .output()
+            .await;
+    }
+
+    Ok(())
+}
+
+/// Applies `--seed name==version` overrides on top of the configured settings
+pub fn apply_seed_pins(settings: &mut VenvConfig, seeds: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    for seed in seeds {
+        let (name, version) = seed
+            .split_once("==")
+            .ok_or_else(|| format!("Invalid seed '{}', expected name==version", seed))?;
+
+        match name.trim().to_lowercase().as_str() {
+            "pip" => settings.pip_version = Some(version.trim().to_string()),
+            "setuptools" => settings.setuptools_version = Some(version.trim().to_string()),
+            other => return Err(format!("Unsupported seed package '{}'", other).into()),
+        }
+    }
+    Ok(())
+}
+
+/// Absolute environment directory and its executables directory
+fn activation_paths() -> Result<(PathBuf, PathBuf), Box<dyn std::error::Error>> {
+    let env_dir = std::path::absolute(venv_dir())?;
+    let bin_dir = std::path::absolute(bin_dir())?;
+    Ok((env_dir, bin_dir))
+}
+
+pub fn detect_shell() -> String {
+    if cfg!(windows) {
+        return "powershell".to_string();
+    }
+
+    std::env::var("SHELL")
+        .ok()
+        .and_then(|shell| Path::new(&shell).file_name().map(|n| n.to_string_lossy().to_string()))
+        .unwrap_or_else(|| "bash".to_string())
+}
+
+/// Renders the snippet that activates the environment in the given shell
+pub fn activation_snippet(shell: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let (env_dir, bin_dir) = activation_paths()?;
+    let env_dir = env_dir.display();
+    let bin_dir = bin_dir.display();
+
+    let snippet = match shell {
+        "bash" | "zsh" | "sh" => format!(
+            "export VIRTUAL_ENV=\"{env_dir}\"\n\
+             export PATH=\"{bin_dir}:$PATH\"\n\
+             unset PYTHONHOME\n"
+        ),
+        "fish" => format!(
+            "set -gx VIRTUAL_ENV \"{env_dir}\"\n\
+             set -gx PATH \"{bin_dir}\" $PATH\n\
+             set -e PYTHONHOME\n"
+        ),
+        "powershell" | "pwsh" => format!(
+            "$env:VIRTUAL_ENV = \"{env_dir}\"\n\
+             $env:PATH = \"{bin_dir}\" + [IO.Path]::PathSeparator + $env:PATH\n\
+             Remove-Item Env:PYTHONHOME -ErrorAction SilentlyContinue\n"
+        ),
+        other => return Err(format!("Unsupported shell '{}'. Use bash, zsh, fish or powershell", other).into()),
+    };
+
+    Ok(snippet)
+}
+
+/// Launches an interactive shell with the environment activated
+pub async fn spawn_shell(shell: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let (env_dir, bin_dir) = activation_paths()?;
+
+    let program = match shell {
+        Some(shell) => shell.to_string(),
+        None if cfg!(windows) => "powershell".to_string(),
+        None => std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string()),
+    };
+
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let mut paths = vec![bin_dir];
+    paths.extend(std::env::split_paths(&path));
+
+    println!("{}", format!("🐚 Entering environment '{}' (exit to leave)", env_dir.display()).cyan());
+
+    let status = Command::new(&program)
+        .env("VIRTUAL_ENV", &env_dir)
+        .env("PATH", std::env::join_paths(paths)?)
+        .env_remove("PYTHONHOME")
+        .status()
+        .await
+        .map_err(|e| format!("Failed to launch shell '{}': {}", program, e))?;
+
+    println!("{}", "👋 Left environment".cyan());
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("Shell exited with {}", status).into())
+    }
+}
@@ -0,0 +1,189 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use regex::Regex;
+use crate::modules::config::{project_tool_section, update_pyproject};
+use crate::modules::installer::normalize_dist_name;
+use crate::modules::models::VersionPolicy;
+use crate::modules::pep440::{PreRelease, Version};
+
+/// Files that hold nothing but a package's version, next to its __init__.py
+const VERSION_FILES: [&str; 3] = ["_version.py", "__about__.py", "version.py"];
+
+// `sa version bump`: the project's version lives in `project.version` and is
+// often repeated as `__version__` in the package (or in a _version.py
+// beside it). Bumping rewrites every copy so they cannot drift apart.
+
+/// The version after bumping `part` (major, minor, patch or pre), optionally
+/// starting a pre-release (a, b or rc). A pre-release bumped by the part it
+/// leads up to is released instead: 2.0.0rc1 bumped by major is 2.0.0.
+pub fn bump(current: &Version, part: &str, label: Option<&str>) -> Result<Version, Box<dyn std::error::Error>> {
+    let phase = label.map(parse_phase).transpose()?;
+    let mut release = current.release.clone();
+    release.resize(release.len().max(3), 0);
+
+    if part == "pre" {
+        let pre = match (current.pre, phase) {
+            // Next pre-release of the same phase, or a later phase
+            (Some((now, number)), None) => (now, number + 1),
+            (Some((now, number)), Some(next)) if next == now => (now, number + 1),
+            (Some((now, _)), Some(next)) if next > now => (next, 1),
+            (Some(_), Some(_)) => return Err(format!("{} is already past the '{}' phase", current, label.unwrap_or_default()).into()),
+            // First pre-release of the next patch
+            (None, phase) => {
+                release[2] += 1;
+                (phase.unwrap_or(PreRelease::Alpha), 1)
+            }
+        };
+        return Ok(Version { epoch: current.epoch, release, pre: Some(pre), post: None, dev: None, local: Vec::new() });
+    }
+
+    let index = match part {
+        "major" => 0,
+        "minor" => 1,
+        "patch" => 2,
+        other => return Err(format!("Unknown version part '{}'; use major, minor, patch or pre", other).into()),
+    };
+    let leads_up = phase.is_none() && current.is_prerelease() && release[index + 1..].iter().all(|number| *number == 0);
+    if !leads_up {
+        release[index] += 1;
+        release[index + 1..].iter_mut().for_each(|number| *number = 0);
+    }
+    Ok(Version { epoch: current.epoch, release, pre: phase.map(|phase| (phase, 1)), post: None, dev: None, local: Vec::new() })
+}
+
+fn parse_phase(label: &str) -> Result<PreRelease, Box<dyn std::error::Error>> {
+    match label {
+        "a" | "alpha" => Ok(PreRelease::Alpha),
+        "b" | "beta" => Ok(PreRelease::Beta),
+        "rc" => Ok(PreRelease::Rc),
+        other => Err(format!("Unknown pre-release label '{}'; use a, b or rc", other).into()),
+    }
+}
+
+/// The version of the project in the current directory and every place it
+/// is written
+pub struct ProjectVersion {
+    pub name: String,
+    pub current: String,
+    /// Whether pyproject.toml declares it (it is not dynamic)
+    pub declared: bool,
+    pub files: Vec<VersionFile>,
+    pub policy: VersionPolicy,
+}
+
+impl ProjectVersion {
+    /// Reads `project.version`, or the package's own when it is dynamic
+    pub fn read(project_dir: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let content = fs::read_to_string(project_dir.join("pyproject.toml")).map_err(|_| "No pyproject.toml in the current directory")?;
+        let pyproject: toml::Value = toml::from_str(&content).map_err(|e| format!("Invalid pyproject.toml: {}", e))?;
+        let project = pyproject.get("project").ok_or("pyproject.toml has no [project] table")?;
+        let name = project.get("name").and_then(|name| name.as_str()).ok_or("pyproject.toml has no project.name")?;
+        let policy: VersionPolicy = match project_tool_section("version")? {
+            Some(section) => section.try_into().map_err(|e| format!("Invalid [tool.sa.version]: {}", e))?,
+            None => VersionPolicy::default(),
+        };
+
+        let files = find_version_files(project_dir, name, &policy);
+        let declared = project.get("version").and_then(|version| version.as_str());
+        let current = declared
+            .or(files.first().map(|file| file.version.as_str()))
+            .ok_or("No version to bump: pyproject.toml has no project.version and the package sets no __version__")?
+            .to_string();
+        Ok(ProjectVersion { name: name.to_string(), current, declared: declared.is_some(), files, policy })
+    }
+
+    /// The tag name of a version, from `tag-format`
+    pub fn tag_name(&self, version: &str) -> String {
+        self.policy.tag_format.replace("{version}", version)
+    }
+
+    /// The files holding the version, relative to `project_dir`
+    pub fn paths(&self, project_dir: &Path) -> Vec<String> {
+        let mut paths: Vec<String> = self.declared.then(|| "pyproject.toml".to_string()).into_iter().collect();
+        paths.extend(self.files.iter().map(|file| file.path.strip_prefix(project_dir).unwrap_or(&file.path).display().to_string()));
+        paths
+    }
+
+    /// Writes `version` everywhere the current one is
+    pub fn write(&self, version: &Version) -> Result<(), Box<dyn std::error::Error>> {
+        if self.declared {
+            let text = version.to_string();
+            update_pyproject(|doc| {
+                // Keep a comment after the value
+                let item = &mut doc["project"]["version"];
+                let decor = item.as_value().map(|value| value.decor().clone());
+                *item = toml_edit::value(text.as_str());
+                if let (Some(decor), Some(value)) = (decor, item.as_value_mut()) {
+                    *value.decor_mut() = decor;
+                }
+                Ok(())
+            })?;
+        }
+        for file in &self.files {
+            write_version_file(file, version)?;
+        }
+        Ok(())
+    }
+}
+
+/// A Python file assigning the version, and the version it holds
+pub struct VersionFile {
+    pub path: PathBuf,
+    pub version: String,
+}
+
+/// The package's version files: `__version__` in its __init__.py and the
+/// usual version modules beside it (in the project root or src/), plus
+/// those listed under `[tool.sa.version]`
+pub fn find_version_files(project_dir: &Path, project_name: &str, policy: &VersionPolicy) -> Vec<VersionFile> {
+    let package = normalize_dist_name(project_name).replace('-', "_");
+    let mut candidates: Vec<PathBuf> = policy.files.iter().map(|file| project_dir.join(file)).collect();
+    for root in [project_dir.to_path_buf(), project_dir.join("src")] {
+        let package_dir = root.join(&package);
+        candidates.push(package_dir.join("__init__.py"));
+        candidates.extend(VERSION_FILES.iter().map(|file| package_dir.join(file)));
+    }
+
+    let mut files = Vec::new();
+    for path in candidates {
+        if files.iter().any(|file: &VersionFile| file.path == path) {
+            continue;
+        }
+        let Ok(content) = fs::read_to_string(&path) else { continue };
+        if let Some(captures) = assignment(&path).captures(&content) {
+            files.push(VersionFile { version: captures[3].to_string(), path });
+        }
+    }
+    files
+}
+
+/// Writes `version` into a version file, and into the version tuple
+/// setuptools-scm writes beside it
+pub fn write_version_file(file: &VersionFile, version: &Version) -> Result<(), Box<dyn std::error::Error>> {
+    let content = fs::read_to_string(&file.path)?;
+    let text = version.to_string();
+    let updated = assignment(&file.path).replace_all(&content, |captures: &regex::Captures| format!("{}{}{}{}", &captures[1], &captures[2], text, &captures[4]));
+    let tuple = Regex::new(r"(?m)^(\s*(?:(?:__version_tuple__|version_tuple)\s*(?::[^=\n]*)?=\s*)+)\([^)\n]*\)").expect("valid pattern");
+    let updated = tuple.replace_all(&updated, |captures: &regex::Captures| format!("{}{}", &captures[1], version_tuple(version)));
+    fs::write(&file.path, updated.as_ref())?;
+    Ok(())
+}
+
+/// `(1, 2, 4, 'rc1')` for 1.2.4rc1, as setuptools-scm spells it
+fn version_tuple(version: &Version) -> String {
+    let mut parts: Vec<String> = version.release.iter().map(u64::to_string).collect();
+    let release = Version { pre: None, post: None, dev: None, local: Vec::new(), ..version.clone() }.to_string();
+    let rest = version.to_string();
+    let rest = rest.strip_prefix(&release).unwrap_or("").trim_start_matches('.');
+    if !rest.is_empty() {
+        parts.push(format!("'{}'", rest));
+    }
+    format!("({})", parts.join(", "))
+}
+
+/// `__version__ = "1.2.3"`, also `version` / `VERSION` in a dedicated
+/// version module (setuptools-scm writes `__version__ = version = '1.2.3'`)
+fn assignment(path: &Path) -> Regex {
+    let names = if path.file_name().is_some_and(|name| name == "__init__.py") { "__version__" } else { "__version__|version|VERSION" };
+    Regex::new(&format!(r#"(?m)^(\s*(?:(?:{})\s*(?::\s*str\s*)?=\s*)+)(['"])([^'"\n]*)(['"])"#, names)).expect("valid pattern")
+}
@@ -1,84 +1,1601 @@
-use std::collections::{HashMap, HashSet};
-use petgraph::{Graph, Directed};
-use petgraph::dot::{Dot, Config};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::path::Path;
+use colored::*;
+use futures_util::future::join_all;
+use petgraph::algo::dominators::simple_fast;
+use petgraph::algo::tarjan_scc;
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use petgraph::visit::{Bfs, EdgeFiltered, EdgeRef, IntoNeighbors};
+use petgraph::{Direction, Graph, Directed};
+use serde_json::Value;
+use crate::modules::graph_layout;
+use crate::modules::http_cache::HttpCache;
+use crate::modules::installer::normalize_dist_name;
+use crate::modules::licenses::{classify_license, read_installed_metadata, LicenseCategory, LicenseExpr};
+use crate::modules::models::{
+    DistMetadata, GraphConflict, GraphConstraint, GraphDocument, GraphEdge, GraphNode, GraphPackage, GraphRequirement,
+    GraphVulnerability, InstalledPackage, SecurityVulnerability,
+};
+use crate::modules::pep440::{compare_versions, SpecifierSet, Version};
+use crate::modules::pep508::{evaluate_marker, MarkerEnvironment, Requirement};
+use crate::modules::security::{severity_rank, SecurityScanner};
+use crate::modules::snapshot::read_lockfile;
+use crate::modules::venv::{env_python_version, venv_dir};
+
+const PYPI_JSON_URL: &str = "https://pypi.org/pypi";
+const FONT_FAMILY: &str = "DejaVu Sans, Helvetica, Arial, sans-serif";
+/// Estimated width of a 12px character, for sizing node boxes
+const CHAR_WIDTH: f64 = 7.0;
+const LINE_HEIGHT: f64 = 15.0;
+const NODE_PADDING_X: f64 = 12.0;
+const NODE_PADDING_Y: f64 = 8.0;
+/// PNGs are rendered at twice the SVG's size so text stays legible
+const PNG_SCALE: f32 = 2.0;
+/// Conflicting packages and requirements are drawn in this red
+const CONFLICT_COLOR: &str = "#c0392b";
+const CONFLICT_FILL: &str = "#fdecea";
+/// Cycles and duplicate versions are noted in this color
+const WARNING_COLOR: &str = "#b9770e";
+/// Node fills run from the usual blue for the smallest packages to this
+/// orange for the largest
+const NODE_FILL: (u8, u8, u8) = (0xee, 0xf3, 0xfb);
+const LARGE_FILL: (u8, u8, u8) = (0xf5, 0xb0, 0x41);
+/// How much larger than its label the largest package's box is drawn
+const SIZE_SCALE: f64 = 0.6;
+/// `--annotate licenses` fills, by license category
+const LICENSE_FILLS: [(&str, &str); 5] = [
+    ("permissive", "#d5f5e3"),
+    ("weak copyleft", "#fcf3cf"),
+    ("strong copyleft", "#f5cba7"),
+    ("proprietary", "#e8daef"),
+    ("unknown", "#e5e7e9"),
+];
+/// `--annotate vulns` fills, by worst advisory severity
+const SEVERITY_FILLS: [(&str, &str); 6] = [
+    ("none", "#d5f5e3"),
+    ("low", "#fcf3cf"),
+    ("medium", "#fad7a0"),
+    ("high", "#f5b7b1"),
+    ("critical", "#f1948a"),
+    ("not checked", "#e5e7e9"),
+];
+/// Lockfile diff fills, by what happened to the package
+const CHANGE_FILLS: [(&str, &str); 5] = [
+    ("added", "#d5f5e3"),
+    ("removed", "#f5b7b1"),
+    ("upgraded", "#fcf3cf"),
+    ("downgraded", "#fad7a0"),
+    ("unchanged", "#eef3fb"),
+];
+/// Lockfile diff edge colors; removed requirements are also dashed
+const ADDED_COLOR: &str = "#27ae60";
+const REMOVED_COLOR: &str = "#c0392b";
+/// `schema_version` of `--format json` output
+const GRAPH_SCHEMA_VERSION: u32 = 1;
+/// Page for `--format html`; the graph's JSON replaces `/*GRAPH_DATA*/null`
+const HTML_TEMPLATE: &str = include_str!("visualize.html");
+/// Python version markers are evaluated for when there is no environment
+const DEFAULT_PYTHON: &str = "3.11";
+
+/// A dependency graph: nodes are normalized package names, with their
+/// metadata in `packages`
+pub struct DependencyGraph {
+    pub graph: Graph<String, GraphRequirement, Directed>,
+    pub root: NodeIndex,
+    pub packages: HashMap<String, GraphPackage>,
+    /// Drawn for the packages depending on the root rather than the root's
+    /// dependencies; edges still point from requiring to required package
+    pub reverse: bool,
+    /// What nodes are colored by, if not their size
+    pub overlay: Option<Overlay>,
+}
+
+/// A color of an overlay's key: category, fill, and how many packages
+/// have it
+type KeyEntry = (&'static str, &'static str, usize);
+
+/// What `--annotate` colors nodes by
+pub enum Overlay {
+    Licenses,
+    /// Advisories affecting each package, by node key; packages missing
+    /// here (no known version) were not checked
+    Vulnerabilities(HashMap<String, Vec<SecurityVulnerability>>),
+    /// What a lockfile diff changed: each package's change and previous
+    /// version, by node key, and each requirement's change
+    Changes {
+        packages: HashMap<String, (&'static str, Option<String>)>,
+        edges: HashMap<EdgeIndex, &'static str>,
+    },
+}
+
+/// Requirements on one package that cannot all be met
+pub struct Conflict {
+    pub package: NodeIndex,
+    /// Each requirement on the package, and whether its version meets it
+    pub constraints: Vec<(EdgeIndex, bool)>,
+}
+
+/// A package's share of the environment's size
+pub struct PackageSize {
+    pub package: NodeIndex,
+    pub size: u64,
+    /// Its size plus that of the packages only it pulls in: what removing
+    /// it would free
+    pub retained: u64,
+    /// The shortest chain of requirements from the root to the package
+    pub chain: Vec<NodeIndex>,
+}
+
+/// Trims a dependency graph to something readable
+#[derive(Default)]
+pub struct GraphFilter {
+    /// Levels of requirements below the root to follow
+    pub depth: Option<usize>,
+    /// Package name globs (`*`, `?`); when any are given, only matching
+    /// packages and the chains of requirements leading to them are kept
+    pub include: Vec<String>,
+    /// Package name globs left out of the graph, along with whatever only
+    /// they pull in
+    pub exclude: Vec<String>,
+}
+
+impl GraphFilter {
+    fn excludes(&self, key: &str) -> bool {
+        self.exclude.iter().any(|pattern| glob_match(&normalize_dist_name(pattern), key))
+    }
+
+    fn includes(&self, key: &str) -> bool {
+        self.include.iter().any(|pattern| glob_match(&normalize_dist_name(pattern), key))
+    }
+}
 
 // Dependency visualization
 pub struct DependencyVisualizer;
 
 impl DependencyVisualizer {
+    /// The graph of `root` and its requirements (all the way down when
+    /// `transitive`, or as deep as `filter` allows), from package metadata
+    /// loaded by `DependencySource`
     pub fn create_dependency_graph(
-        package_name: &str,
-        dependencies: &HashMap<String, Vec<String>>,
+        root: &str,
+        packages: HashMap<String, GraphPackage>,
         transitive: bool,
-    ) -> Graph<String, (), Directed> {
+        filter: &GraphFilter,
+    ) -> DependencyGraph {
         let mut graph = Graph::new();
-        let mut node_indices = HashMap::new();
+        let mut node_indices: HashMap<String, NodeIndex> = HashMap::new();
 
-        // Add root node
-        let root_idx = graph.add_node(package_name.to_string());
-        node_indices.insert(package_name.to_string(), root_idx);
+        let root_key = normalize_dist_name(&Requirement::parse(root).map(|root| root.name).unwrap_or_else(|_| root.to_string()));
+        let root_idx = graph.add_node(root_key.clone());
+        node_indices.insert(root_key.clone(), root_idx);
 
-        if let Some(deps) = dependencies.get(package_name) {
-            Self::add_dependencies_to_graph(
-                &mut graph,
-                &mut node_indices,
-                package_name,
-                deps,
-                dependencies,
-                transitive,
-                &mut HashSet::new(),
-            );
+        // Each package is expanded once, breadth first so `depth` counts
+        // the shortest chain; requirements of packages already in the graph
+        // become edges to their nodes
+        let max_depth = filter.depth.unwrap_or(if transitive { usize::MAX } else { 1 });
+        let mut pending = VecDeque::from([(root_key, 0)]);
+        while let Some((parent, depth)) = pending.pop_front() {
+            let parent_idx = node_indices[&parent];
+            let Some(package) = packages.get(&parent) else { continue };
+            for requirement in package.requires.iter().filter(|requirement| !filter.excludes(&requirement.name)) {
+                let dep_idx = match node_indices.get(&requirement.name) {
+                    Some(&idx) => idx,
+                    None => {
+                        let idx = graph.add_node(requirement.name.clone());
+                        node_indices.insert(requirement.name.clone(), idx);
+                        if depth + 1 < max_depth {
+                            pending.push_back((requirement.name.clone(), depth + 1));
+                        }
+                        idx
+                    }
+                };
+                graph.add_edge(parent_idx, dep_idx, requirement.clone());
+            }
         }
 
-        graph
+        if !filter.include.is_empty() {
+            // Keep matches and every package with a path to one; the root
+            // comes first, so it keeps index 0
+            let mut keep: HashSet<NodeIndex> = graph.node_indices().filter(|&idx| filter.includes(&graph[idx])).collect();
+            keep.insert(root_idx);
+            let mut queue: Vec<NodeIndex> = keep.iter().copied().collect();
+            while let Some(idx) = queue.pop() {
+                for parent in graph.neighbors_directed(idx, Direction::Incoming) {
+                    if keep.insert(parent) {
+                        queue.push(parent);
+                    }
+                }
+            }
+            graph = graph.filter_map(|idx, key| keep.contains(&idx).then(|| key.clone()), |_, requirement| Some(requirement.clone()));
+        }
+
+        DependencyGraph { graph, root: root_idx, packages, reverse: false, overlay: None }
     }
 
-    fn add_dependencies_to_graph(
-        graph: &mut Graph<String, (), Directed>,
-        node_indices: &mut HashMap<String, petgraph::graph::NodeIndex>,
-        parent: &str,
-        deps: &[String],
-        all_dependencies: &HashMap<String, Vec<String>>,
-        transitive: bool,
-        visited: &mut HashSet<String>,
-    ) {
-        if visited.contains(parent) {
-            return; // Prevent cycles
+    /// The graph of `root` and the packages depending on it, from
+    /// `DependencySource::load_dependents`
+    pub fn create_reverse_graph(root: &str, packages: HashMap<String, GraphPackage>) -> DependencyGraph {
+        let mut graph = Graph::new();
+        let root_key = normalize_dist_name(&Requirement::parse(root).map(|root| root.name).unwrap_or_else(|_| root.to_string()));
+        let mut keys: Vec<&String> = packages.keys().filter(|key| **key != root_key).collect();
+        keys.sort();
+
+        let root_idx = graph.add_node(root_key.clone());
+        let mut node_indices: HashMap<String, NodeIndex> = HashMap::from([(root_key, root_idx)]);
+        for key in keys {
+            node_indices.insert(key.clone(), graph.add_node(key.clone()));
+        }
+        for (key, package) in &packages {
+            for requirement in &package.requires {
+                if let Some(&dep_idx) = node_indices.get(&requirement.name) {
+                    graph.add_edge(node_indices[key], dep_idx, requirement.clone());
+                }
+            }
+        }
+
+        DependencyGraph { graph, root: root_idx, packages, reverse: true, overlay: None }
+    }
+
+    /// The packages of two lockfiles (from `DependencySource::load_pins`)
+    /// in one graph, under a root node named `name`: the root points at
+    /// the packages nothing else requires, and each package and
+    /// requirement is marked added, removed or, for packages, upgraded,
+    /// downgraded or unchanged
+    pub fn create_diff_graph(name: &str, old: HashMap<String, GraphPackage>, new: HashMap<String, GraphPackage>) -> DependencyGraph {
+        let mut graph = Graph::new();
+        let root_idx = graph.add_node(name.to_string());
+        let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+        let node_indices: HashMap<&String, NodeIndex> = keys.iter().map(|key| (*key, graph.add_node((*key).clone()))).collect();
+
+        let mut changes = HashMap::new();
+        for key in &keys {
+            let change = match (old.get(*key), new.get(*key)) {
+                (Some(before), Some(after)) => {
+                    let ordering = compare_versions(after.version.as_deref().unwrap_or_default(), before.version.as_deref().unwrap_or_default());
+                    (if ordering.is_gt() { "upgraded" } else if ordering.is_lt() { "downgraded" } else { "unchanged" }, before.version.clone())
+                }
+                (None, Some(_)) => ("added", None),
+                _ => ("removed", None),
+            };
+            changes.insert((*key).clone(), change);
+        }
+
+        // A requirement kept by the new lockfile is drawn as it is there
+        let mut edges = HashMap::new();
+        for key in &keys {
+            let required = |packages: &HashMap<String, GraphPackage>| -> Vec<GraphRequirement> {
+                packages
+                    .get(*key)
+                    .map(|package| package.requires.iter().filter(|requirement| packages.contains_key(&requirement.name)).cloned().collect())
+                    .unwrap_or_default()
+            };
+            let (before, after) = (required(&old), required(&new));
+            for requirement in &after {
+                let change = if before.iter().any(|previous| previous.name == requirement.name) { "unchanged" } else { "added" };
+                let edge = graph.add_edge(node_indices[key], node_indices[&requirement.name], requirement.clone());
+                edges.insert(edge, change);
+            }
+            for requirement in before.iter().filter(|previous| !after.iter().any(|requirement| requirement.name == previous.name)) {
+                let edge = graph.add_edge(node_indices[key], node_indices[&requirement.name], requirement.clone());
+                edges.insert(edge, "removed");
+            }
+        }
+
+        // Top-level packages hang off the root, then, until every package
+        // is reached, one package of each cycle nothing outside it requires
+        let top_level: Vec<NodeIndex> = keys
+            .iter()
+            .map(|key| node_indices[key])
+            .filter(|idx| graph.neighbors_directed(*idx, Direction::Incoming).next().is_none())
+            .collect();
+        let mut reached = HashSet::from([root_idx]);
+        for idx in top_level.into_iter().chain(keys.iter().map(|key| node_indices[key])) {
+            if reached.contains(&idx) {
+                continue;
+            }
+            let key = graph[idx].clone();
+            let change = match changes[&key].0 {
+                change @ ("added" | "removed") => change,
+                _ => "unchanged",
+            };
+            let edge = graph.add_edge(root_idx, idx, GraphRequirement { name: key, extras: Vec::new(), specifier: String::new(), marker: None });
+            edges.insert(edge, change);
+            let mut bfs = Bfs::new(&graph, idx);
+            while let Some(next) = bfs.next(&graph) {
+                reached.insert(next);
+            }
+        }
+
+        let mut packages = old;
+        packages.extend(new);
+        DependencyGraph { graph, root: root_idx, packages, reverse: false, overlay: Some(Overlay::Changes { packages: changes, edges }) }
+    }
+
+    pub fn export_dot(graph: &DependencyGraph) -> String {
+        let mut dot = String::from("digraph dependencies {\n    node [shape=box, style=rounded];\n");
+        // Dependents below the package they depend on
+        if graph.reverse {
+            dot.push_str("    rankdir=BT;\n");
+        }
+        let conflicts = graph.conflicts();
+        let (conflict_nodes, conflict_edges) = conflict_sets(&conflicts);
+        let red = format!("color=\"{0}\", fontcolor=\"{0}\"", CONFLICT_COLOR);
+        let weights = graph.size_weights();
+        for idx in graph.graph.node_indices() {
+            let key = &graph.graph[idx];
+            let mut attributes = vec![format!("label={}", dot_id(&graph.node_text(idx)))];
+            let weight = weights.get(&idx).copied();
+            let fill = graph.overlay_fill(idx).map(str::to_string).or_else(|| weight.map(size_fill));
+            let style = if idx == graph.root { "rounded,bold" } else { "rounded" };
+            match fill {
+                Some(fill) => attributes.push(format!("style=\"{},filled\", fillcolor=\"{}\"", style, fill)),
+                None if idx == graph.root => attributes.push(format!("style=\"{}\"", style)),
+                None => {}
+            }
+            if let Some(weight) = weight {
+                attributes.push(format!("width={:.2}, height={:.2}", 0.75 * (1.0 + SIZE_SCALE * weight), 0.5 * (1.0 + SIZE_SCALE * weight)));
+            }
+            if conflict_nodes.contains(&idx) {
+                attributes.push(red.clone());
+            }
+            dot.push_str(&format!("    {} [{}];\n", dot_id(key), attributes.join(", ")));
+        }
+        for edge in graph.graph.edge_indices() {
+            let (from, to) = graph.graph.edge_endpoints(edge).expect("edge of this graph");
+            let requirement = &graph.graph[edge];
+            let mut attributes = Vec::new();
+            if !requirement.specifier.is_empty() {
+                attributes.push(format!("label={}", dot_id(&requirement.specifier)));
+            }
+            if conflict_edges.contains(&edge) {
+                attributes.push(red.clone());
+            } else {
+                match graph.edge_change(edge) {
+                    Some("added") => attributes.push(format!("color=\"{}\"", ADDED_COLOR)),
+                    Some(_) => attributes.push(format!("color=\"{}\", style=dashed", REMOVED_COLOR)),
+                    None => {}
+                }
+            }
+            let attributes = if attributes.is_empty() { String::new() } else { format!(" [{}]", attributes.join(", ")) };
+            dot.push_str(&format!("    {} -> {}{};\n", dot_id(&graph.graph[from]), dot_id(&graph.graph[to]), attributes));
+        }
+        if !conflicts.is_empty() {
+            // Left-justified lines (`\l`) in a note beside the graph
+            let lines: Vec<String> = graph.conflict_legend(&conflicts).iter().map(|line| dot_escape(line)).collect();
+            dot.push_str(&format!("    \"sa_conflicts\" [shape=note, {}, label=\"{}\\l\"];\n", red, lines.join("\\l")));
+        }
+        let warnings = graph.warnings();
+        if !warnings.is_empty() {
+            let lines: Vec<String> = std::iter::once("Warnings").chain(warnings.iter().map(String::as_str)).map(dot_escape).collect();
+            dot.push_str(&format!(
+                "    \"sa_warnings\" [shape=note, color=\"{0}\", fontcolor=\"{0}\", label=\"{1}\\l\"];\n",
+                WARNING_COLOR,
+                lines.join("\\l")
+            ));
+        }
+        if let Some((title, key)) = graph.overlay_key() {
+            // An HTML-like label: a table of the colors in use
+            let rows: String = key
+                .iter()
+                .map(|(name, fill, count)| format!("<tr><td bgcolor=\"{}\">{} ({})</td></tr>", fill, name, count))
+                .collect();
+            dot.push_str(&format!(
+                "    \"sa_key\" [shape=plaintext, label=<<table border=\"0\" cellborder=\"1\" cellspacing=\"0\" cellpadding=\"4\"><tr><td><b>{}</b></td></tr>{}</table>>];\n",
+                title, rows
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// A Mermaid `graph TD` definition (`graph BT` for dependents), which
+    /// GitHub renders in ```mermaid blocks
+    pub fn export_mermaid(graph: &DependencyGraph) -> String {
+        let mut mermaid = String::from(if graph.reverse { "graph BT\n" } else { "graph TD\n" });
+        for idx in graph.graph.node_indices() {
+            mermaid.push_str(&format!("    n{}[\"{}\"]\n", idx.index(), mermaid_text(&graph.node_text(idx).replace('\n', "<br/>"))));
+        }
+        for edge in graph.graph.edge_indices() {
+            let (from, to) = graph.graph.edge_endpoints(edge).expect("edge of this graph");
+            let requirement = &graph.graph[edge];
+            let label = if requirement.specifier.is_empty() { String::new() } else { format!("|\"{}\"|", mermaid_text(&requirement.specifier)) };
+            mermaid.push_str(&format!("    n{} -->{} n{}\n", from.index(), label, to.index()));
+        }
+        mermaid.push_str(&format!("    style n{} stroke-width:3px\n", graph.root.index()));
+        // Links are numbered in the order they were written
+        for edge in graph.graph.edge_indices() {
+            match graph.edge_change(edge) {
+                Some("added") => mermaid.push_str(&format!("    linkStyle {} stroke:{}\n", edge.index(), ADDED_COLOR)),
+                Some(_) => mermaid.push_str(&format!("    linkStyle {} stroke:{},stroke-dasharray:4 3\n", edge.index(), REMOVED_COLOR)),
+                None => {}
+            }
+        }
+        let weights = graph.size_weights();
+        for idx in graph.graph.node_indices() {
+            let fill = graph.overlay_fill(idx).map(str::to_string).or_else(|| weights.get(&idx).map(|weight| size_fill(*weight)));
+            if let Some(fill) = fill {
+                mermaid.push_str(&format!("    style n{} fill:{}\n", idx.index(), fill));
+            }
         }
-        visited.insert(parent.to_string());
+        for conflict in graph.conflicts() {
+            // An overlay's fill stays; the red outline still marks the conflict
+            let fill = if graph.overlay.is_some() { String::new() } else { format!("fill:{},", CONFLICT_FILL) };
+            mermaid.push_str(&format!("    style n{} {}stroke:{}\n", conflict.package.index(), fill, CONFLICT_COLOR));
+        }
+        for warning in graph.warnings() {
+            mermaid.push_str(&format!("    %% Warning: {}\n", warning));
+        }
+        mermaid
+    }
+
+    /// The graph as a `GraphDocument`: every package with its distance from
+    /// the root, and every requirement between them
+    pub fn graph_document(graph: &DependencyGraph) -> GraphDocument {
+        let depths = graph.depths();
+        let conflicts = graph.conflicts();
+        let (conflict_nodes, conflict_edges) = conflict_sets(&conflicts);
+        let nodes = graph
+            .graph
+            .node_indices()
+            .map(|idx| {
+                let key = &graph.graph[idx];
+                let package = graph.packages.get(key);
+                let depth = depths.get(&idx).copied().unwrap_or_default();
+                GraphNode {
+                    id: key.clone(),
+                    name: package.map_or_else(|| key.clone(), |package| package.name.clone()),
+                    version: package.and_then(|package| package.version.clone()),
+                    dependency: match depth {
+                        0 => "root",
+                        1 => "direct",
+                        _ => "transitive",
+                    }
+                    .to_string(),
+                    depth,
+                    source: package.map_or_else(|| "missing".to_string(), |package| package.source.clone()),
+                    license: package.and_then(|package| package.license.clone()),
+                    license_category: graph.license_category(idx).as_str().to_string(),
+                    size: package.and_then(|package| package.size),
+                    conflict: conflict_nodes.contains(&idx),
+                    vulnerabilities: match &graph.overlay {
+                        Some(Overlay::Vulnerabilities(found)) => found.get(key).map(|vulns| {
+                            vulns
+                                .iter()
+                                .map(|vuln| GraphVulnerability {
+                                    id: vuln.id.clone(),
+                                    severity: vuln.severity.clone(),
+                                    fixed_version: vuln.fixed_version.clone(),
+                                })
+                                .collect()
+                        }),
+                        _ => None,
+                    },
+                    change: graph.change(idx).map(|(change, _)| change.to_string()),
+                    previous_version: graph.change(idx).and_then(|(_, previous)| previous.clone()),
+                    other_versions: package.map(|package| package.other_versions.clone()).unwrap_or_default(),
+                }
+            })
+            .collect();
+        let edges = graph
+            .graph
+            .edge_indices()
+            .map(|edge| {
+                let (from, to) = graph.graph.edge_endpoints(edge).expect("edge of this graph");
+                let requirement = &graph.graph[edge];
+                GraphEdge {
+                    from: graph.graph[from].clone(),
+                    to: graph.graph[to].clone(),
+                    specifier: Some(requirement.specifier.clone()).filter(|specifier| !specifier.is_empty()),
+                    extras: requirement.extras.clone(),
+                    marker: requirement.marker.clone(),
+                    conflict: conflict_edges.contains(&edge),
+                    change: match &graph.overlay {
+                        Some(Overlay::Changes { edges, .. }) => edges.get(&edge).map(|change| change.to_string()),
+                        _ => None,
+                    },
+                }
+            })
+            .collect();
+        let conflicts = conflicts
+            .iter()
+            .map(|conflict| GraphConflict {
+                package: graph.graph[conflict.package].clone(),
+                version: graph.packages.get(&graph.graph[conflict.package]).and_then(|package| package.version.clone()),
+                constraints: conflict
+                    .constraints
+                    .iter()
+                    .map(|&(edge, satisfied)| GraphConstraint {
+                        required_by: graph.graph[graph.graph.edge_endpoints(edge).expect("edge of this graph").0].clone(),
+                        specifier: graph.graph[edge].specifier.clone(),
+                        satisfied,
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        GraphDocument {
+            schema_version: GRAPH_SCHEMA_VERSION,
+            root: graph.graph[graph.root].clone(),
+            reverse: graph.reverse,
+            annotate: match graph.overlay {
+                Some(Overlay::Licenses) => Some("licenses".to_string()),
+                Some(Overlay::Vulnerabilities(_)) => Some("vulns".to_string()),
+                Some(Overlay::Changes { .. }) => Some("changes".to_string()),
+                None => None,
+            },
+            nodes,
+            edges,
+            conflicts,
+            cycles: graph.cycles().iter().map(|cycle| cycle.iter().map(|&idx| graph.graph[idx].clone()).collect()).collect(),
+        }
+    }
 
-        let parent_idx = *node_indices.get(parent).unwrap();
+    pub fn export_json(graph: &DependencyGraph) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(serde_json::to_string_pretty(&Self::graph_document(graph))?)
+    }
+
+    /// A self-contained page with a zoomable, searchable force-directed
+    /// drawing of the graph; hovering a package shows its version, license
+    /// and size
+    pub fn export_html(graph: &DependencyGraph) -> Result<String, Box<dyn std::error::Error>> {
+        let data = serde_json::to_string(&Self::graph_document(graph))?;
+        // `</` would end the script element early
+        Ok(HTML_TEMPLATE
+            .replace("__TITLE__", &xml_escape(&graph.label(graph.root, " ")))
+            .replace("/*GRAPH_DATA*/null", &data.replace("</", "<\\/")))
+    }
+
+    /// The graph drawn top-down from the root, as a standalone SVG
+    pub fn export_svg(graph: &DependencyGraph) -> String {
+        // Larger packages get larger boxes
+        let weights = graph.size_weights();
+        let box_size = |idx: NodeIndex| {
+            let (width, height) = node_size(&graph.node_text(idx));
+            let scale = 1.0 + SIZE_SCALE * weights.get(&idx).copied().unwrap_or_default();
+            (width * scale, height * scale)
+        };
+        // Dependents are laid out below the package they depend on, by
+        // laying out the reversed graph and turning the edges back around
+        let layout = if graph.reverse {
+            let mut reversed = graph.graph.clone();
+            reversed.reverse();
+            let mut layout = graph_layout::layered(&reversed, graph.root, box_size);
+            for route in layout.edges.values_mut() {
+                route.points.reverse();
+            }
+            layout
+        } else {
+            graph_layout::layered(&graph.graph, graph.root, box_size)
+        };
+        let conflicts = graph.conflicts();
+        let (conflict_nodes, conflict_edges) = conflict_sets(&conflicts);
+        let legend = graph.conflict_legend(&conflicts);
+        // The legend goes under the graph, then any warnings
+        let (legend_width, legend_height) = notes_size(&legend);
+        let warnings = graph.warnings();
+        let (warnings_width, warnings_height) = notes_size(&warnings);
+        // And the key to an overlay's colors under that
+        let key = graph.overlay_key();
+        let key_lines: Vec<(String, &str)> = key
+            .iter()
+            .flat_map(|(_, entries)| entries.iter().map(|(name, fill, count)| (format!("{} ({})", name, count), *fill)))
+            .collect();
+        // Entries are indented past their swatch; the bold title is not
+        let key_width = key_lines
+            .iter()
+            .map(|(line, _)| line.chars().count() as f64 * CHAR_WIDTH + 20.0)
+            .chain(key.iter().map(|(title, _)| title.chars().count() as f64 * (CHAR_WIDTH + 0.5)))
+            .fold(0.0, f64::max)
+            + 2.0 * NODE_PADDING_X;
+        let key_height = if key.is_none() { 0.0 } else { (key_lines.len() + 1) as f64 * LINE_HEIGHT + 2.0 * NODE_PADDING_Y };
+        let (width, height) = (
+            layout.width.max(legend_width + 40.0).max(warnings_width + 40.0).max(key_width + 40.0).ceil(),
+            (layout.height + legend_height + warnings_height + key_height).ceil(),
+        );
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\" font-family=\"{2}\" font-size=\"12\">\n",
+            width, height, FONT_FAMILY
+        );
+        svg.push_str("  <defs>\n    <marker id=\"arrow\" viewBox=\"0 0 10 10\" refX=\"10\" refY=\"5\" markerWidth=\"7\" markerHeight=\"7\" orient=\"auto\">\n");
+        svg.push_str("      <path d=\"M0,0 L10,5 L0,10 z\" fill=\"#7f8c8d\"/>\n    </marker>\n");
+        svg.push_str("    <marker id=\"conflict-arrow\" viewBox=\"0 0 10 10\" refX=\"10\" refY=\"5\" markerWidth=\"7\" markerHeight=\"7\" orient=\"auto\">\n");
+        svg.push_str(&format!("      <path d=\"M0,0 L10,5 L0,10 z\" fill=\"{}\"/>\n    </marker>\n", CONFLICT_COLOR));
+        svg.push_str("    <marker id=\"added-arrow\" viewBox=\"0 0 10 10\" refX=\"10\" refY=\"5\" markerWidth=\"7\" markerHeight=\"7\" orient=\"auto\">\n");
+        svg.push_str(&format!("      <path d=\"M0,0 L10,5 L0,10 z\" fill=\"{}\"/>\n    </marker>\n  </defs>\n", ADDED_COLOR));
+        svg.push_str("  <rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n");
+
+        // Labels go over every edge line
+        let mut labels = String::new();
+        for edge in graph.graph.edge_indices() {
+            let route = &layout.edges[&edge];
+            let change = graph.edge_change(edge);
+            let dash = if route.back || change == Some("removed") { " stroke-dasharray=\"4 3\"" } else { "" };
+            let (color, marker) = match change {
+                _ if conflict_edges.contains(&edge) => (CONFLICT_COLOR, "conflict-arrow"),
+                Some("added") => (ADDED_COLOR, "added-arrow"),
+                Some(_) => (REMOVED_COLOR, "conflict-arrow"),
+                None => ("#7f8c8d", "arrow"),
+            };
+            svg.push_str(&format!(
+                "  <path d=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"1.2\"{} marker-end=\"url(#{})\"/>\n",
+                edge_path(&route.points),
+                color,
+                dash,
+                marker
+            ));
+            let specifier = &graph.graph[edge].specifier;
+            if !specifier.is_empty() {
+                // Along the last stretch, where edges into one node have
+                // spread apart less than edges out of one node
+                let last = route.points.len() - 1;
+                let (x, y) = curve_point(route.points[last - 1], route.points[last], 0.7);
+                labels.push_str(&format!(
+                    "  <text x=\"{:.1}\" y=\"{:.1}\" font-size=\"10\" fill=\"{}\" stroke=\"white\" stroke-width=\"3\" paint-order=\"stroke\">{}</text>\n",
+                    x + 4.0,
+                    y,
+                    color,
+                    xml_escape(specifier)
+                ));
+            }
+        }
+        svg.push_str(&labels);
+
+        for idx in graph.graph.node_indices() {
+            let node = &layout.nodes[&idx];
+            let label = graph.node_text(idx);
+            let stroke_width = if idx == graph.root { 2.5 } else { 1.0 };
+            let conflict = conflict_nodes.contains(&idx);
+            let stroke = if conflict { CONFLICT_COLOR } else { "#34495e" };
+            let fill = match graph.overlay_fill(idx) {
+                Some(fill) => fill.to_string(),
+                None if conflict => CONFLICT_FILL.to_string(),
+                None => size_fill(weights.get(&idx).copied().unwrap_or_default()),
+            };
+            svg.push_str(&format!("  <g>\n    <title>{}</title>\n", xml_escape(&label.replace('\n', " "))));
+            svg.push_str(&format!(
+                "    <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"6\" fill=\"{}\" stroke=\"{}\" stroke-width=\"{}\"/>\n",
+                node.x, node.y, node.width, node.height, fill, stroke, stroke_width
+            ));
+            // Lines centered in the box, which may be scaled up
+            let top = node.y + (node.height - label.lines().count() as f64 * LINE_HEIGHT) / 2.0;
+            for (line, text) in label.lines().enumerate() {
+                let fill = if line == 0 { "#2c3e50" } else { "#7f8c8d" };
+                svg.push_str(&format!(
+                    "    <text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\" fill=\"{}\">{}</text>\n",
+                    node.x + node.width / 2.0,
+                    top + LINE_HEIGHT * (line as f64 + 1.0) - 4.0,
+                    fill,
+                    xml_escape(text)
+                ));
+            }
+            svg.push_str("  </g>\n");
+        }
+
+        svg.push_str(&notes_box(layout.height, legend_width, CONFLICT_COLOR, "Version conflicts", &legend));
+        svg.push_str(&notes_box(layout.height + legend_height, warnings_width, WARNING_COLOR, "Warnings", &warnings));
+
+        if let Some((title, _)) = key {
+            let top = layout.height + legend_height + warnings_height;
+            svg.push_str(&format!(
+                "  <rect x=\"20\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"4\" fill=\"white\" stroke=\"#34495e\"/>\n",
+                top,
+                key_width,
+                key_height - NODE_PADDING_Y
+            ));
+            svg.push_str(&format!(
+                "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"#2c3e50\" font-weight=\"bold\">{}</text>\n",
+                20.0 + NODE_PADDING_X,
+                top + NODE_PADDING_Y + LINE_HEIGHT - 4.0,
+                title
+            ));
+            for (line, (text, fill)) in key_lines.iter().enumerate() {
+                let baseline = top + NODE_PADDING_Y + LINE_HEIGHT * (line as f64 + 2.0) - 4.0;
+                svg.push_str(&format!(
+                    "  <rect x=\"{:.1}\" y=\"{:.1}\" width=\"12\" height=\"12\" fill=\"{}\" stroke=\"#34495e\" stroke-width=\"0.5\"/>\n",
+                    20.0 + NODE_PADDING_X,
+                    baseline - 10.0,
+                    fill
+                ));
+                svg.push_str(&format!(
+                    "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"#2c3e50\">{}</text>\n",
+                    20.0 + NODE_PADDING_X + 20.0,
+                    baseline,
+                    xml_escape(text)
+                ));
+            }
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// The SVG drawing rasterized, with text in the system's fonts
+    pub fn export_png(graph: &DependencyGraph) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut options = resvg::usvg::Options::default();
+        options.fontdb_mut().load_system_fonts();
+        if options.fontdb.is_empty() {
+            eprintln!("{}", "Warning: no system fonts found; the PNG will have no text (use --format svg)".yellow());
+        }
+        let tree = resvg::usvg::Tree::from_str(&Self::export_svg(graph), &options)?;
+        let size = tree.size();
+        let (width, height) = ((size.width() * PNG_SCALE).ceil() as u32, (size.height() * PNG_SCALE).ceil() as u32);
+        let mut pixmap = resvg::tiny_skia::Pixmap::new(width, height)
+            .ok_or_else(|| format!("The graph is too large to render as a {}x{} PNG; use --format svg", width, height))?;
+        resvg::render(&tree, resvg::tiny_skia::Transform::from_scale(PNG_SCALE, PNG_SCALE), &mut pixmap.as_mut());
+        Ok(pixmap.encode_png()?)
+    }
+
+    /// An indented tree from the root, like `cargo tree` (dependents under
+    /// the root for a reverse graph); packages shown before are marked
+    /// `(*)` instead of repeated. `ascii` draws the branches with plain
+    /// characters, `color` adds terminal colors.
+    pub fn export_tree(graph: &DependencyGraph, ascii: bool, color: bool) -> String {
+        let (conflict_nodes, conflict_edges) = conflict_sets(&graph.conflicts());
+        let mut printer = TreePrinter { graph, ascii, color, conflict_nodes, conflict_edges, expanded: HashSet::from([graph.root]), lines: Vec::new() };
+        let root = printer.entry(graph.root, None);
+        printer.lines.push(root);
+        printer.children(graph.root, "");
+        printer.lines.join("\n") + "\n"
+    }
+}
+
+impl DependencyGraph {
+    /// Packages whose requirements disagree: an installed or pinned version
+    /// failing a requirement on it, or, for a package taken from PyPI
+    /// unpinned, no final release meeting all of them
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let mut conflicts = Vec::new();
+        for idx in self.graph.node_indices() {
+            let Some(package) = self.packages.get(&self.graph[idx]) else { continue };
+            // A diff's removed packages and requirements no longer apply
+            if self.overlay_category(idx) == Some("removed") {
+                continue;
+            }
+            let constraints: Vec<(EdgeIndex, SpecifierSet)> = self
+                .graph
+                .edges_directed(idx, Direction::Incoming)
+                .filter(|edge| self.edge_change(edge.id()) != Some("removed"))
+                .filter(|edge| !edge.weight().specifier.is_empty() && !edge.weight().specifier.starts_with('@'))
+                .filter_map(|edge| SpecifierSet::parse(&edge.weight().specifier).ok().map(|set| (edge.id(), set)))
+                .collect();
+            if constraints.is_empty() {
+                continue;
+            }
 
-        for dep in deps {
-            let dep_idx = if let Some(&idx) = node_indices.get(dep) {
-                idx
+            let version = package.version.as_deref().and_then(|version| Version::parse(version).ok());
+            let met: Vec<(EdgeIndex, bool)> = constraints
+                .iter()
+                .map(|(edge, set)| (*edge, version.as_ref().is_none_or(|version| set.contains(version))))
+                .collect();
+            let conflicting = if package.releases.is_empty() {
+                met.iter().any(|(_, satisfied)| !satisfied)
             } else {
-                let idx = graph.add_node(dep.clone());
-                node_indices.insert(dep.clone(), idx);
-                idx
+                !package
+                    .releases
+                    .iter()
+                    .filter_map(|release| Version::parse(release).ok())
+                    .filter(|release| !release.is_prerelease())
+                    .any(|release| constraints.iter().all(|(_, set)| set.contains(&release)))
             };
+            if conflicting {
+                conflicts.push(Conflict { package: idx, constraints: met });
+            }
+        }
+        conflicts
+    }
 
-            graph.add_edge(parent_idx, dep_idx, ());
+    /// One line per conflicting package, then one per requirement on it
+    pub fn conflict_legend(&self, conflicts: &[Conflict]) -> Vec<String> {
+        let mut lines = Vec::new();
+        for conflict in conflicts {
+            lines.push(self.label(conflict.package, " "));
+            for &(edge, satisfied) in &conflict.constraints {
+                let (from, _) = self.graph.edge_endpoints(edge).expect("edge of this graph");
+                let requirer = self.packages.get(&self.graph[from]).map_or(self.graph[from].as_str(), |package| package.name.as_str());
+                let note = if satisfied { "" } else { "  (not met)" };
+                lines.push(format!("  {} requires {}{}", requirer, self.graph[edge].specifier, note));
+            }
+        }
+        lines
+    }
 
-            if transitive {
-                if let Some(sub_deps) = all_dependencies.get(dep) {
-                    Self::add_dependencies_to_graph(
-                        graph,
-                        node_indices,
-                        dep,
-                        sub_deps,
-                        all_dependencies,
-                        transitive,
-                        visited,
-                    );
+    /// Groups of packages requiring each other, as the shortest way around
+    /// from each group's package nearest the root; requirements a lockfile
+    /// diff removed no longer count
+    pub fn cycles(&self) -> Vec<Vec<NodeIndex>> {
+        let live = EdgeFiltered::from_fn(&self.graph, |edge| self.edge_change(edge.id()) != Some("removed"));
+        let depths = self.depths();
+        let mut cycles = Vec::new();
+        for group in tarjan_scc(&live).into_iter().filter(|group| group.len() > 1) {
+            let members: HashSet<NodeIndex> = group.iter().copied().collect();
+            let start = *group
+                .iter()
+                .min_by_key(|idx| (depths.get(idx).copied().unwrap_or(usize::MAX), &self.graph[**idx]))
+                .expect("groups are not empty");
+
+            // Breadth first within the group until an edge leads back
+            let mut previous: HashMap<NodeIndex, NodeIndex> = HashMap::new();
+            let mut queue = VecDeque::from([start]);
+            let mut last = start;
+            'search: while let Some(idx) = queue.pop_front() {
+                for next in (&live).neighbors(idx).filter(|next| members.contains(next)) {
+                    if next == start {
+                        last = idx;
+                        break 'search;
+                    }
+                    if let Entry::Vacant(entry) = previous.entry(next) {
+                        entry.insert(idx);
+                        queue.push_back(next);
+                    }
                 }
             }
+            let mut cycle = vec![last];
+            while let Some(&idx) = previous.get(cycle.last().expect("starts with one")) {
+                cycle.push(idx);
+            }
+            cycle.reverse();
+            cycles.push(cycle);
         }
+        cycles.sort_by_key(|cycle| depths.get(&cycle[0]).copied().unwrap_or(usize::MAX));
+        cycles
+    }
+
+    /// The warnings shown alongside the graph: one line per cycle, then
+    /// one per package found at several versions
+    pub fn warnings(&self) -> Vec<String> {
+        let name = |idx: NodeIndex| self.packages.get(&self.graph[idx]).map_or(self.graph[idx].as_str(), |package| package.name.as_str());
+        let mut lines: Vec<String> = self
+            .cycles()
+            .iter()
+            .map(|cycle| format!("Dependency cycle: {}", cycle.iter().chain(&cycle[..1]).map(|&idx| name(idx)).collect::<Vec<_>>().join(" → ")))
+            .collect();
+        for idx in self.graph.node_indices().filter(|&idx| self.overlay_category(idx) != Some("removed")) {
+            if let Some(package) = self.packages.get(&self.graph[idx]).filter(|package| !package.other_versions.is_empty()) {
+                lines.push(format!("Duplicate versions: {} shown, {} also present", self.label(idx, " "), package.other_versions.join(", ")));
+            }
+        }
+        lines
+    }
 
-        visited.remove(parent);
+    /// Fewest requirement steps from the root to each package it reaches,
+    /// against the edges for dependents
+    fn depths(&self) -> HashMap<NodeIndex, usize> {
+        let direction = if self.reverse { Direction::Incoming } else { Direction::Outgoing };
+        let mut depths = HashMap::from([(self.root, 0)]);
+        let mut queue = VecDeque::from([self.root]);
+        while let Some(idx) = queue.pop_front() {
+            let depth = depths[&idx];
+            for next in self.graph.neighbors_directed(idx, direction) {
+                if let Entry::Vacant(entry) = depths.entry(next) {
+                    entry.insert(depth + 1);
+                    queue.push_back(next);
+                }
+            }
+        }
+        depths
+    }
+
+    /// The `limit` largest packages of known size, largest first
+    pub fn largest(&self, limit: usize) -> Vec<PackageSize> {
+        let size = |idx: NodeIndex| self.packages.get(&self.graph[idx]).and_then(|package| package.size).unwrap_or_default();
+
+        // A package retains everything it dominates: the packages every
+        // chain from the root reaches through it
+        let dominators = simple_fast(&self.graph, self.root);
+        let mut retained: HashMap<NodeIndex, u64> = HashMap::new();
+        for idx in self.graph.node_indices() {
+            let mut dominator = Some(idx);
+            while let Some(current) = dominator.filter(|current| *current != self.root) {
+                *retained.entry(current).or_default() += size(idx);
+                dominator = dominators.immediate_dominator(current);
+            }
+        }
+
+        let mut parents: HashMap<NodeIndex, NodeIndex> = HashMap::new();
+        let mut queue = VecDeque::from([self.root]);
+        while let Some(idx) = queue.pop_front() {
+            for next in self.graph.neighbors(idx) {
+                if next != self.root {
+                    if let Entry::Vacant(entry) = parents.entry(next) {
+                        entry.insert(idx);
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+
+        let mut sizes: Vec<PackageSize> = self
+            .graph
+            .node_indices()
+            .filter(|idx| *idx != self.root && size(*idx) > 0)
+            .map(|idx| {
+                let mut chain = vec![idx];
+                while let Some(parent) = parents.get(chain.last().expect("chain is never empty")) {
+                    chain.push(*parent);
+                }
+                chain.reverse();
+                PackageSize { package: idx, size: size(idx), retained: retained.get(&idx).copied().unwrap_or_default(), chain }
+            })
+            .collect();
+        sizes.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| self.graph[a.package].cmp(&self.graph[b.package])));
+        sizes.truncate(limit);
+        sizes
+    }
+
+    /// Checks every package of known version against the vulnerability
+    /// database, for `--annotate vulns`
+    pub async fn annotate_vulnerabilities(&mut self, scanner: &mut SecurityScanner) -> Result<(), Box<dyn std::error::Error>> {
+        let targets: Vec<InstalledPackage> = self
+            .packages
+            .values()
+            .filter_map(|package| package.version.clone().map(|version| InstalledPackage { name: package.name.clone(), version }))
+            .collect();
+        let findings = scanner.scan_installed(&targets).await?;
+        let found = findings.into_iter().map(|(package, vulns)| (normalize_dist_name(&package.name), vulns)).collect();
+        self.overlay = Some(Overlay::Vulnerabilities(found));
+        Ok(())
+    }
+
+    /// In a lockfile diff, how many packages were added, removed, upgraded
+    /// and downgraded
+    pub fn change_counts(&self) -> Vec<(&'static str, usize)> {
+        let Some(Overlay::Changes { packages, .. }) = &self.overlay else { return Vec::new() };
+        CHANGE_FILLS
+            .iter()
+            .map(|(name, _)| (*name, packages.values().filter(|(change, _)| change == name).count()))
+            .filter(|(name, count)| *name != "unchanged" && *count > 0)
+            .collect()
+    }
+
+    /// In a lockfile diff, a package's change and previous version
+    fn change(&self, idx: NodeIndex) -> Option<&(&'static str, Option<String>)> {
+        match &self.overlay {
+            Some(Overlay::Changes { packages, .. }) => packages.get(&self.graph[idx]),
+            _ => None,
+        }
     }
 
-    pub fn export_dot(graph: &Graph<String, (), Directed>) -> String {
-        format!("{:?}", Dot::with_config(graph, &[Config::EdgeNoLabel]))
+    fn license_category(&self, idx: NodeIndex) -> LicenseCategory {
+        self.packages
+            .get(&self.graph[idx])
+            .and_then(|package| package.license.as_deref())
+            .and_then(|license| LicenseExpr::parse(license).ok())
+            .map_or(LicenseCategory::Unknown, |license| license.category())
+    }
+
+    /// The overlay's name for a node's color: its license category, or the
+    /// severity of its worst advisory
+    fn overlay_category(&self, idx: NodeIndex) -> Option<&'static str> {
+        match self.overlay.as_ref()? {
+            Overlay::Licenses => Some(self.license_category(idx).as_str()),
+            Overlay::Vulnerabilities(found) => Some(match found.get(&self.graph[idx]) {
+                // Severity ranks 1 to 4 follow "none" in SEVERITY_FILLS
+                Some(vulns) => SEVERITY_FILLS[vulns.iter().map(|vuln| severity_rank(&vuln.severity) as usize).max().unwrap_or(0)].0,
+                None => "not checked",
+            }),
+            Overlay::Changes { packages, .. } => packages.get(&self.graph[idx]).map(|(change, _)| *change),
+        }
+    }
+
+    /// In a lockfile diff, whether a requirement was added or removed
+    fn edge_change(&self, edge: EdgeIndex) -> Option<&'static str> {
+        match &self.overlay {
+            Some(Overlay::Changes { edges, .. }) => edges.get(&edge).copied().filter(|change| *change != "unchanged"),
+            _ => None,
+        }
+    }
+
+    fn overlay_fill(&self, idx: NodeIndex) -> Option<&'static str> {
+        let category = self.overlay_category(idx)?;
+        LICENSE_FILLS.iter().chain(SEVERITY_FILLS.iter()).chain(CHANGE_FILLS.iter()).find(|(name, _)| *name == category).map(|(_, fill)| *fill)
+    }
+
+    /// The overlay's title and the colors in use, with how many packages
+    /// have each
+    fn overlay_key(&self) -> Option<(&'static str, Vec<KeyEntry>)> {
+        let (title, fills): (&str, &[(&str, &str)]) = match self.overlay.as_ref()? {
+            Overlay::Licenses => ("Licenses", &LICENSE_FILLS),
+            Overlay::Vulnerabilities(_) => ("Worst vulnerability", &SEVERITY_FILLS),
+            Overlay::Changes { .. } => ("Changes", &CHANGE_FILLS),
+        };
+        let categories: Vec<&str> = self.graph.node_indices().filter_map(|idx| self.overlay_category(idx)).collect();
+        let key = fills
+            .iter()
+            .map(|(name, fill)| (*name, *fill, categories.iter().filter(|category| *category == name).count()))
+            .filter(|(_, _, count)| *count > 0)
+            .collect();
+        Some((title, key))
+    }
+
+    /// An extra label line for the overlay: the license, or the advisories
+    fn overlay_note(&self, idx: NodeIndex) -> Option<String> {
+        let key = &self.graph[idx];
+        match self.overlay.as_ref()? {
+            Overlay::Licenses => Some(
+                self.packages.get(key).and_then(|package| package.license.clone()).unwrap_or_else(|| "unknown license".to_string()),
+            ),
+            Overlay::Vulnerabilities(found) => Some(match found.get(key).map(Vec::len) {
+                None => "not checked".to_string(),
+                Some(0) => "no known vulnerabilities".to_string(),
+                Some(1) => format!("1 vulnerability ({})", self.overlay_category(idx).unwrap_or_default()),
+                Some(count) => format!("{} vulnerabilities (worst {})", count, self.overlay_category(idx).unwrap_or_default()),
+            }),
+            Overlay::Changes { packages, .. } => match packages.get(key)? {
+                (change @ ("upgraded" | "downgraded"), Some(previous)) => Some(format!("{} from {}", change, previous)),
+                ("unchanged", _) => None,
+                (change, _) => Some(change.to_string()),
+            },
+        }
+    }
+
+    /// How large each package of known size is next to the largest, from 0
+    /// to 1; by square root, so box areas grow with size
+    fn size_weights(&self) -> HashMap<NodeIndex, f64> {
+        let sizes: Vec<(NodeIndex, u64)> = self
+            .graph
+            .node_indices()
+            .filter_map(|idx| self.packages.get(&self.graph[idx]).and_then(|package| package.size).map(|size| (idx, size)))
+            .collect();
+        let largest = sizes.iter().map(|(_, size)| *size).max().unwrap_or_default();
+        if largest == 0 {
+            return HashMap::new();
+        }
+        sizes.into_iter().map(|(idx, size)| (idx, (size as f64 / largest as f64).sqrt())).collect()
+    }
+
+    /// A node's label, with the package's size when known and the
+    /// overlay's note on further lines
+    fn node_text(&self, idx: NodeIndex) -> String {
+        let mut lines = vec![self.label(idx, "\n")];
+        lines.extend(self.packages.get(&self.graph[idx]).and_then(|package| package.size).map(format_size));
+        lines.extend(self.overlay_note(idx));
+        lines.join("\n")
+    }
+
+    /// Display name and version of a node, joined by `separator`
+    pub fn label(&self, idx: NodeIndex, separator: &str) -> String {
+        let key = &self.graph[idx];
+        match self.packages.get(key) {
+            Some(GraphPackage { name, version: Some(version), .. }) => format!("{}{}{}", name, separator, version),
+            Some(package) => package.name.clone(),
+            None => key.clone(),
+        }
+    }
+}
+
+/// Bytes as B, KB, MB or GB
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Fill for a node of size `weight` (0 to 1), between the usual blue and
+/// orange
+fn size_fill(weight: f64) -> String {
+    let mix = |from: u8, to: u8| (from as f64 + (to as f64 - from as f64) * weight.clamp(0.0, 1.0)).round() as u8;
+    format!("#{:02x}{:02x}{:02x}", mix(NODE_FILL.0, LARGE_FILL.0), mix(NODE_FILL.1, LARGE_FILL.1), mix(NODE_FILL.2, LARGE_FILL.2))
+}
+
+struct TreePrinter<'a> {
+    graph: &'a DependencyGraph,
+    ascii: bool,
+    color: bool,
+    conflict_nodes: HashSet<NodeIndex>,
+    conflict_edges: HashSet<EdgeIndex>,
+    /// Packages whose requirements have been printed
+    expanded: HashSet<NodeIndex>,
+    lines: Vec<String>,
+}
+
+impl TreePrinter<'_> {
+    fn children(&mut self, idx: NodeIndex, prefix: &str) {
+        let graph = &self.graph.graph;
+        let mut children: Vec<(NodeIndex, EdgeIndex)> = if self.graph.reverse {
+            graph.edges_directed(idx, Direction::Incoming).map(|edge| (edge.source(), edge.id())).collect()
+        } else {
+            graph.edges(idx).map(|edge| (edge.target(), edge.id())).collect()
+        };
+        children.sort_by(|a, b| graph[a.0].cmp(&graph[b.0]));
+
+        let (tee, corner, pipe) = if self.ascii { ("|-- ", "`-- ", "|   ") } else { ("├── ", "└── ", "│   ") };
+        for (position, &(child, edge)) in children.iter().enumerate() {
+            let last = position + 1 == children.len();
+            let repeated = !self.expanded.insert(child);
+            let mut line = format!("{}{}{}", prefix, if last { corner } else { tee }, self.entry(child, Some(edge)));
+            if repeated {
+                line.push_str(" (*)");
+            }
+            self.lines.push(line);
+            if !repeated {
+                self.children(child, &format!("{}{}", prefix, if last { "    " } else { pipe }));
+            }
+        }
+    }
+
+    /// `name version (specifier) [overlay note]`, colored when asked
+    fn entry(&self, idx: NodeIndex, edge: Option<EdgeIndex>) -> String {
+        let graph = self.graph;
+        let paint = |text: String, color: Option<Color>| match color {
+            Some(color) if self.color => text.color(color).to_string(),
+            _ => text,
+        };
+        let name = graph.packages.get(&graph.graph[idx]).map_or(graph.graph[idx].as_str(), |package| package.name.as_str());
+        let conflict = self.conflict_nodes.contains(&idx);
+        let mut entry = if conflict && self.color { name.red().bold().to_string() } else { name.to_string() };
+        if let Some(version) = graph.packages.get(&graph.graph[idx]).and_then(|package| package.version.clone()) {
+            entry.push(' ');
+            entry.push_str(&paint(version, Some(if conflict { Color::Red } else { Color::BrightBlack })));
+        }
+        if let Some(edge) = edge {
+            let specifier = &graph.graph[edge].specifier;
+            if !specifier.is_empty() {
+                let color = if self.conflict_edges.contains(&edge) { Color::Red } else { Color::BrightBlack };
+                entry.push_str(&format!(" {}", paint(format!("({})", specifier), Some(color))));
+            }
+            if let Some(change) = graph.edge_change(edge).filter(|_| matches!(graph.overlay_category(idx), Some("unchanged" | "upgraded" | "downgraded"))) {
+                let (note, color) = if change == "added" { ("[new requirement]", Color::Green) } else { ("[requirement removed]", Color::Red) };
+                entry.push_str(&format!(" {}", paint(note.to_string(), Some(color))));
+            }
+        }
+        if let Some(note) = graph.overlay_note(idx) {
+            entry.push_str(&format!(" {}", paint(format!("[{}]", note), graph.overlay_category(idx).and_then(category_color))));
+        }
+        entry
+    }
+}
+
+/// Terminal color for an overlay category, from harmless to alarming
+fn category_color(category: &str) -> Option<Color> {
+    match category {
+        "permissive" | "none" | "added" => Some(Color::Green),
+        "weak copyleft" | "low" | "medium" | "upgraded" | "downgraded" => Some(Color::Yellow),
+        "strong copyleft" | "high" | "critical" | "removed" => Some(Color::Red),
+        "proprietary" => Some(Color::Magenta),
+        "unknown" | "not checked" => Some(Color::BrightBlack),
+        _ => None,
+    }
+}
+
+/// Width and height of a node box holding `label`
+fn node_size(label: &str) -> (f64, f64) {
+    let longest = label.lines().map(|line| line.chars().count()).max().unwrap_or(0);
+    let lines = label.lines().count().max(1);
+    (longest as f64 * CHAR_WIDTH + 2.0 * NODE_PADDING_X, lines as f64 * LINE_HEIGHT + 2.0 * NODE_PADDING_Y)
+}
+
+/// Width and height of a box of notes under an SVG drawing; none when
+/// there are no lines
+fn notes_size(lines: &[String]) -> (f64, f64) {
+    if lines.is_empty() {
+        return (0.0, 0.0);
+    }
+    let longest = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+    (longest as f64 * CHAR_WIDTH + 2.0 * NODE_PADDING_X, (lines.len() + 1) as f64 * LINE_HEIGHT + 2.0 * NODE_PADDING_Y)
+}
+
+/// A box of notes with a bold title, `top` pixels down an SVG drawing
+fn notes_box(top: f64, width: f64, color: &str, title: &str, lines: &[String]) -> String {
+    if lines.is_empty() {
+        return String::new();
+    }
+    let (_, height) = notes_size(lines);
+    let mut svg = format!(
+        "  <rect x=\"20\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"4\" fill=\"white\" stroke=\"{}\"/>\n",
+        top,
+        width,
+        height - NODE_PADDING_Y,
+        color
+    );
+    for (line, text) in std::iter::once(title).chain(lines.iter().map(String::as_str)).enumerate() {
+        let weight = if line == 0 { " font-weight=\"bold\"" } else { "" };
+        svg.push_str(&format!(
+            "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\"{} xml:space=\"preserve\">{}</text>\n",
+            20.0 + NODE_PADDING_X,
+            top + NODE_PADDING_Y + LINE_HEIGHT * (line as f64 + 1.0) - 4.0,
+            color,
+            weight,
+            xml_escape(text)
+        ));
+    }
+    svg
+}
+
+/// SVG path data through `points`, curving in and out vertically
+fn edge_path(points: &[(f64, f64)]) -> String {
+    let mut path = format!("M{:.1},{:.1}", points[0].0, points[0].1);
+    for pair in points.windows(2) {
+        let ((x1, y1), (x2, y2)) = (pair[0], pair[1]);
+        let middle = (y1 + y2) / 2.0;
+        path.push_str(&format!(" C{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}", x1, middle, x2, middle, x2, y2));
+    }
+    path
+}
+
+/// The point `t` (0 to 1) along one curve of `edge_path`
+fn curve_point((x1, y1): (f64, f64), (x2, y2): (f64, f64), t: f64) -> (f64, f64) {
+    let along = 3.0 * t * t - 2.0 * t * t * t;
+    let down = 1.5 * t - 1.5 * t * t + t * t * t;
+    (x1 + (x2 - x1) * along, y1 + (y2 - y1) * down)
+}
+
+fn xml_escape(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Text for a quoted Mermaid label, where quotes and angle brackets are
+/// written as entity codes (`<br/>` line breaks are kept)
+fn mermaid_text(value: &str) -> String {
+    value
+        .replace('"', "#quot;")
+        .replace('<', "#lt;")
+        .replace('>', "#gt;")
+        .replace("#lt;br/#gt;", "<br/>")
+}
+
+/// Conflicting packages, and the requirements on them their versions fail
+fn conflict_sets(conflicts: &[Conflict]) -> (HashSet<NodeIndex>, HashSet<EdgeIndex>) {
+    let nodes = conflicts.iter().map(|conflict| conflict.package).collect();
+    let edges = conflicts
+        .iter()
+        .flat_map(|conflict| conflict.constraints.iter().filter(|(_, satisfied)| !satisfied).map(|(edge, _)| *edge))
+        .collect();
+    (nodes, edges)
+}
+
+/// A quoted DOT identifier
+fn dot_id(value: &str) -> String {
+    format!("\"{}\"", dot_escape(value))
+}
+
+fn dot_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Whether `name` matches `pattern`, where `*` stands for any run of
+/// characters and `?` for one
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // After a `*`: where the pattern resumes, and where in the name the
+    // star's match ends so far
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((resume, matched)) => {
+                    star = Some((resume, matched + 1));
+                    p = resume;
+                    n = matched + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// Where graph metadata comes from: the distributions installed in the
+// project environment, versions pinned by a lockfile, and PyPI's JSON API
+// for anything not installed (or installed at another version than pinned).
+// Requirements are filtered by their markers for the environment's Python
+// on this platform.
+pub struct DependencySource {
+    installed: HashMap<String, DistMetadata>,
+    pins: HashMap<String, String>,
+    /// Versions installed or pinned besides the one used, by key
+    others: HashMap<String, Vec<String>>,
+    markers: MarkerEnvironment,
+    http: HttpCache,
+}
+
+impl DependencySource {
+    pub fn new(lockfile: Option<&Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let env_dir = venv_dir();
+        // Every version each name is installed or pinned at: a second
+        // dist-info (an interrupted upgrade, a user-site install) or a
+        // second pin is reported, not silently dropped
+        let mut installed: HashMap<String, DistMetadata> = HashMap::new();
+        let mut installed_versions: HashMap<String, Vec<String>> = HashMap::new();
+        if env_dir.is_dir() {
+            for dist in read_installed_metadata(&env_dir) {
+                let key = normalize_dist_name(&dist.name);
+                installed_versions.entry(key.clone()).or_default().push(dist.version.clone());
+                installed.entry(key).or_insert(dist);
+            }
+        }
+
+        let mut pins: HashMap<String, String> = HashMap::new();
+        let mut pinned_versions: HashMap<String, Vec<String>> = HashMap::new();
+        if let Some(lockfile) = lockfile {
+            let entries = read_lockfile(lockfile)?
+                .ok_or_else(|| format!("{} pins only some packages; lock it first so every line is name==version", lockfile.display()))?;
+            for pin in entries {
+                let key = normalize_dist_name(&pin.name);
+                pinned_versions.entry(key.clone()).or_default().push(pin.version.clone());
+                pins.entry(key).or_insert(pin.version);
+            }
+        }
+
+        // Versions besides the one used, where one source had several; a
+        // pin differing from the installed version is the usual `-r` case
+        let mut others: HashMap<String, Vec<String>> = HashMap::new();
+        for (key, versions) in installed_versions.iter().chain(&pinned_versions).filter(|(_, versions)| versions.len() > 1) {
+            let used = pins.get(key).or_else(|| installed.get(key).map(|dist| &dist.version)).expect("found above");
+            let entry = others.entry(key.clone()).or_default();
+            for version in versions {
+                if compare_versions(version, used).is_ne() && !entry.iter().any(|other| compare_versions(other, version).is_eq()) {
+                    entry.push(version.clone());
+                }
+            }
+            entry.sort_by(|a, b| compare_versions(a, b));
+        }
+        others.retain(|_, versions| !versions.is_empty());
+
+        let python = env_python_version().unwrap_or_else(|| DEFAULT_PYTHON.to_string());
+        Ok(DependencySource { installed, pins, others, markers: MarkerEnvironment::current(&python), http: HttpCache::new()? })
+    }
+
+    /// Metadata for `root` (a requirement such as `requests[socks]`) and
+    /// everything it pulls in; with `transitive` off only the root's own
+    /// requirements are looked up, and its dependencies get just a version
+    pub async fn load(&self, root: &str, transitive: bool, filter: &GraphFilter) -> Result<HashMap<String, GraphPackage>, Box<dyn std::error::Error>> {
+        let root = Requirement::parse(root)?;
+        let root_key = normalize_dist_name(&root.name);
+        let mut packages: HashMap<String, GraphPackage> = HashMap::new();
+        let mut extras: HashMap<String, BTreeSet<String>> = HashMap::from([(root_key.clone(), root.extras.into_iter().collect())]);
+
+        let root_package = self.package(&root_key, &extras[&root_key]).await?;
+        let mut frontier = Vec::new();
+        for requirement in &root_package.requires {
+            extras.entry(requirement.name.clone()).or_default().extend(requirement.extras.iter().cloned());
+            frontier.push(requirement.name.clone());
+        }
+        packages.insert(root_key.clone(), root_package);
+
+        frontier.retain(|key| !filter.excludes(key));
+        if !transitive || filter.depth == Some(1) {
+            for key in frontier {
+                packages.entry(key.clone()).or_insert_with(|| self.leaf(&key));
+            }
+            return Ok(packages);
+        }
+
+        // Breadth first, fetching each level's metadata concurrently. A
+        // package reached again with new extras is looked up again.
+        let mut expanded: HashMap<String, BTreeSet<String>> = HashMap::from([(root_key.clone(), extras[&root_key].clone())]);
+        let mut depth = 1;
+        while !frontier.is_empty() {
+            frontier.sort();
+            frontier.dedup();
+            frontier.retain(|key| expanded.get(key) != extras.get(key) && !filter.excludes(key));
+            let lookups = frontier.iter().map(|key| async { (key.clone(), self.package(key, &extras[key]).await) });
+            let mut next = Vec::new();
+            for (key, result) in join_all(lookups).await {
+                let package = result.unwrap_or_else(|e| {
+                    eprintln!("{}", format!("Warning: no metadata for {}: {}", key, e).yellow());
+                    GraphPackage { source: "missing".to_string(), ..self.leaf(&key) }
+                });
+                for requirement in &package.requires {
+                    extras.entry(requirement.name.clone()).or_default().extend(requirement.extras.iter().cloned());
+                    next.push(requirement.name.clone());
+                }
+                expanded.insert(key.clone(), extras[&key].clone());
+                packages.insert(key, package);
+            }
+            // Packages at the last level are looked up, not expanded
+            if filter.depth.is_some_and(|max| depth >= max) {
+                break;
+            }
+            depth += 1;
+            frontier = next;
+        }
+        Ok(packages)
+    }
+
+    /// Metadata for every package the lockfile pins, with the extras the
+    /// pinned packages ask of each other
+    pub async fn load_pins(&self) -> Result<HashMap<String, GraphPackage>, Box<dyn std::error::Error>> {
+        let mut packages: HashMap<String, GraphPackage> = HashMap::new();
+        let mut extras: HashMap<String, BTreeSet<String>> = HashMap::new();
+        let mut expanded: HashMap<String, BTreeSet<String>> = HashMap::new();
+        loop {
+            // A package asked for new extras is looked up again
+            let pending: Vec<(String, BTreeSet<String>)> = self
+                .pins
+                .keys()
+                .map(|key| (key.clone(), extras.get(key).cloned().unwrap_or_default()))
+                .filter(|(key, wanted)| expanded.get(key) != Some(wanted))
+                .collect();
+            if pending.is_empty() {
+                return Ok(packages);
+            }
+            let lookups = pending.iter().map(|(key, wanted)| async { (key.clone(), wanted.clone(), self.package(key, wanted).await) });
+            for (key, wanted, result) in join_all(lookups).await {
+                let package = result.unwrap_or_else(|e| {
+                    eprintln!("{}", format!("Warning: no metadata for {}: {}", key, e).yellow());
+                    GraphPackage { source: "missing".to_string(), ..self.leaf(&key) }
+                });
+                expanded.insert(key.clone(), wanted);
+                packages.insert(key, package);
+            }
+            for package in packages.values() {
+                for requirement in package.requires.iter().filter(|requirement| !requirement.extras.is_empty()) {
+                    extras.entry(requirement.name.clone()).or_default().extend(requirement.extras.iter().cloned());
+                }
+            }
+        }
+    }
+
+    /// Installed packages that depend on `root`, directly or (with
+    /// `transitive`) through other packages, plus `root` itself
+    pub fn load_dependents(&self, root: &str, transitive: bool) -> Result<HashMap<String, GraphPackage>, Box<dyn std::error::Error>> {
+        if self.installed.is_empty() {
+            return Err("No project environment found. Create one with 'sa env create'".into());
+        }
+        let root_key = normalize_dist_name(&Requirement::parse(root)?.name);
+
+        // Extras any installed package asks of another decide which of its
+        // optional requirements count
+        let mut extras: HashMap<String, BTreeSet<String>> = HashMap::new();
+        for dist in self.installed.values() {
+            for requirement in dist.requirements.iter().filter_map(|entry| Requirement::parse(entry).ok()) {
+                extras.entry(normalize_dist_name(&requirement.name)).or_default().extend(requirement.extras);
+            }
+        }
+        let all: HashMap<String, GraphPackage> = self
+            .installed
+            .iter()
+            .map(|(key, dist)| {
+                let requested = extras.get(key).cloned().unwrap_or_default();
+                (key.clone(), self.graph_package(key, dist.clone(), dist.installed_size, "installed", &requested))
+            })
+            .collect();
+
+        let mut dependents: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (key, package) in &all {
+            for requirement in &package.requires {
+                dependents.entry(requirement.name.as_str()).or_default().push(key.as_str());
+            }
+        }
+        let mut found: BTreeSet<String> = BTreeSet::new();
+        let mut frontier = vec![root_key.as_str()];
+        while let Some(key) = frontier.pop() {
+            for &dependent in dependents.get(key).into_iter().flatten() {
+                if dependent != root_key && found.insert(dependent.to_string()) && transitive {
+                    frontier.push(dependent);
+                }
+            }
+        }
+
+        let mut packages: HashMap<String, GraphPackage> = all.into_iter().filter(|(key, _)| found.contains(key)).collect();
+        if !self.installed.contains_key(&root_key) {
+            eprintln!("{}", format!("Warning: {} is not installed in the project environment", root).yellow());
+        }
+        let root_package = self.leaf(&root_key);
+        packages.insert(root_key, root_package);
+        Ok(packages)
+    }
+
+    /// A package whose own requirements are not needed
+    fn leaf(&self, key: &str) -> GraphPackage {
+        let installed = self.installed.get(key);
+        GraphPackage {
+            name: installed.map(|dist| dist.name.clone()).unwrap_or_else(|| key.to_string()),
+            version: self.pins.get(key).cloned().or_else(|| installed.map(|dist| dist.version.clone())),
+            requires: Vec::new(),
+            source: if installed.is_some() { "installed" } else { "pypi" }.to_string(),
+            license: installed.and_then(classify_license).map(|license| license.to_string()),
+            size: installed.and_then(|dist| dist.installed_size),
+            releases: Vec::new(),
+            other_versions: self.others.get(key).cloned().unwrap_or_default(),
+        }
+    }
+
+    /// A package with its requirements, from the environment when the
+    /// installed version is the one wanted, else from PyPI
+    async fn package(&self, key: &str, extras: &BTreeSet<String>) -> Result<GraphPackage, Box<dyn std::error::Error>> {
+        let pinned = self.pins.get(key);
+        let installed = self
+            .installed
+            .get(key)
+            .filter(|dist| pinned.is_none_or(|version| compare_versions(version, &dist.version).is_eq()));
+
+        let mut releases = Vec::new();
+        let (dist, size, source) = match installed {
+            Some(dist) => (dist.clone(), dist.installed_size, "installed"),
+            None => {
+                let url = match pinned {
+                    Some(version) => format!("{}/{}/{}/json", PYPI_JSON_URL, key, version),
+                    None => format!("{}/{}/json", PYPI_JSON_URL, key),
+                };
+                let project = self
+                    .http
+                    .get_json(&url)
+                    .await
+                    .map_err(|e| format!("'{}' is not installed and could not be looked up on PyPI: {}", key, e))?;
+                let info = &project["info"];
+                let strings = |value: &Value| -> Vec<String> {
+                    value.as_array().map(|entries| entries.iter().filter_map(Value::as_str).map(str::to_string).collect()).unwrap_or_default()
+                };
+                let dist = DistMetadata {
+                    name: info["name"].as_str().unwrap_or(key).to_string(),
+                    version: info["version"].as_str().unwrap_or_default().to_string(),
+                    license_expression: info["license_expression"].as_str().map(str::to_string),
+                    license: info["license"].as_str().map(str::to_string),
+                    classifiers: strings(&info["classifiers"]),
+                    requires: Vec::new(),
+                    requirements: strings(&info["requires_dist"]),
+                    installed_size: None,
+                };
+                // A wheel's size when the release has one, else the sdist's
+                let files = project["urls"].as_array().cloned().unwrap_or_default();
+                let size = files
+                    .iter()
+                    .find(|file| file["packagetype"] == "bdist_wheel")
+                    .or_else(|| files.first())
+                    .and_then(|file| file["size"].as_u64());
+                if pinned.is_none() {
+                    releases = project["releases"].as_object().map(|releases| releases.keys().cloned().collect()).unwrap_or_default();
+                }
+                (dist, size, "pypi")
+            }
+        };
+        Ok(GraphPackage { releases, ..self.graph_package(key, dist, size, source, extras) })
+    }
+
+    /// A graph package for `dist`, keeping the requirements whose markers
+    /// hold here with `extras` requested
+    fn graph_package(&self, key: &str, dist: DistMetadata, size: Option<u64>, source: &str, extras: &BTreeSet<String>) -> GraphPackage {
+        let license = classify_license(&dist).map(|license| license.to_string());
+        let extras: Vec<String> = extras.iter().cloned().collect();
+        let mut requires: Vec<GraphRequirement> = Vec::new();
+        for entry in &dist.requirements {
+            let Ok(requirement) = Requirement::parse(entry) else { continue };
+            // Markers SA cannot evaluate keep the requirement
+            let applies = requirement
+                .marker
+                .as_deref()
+                .is_none_or(|marker| evaluate_marker(marker, &self.markers, &extras).unwrap_or(true));
+            let dep = normalize_dist_name(&requirement.name);
+            if !applies || dep == key || requires.iter().any(|existing| existing.name == dep) {
+                continue;
+            }
+            requires.push(GraphRequirement {
+                name: dep,
+                extras: requirement.extras,
+                specifier: requirement.specifier,
+                marker: requirement.marker,
+            });
+        }
+
+        GraphPackage {
+            version: Some(dist.version).filter(|v| !v.is_empty()),
+            name: dist.name,
+            requires,
+            source: source.to_string(),
+            license,
+            size,
+            releases: Vec::new(),
+            other_versions: self.others.get(key).cloned().unwrap_or_default(),
+        }
     }
 }
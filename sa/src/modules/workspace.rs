@@ -0,0 +1,219 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::DocumentMut;
+use crate::modules::installer::normalize_dist_name;
+use crate::modules::models::WorkspaceConfig;
+use crate::modules::pep508::Requirement;
+use crate::modules::visualize::glob_match;
+
+// Monorepos: the root pyproject.toml lists its member projects under
+// `[tool.sa.workspace]`. Members depend on each other by name, while they
+// are developed together usually as path dependencies (`core @ file:../core`).
+// Building or publishing them all goes dependencies first, and since indexes
+// refuse distributions that depend on local paths, a path dependency can be
+// replaced by the version of the member just published.
+
+/// A project of the workspace
+pub struct Member {
+    pub name: String,
+    pub dir: PathBuf,
+    /// Normalized names of the members it depends on
+    pub depends_on: Vec<String>,
+    /// Of those, the ones it references by path (a direct reference)
+    pub path_deps: Vec<String>,
+}
+
+/// The member projects `config` lists below `root`, in the order listed
+pub fn load_members(root: &Path, config: &WorkspaceConfig) -> Result<Vec<Member>, Box<dyn std::error::Error>> {
+    if config.members.is_empty() {
+        return Err("No workspace members; list the member projects under [tool.sa.workspace] members".into());
+    }
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    for pattern in &config.members {
+        let matched = expand(root, pattern);
+        if matched.is_empty() {
+            return Err(format!("Workspace member '{}' matches no directory with a pyproject.toml", pattern).into());
+        }
+        for dir in matched {
+            let relative = dir.strip_prefix(root).unwrap_or(&dir).to_string_lossy().replace('\\', "/");
+            if !dirs.contains(&dir) && !config.exclude.iter().any(|exclude| glob_match(exclude.trim_end_matches('/'), &relative)) {
+                dirs.push(dir);
+            }
+        }
+    }
+
+    let mut projects: Vec<(String, PathBuf, Vec<String>)> = Vec::new();
+    for dir in dirs {
+        let (name, requirements) = read_project(&dir)?;
+        if let Some((_, other, _)) = projects.iter().find(|(other, _, _)| normalize_dist_name(other) == normalize_dist_name(&name)) {
+            return Err(format!("Workspace members {} and {} are both named '{}'", other.display(), dir.display(), name).into());
+        }
+        projects.push((name, dir, requirements));
+    }
+
+    let names: HashSet<String> = projects.iter().map(|(name, _, _)| normalize_dist_name(name)).collect();
+    let members = projects
+        .into_iter()
+        .map(|(name, dir, requirements)| {
+            let (mut depends_on, mut path_deps) = (Vec::new(), Vec::new());
+            for requirement in requirements.iter().filter_map(|requirement| Requirement::parse(requirement).ok()) {
+                let dependency = normalize_dist_name(&requirement.name);
+                if !names.contains(&dependency) || dependency == normalize_dist_name(&name) {
+                    continue;
+                }
+                if requirement.specifier.starts_with('@') && !path_deps.contains(&dependency) {
+                    path_deps.push(dependency.clone());
+                }
+                if !depends_on.contains(&dependency) {
+                    depends_on.push(dependency);
+                }
+            }
+            Member { name, dir, depends_on, path_deps }
+        })
+        .collect();
+    Ok(members)
+}
+
+/// Members with every member they depend on before them, otherwise in the
+/// order listed
+pub fn build_order(members: &[Member]) -> Result<Vec<&Member>, Box<dyn std::error::Error>> {
+    let mut done: HashSet<String> = HashSet::new();
+    let mut order = Vec::new();
+    while order.len() < members.len() {
+        let next = members
+            .iter()
+            .find(|member| !done.contains(&normalize_dist_name(&member.name)) && member.depends_on.iter().all(|dependency| done.contains(dependency)));
+        let Some(next) = next else {
+            let stuck: Vec<&str> = members.iter().filter(|member| !done.contains(&normalize_dist_name(&member.name))).map(|member| member.name.as_str()).collect();
+            return Err(format!("Workspace members depend on each other in a cycle: {}", stuck.join(", ")).into());
+        };
+        done.insert(normalize_dist_name(&next.name));
+        order.push(next);
+    }
+    Ok(order)
+}
+
+/// A member's directory as the working directory; the previous one is
+/// restored when dropped
+pub struct MemberDir {
+    previous: PathBuf,
+}
+
+impl MemberDir {
+    pub fn enter(member: &Member) -> Result<Self, Box<dyn std::error::Error>> {
+        let previous = std::env::current_dir()?;
+        std::env::set_current_dir(&member.dir).map_err(|e| format!("Failed to enter {}: {}", member.dir.display(), e))?;
+        Ok(MemberDir { previous })
+    }
+}
+
+impl Drop for MemberDir {
+    fn drop(&mut self) {
+        let _ = std::env::set_current_dir(&self.previous);
+    }
+}
+
+/// A member's pyproject.toml with its path dependencies on other members
+/// replaced by `name==version`; the original is put back when dropped
+pub struct PinnedPyproject {
+    path: PathBuf,
+    original: String,
+    /// The requirements written in place of the path dependencies
+    pub pins: Vec<String>,
+}
+
+impl PinnedPyproject {
+    /// Pins each path dependency of `member` to its version in `versions`
+    /// (normalized name to version), which must have them all
+    pub fn pin(member: &Member, versions: &HashMap<String, String>) -> Result<Self, Box<dyn std::error::Error>> {
+        if let Some(missing) = member.path_deps.iter().find(|dependency| !versions.contains_key(*dependency)) {
+            return Err(format!("{} depends on {} by path, but no version of it was published", member.name, missing).into());
+        }
+        let path = member.dir.join("pyproject.toml");
+        let original = fs::read_to_string(&path)?;
+        let mut doc: DocumentMut = original.parse().map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+
+        let mut pins = Vec::new();
+        if let Some(project) = doc.get_mut("project").and_then(|project| project.as_table_like_mut()) {
+            if let Some(dependencies) = project.get_mut("dependencies").and_then(|item| item.as_array_mut()) {
+                pin_array(dependencies, versions, &mut pins);
+            }
+            if let Some(optional) = project.get_mut("optional-dependencies").and_then(|item| item.as_table_like_mut()) {
+                for (_, group) in optional.iter_mut() {
+                    if let Some(dependencies) = group.as_array_mut() {
+                        pin_array(dependencies, versions, &mut pins);
+                    }
+                }
+            }
+        }
+        fs::write(&path, doc.to_string())?;
+        Ok(PinnedPyproject { path, original, pins })
+    }
+}
+
+impl Drop for PinnedPyproject {
+    fn drop(&mut self) {
+        let _ = fs::write(&self.path, &self.original);
+    }
+}
+
+fn pin_array(dependencies: &mut toml_edit::Array, versions: &HashMap<String, String>, pins: &mut Vec<String>) {
+    for value in dependencies.iter_mut() {
+        let Some(requirement) = value.as_str().and_then(|text| Requirement::parse(text).ok()) else { continue };
+        let Some(version) = versions.get(&normalize_dist_name(&requirement.name)).filter(|_| requirement.specifier.starts_with('@')) else { continue };
+
+        let extras = if requirement.extras.is_empty() { String::new() } else { format!("[{}]", requirement.extras.join(",")) };
+        let marker = requirement.marker.as_deref().map(|marker| format!("; {}", marker)).unwrap_or_default();
+        let pinned = format!("{}{}=={}{}", requirement.name, extras, version, marker);
+        // Keep the comments around the entry
+        let decor = value.decor().clone();
+        *value = toml_edit::Value::from(pinned.as_str());
+        *value.decor_mut() = decor;
+        pins.push(pinned);
+    }
+}
+
+/// Directories matching a member pattern that hold a pyproject.toml
+fn expand(root: &Path, pattern: &str) -> Vec<PathBuf> {
+    let mut dirs = vec![root.to_path_buf()];
+    for component in pattern.split('/').filter(|component| !component.is_empty() && *component != ".") {
+        dirs = dirs
+            .into_iter()
+            .flat_map(|dir| {
+                if !component.contains(['*', '?']) {
+                    return vec![dir.join(component)];
+                }
+                let mut matched: Vec<PathBuf> = fs::read_dir(&dir)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|entry| entry.ok())
+                    .filter(|entry| entry.path().is_dir() && glob_match(component, &entry.file_name().to_string_lossy()))
+                    .map(|entry| entry.path())
+                    .collect();
+                matched.sort();
+                matched
+            })
+            .collect();
+    }
+    dirs.into_iter().filter(|dir| dir.join("pyproject.toml").is_file()).collect()
+}
+
+/// A member's project name, and its dependencies including the optional ones
+fn read_project(dir: &Path) -> Result<(String, Vec<String>), Box<dyn std::error::Error>> {
+    let path = dir.join("pyproject.toml");
+    let pyproject: toml::Value = toml::from_str(&fs::read_to_string(&path)?).map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+    let project = pyproject.get("project").ok_or_else(|| format!("{} has no [project] table", path.display()))?;
+    let name = project.get("name").and_then(|name| name.as_str()).ok_or_else(|| format!("{} has no project.name", path.display()))?;
+
+    let strings = |value: Option<&toml::Value>| -> Vec<String> {
+        value.and_then(|value| value.as_array()).into_iter().flatten().filter_map(|item| item.as_str()).map(str::to_string).collect()
+    };
+    let mut requirements = strings(project.get("dependencies"));
+    if let Some(optional) = project.get("optional-dependencies").and_then(|optional| optional.as_table()) {
+        for group in optional.values() {
+            requirements.extend(strings(Some(group)));
+        }
+    }
+    Ok((name.to_string(), requirements))
+}